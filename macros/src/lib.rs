@@ -0,0 +1,274 @@
+#![warn(rust_2018_idioms)]
+
+//! Procedural macros for the [`jsonl`](https://docs.rs/jsonl) crate. Use them through `jsonl` with
+//! its `derive` feature enabled rather than depending on this crate directly.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::punctuated::Punctuated;
+use syn::{
+    parse_macro_input, parse_quote, FnArg, GenericArgument, ItemEnum, ItemTrait, LitInt, LitStr,
+    Meta, Pat, PathArguments, ReturnType, Token, TraitItem, Type,
+};
+
+/// Generates a typed client and a server for the remote procedure calls described by a trait.
+///
+/// Every method of the trait must take `&self` or `&mut self`, name each of its parameters, and
+/// return `Result<T, jsonl::ErrorObject>`. For a trait called `Calculator`, this generates:
+///
+/// - `CalculatorClient<R, W>`, which wraps a `jsonl::Client` and has a method for each method of
+///   the trait that calls it remotely;
+/// - `CalculatorServer<S>`, which wraps any implementation of the trait and implements
+///   `jsonl::Service`, so that it can be passed to `jsonl::serve`.
+///
+/// Parameters are sent as a JSON array in the order they are declared, and each method is called
+/// by its name.
+#[proc_macro_attribute]
+pub fn rpc(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let item_trait = parse_macro_input!(item as ItemTrait);
+
+    match expand_rpc(&item_trait) {
+        Ok(expanded) => expanded.into(),
+        Err(e) => {
+            let error = e.to_compile_error();
+            quote!(#item_trait #error).into()
+        }
+    }
+}
+
+fn expand_rpc(item_trait: &ItemTrait) -> syn::Result<TokenStream2> {
+    let vis = &item_trait.vis;
+    let trait_ident = &item_trait.ident;
+    let client_ident = format_ident!("{}Client", trait_ident);
+    let server_ident = format_ident!("{}Server", trait_ident);
+
+    let mut client_methods = Vec::new();
+    let mut server_arms = Vec::new();
+
+    for item in &item_trait.items {
+        let method = match item {
+            TraitItem::Fn(method) => method,
+            _ => continue,
+        };
+
+        let sig = &method.sig;
+        let ident = &sig.ident;
+        let name = ident.to_string();
+
+        match sig.inputs.first() {
+            Some(FnArg::Receiver(receiver)) if receiver.reference.is_some() => {}
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    sig,
+                    "RPC methods must take `&self` or `&mut self`",
+                ))
+            }
+        }
+
+        let mut arg_idents = Vec::new();
+        let mut arg_types = Vec::new();
+
+        for input in sig.inputs.iter().skip(1) {
+            let pat_type = match input {
+                FnArg::Typed(pat_type) => pat_type,
+                FnArg::Receiver(_) => unreachable!(),
+            };
+
+            match &*pat_type.pat {
+                Pat::Ident(pat_ident) => arg_idents.push(&pat_ident.ident),
+                pat => {
+                    return Err(syn::Error::new_spanned(
+                        pat,
+                        "RPC method parameters must be plain identifiers",
+                    ))
+                }
+            }
+
+            arg_types.push(&*pat_type.ty);
+        }
+
+        let ok_type = result_ok_type(&sig.output)?;
+
+        client_methods.push(quote! {
+            pub fn #ident(
+                &mut self,
+                #(#arg_idents: #arg_types),*
+            ) -> ::std::result::Result<#ok_type, ::jsonl::RpcError> {
+                self.client.call(#name, &(#(#arg_idents,)*))
+            }
+        });
+
+        server_arms.push(quote! {
+            #name => {
+                let (#(#arg_idents,)*): (#(#arg_types,)*) =
+                    ::jsonl::serde_json::from_value(params)
+                        .map_err(::jsonl::ErrorObject::invalid_params)?;
+                let result = self.0.#ident(#(#arg_idents),*)?;
+                ::jsonl::serde_json::to_value(result).map_err(::jsonl::ErrorObject::internal_error)
+            }
+        });
+    }
+
+    Ok(quote! {
+        #item_trait
+
+        #[derive(Debug)]
+        #vis struct #client_ident<R, W> {
+            client: ::jsonl::Client<R, W>,
+        }
+
+        impl<R: ::std::io::BufRead, W: ::std::io::Write> #client_ident<R, W> {
+            pub fn new(reader: R, writer: W) -> Self {
+                Self {
+                    client: ::jsonl::Client::new(reader, writer),
+                }
+            }
+
+            pub fn into_inner(self) -> ::jsonl::Client<R, W> {
+                self.client
+            }
+
+            #(#client_methods)*
+        }
+
+        #[derive(Debug)]
+        #vis struct #server_ident<S>(pub S);
+
+        impl<S: #trait_ident> ::jsonl::Service for #server_ident<S> {
+            fn call(
+                &mut self,
+                method: &str,
+                params: ::jsonl::serde_json::Value,
+            ) -> ::std::result::Result<::jsonl::serde_json::Value, ::jsonl::ErrorObject> {
+                match method {
+                    #(#server_arms)*
+                    _ => ::std::result::Result::Err(::jsonl::ErrorObject::method_not_found(method)),
+                }
+            }
+        }
+    })
+}
+
+/// Extracts `T` from a return type of `Result<T, E>`.
+fn result_ok_type(output: &ReturnType) -> syn::Result<&Type> {
+    let error = || syn::Error::new_spanned(output, "RPC methods must return a `Result`");
+
+    let ty = match output {
+        ReturnType::Type(_, ty) => ty,
+        ReturnType::Default => return Err(error()),
+    };
+
+    let segment = match &**ty {
+        Type::Path(type_path) => type_path.path.segments.last().ok_or_else(error)?,
+        _ => return Err(error()),
+    };
+
+    if segment.ident != "Result" {
+        return Err(error());
+    }
+
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) => match args.args.first() {
+            Some(GenericArgument::Type(ok_type)) => Ok(ok_type),
+            _ => Err(error()),
+        },
+        _ => Err(error()),
+    }
+}
+
+/// Turns an enum into a protocol message type, implementing `serde`’s `Serialize` and
+/// `Deserialize` and `jsonl::Message` for it in a standard way.
+///
+/// Each variant is serialized as an object with a `type` field holding the variant’s name in
+/// `snake_case`, alongside the variant’s own fields. The tag field can be changed with
+/// `#[jsonl::message(tag = "kind")]`, and the protocol version recorded in `jsonl::Message` (one by
+/// default) with `#[jsonl::message(version = 2)]`.
+///
+/// A single unit variant can be marked `#[message(unknown)]` to catch messages of any type not
+/// known to this version of the enum, instead of failing to deserialize them.
+#[proc_macro_attribute]
+pub fn message(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attr with Punctuated::<Meta, Token![,]>::parse_terminated);
+    let item_enum = parse_macro_input!(item as ItemEnum);
+
+    match expand_message(args, item_enum) {
+        Ok(expanded) => expanded.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand_message(
+    args: Punctuated<Meta, Token![,]>,
+    mut item_enum: ItemEnum,
+) -> syn::Result<TokenStream2> {
+    let mut tag = LitStr::new("type", proc_macro2::Span::call_site());
+    let mut version = LitInt::new("1", proc_macro2::Span::call_site());
+
+    for arg in &args {
+        match arg {
+            Meta::NameValue(name_value) if name_value.path.is_ident("tag") => {
+                let value = &name_value.value;
+                tag = syn::parse2(quote!(#value))?;
+            }
+            Meta::NameValue(name_value) if name_value.path.is_ident("version") => {
+                let value = &name_value.value;
+                version = syn::parse2(quote!(#value))?;
+            }
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    arg,
+                    "expected `tag = \"...\"` or `version = ...`",
+                ))
+            }
+        }
+    }
+
+    let mut unknown_variant = None;
+
+    for variant in &mut item_enum.variants {
+        let len = variant.attrs.len();
+        variant.attrs.retain(|attr| {
+            !(attr.path().is_ident("message")
+                && attr
+                    .parse_args::<syn::Ident>()
+                    .is_ok_and(|ident| ident == "unknown"))
+        });
+
+        if variant.attrs.len() == len {
+            continue;
+        }
+
+        if unknown_variant.is_some() || !matches!(variant.fields, syn::Fields::Unit) {
+            return Err(syn::Error::new_spanned(
+                &variant.ident,
+                "only a single unit variant can be marked `#[message(unknown)]`",
+            ));
+        }
+
+        variant.attrs.push(parse_quote!(#[serde(other)]));
+        unknown_variant = Some(variant.ident.clone());
+    }
+
+    let ident = &item_enum.ident;
+    let (impl_generics, ty_generics, where_clause) = item_enum.generics.split_for_impl();
+    let is_unknown = match &unknown_variant {
+        Some(variant) => quote!(::std::matches!(self, Self::#variant)),
+        None => quote!(false),
+    };
+
+    Ok(quote! {
+        #[derive(::jsonl::serde::Serialize, ::jsonl::serde::Deserialize)]
+        #[serde(crate = "::jsonl::serde", tag = #tag, rename_all = "snake_case")]
+        #item_enum
+
+        impl #impl_generics ::jsonl::Message for #ident #ty_generics #where_clause {
+            const TAG: &'static str = #tag;
+            const VERSION: u64 = #version;
+
+            fn is_unknown(&self) -> bool {
+                #is_unknown
+            }
+        }
+    })
+}
@@ -0,0 +1,135 @@
+#[cfg(not(feature = "tokio"))]
+mod imports {
+    pub(super) use std::net::{TcpListener, TcpStream};
+    pub(super) use std::os::unix::net::{UnixListener, UnixStream};
+}
+#[cfg(feature = "tokio")]
+mod imports {
+    pub(super) use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+}
+
+use imports::*;
+use socket2::{Domain, Socket, Type};
+use std::env;
+use std::io;
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The first file descriptor passed by systemd, as defined by `sd_listen_fds(3)`.
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+static ACTIVATED_SOCKETS_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// A socket passed to the current process by systemd socket activation.
+///
+/// Listening sockets are passed for units with `Accept=no` (the default), while already-connected
+/// streams are passed for units with `Accept=yes`. Create a [`crate::Connection`] from a stream
+/// with [`crate::Connection::new_from_tcp_stream`], [`crate::Connection::new_from_unix_stream`] or
+/// [`crate::ConnectionBuilder`].
+#[derive(Debug)]
+pub enum ActivatedSocket {
+    TcpListener(TcpListener),
+    UnixListener(UnixListener),
+    TcpStream(TcpStream),
+    UnixStream(UnixStream),
+}
+
+/// Takes ownership of the sockets passed to the current process by systemd, along with the names
+/// given to them by `FileDescriptorName=` (if any).
+///
+/// The `LISTEN_PID`, `LISTEN_FDS` and `LISTEN_FDNAMES` environment variables are removed so that
+/// child processes do not mistake the sockets for their own, and all subsequent calls return an
+/// empty `Vec`. An empty `Vec` is also returned if the process was not socket-activated.
+///
+/// An error is returned if one of the file descriptors is not a TCP or Unix domain stream socket.
+pub fn activated_sockets() -> io::Result<Vec<(Option<String>, ActivatedSocket)>> {
+    let listen_pid = env::var("LISTEN_PID");
+    let listen_fds = env::var("LISTEN_FDS");
+    let listen_fdnames = env::var("LISTEN_FDNAMES");
+
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+
+    let is_for_us = listen_pid.is_ok_and(|pid| pid == std::process::id().to_string());
+
+    if !is_for_us || ACTIVATED_SOCKETS_TAKEN.swap(true, Ordering::SeqCst) {
+        return Ok(Vec::new());
+    }
+
+    let num_fds: RawFd = match listen_fds.map(|num_fds| num_fds.parse()) {
+        Ok(Ok(num_fds)) => num_fds,
+        Ok(Err(_)) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "LISTEN_FDS is not a valid number",
+            ))
+        }
+        Err(_) => return Ok(Vec::new()),
+    };
+
+    let mut names = listen_fdnames
+        .ok()
+        .map(|names| names.split(':').map(String::from).collect::<Vec<_>>())
+        .unwrap_or_default()
+        .into_iter();
+
+    (SD_LISTEN_FDS_START..SD_LISTEN_FDS_START + num_fds)
+        .map(|fd| {
+            // SAFETY: systemd guarantees that these file descriptors are open and were passed to
+            // this process, and ACTIVATED_SOCKETS_TAKEN ensures we only ever take them once.
+            let socket = unsafe { Socket::from_raw_fd(fd) };
+            Ok((names.next(), ActivatedSocket::new(socket, fd)?))
+        })
+        .collect()
+}
+
+/// Converts a `socket2::Socket` into the `std` or Tokio socket type of the same name.
+macro_rules! from_socket {
+    ($ty:ident, $socket:expr) => {{
+        #[cfg(not(feature = "tokio"))]
+        let socket = $ty::from($socket);
+        #[cfg(feature = "tokio")]
+        let socket = $ty::from_std($socket.into())?;
+        socket
+    }};
+}
+
+impl ActivatedSocket {
+    fn new(socket: Socket, fd: RawFd) -> io::Result<Self> {
+        let not_supported = || {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "file descriptor {} is not a TCP or Unix domain stream socket",
+                    fd
+                ),
+            )
+        };
+
+        if socket.r#type().map_err(|_| not_supported())? != Type::STREAM {
+            return Err(not_supported());
+        }
+
+        socket.set_cloexec(true)?;
+        #[cfg(feature = "tokio")]
+        socket.set_nonblocking(true)?;
+
+        let domain = socket.domain()?;
+        let is_listener = socket.is_listener()?;
+
+        let activated_socket = match (domain, is_listener) {
+            (Domain::IPV4 | Domain::IPV6, true) => {
+                Self::TcpListener(from_socket!(TcpListener, socket))
+            }
+            (Domain::UNIX, true) => Self::UnixListener(from_socket!(UnixListener, socket)),
+            (Domain::IPV4 | Domain::IPV6, false) => {
+                Self::TcpStream(from_socket!(TcpStream, socket))
+            }
+            (Domain::UNIX, false) => Self::UnixStream(from_socket!(UnixStream, socket)),
+            _ => return Err(not_supported()),
+        };
+
+        Ok(activated_socket)
+    }
+}
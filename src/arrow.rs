@@ -0,0 +1,100 @@
+use arrow_array::{RecordBatch, RecordBatchReader};
+use arrow_json::reader::{infer_json_schema, ReaderBuilder};
+use arrow_json::LineDelimitedWriter;
+use arrow_schema::{ArrowError, SchemaRef};
+use std::io::{BufRead, Chain, Cursor, Read, Write};
+use std::sync::Arc;
+
+/// An iterator over the Arrow record batches read from JSON Lines, created by
+/// [`read_record_batches`] and [`read_record_batches_with_schema`].
+#[derive(Debug)]
+pub struct RecordBatches<R> {
+    reader: arrow_json::Reader<Chain<Cursor<Vec<u8>>, R>>,
+}
+
+impl<R: BufRead> RecordBatchReader for RecordBatches<R> {
+    fn schema(&self) -> SchemaRef {
+        self.reader.schema()
+    }
+}
+
+impl<R: BufRead> Iterator for RecordBatches<R> {
+    type Item = Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.reader.next()
+    }
+}
+
+/// Reads JSON Lines into Arrow record batches of up to `batch_size` rows each, inferring the
+/// schema from the first `infer_records` records (or from every record, if `None`).
+///
+/// Only the records used for schema inference are held in memory at once; the rest are streamed
+/// from the reader as the returned iterator is advanced. Records after those used for inference
+/// that do not match the inferred schema cause an error.
+pub fn read_record_batches<R: BufRead>(
+    mut reader: R,
+    batch_size: usize,
+    infer_records: Option<usize>,
+) -> Result<RecordBatches<R>, ArrowError> {
+    let mut sample = Vec::new();
+    let mut num_records = 0;
+
+    while infer_records.is_none_or(|infer_records| num_records < infer_records) {
+        let start = sample.len();
+
+        if reader.read_until(b'\n', &mut sample)? == 0 {
+            break;
+        }
+
+        if !sample[start..].iter().all(u8::is_ascii_whitespace) {
+            num_records += 1;
+        }
+    }
+
+    let (schema, _) = infer_json_schema(&sample[..], None)?;
+
+    read_with_sample(sample, reader, Arc::new(schema), batch_size)
+}
+
+/// Reads JSON Lines into Arrow record batches of up to `batch_size` rows each, using the given
+/// schema.
+pub fn read_record_batches_with_schema<R: BufRead>(
+    reader: R,
+    schema: SchemaRef,
+    batch_size: usize,
+) -> Result<RecordBatches<R>, ArrowError> {
+    read_with_sample(Vec::new(), reader, schema, batch_size)
+}
+
+fn read_with_sample<R: BufRead>(
+    sample: Vec<u8>,
+    reader: R,
+    schema: SchemaRef,
+    batch_size: usize,
+) -> Result<RecordBatches<R>, ArrowError> {
+    let reader = ReaderBuilder::new(schema)
+        .with_batch_size(batch_size)
+        .build(Cursor::new(sample).chain(reader))?;
+
+    Ok(RecordBatches { reader })
+}
+
+/// Writes Arrow record batches as JSON Lines, one record per row.
+///
+/// The batches are written as they are produced, so this can be used to stream the output of an
+/// Arrow-based reader (for instance a Parquet file read with `parquet::arrow`) straight into JSON
+/// Lines. Null values are omitted from the written objects.
+pub fn write_record_batches<W, I>(writer: W, batches: I) -> Result<(), ArrowError>
+where
+    W: Write,
+    I: IntoIterator<Item = Result<RecordBatch, ArrowError>>,
+{
+    let mut writer = LineDelimitedWriter::new(writer);
+
+    for batch in batches {
+        writer.write(&batch?)?;
+    }
+
+    writer.finish()
+}
@@ -0,0 +1,304 @@
+use crate::{Client, ConnectionState, Lifecycle, RetryPolicy, RpcError, WriteError};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+use std::io::{self, BufReader};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+type TcpClient = Client<BufReader<TcpStream>, TcpStream>;
+type StateCallback = Arc<dyn Fn(SocketAddr, ConnectionState, ConnectionState) + Send + Sync>;
+
+/// How a [`MultiEndpointClient`] picks which endpoint to send each call to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Balance {
+    /// Takes turns between endpoints.
+    #[default]
+    RoundRobin,
+    /// Picks the endpoint with the fewest calls in flight or waiting, taking turns between those
+    /// tied.
+    LeastOutstanding,
+}
+
+/// Makes remote procedure calls to a service served from several addresses, spreading calls
+/// between them and steering clear of those that fail.
+///
+/// A connection to each endpoint is opened when it is first picked, and calls to the same endpoint
+/// take turns on it, so a `MultiEndpointClient` can be shared between threads. When a call fails
+/// because its connection did, the connection is dropped and the endpoint skipped for the delay
+/// its [`RetryPolicy`] gives for its number of consecutive failures, after which it is reconnected
+/// to. Each
+/// endpoint’s connection moves through the [`ConnectionState`]s as this happens, which can be
+/// followed with [`MultiEndpointClient::on_state_change`].
+///
+/// Calls made with [`MultiEndpointClient::call_idempotent`] are retried on the other endpoints
+/// when their connection fails, as often as the policy allows, so only use it for methods that are
+/// safe to run twice. Errors returned by the service itself are never retried.
+pub struct MultiEndpointClient {
+    endpoints: Vec<Endpoint>,
+    balance: Balance,
+    next: AtomicUsize,
+    connect_timeout: Option<Duration>,
+    timeout: Option<Duration>,
+    retry_policy: RetryPolicy<RpcError>,
+}
+
+impl fmt::Debug for MultiEndpointClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MultiEndpointClient")
+            .field("endpoints", &self.endpoints)
+            .field("balance", &self.balance)
+            .field("connect_timeout", &self.connect_timeout)
+            .field("timeout", &self.timeout)
+            .field("retry_policy", &self.retry_policy)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+struct Endpoint {
+    addr: SocketAddr,
+    /// The number of calls in flight on this endpoint or waiting for their turn.
+    outstanding: AtomicUsize,
+    /// When the endpoint may be tried again after failing, kept apart from `state` so that it can
+    /// be checked while a call holds the connection.
+    down_until: Mutex<Option<Instant>>,
+    state: Mutex<EndpointState>,
+}
+
+#[derive(Debug)]
+struct EndpointState {
+    client: Option<TcpClient>,
+    lifecycle: Lifecycle,
+    num_failures: u32,
+}
+
+impl Endpoint {
+    fn is_available(&self, now: Instant) -> bool {
+        self.down_until
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_none_or(|down_until| now >= down_until)
+    }
+}
+
+/// Counts a call as outstanding on an endpoint for as long as it is alive.
+struct Outstanding<'a>(&'a AtomicUsize);
+
+impl<'a> Outstanding<'a> {
+    fn new(count: &'a AtomicUsize) -> Self {
+        count.fetch_add(1, Ordering::SeqCst);
+        Self(count)
+    }
+}
+
+impl Drop for Outstanding<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl MultiEndpointClient {
+    /// Creates a new `MultiEndpointClient` for the given endpoints, taking turns between them.
+    pub fn new<I: IntoIterator<Item = SocketAddr>>(addrs: I) -> Self {
+        let endpoints = addrs
+            .into_iter()
+            .map(|addr| Endpoint {
+                addr,
+                outstanding: AtomicUsize::new(0),
+                down_until: Mutex::new(None),
+                state: Mutex::new(EndpointState {
+                    client: None,
+                    lifecycle: Lifecycle::default(),
+                    num_failures: 0,
+                }),
+            })
+            .collect();
+
+        Self {
+            endpoints,
+            balance: Balance::default(),
+            next: AtomicUsize::new(0),
+            connect_timeout: None,
+            timeout: None,
+            retry_policy: RetryPolicy::new()
+                .backoff(Duration::from_millis(100), Duration::from_secs(30)),
+        }
+    }
+
+    /// Sets how endpoints are picked for each call.
+    pub fn balance(mut self, balance: Balance) -> Self {
+        self.balance = balance;
+        self
+    }
+
+    /// Sets how long to wait when connecting to an endpoint before counting it as failed.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets a timeout sent with every request, as with [`Client::timeout`].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how long failed endpoints are skipped for, and how many endpoints
+    /// [`MultiEndpointClient::call_idempotent`] tries. Defaults to three attempts, backing off from
+    /// 100 milliseconds up to 30 seconds.
+    pub fn retry_policy(mut self, policy: RetryPolicy<RpcError>) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Adds a callback run with the endpoint’s address and its old and new states whenever the
+    /// state of the connection to an endpoint changes.
+    pub fn on_state_change<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(SocketAddr, ConnectionState, ConnectionState) + Send + Sync + 'static,
+    {
+        let callback: StateCallback = Arc::new(callback);
+
+        for endpoint in &mut self.endpoints {
+            let addr = endpoint.addr;
+            let callback = Arc::clone(&callback);
+            let state = endpoint.state.get_mut().unwrap_or_else(|e| e.into_inner());
+
+            state
+                .lifecycle
+                .on_change(move |from, to| callback(addr, from, to));
+        }
+
+        self
+    }
+
+    /// Calls `method` with the given parameters on one endpoint, waiting for its result.
+    pub fn call<P, T>(&self, method: &str, params: &P) -> Result<T, RpcError>
+    where
+        P: Serialize,
+        T: DeserializeOwned,
+    {
+        match self.pick().first() {
+            Some(endpoint) => self.call_on(endpoint, method, params),
+            None => Err(no_endpoint_available()),
+        }
+    }
+
+    /// Calls `method` with the given parameters, waiting for its result, and trying each other
+    /// available endpoint in turn if the connection fails, as often as the retry policy allows.
+    pub fn call_idempotent<P, T>(&self, method: &str, params: &P) -> Result<T, RpcError>
+    where
+        P: Serialize,
+        T: DeserializeOwned,
+    {
+        let mut last_error = None;
+
+        for (num_attempts, endpoint) in (1..).zip(self.pick()) {
+            match self.call_on(endpoint, method, params) {
+                Err(e @ RpcError::Read(_)) | Err(e @ RpcError::Write(_))
+                    if self.retry_policy.should_retry(num_attempts, &e) =>
+                {
+                    last_error = Some(e)
+                }
+                outcome => return outcome,
+            }
+        }
+
+        Err(last_error.unwrap_or_else(no_endpoint_available))
+    }
+
+    /// Returns the available endpoints in the order they should be tried.
+    fn pick(&self) -> Vec<&Endpoint> {
+        let now = Instant::now();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let len = self.endpoints.len();
+
+        let mut endpoints: Vec<_> = (0..len)
+            .filter_map(|i| self.endpoints.get(start.wrapping_add(i) % len))
+            .filter(|endpoint| endpoint.is_available(now))
+            .collect();
+
+        if self.balance == Balance::LeastOutstanding {
+            // The sort is stable, so endpoints that are tied keep taking turns.
+            endpoints.sort_by_key(|endpoint| endpoint.outstanding.load(Ordering::SeqCst));
+        }
+
+        endpoints
+    }
+
+    fn call_on<P, T>(&self, endpoint: &Endpoint, method: &str, params: &P) -> Result<T, RpcError>
+    where
+        P: Serialize,
+        T: DeserializeOwned,
+    {
+        let _outstanding = Outstanding::new(&endpoint.outstanding);
+        let mut guard = endpoint.state.lock().unwrap_or_else(|e| e.into_inner());
+        let state = &mut *guard;
+
+        let client = match &mut state.client {
+            Some(client) => client,
+            client @ None => {
+                let _ = state.lifecycle.transition(ConnectionState::Connecting);
+
+                match self.connect(endpoint.addr) {
+                    Ok(connected) => {
+                        let _ = state.lifecycle.transition(ConnectionState::Established);
+                        client.insert(connected)
+                    }
+                    Err(e) => {
+                        self.fail(endpoint, state);
+                        return Err(RpcError::Write(WriteError::Io(e)));
+                    }
+                }
+            }
+        };
+
+        match client.call(method, params) {
+            Err(e @ RpcError::Read(_)) | Err(e @ RpcError::Write(_)) => {
+                self.fail(endpoint, state);
+                Err(e)
+            }
+            outcome => {
+                state.num_failures = 0;
+                outcome
+            }
+        }
+    }
+
+    fn connect(&self, addr: SocketAddr) -> io::Result<TcpClient> {
+        let stream = match self.connect_timeout {
+            Some(timeout) => TcpStream::connect_timeout(&addr, timeout)?,
+            None => TcpStream::connect(addr)?,
+        };
+        let client = Client::new(BufReader::new(stream.try_clone()?), stream);
+
+        Ok(match self.timeout {
+            Some(timeout) => client.timeout(timeout),
+            None => client,
+        })
+    }
+
+    /// Drops the connection to an endpoint that has failed, and skips it until its backoff ends.
+    fn fail(&self, endpoint: &Endpoint, state: &mut EndpointState) {
+        state.client = None;
+        let _ = state.lifecycle.transition(ConnectionState::Closed);
+
+        state.num_failures = state.num_failures.saturating_add(1);
+        let backoff = self.retry_policy.delay(state.num_failures);
+
+        *endpoint
+            .down_until
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = Instant::now().checked_add(backoff);
+    }
+}
+
+fn no_endpoint_available() -> RpcError {
+    RpcError::Write(WriteError::Io(io::Error::new(
+        io::ErrorKind::NotConnected,
+        "no endpoint is available",
+    )))
+}
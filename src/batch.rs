@@ -0,0 +1,167 @@
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::mem;
+
+/// A batch of JSON objects stored column by column, as produced by [`RecordBatcher`].
+///
+/// Every column has one value per row, with `null` standing in for rows in which the object did
+/// not have that key.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ColumnBatch {
+    columns: Vec<(String, Vec<Value>)>,
+    num_rows: usize,
+}
+
+impl ColumnBatch {
+    /// Returns the number of rows in the batch.
+    pub fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+
+    /// Returns the name and values of each column, in the order the columns were first seen.
+    pub fn columns(&self) -> impl Iterator<Item = (&str, &[Value])> {
+        self.columns
+            .iter()
+            .map(|(name, values)| (name.as_str(), values.as_slice()))
+    }
+
+    /// Returns the values of the column with the given name.
+    pub fn column(&self, name: &str) -> Option<&[Value]> {
+        self.columns().find(|(n, _)| *n == name).map(|(_, v)| v)
+    }
+
+    /// Consumes the batch, returning the name and values of each column.
+    pub fn into_columns(self) -> Vec<(String, Vec<Value>)> {
+        self.columns
+    }
+}
+
+/// A destination for the batches produced by a [`RecordBatcher`].
+///
+/// Implement this to ingest JSON Lines into a dataframe library or query engine. It is also
+/// implemented for closures taking a [`ColumnBatch`].
+pub trait BatchSink {
+    type Error;
+
+    /// Receives a full (or, at the end of the input, final) batch.
+    fn write_batch(&mut self, batch: ColumnBatch) -> Result<(), Self::Error>;
+}
+
+impl<F, E> BatchSink for F
+where
+    F: FnMut(ColumnBatch) -> Result<(), E>,
+{
+    type Error = E;
+
+    fn write_batch(&mut self, batch: ColumnBatch) -> Result<(), E> {
+        self(batch)
+    }
+}
+
+/// Groups JSON objects into [`ColumnBatch`]es of a fixed number of rows, handing each one to a
+/// [`BatchSink`] as soon as it is full.
+///
+/// Columns are never dropped once seen: every batch contains every column seen so far, in the
+/// order they were first seen, so sinks can rely on the set of columns only ever growing.
+#[derive(Debug)]
+pub struct RecordBatcher<S> {
+    sink: S,
+    batch_size: usize,
+    columns: Vec<(String, Vec<Value>)>,
+    column_indices: HashMap<String, usize>,
+    num_rows: usize,
+}
+
+impl<S: BatchSink> RecordBatcher<S> {
+    /// Creates a new `RecordBatcher` that writes batches of `batch_size` rows to `sink`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `batch_size` is zero.
+    pub fn new(sink: S, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "batch size must be greater than zero");
+
+        Self {
+            sink,
+            batch_size,
+            columns: Vec::new(),
+            column_indices: HashMap::new(),
+            num_rows: 0,
+        }
+    }
+
+    /// Adds an object to the current batch, writing the batch to the sink if it is now full.
+    pub fn push(&mut self, record: Map<String, Value>) -> Result<(), S::Error> {
+        for (key, value) in record {
+            let index = match self.column_indices.get(&key) {
+                Some(index) => *index,
+                None => {
+                    let index = self.columns.len();
+                    self.columns
+                        .push((key.clone(), vec![Value::Null; self.num_rows]));
+                    self.column_indices.insert(key, index);
+                    index
+                }
+            };
+
+            self.columns[index].1.push(value);
+        }
+
+        self.num_rows += 1;
+
+        for (_, values) in &mut self.columns {
+            if values.len() < self.num_rows {
+                values.push(Value::Null);
+            }
+        }
+
+        if self.num_rows == self.batch_size {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Adds every object yielded by `records`, writing batches to the sink as they fill up.
+    pub fn push_all<I: IntoIterator<Item = Map<String, Value>>>(
+        &mut self,
+        records: I,
+    ) -> Result<(), S::Error> {
+        for record in records {
+            self.push(record)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the current batch to the sink, even if it is not yet full. Does nothing if the
+    /// current batch is empty.
+    pub fn flush(&mut self) -> Result<(), S::Error> {
+        if self.num_rows == 0 {
+            return Ok(());
+        }
+
+        let batch_size = self.batch_size;
+        let columns = self
+            .columns
+            .iter_mut()
+            .map(|(name, values)| {
+                let values = mem::replace(values, Vec::with_capacity(batch_size));
+                (name.clone(), values)
+            })
+            .collect();
+
+        let batch = ColumnBatch {
+            columns,
+            num_rows: mem::take(&mut self.num_rows),
+        };
+
+        self.sink.write_batch(batch)
+    }
+
+    /// Writes any remaining rows to the sink, returning the sink.
+    pub fn finish(mut self) -> Result<S, S::Error> {
+        self.flush()?;
+        Ok(self.sink)
+    }
+}
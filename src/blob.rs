@@ -0,0 +1,286 @@
+use crate::WriteError;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt::Write as _;
+use std::io;
+
+/// How many bytes of a blob [`write_blob`] puts in each line, before base64 encoding.
+pub const BLOB_CHUNK_SIZE: usize = 48 * 1024;
+
+/// The line that ends a blob written by [`write_blob`], written as
+/// `{"blob":{"size":1048576,"sha256":"…"}}`.
+///
+/// It follows the chunks rather than preceding them so that the sender never needs the whole blob
+/// in memory, nor to read it twice.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BlobManifest {
+    /// The size of the blob in bytes.
+    pub size: u64,
+    /// The SHA-256 digest of the blob, in lowercase hexadecimal.
+    pub sha256: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum BlobLine {
+    Chunk(String),
+    Blob(BlobManifest),
+}
+
+fn to_hex(digest: &[u8]) -> String {
+    digest.iter().fold(String::new(), |mut hex, b| {
+        let _ = write!(hex, "{:02x}", b);
+        hex
+    })
+}
+
+fn invalid_data<E>(e: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+/// Reads a blob sent with [`write_blob`] back out of a JSON Lines stream, one chunk at a time.
+///
+/// Only one chunk is held in memory at once. The blob’s size and checksum are checked against its
+/// manifest once every chunk has been read; if they do not match, the read that reaches the end of
+/// the blob fails with [`io::ErrorKind::InvalidData`], so do not act on the contents until the
+/// reader has returned EOF. Lines after the manifest are left unread.
+#[derive(Debug)]
+pub struct BlobReader<R> {
+    reader: R,
+    line: Vec<u8>,
+    chunk: Vec<u8>,
+    pos: usize,
+    size: u64,
+    hasher: Sha256,
+    manifest: Option<BlobManifest>,
+}
+
+impl<R> BlobReader<R> {
+    /// Creates a new `BlobReader` reading the blob that starts at the next line of `reader`.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: Vec::new(),
+            chunk: Vec::new(),
+            pos: 0,
+            size: 0,
+            hasher: Sha256::new(),
+            manifest: None,
+        }
+    }
+
+    /// Returns the blob’s manifest, once the whole blob has been read and checked.
+    pub fn manifest(&self) -> Option<&BlobManifest> {
+        self.manifest.as_ref()
+    }
+
+    /// Consumes the `BlobReader`, returning the wrapped reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Copies as much of the current chunk as fits into `buf`.
+    fn copy_chunk(&mut self, buf: &mut [u8]) -> usize {
+        let remaining = self.chunk.get(self.pos..).unwrap_or_default();
+        let num_bytes = remaining.len().min(buf.len());
+
+        buf[..num_bytes].copy_from_slice(&remaining[..num_bytes]);
+        self.pos += num_bytes;
+
+        num_bytes
+    }
+
+    /// Handles the line in `self.line`, either decoding a chunk or checking the manifest.
+    fn accept_line(&mut self) -> io::Result<()> {
+        if self.line.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "stream ended before blob manifest",
+            ));
+        }
+
+        let line: BlobLine = serde_json::from_slice(&self.line).map_err(invalid_data)?;
+        self.line.clear();
+
+        match line {
+            BlobLine::Chunk(encoded) => {
+                self.chunk.clear();
+                self.pos = 0;
+                STANDARD
+                    .decode_vec(encoded, &mut self.chunk)
+                    .map_err(invalid_data)?;
+
+                self.size += self.chunk.len() as u64;
+                self.hasher.update(&self.chunk);
+            }
+            BlobLine::Blob(manifest) => {
+                if manifest.size != self.size {
+                    return Err(invalid_data(format!(
+                        "blob is {} bytes but manifest says {}",
+                        self.size, manifest.size
+                    )));
+                }
+
+                let sha256 = to_hex(&std::mem::take(&mut self.hasher).finalize());
+
+                if !manifest.sha256.eq_ignore_ascii_case(&sha256) {
+                    return Err(invalid_data("blob does not match manifest checksum"));
+                }
+
+                self.manifest = Some(manifest);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl<R: io::BufRead> io::Read for BlobReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos >= self.chunk.len() && self.manifest.is_none() {
+            self.reader.read_until(b'\n', &mut self.line)?;
+            self.accept_line()?;
+        }
+
+        Ok(self.copy_chunk(buf))
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R: tokio::io::AsyncBufRead + Unpin> tokio::io::AsyncRead for BlobReader<R> {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<io::Result<()>> {
+        use std::pin::Pin;
+        use std::task::Poll;
+
+        let this = self.get_mut();
+
+        while this.pos >= this.chunk.len() && this.manifest.is_none() {
+            let available = match Pin::new(&mut this.reader).poll_fill_buf(cx) {
+                Poll::Ready(Ok(available)) => available,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            let (num_bytes, is_line_complete) = match available.iter().position(|b| *b == b'\n') {
+                Some(i) => (i + 1, true),
+                None => (available.len(), available.is_empty()),
+            };
+
+            this.line.extend_from_slice(&available[..num_bytes]);
+            Pin::new(&mut this.reader).consume(num_bytes);
+
+            if is_line_complete {
+                this.accept_line()?;
+            }
+        }
+
+        let num_bytes = this.copy_chunk(buf.initialize_unfilled());
+        buf.advance(num_bytes);
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Sends everything read from `blob` as a sequence of base64-encoded chunk lines, each holding
+/// [`BLOB_CHUNK_SIZE`] bytes, followed by a [`BlobManifest`] line, and returns the manifest.
+///
+/// This avoids holding a large payload in memory as one enormous JSON string on either side:
+/// only one chunk at a time is buffered. Read the blob back with [`BlobReader`].
+#[cfg(not(feature = "tokio"))]
+pub fn write_blob<W, B>(mut writer: W, mut blob: B) -> Result<BlobManifest, WriteError>
+where
+    W: io::Write,
+    B: io::Read,
+{
+    let mut buf = vec![0; BLOB_CHUNK_SIZE];
+    let mut size = 0;
+    let mut hasher = Sha256::new();
+
+    loop {
+        let mut len = 0;
+
+        while len < buf.len() {
+            match blob.read(&mut buf[len..]) {
+                Ok(0) => break,
+                Ok(num_bytes) => len += num_bytes,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(WriteError::Io(e)),
+            }
+        }
+
+        if len == 0 {
+            break;
+        }
+
+        let chunk = &buf[..len];
+        size += len as u64;
+        hasher.update(chunk);
+        crate::write(&mut writer, &BlobLine::Chunk(STANDARD.encode(chunk)))?;
+    }
+
+    let manifest = BlobManifest {
+        size,
+        sha256: to_hex(&hasher.finalize()),
+    };
+    crate::write(&mut writer, &BlobLine::Blob(manifest.clone()))?;
+
+    Ok(manifest)
+}
+
+/// Sends everything read from `blob` as a sequence of base64-encoded chunk lines, each holding
+/// [`BLOB_CHUNK_SIZE`] bytes, followed by a [`BlobManifest`] line, and returns the manifest.
+///
+/// This avoids holding a large payload in memory as one enormous JSON string on either side:
+/// only one chunk at a time is buffered. Read the blob back with [`BlobReader`].
+#[cfg(feature = "tokio")]
+pub async fn write_blob<W, B>(mut writer: W, mut blob: B) -> Result<BlobManifest, WriteError>
+where
+    W: tokio::io::AsyncWrite + Unpin,
+    B: tokio::io::AsyncRead + Unpin,
+{
+    use tokio::io::AsyncReadExt;
+
+    let mut buf = vec![0; BLOB_CHUNK_SIZE];
+    let mut size = 0;
+    let mut hasher = Sha256::new();
+
+    loop {
+        let mut len = 0;
+
+        while len < buf.len() {
+            match blob.read(&mut buf[len..]).await {
+                Ok(0) => break,
+                Ok(num_bytes) => len += num_bytes,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(WriteError::Io(e)),
+            }
+        }
+
+        if len == 0 {
+            break;
+        }
+
+        let chunk = &buf[..len];
+        size += len as u64;
+        hasher.update(chunk);
+        crate::write(&mut writer, &BlobLine::Chunk(STANDARD.encode(chunk))).await?;
+    }
+
+    let manifest = BlobManifest {
+        size,
+        sha256: to_hex(&hasher.finalize()),
+    };
+    crate::write(&mut writer, &BlobLine::Blob(manifest.clone())).await?;
+
+    Ok(manifest)
+}
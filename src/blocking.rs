@@ -0,0 +1,199 @@
+//! The `std`-based API, which is always available so that crates that read and write JSON Lines
+//! synchronously keep working when another crate in the same build enables the `tokio` feature.
+//!
+//! Without the `tokio` feature, the top level of this crate re-exports what is here, including
+//! [`Connection`], so the two are the same. With it, the top level switches to Tokio’s IO
+//! primitives while this module stays as it is, so libraries that only need blocking IO should use
+//! `jsonl::blocking` rather than the top level to be unaffected by which features are enabled.
+//!
+//! There is no blocking [`ConnectionBuilder`](crate::ConnectionBuilder) here: with the `tokio`
+//! feature it builds asynchronous connections, so sockets for a blocking [`Connection`] have to be
+//! connected and configured by hand.
+
+#[cfg(feature = "log")]
+use crate::wire_log;
+use crate::{canonical, format, ReadError, WriteError};
+use std::io::{BufRead, Write};
+
+mod connection;
+mod transport;
+
+pub use crate::{iter, Records};
+pub use connection::Connection;
+pub use transport::{StdioTransport, Transport};
+
+/// Reads a line from the reader and deserializes it into a given type.
+///
+/// The whole line is read into memory however long it is, so use [`read_with_limit`] when the
+/// reader is not trusted.
+pub fn read<R: BufRead, T: serde::de::DeserializeOwned>(reader: R) -> Result<T, ReadError> {
+    read_with_len(reader).map(|(t, _)| t)
+}
+
+/// Reads a line from the reader and deserializes it into a given type, returning `None` instead
+/// of [`ReadError::Eof`] if the reader has already reached EOF, so that a loop over every line can
+/// stop there without matching on the error.
+pub fn read_opt<R: BufRead, T: serde::de::DeserializeOwned>(
+    reader: R,
+) -> Result<Option<T>, ReadError> {
+    match read(reader) {
+        Ok(t) => Ok(Some(t)),
+        Err(ReadError::Eof) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Reads a line from the reader and deserializes it into a given type, also returning the
+/// length of the line in bytes, including its newline.
+pub fn read_with_len<R: BufRead, T: serde::de::DeserializeOwned>(
+    mut reader: R,
+) -> Result<(T, usize), ReadError> {
+    let mut buf = String::new();
+    let num_bytes_read = reader.read_line(&mut buf).map_err(ReadError::Io)?;
+
+    if num_bytes_read == 0 {
+        return Err(ReadError::Eof);
+    }
+
+    #[cfg(feature = "log")]
+    wire_log::inbound(&buf);
+
+    let t = serde_json::from_str(&buf).map_err(ReadError::Deserialize)?;
+
+    Ok((t, num_bytes_read))
+}
+
+/// Reads a line from the reader and deserializes it into a given type, failing with
+/// [`ReadError::LineTooLong`] as soon as more than `max_bytes` have been read without reaching the
+/// end of the line, not counting its newline.
+///
+/// The line is read in chunks, so no more than `max_bytes` and what the reader has buffered is
+/// ever held in memory. The rest of a line that is too long is left unread, so the reader should be
+/// abandoned after this error rather than read from again.
+pub fn read_with_limit<R: BufRead, T: serde::de::DeserializeOwned>(
+    reader: R,
+    max_bytes: usize,
+) -> Result<T, ReadError> {
+    read_with_len_and_limit(reader, max_bytes).map(|(t, _)| t)
+}
+
+/// Reads a line like [`read_with_limit`], also returning its length as [`read_with_len`] does.
+pub(crate) fn read_with_len_and_limit<R: BufRead, T: serde::de::DeserializeOwned>(
+    mut reader: R,
+    max_bytes: usize,
+) -> Result<(T, usize), ReadError> {
+    let mut buf = Vec::new();
+
+    loop {
+        let available = match reader.fill_buf() {
+            Ok(available) => available,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(ReadError::Io(e)),
+        };
+
+        let (num_bytes, is_line_complete) = match available.iter().position(|b| *b == b'\n') {
+            Some(i) => (i + 1, true),
+            None => (available.len(), available.is_empty()),
+        };
+
+        buf.extend_from_slice(&available[..num_bytes]);
+        reader.consume(num_bytes);
+
+        if buf.len() - usize::from(buf.ends_with(b"\n")) > max_bytes {
+            return Err(ReadError::LineTooLong { max: max_bytes });
+        }
+
+        if is_line_complete {
+            break;
+        }
+    }
+
+    if buf.is_empty() {
+        return Err(ReadError::Eof);
+    }
+
+    let num_bytes_read = buf.len();
+    let line = String::from_utf8(buf)
+        .map_err(|e| ReadError::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, e)))?;
+
+    #[cfg(feature = "log")]
+    wire_log::inbound(&line);
+
+    let t = serde_json::from_str(&line).map_err(ReadError::Deserialize)?;
+
+    Ok((t, num_bytes_read))
+}
+
+/// Writes a given value to the writer, serializing it into JSON.
+pub fn write<W: Write, T: serde::Serialize>(writer: W, t: &T) -> Result<(), WriteError> {
+    write_with_len(writer, t).map(|_| ())
+}
+
+/// Writes a given value to the writer, serializing it into JSON, and returns the length of the
+/// line written in bytes, including its newline.
+pub fn write_with_len<W: Write, T: serde::Serialize>(
+    mut writer: W,
+    t: &T,
+) -> Result<usize, WriteError> {
+    // We use to_string here instead of to_vec because it verifies that the JSON is valid UTF-8,
+    // which is required by the JSON Lines specification (https://jsonlines.org).
+    let json = serde_json::to_string(t).map_err(WriteError::Serialize)?;
+
+    #[cfg(feature = "log")]
+    wire_log::outbound(&json);
+
+    writer.write_all(json.as_bytes()).map_err(WriteError::Io)?;
+    writer.write_all(b"\n").map_err(WriteError::Io)?;
+
+    Ok(json.len() + 1)
+}
+
+/// Writes a given value to the writer, serializing it into JSON with a custom
+/// [`Formatter`](serde_json::ser::Formatter), such as one that escapes differently to match
+/// what a downstream tool expects. [`AsciiFormatter`](crate::AsciiFormatter), for
+/// example, escapes every non-ASCII character.
+///
+/// The formatter must write everything on one line, so formatters that add line breaks, such
+/// as [`PrettyFormatter`](serde_json::ser::PrettyFormatter), fail with
+/// [`WriteError::Serialize`], as do formatters that write invalid UTF-8.
+pub fn write_with_formatter<W, T, F>(mut writer: W, t: &T, formatter: F) -> Result<(), WriteError>
+where
+    W: Write,
+    T: serde::Serialize,
+    F: serde_json::ser::Formatter,
+{
+    let json = format::to_string_with_formatter(t, formatter)?;
+
+    #[cfg(feature = "log")]
+    wire_log::outbound(&json);
+
+    writer.write_all(json.as_bytes()).map_err(WriteError::Io)?;
+    writer.write_all(b"\n").map_err(WriteError::Io)?;
+
+    Ok(())
+}
+
+/// Writes a given value to the writer in the canonical form described in [`canonicalize`], so
+/// that equal values are always written as identical bytes.
+pub fn write_canonical<W: Write, T: serde::Serialize>(writer: W, t: &T) -> Result<(), WriteError> {
+    let value = serde_json::to_value(t).map_err(WriteError::Serialize)?;
+    write(writer, &canonical::canonical_value(value))
+}
+
+/// Writes every value yielded by `values` to the writer, serializing each into JSON, and
+/// returns how many were written. Writing stops at the first error.
+pub fn write_all<W, I>(mut writer: W, values: I) -> Result<usize, WriteError>
+where
+    W: Write,
+    I: IntoIterator,
+    I::Item: serde::Serialize,
+{
+    let mut num_written = 0;
+
+    for value in values {
+        write(&mut writer, &value)?;
+        num_written += 1;
+    }
+
+    Ok(num_written)
+}
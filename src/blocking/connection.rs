@@ -0,0 +1,205 @@
+use std::io::{self, BufRead, BufReader, Stdin, Stdout, Write};
+use std::net::{Shutdown, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::process::{Child, ChildStdin, ChildStdout};
+
+/// Use this type when you have both a reader and writer, and want them to be grouped together.
+///
+/// There are situations in which you have both a reader and a writer being passed around code,
+/// always kept together. This forms what is known as a ‘[data clump]’, and harms code readability.
+/// By grouping the two together it makes clear that they are both needed, and prevents mistakes
+/// when one is forgotten.
+///
+/// `Connection` is internally a pair of a reader and a writer, and delegates to [`super::read`]
+/// and [`super::write`] for [`Connection::read`] and [`Connection::write`] respectively. It can be
+/// created from a reader and writer directly, or from any [`Transport`](super::Transport) with
+/// [`Connection::from_transport`].
+///
+/// This is the `std`-based `Connection`, which stays available here when the `tokio` feature
+/// makes the top-level [`Connection`](crate::Connection) asynchronous.
+///
+/// [data clump]: https://youtu.be/DC-pQPq0acs?t=521
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Connection<R: BufRead, W: Write> {
+    reader: R,
+    writer: W,
+    max_line_len: Option<usize>,
+}
+
+impl<R: BufRead, W: Write> Connection<R, W> {
+    pub fn new(reader: R, writer: W) -> Self {
+        Self {
+            reader,
+            writer,
+            max_line_len: None,
+        }
+    }
+
+    /// Sets the maximum length in bytes of the lines read, not including their newlines, beyond
+    /// which reads fail with [`ReadError::LineTooLong`](crate::ReadError::LineTooLong) instead of
+    /// buffering the rest of the line; see [`super::read_with_limit`]. Lines are unlimited by
+    /// default, which is only safe when the peer is trusted.
+    pub fn max_line_len(mut self, max_bytes: usize) -> Self {
+        self.max_line_len = Some(max_bytes);
+        self
+    }
+
+    /// Returns a mutable reference to the contained reader, for transport-specific operations.
+    #[cfg_attr(any(feature = "tokio", not(feature = "fd-passing")), allow(dead_code))]
+    pub(crate) fn reader_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Returns a mutable reference to the contained writer, for transport-specific operations.
+    #[cfg_attr(feature = "tokio", allow(dead_code))]
+    pub(crate) fn writer_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Consumes the `Connection`, returning the contained reader and writer.
+    pub fn into_parts(self) -> (R, W) {
+        (self.reader, self.writer)
+    }
+
+    /// Reads a line from the reader and deserializes it into a given type.
+    pub fn read<T: serde::de::DeserializeOwned>(&mut self) -> Result<T, crate::ReadError> {
+        self.read_with_len().map(|(t, _)| t)
+    }
+
+    /// Reads a line from the reader and deserializes it into a given type, returning `None` at EOF;
+    /// see [`super::read_opt`].
+    pub fn read_opt<T: serde::de::DeserializeOwned>(
+        &mut self,
+    ) -> Result<Option<T>, crate::ReadError> {
+        match self.read() {
+            Ok(t) => Ok(Some(t)),
+            Err(crate::ReadError::Eof) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes a given value to the writer, serializing it into JSON.
+    pub fn write<T: serde::Serialize>(&mut self, t: &T) -> Result<(), crate::WriteError> {
+        super::write(&mut self.writer, t)
+    }
+
+    /// Reads a line from the reader and deserializes it into a given type, also returning the
+    /// length of the line in bytes, including its newline.
+    pub fn read_with_len<T: serde::de::DeserializeOwned>(
+        &mut self,
+    ) -> Result<(T, usize), crate::ReadError> {
+        match self.max_line_len {
+            Some(max_bytes) => super::read_with_len_and_limit(&mut self.reader, max_bytes),
+            None => super::read_with_len(&mut self.reader),
+        }
+    }
+
+    /// Writes a given value to the writer, serializing it into JSON, and returns the length of the
+    /// line written in bytes, including its newline.
+    pub fn write_with_len<T: serde::Serialize>(
+        &mut self,
+        t: &T,
+    ) -> Result<usize, crate::WriteError> {
+        super::write_with_len(&mut self.writer, t)
+    }
+
+    /// Writes a given value to the writer in canonical form; see [`super::write_canonical`].
+    pub fn write_canonical<T: serde::Serialize>(&mut self, t: &T) -> Result<(), crate::WriteError> {
+        super::write_canonical(&mut self.writer, t)
+    }
+
+    /// Writes a given value to the writer with a custom formatter; see
+    /// [`super::write_with_formatter`].
+    pub fn write_with_formatter<T, F>(
+        &mut self,
+        t: &T,
+        formatter: F,
+    ) -> Result<(), crate::WriteError>
+    where
+        T: serde::Serialize,
+        F: serde_json::ser::Formatter,
+    {
+        super::write_with_formatter(&mut self.writer, t, formatter)
+    }
+
+    /// Writes every value yielded by `values` to the writer, returning how many were written. This
+    /// is a fallible counterpart to [`Extend::extend`].
+    pub fn write_all<I>(&mut self, values: I) -> Result<usize, crate::WriteError>
+    where
+        I: IntoIterator,
+        I::Item: serde::Serialize,
+    {
+        super::write_all(&mut self.writer, values)
+    }
+
+    /// Returns an iterator that reads every remaining line from the reader, deserializing each
+    /// into a given type, and ends cleanly at EOF; see [`super::iter`].
+    pub fn iter<T: serde::de::DeserializeOwned>(&mut self) -> super::Records<&mut R, T> {
+        super::iter(&mut self.reader)
+    }
+
+    /// Flushes the contained writer’s buffer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<'a> Connection<BufReader<&'a mut ChildStdout>, &'a mut ChildStdin> {
+    /// Creates a new `Connection` that uses the `stdin` of a child process as the writer and the
+    /// child process’ `stdout` as the reader. This facilitates communication with this child process
+    /// by passing data into its `stdin` and reading from its `stdout`.
+    pub fn new_from_child(child: &'a mut Child) -> Option<Self> {
+        Self::from_transport(child).ok()
+    }
+}
+
+impl Connection<BufReader<Stdin>, Stdout> {
+    /// Creates a new `Connection` from the stdio of the current process – `stdin` is used as the reader
+    /// and `stdout` is used as the writer.
+    pub fn new_from_stdio() -> Self {
+        Self::from_infallible_transport(super::StdioTransport)
+    }
+
+    /// Switches to an interactive mode for debugging by hand, in which input may be typed in a
+    /// relaxed form of JSON with single-quoted strings and trailing commas. Output is still strict
+    /// JSON. See [`LenientReader`](crate::LenientReader) for exactly what is accepted.
+    pub fn interactive(self) -> Connection<crate::LenientReader<BufReader<Stdin>>, Stdout> {
+        Connection::new(crate::LenientReader::new(self.reader), self.writer)
+    }
+}
+
+impl Connection<BufReader<Stdin>, crate::TerminalWriter<Stdout>> {
+    /// Creates a new `Connection` from the stdio of the current process like
+    /// [`Connection::new_from_stdio`], applying `mode` to everything written if `stdout` is a
+    /// terminal.
+    pub fn new_from_stdio_with_terminal_mode(mode: crate::TerminalMode) -> Self {
+        let (reader, writer) = Connection::new_from_stdio().into_parts();
+        Self::new(reader, crate::TerminalWriter::new(writer, mode))
+    }
+}
+
+impl Connection<BufReader<TcpStream>, TcpStream> {
+    /// Creates a new `Connection` from a TCP stream.
+    pub fn new_from_tcp_stream(tcp_stream: TcpStream) -> io::Result<Self> {
+        Self::from_transport(tcp_stream)
+    }
+
+    /// Closes the TCP stream.
+    pub fn shutdown(self) -> io::Result<()> {
+        self.writer.shutdown(Shutdown::Both)
+    }
+}
+
+#[cfg(unix)]
+impl Connection<BufReader<UnixStream>, UnixStream> {
+    /// Creates a new `Connection` from a Unix domain socket stream.
+    pub fn new_from_unix_stream(unix_stream: UnixStream) -> io::Result<Self> {
+        Self::from_transport(unix_stream)
+    }
+
+    /// Closes the Unix domain socket stream.
+    pub fn shutdown(self) -> io::Result<()> {
+        self.writer.shutdown(Shutdown::Both)
+    }
+}
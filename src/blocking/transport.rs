@@ -0,0 +1,125 @@
+use super::Connection;
+use std::convert::Infallible;
+use std::io::{self, BufRead, BufReader, Stdin, Stdout, Write};
+use std::net::TcpStream;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::process::{Child, ChildStdin, ChildStdout};
+
+/// A byte stream that a [`Connection`] can be created from with [`Connection::from_transport`].
+///
+/// Implement this to speak JSON Lines over a transport this crate does not know about, such as a
+/// shared memory ring, RDMA or a test double, without wrapping it to fit [`Connection::new`]. It is
+/// implemented for the streams this crate connects with, and for any pair of a reader and writer.
+///
+/// This is the `std`-based `Transport`, which stays available here when the `tokio` feature makes
+/// the top-level [`Transport`](crate::Transport) asynchronous.
+pub trait Transport {
+    /// The half of the transport that lines are read from.
+    type Reader: BufRead;
+    /// The half of the transport that lines are written to.
+    type Writer: Write;
+    /// The error splitting the transport can fail with, which is [`Infallible`] for transports
+    /// that cannot fail.
+    type Error;
+
+    /// Splits the transport into the halves used for reading and for writing.
+    fn split(self) -> Result<(Self::Reader, Self::Writer), Self::Error>;
+}
+
+/// The stdio of the current process as a [`Transport`], with `stdin` as the reader and `stdout`
+/// as the writer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct StdioTransport;
+
+impl<R: BufRead, W: Write> Transport for (R, W) {
+    type Reader = R;
+    type Writer = W;
+    type Error = Infallible;
+
+    fn split(self) -> Result<(R, W), Infallible> {
+        Ok(self)
+    }
+}
+
+impl<R: BufRead, W: Write> Connection<R, W> {
+    /// Creates a new `Connection` from any [`Transport`].
+    pub fn from_transport<T>(transport: T) -> Result<Self, T::Error>
+    where
+        T: Transport<Reader = R, Writer = W>,
+    {
+        let (reader, writer) = transport.split()?;
+        Ok(Self::new(reader, writer))
+    }
+
+    /// Creates a new `Connection` from a [`Transport`] that cannot fail to split.
+    pub(crate) fn from_infallible_transport<T>(transport: T) -> Self
+    where
+        T: Transport<Reader = R, Writer = W, Error = Infallible>,
+    {
+        match transport.split() {
+            Ok((reader, writer)) => Self::new(reader, writer),
+            Err(never) => match never {},
+        }
+    }
+}
+
+impl Transport for StdioTransport {
+    type Reader = BufReader<Stdin>;
+    type Writer = Stdout;
+    type Error = Infallible;
+
+    fn split(self) -> Result<(Self::Reader, Self::Writer), Infallible> {
+        Ok((BufReader::new(io::stdin()), io::stdout()))
+    }
+}
+
+/// A child process, spawned with its `stdin` and `stdout` piped, whose `stdout` is read from and
+/// whose `stdin` is written to.
+impl<'a> Transport for &'a mut Child {
+    type Reader = BufReader<&'a mut ChildStdout>;
+    type Writer = &'a mut ChildStdin;
+    type Error = io::Error;
+
+    fn split(self) -> io::Result<(Self::Reader, Self::Writer)> {
+        match (self.stdout.as_mut(), self.stdin.as_mut()) {
+            (Some(stdout), Some(stdin)) => Ok((BufReader::new(stdout), stdin)),
+            _ => Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "child process has no piped stdin or stdout",
+            )),
+        }
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl Transport for crate::ServerStream {
+    type Reader = BufReader<crate::ServerStream>;
+    type Writer = crate::ServerStream;
+    type Error = Infallible;
+
+    fn split(self) -> Result<(Self::Reader, Self::Writer), Infallible> {
+        Ok((BufReader::new(self.clone()), self))
+    }
+}
+
+impl Transport for TcpStream {
+    type Reader = BufReader<TcpStream>;
+    type Writer = TcpStream;
+    type Error = io::Error;
+
+    fn split(self) -> io::Result<(Self::Reader, Self::Writer)> {
+        Ok((BufReader::new(self.try_clone()?), self))
+    }
+}
+
+#[cfg(unix)]
+impl Transport for UnixStream {
+    type Reader = BufReader<UnixStream>;
+    type Writer = UnixStream;
+    type Error = io::Error;
+
+    fn split(self) -> io::Result<(Self::Reader, Self::Writer)> {
+        Ok((BufReader::new(self.try_clone()?), self))
+    }
+}
@@ -0,0 +1,211 @@
+use crate::{Client, ErrorObject, RpcError};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{BufRead, Write};
+use std::time::{Duration, Instant};
+
+/// Whether a [`CircuitBreaker`] is letting calls through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CircuitState {
+    /// Calls go through as normal.
+    Closed,
+    /// Calls fail straight away, without being made, until the open period ends.
+    Open,
+    /// A limited number of probe calls are let through to find out whether the endpoint has
+    /// recovered.
+    HalfOpen,
+}
+
+/// Counts of what a [`CircuitBreaker`] has seen since it was created.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct CircuitMetrics {
+    pub num_successes: u64,
+    pub num_failures: u64,
+    /// Calls failed straight away because the circuit was open.
+    pub num_rejected: u64,
+    /// Times the circuit went from closed or half-open to open.
+    pub num_opened: u64,
+}
+
+/// The error returned in place of making a call while a [`CircuitBreaker`] is open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("circuit breaker is open, retry after {retry_after:?}")]
+pub struct CircuitOpen {
+    /// How long until the circuit lets a probe call through.
+    pub retry_after: Duration,
+}
+
+/// An error from a call made through a [`CircuitBreaker`].
+#[derive(Debug, thiserror::Error)]
+pub enum CircuitError<E> {
+    #[error(transparent)]
+    Open(#[from] CircuitOpen),
+    #[error("call failed")]
+    Call(#[source] E),
+}
+
+/// Makes calls to a flaky endpoint fail fast once it appears to be down, rather than letting them
+/// pile up waiting for timeouts.
+///
+/// The circuit starts closed. After `failure_threshold` consecutive failures it opens, and every
+/// call fails straight away with [`CircuitOpen`] for the open period. It then goes half-open, and
+/// lets through a few probe calls: if they all succeed it closes again, while any failure opens it
+/// for another period.
+///
+/// Wrap calls with [`CircuitBreaker::call`], or for RPC use [`Client::call_with_breaker`].
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    open_for: Duration,
+    num_probes: u32,
+    state: CircuitState,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    probe_successes: u32,
+    metrics: CircuitMetrics,
+}
+
+impl CircuitBreaker {
+    /// Creates a new `CircuitBreaker` that opens after `failure_threshold` consecutive failures
+    /// and stays open for `open_for`, with a single probe call closing it again.
+    pub fn new(failure_threshold: u32, open_for: Duration) -> Self {
+        Self {
+            failure_threshold,
+            open_for,
+            num_probes: 1,
+            state: CircuitState::Closed,
+            consecutive_failures: 0,
+            opened_at: None,
+            probe_successes: 0,
+            metrics: CircuitMetrics::default(),
+        }
+    }
+
+    /// Sets how many probe calls must succeed in a row while half-open before the circuit closes.
+    pub fn probes(mut self, num_probes: u32) -> Self {
+        self.num_probes = num_probes.max(1);
+        self
+    }
+
+    /// Returns the current state of the circuit.
+    pub fn state(&mut self) -> CircuitState {
+        self.refresh();
+        self.state
+    }
+
+    /// Returns counts of the calls made and refused so far.
+    pub fn metrics(&self) -> CircuitMetrics {
+        self.metrics
+    }
+
+    /// Checks whether a call may be made now, counting it as rejected if not.
+    ///
+    /// Every call allowed must be followed by [`CircuitBreaker::record_success`] or
+    /// [`CircuitBreaker::record_failure`] once its outcome is known.
+    pub fn check(&mut self) -> Result<(), CircuitOpen> {
+        self.refresh();
+
+        match (self.state, self.opened_at) {
+            (CircuitState::Open, Some(opened_at)) => {
+                self.metrics.num_rejected += 1;
+                Err(CircuitOpen {
+                    retry_after: self.open_for.saturating_sub(opened_at.elapsed()),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Records that a call succeeded.
+    pub fn record_success(&mut self) {
+        self.metrics.num_successes += 1;
+        self.consecutive_failures = 0;
+
+        if self.state == CircuitState::HalfOpen {
+            self.probe_successes += 1;
+
+            if self.probe_successes >= self.num_probes {
+                self.state = CircuitState::Closed;
+                self.opened_at = None;
+            }
+        }
+    }
+
+    /// Records that a call failed.
+    pub fn record_failure(&mut self) {
+        self.metrics.num_failures += 1;
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+
+        let should_open = match self.state {
+            CircuitState::Closed => self.consecutive_failures >= self.failure_threshold,
+            CircuitState::HalfOpen => true,
+            CircuitState::Open => false,
+        };
+
+        if should_open {
+            self.state = CircuitState::Open;
+            self.opened_at = Some(Instant::now());
+            self.metrics.num_opened += 1;
+        }
+    }
+
+    /// Makes the call `f` if the circuit allows it, recording whether it succeeded.
+    pub fn call<T, E, F>(&mut self, f: F) -> Result<T, CircuitError<E>>
+    where
+        F: FnOnce() -> Result<T, E>,
+    {
+        self.check()?;
+
+        match f() {
+            Ok(t) => {
+                self.record_success();
+                Ok(t)
+            }
+            Err(e) => {
+                self.record_failure();
+                Err(CircuitError::Call(e))
+            }
+        }
+    }
+
+    /// Moves from open to half-open once the open period has ended.
+    fn refresh(&mut self) {
+        if let (CircuitState::Open, Some(opened_at)) = (self.state, self.opened_at) {
+            if opened_at.elapsed() >= self.open_for {
+                self.state = CircuitState::HalfOpen;
+                self.probe_successes = 0;
+            }
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> Client<R, W> {
+    /// Calls `method` with the given parameters through `breaker`, waiting for its result.
+    ///
+    /// Failures of the connection and calls that miss their deadline count as failures, while
+    /// other errors returned by the service show that it is up, and so count as successes.
+    pub fn call_with_breaker<P, T>(
+        &mut self,
+        breaker: &mut CircuitBreaker,
+        method: &str,
+        params: &P,
+    ) -> Result<T, CircuitError<RpcError>>
+    where
+        P: Serialize,
+        T: DeserializeOwned,
+    {
+        breaker.check()?;
+
+        let outcome = self.call(method, params);
+
+        match &outcome {
+            Err(RpcError::Read(_)) | Err(RpcError::Write(_)) => breaker.record_failure(),
+            Err(RpcError::Remote(e)) if e.code == ErrorObject::DEADLINE_EXCEEDED => {
+                breaker.record_failure()
+            }
+            _ => breaker.record_success(),
+        }
+
+        outcome.map_err(CircuitError::Call)
+    }
+}
@@ -0,0 +1,361 @@
+#[cfg(not(feature = "tokio"))]
+mod imports {
+    pub(super) use std::io::{self, BufReader};
+    pub(super) use std::net::{TcpStream, ToSocketAddrs};
+    #[cfg(unix)]
+    pub(super) use std::os::unix::net::UnixStream;
+}
+#[cfg(feature = "tokio")]
+mod imports {
+    pub(super) use tokio::io::{self, BufReader};
+    pub(super) use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf, ReadHalf, WriteHalf};
+    pub(super) use tokio::net::{lookup_host, TcpStream, ToSocketAddrs};
+    #[cfg(unix)]
+    pub(super) use tokio::net::{unix, UnixStream};
+    pub(super) use tokio::task::JoinSet;
+}
+
+use crate::{Connection, RetryPolicy};
+use imports::*;
+use socket2::{SockRef, TcpKeepalive};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// How long to wait for a connection attempt before starting the next one in parallel, as
+/// recommended by [RFC 8305](https://www.rfc-editor.org/rfc/rfc8305#section-8).
+#[cfg(feature = "tokio")]
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Configures socket options before handing back a [`Connection`] built from a socket.
+///
+/// Options that are never set are left at whatever the operating system (or the socket’s previous
+/// owner) chose. Options that only make sense for TCP, such as [`ConnectionBuilder::nodelay`] and
+/// [`ConnectionBuilder::keepalive`], are ignored when building from a Unix domain socket.
+///
+/// The builder always builds the top-level [`Connection`], so with the `tokio` feature it only
+/// builds asynchronous connections; there is no blocking equivalent in
+/// [`blocking`](crate::blocking).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ConnectionBuilder {
+    nodelay: Option<bool>,
+    keepalive: Option<Duration>,
+    recv_buffer_size: Option<usize>,
+    send_buffer_size: Option<usize>,
+    linger: Option<Option<Duration>>,
+    connect_timeout: Option<Duration>,
+}
+
+impl ConnectionBuilder {
+    /// Creates a new `ConnectionBuilder` that leaves all socket options untouched.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets `TCP_NODELAY`, which disables Nagle’s algorithm when `true`. Since JSON Lines messages
+    /// are usually small and latency-sensitive, this is often what you want.
+    pub fn nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = Some(nodelay);
+        self
+    }
+
+    /// Enables TCP keepalive, sending the first probe after the connection has been idle for
+    /// `idle`.
+    pub fn keepalive(mut self, idle: Duration) -> Self {
+        self.keepalive = Some(idle);
+        self
+    }
+
+    /// Sets the size of the socket’s receive buffer (`SO_RCVBUF`).
+    pub fn recv_buffer_size(mut self, size: usize) -> Self {
+        self.recv_buffer_size = Some(size);
+        self
+    }
+
+    /// Sets the size of the socket’s send buffer (`SO_SNDBUF`).
+    pub fn send_buffer_size(mut self, size: usize) -> Self {
+        self.send_buffer_size = Some(size);
+        self
+    }
+
+    /// Sets `SO_LINGER`. `None` disables lingering, while `Some` makes closing the socket block
+    /// for up to the given duration while unsent data is transmitted.
+    pub fn linger(mut self, linger: Option<Duration>) -> Self {
+        self.linger = Some(linger);
+        self
+    }
+
+    /// Sets how long [`ConnectionBuilder::connect_tcp`] waits for each individual connection
+    /// attempt before giving up on that address.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    fn configure_tcp(&self, socket: SockRef<'_>) -> io::Result<()> {
+        if let Some(nodelay) = self.nodelay {
+            socket.set_tcp_nodelay(nodelay)?;
+        }
+
+        if let Some(idle) = self.keepalive {
+            socket.set_tcp_keepalive(&TcpKeepalive::new().with_time(idle))?;
+        }
+
+        self.configure(socket)
+    }
+
+    fn configure(&self, socket: SockRef<'_>) -> io::Result<()> {
+        if let Some(size) = self.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+
+        if let Some(size) = self.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+
+        if let Some(linger) = self.linger {
+            socket.set_linger(linger)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Orders resolved addresses so that address families alternate, starting with whichever family
+/// the resolver listed first (see [RFC 8305 section 4](https://www.rfc-editor.org/rfc/rfc8305#section-4)).
+fn interleave_address_families(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let prefer_ipv6 = addrs.first().is_some_and(SocketAddr::is_ipv6);
+    let (preferred, other): (Vec<_>, Vec<_>) = addrs
+        .into_iter()
+        .partition(|addr| addr.is_ipv6() == prefer_ipv6);
+
+    let mut interleaved = Vec::with_capacity(preferred.len() + other.len());
+    let mut preferred = preferred.into_iter();
+    let mut other = other.into_iter();
+
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => break,
+            (a, b) => interleaved.extend(a.into_iter().chain(b)),
+        }
+    }
+
+    interleaved
+}
+
+fn no_addresses_error() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "could not resolve to any addresses",
+    )
+}
+
+#[cfg(not(feature = "tokio"))]
+impl ConnectionBuilder {
+    /// Resolves `addrs` and connects to each resulting address in turn until one succeeds, then
+    /// applies the configured options and creates a new `Connection` from the stream.
+    ///
+    /// Addresses are tried alternating between IPv6 and IPv4. Each attempt is bounded by
+    /// [`ConnectionBuilder::connect_timeout`] if it has been set. If every attempt fails, the error
+    /// from the last attempt is returned.
+    pub fn connect_tcp<A: ToSocketAddrs>(
+        &self,
+        addrs: A,
+    ) -> io::Result<Connection<BufReader<TcpStream>, TcpStream>> {
+        let tcp_stream = self.connect_tcp_stream(addrs)?;
+        self.tcp_stream(tcp_stream)
+    }
+
+    /// Connects as in [`ConnectionBuilder::connect_tcp`], trying again as often as `policy`
+    /// allows if connecting fails.
+    pub fn connect_tcp_with_retry<A: ToSocketAddrs + Clone>(
+        &self,
+        addrs: A,
+        policy: &RetryPolicy<io::Error>,
+    ) -> io::Result<Connection<BufReader<TcpStream>, TcpStream>> {
+        policy.run(|| self.connect_tcp(addrs.clone()))
+    }
+
+    /// Connects to `host` and `port` through the given proxy server, then applies the configured
+    /// options and creates a new `Connection` from the tunnelled stream.
+    ///
+    /// `host` is passed to the proxy unresolved, so it may be a hostname that only the proxy is
+    /// able to resolve. The connection to the proxy itself is made as in
+    /// [`ConnectionBuilder::connect_tcp`].
+    #[cfg(feature = "proxy")]
+    pub fn connect_tcp_via_proxy(
+        &self,
+        proxy: &crate::Proxy,
+        host: &str,
+        port: u16,
+    ) -> io::Result<Connection<BufReader<TcpStream>, TcpStream>> {
+        let mut tcp_stream = self.connect_tcp_stream(proxy.addr())?;
+        proxy.handshake(&mut tcp_stream, host, port)?;
+        self.tcp_stream(tcp_stream)
+    }
+
+    fn connect_tcp_stream<A: ToSocketAddrs>(&self, addrs: A) -> io::Result<TcpStream> {
+        let addrs = interleave_address_families(addrs.to_socket_addrs()?.collect());
+        let mut last_err = None;
+
+        for addr in addrs {
+            let result = match self.connect_timeout {
+                Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+                None => TcpStream::connect(addr),
+            };
+
+            match result {
+                Ok(tcp_stream) => return Ok(tcp_stream),
+                Err(e) => last_err = Some(e),
+            }
+        }
+
+        Err(last_err.unwrap_or_else(no_addresses_error))
+    }
+
+    /// Applies the configured options to a TCP stream and creates a new `Connection` from it.
+    pub fn tcp_stream(
+        &self,
+        tcp_stream: TcpStream,
+    ) -> io::Result<Connection<BufReader<TcpStream>, TcpStream>> {
+        self.configure_tcp(SockRef::from(&tcp_stream))?;
+        Connection::new_from_tcp_stream(tcp_stream)
+    }
+
+    /// Applies the configured options to a Unix domain socket stream and creates a new
+    /// `Connection` from it.
+    #[cfg(unix)]
+    pub fn unix_stream(
+        &self,
+        unix_stream: UnixStream,
+    ) -> io::Result<Connection<BufReader<UnixStream>, UnixStream>> {
+        self.configure(SockRef::from(&unix_stream))?;
+        Connection::new_from_unix_stream(unix_stream)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl ConnectionBuilder {
+    /// Resolves `addrs` and races connections to the resulting addresses, then applies the
+    /// configured options to the first stream to connect and creates a new `Connection` from it.
+    ///
+    /// This follows the ‘Happy Eyeballs’ algorithm from
+    /// [RFC 8305](https://www.rfc-editor.org/rfc/rfc8305): addresses are tried alternating between
+    /// IPv6 and IPv4, and a new attempt is started whenever the previous one fails or has not
+    /// completed within 250 milliseconds. Each attempt is bounded by
+    /// [`ConnectionBuilder::connect_timeout`] if it has been set. If every attempt fails, the error
+    /// from the last attempt to fail is returned.
+    pub async fn connect_tcp<A: ToSocketAddrs>(
+        &self,
+        addrs: A,
+    ) -> io::Result<Connection<BufReader<OwnedReadHalf>, OwnedWriteHalf>> {
+        let tcp_stream = self.connect_tcp_stream(addrs).await?;
+        self.configure_tcp(SockRef::from(&tcp_stream))?;
+        Ok(Connection::new_from_owned_tcp_stream(tcp_stream))
+    }
+
+    /// Connects as in [`ConnectionBuilder::connect_tcp`], trying again as often as `policy`
+    /// allows if connecting fails.
+    pub async fn connect_tcp_with_retry<A: ToSocketAddrs + Clone>(
+        &self,
+        addrs: A,
+        policy: &RetryPolicy<io::Error>,
+    ) -> io::Result<Connection<BufReader<OwnedReadHalf>, OwnedWriteHalf>> {
+        policy.run(|| self.connect_tcp(addrs.clone())).await
+    }
+
+    /// Connects to `host` and `port` through the given proxy server, then applies the configured
+    /// options and creates a new `Connection` from the tunnelled stream.
+    ///
+    /// `host` is passed to the proxy unresolved, so it may be a hostname that only the proxy is
+    /// able to resolve. The connection to the proxy itself is made as in
+    /// [`ConnectionBuilder::connect_tcp`].
+    #[cfg(feature = "proxy")]
+    pub async fn connect_tcp_via_proxy(
+        &self,
+        proxy: &crate::Proxy,
+        host: &str,
+        port: u16,
+    ) -> io::Result<Connection<BufReader<OwnedReadHalf>, OwnedWriteHalf>> {
+        let mut tcp_stream = self.connect_tcp_stream(proxy.addr()).await?;
+        proxy.handshake(&mut tcp_stream, host, port).await?;
+        self.configure_tcp(SockRef::from(&tcp_stream))?;
+        Ok(Connection::new_from_owned_tcp_stream(tcp_stream))
+    }
+
+    async fn connect_tcp_stream<A: ToSocketAddrs>(&self, addrs: A) -> io::Result<TcpStream> {
+        let addrs = interleave_address_families(lookup_host(addrs).await?.collect());
+        let mut addrs = addrs.into_iter();
+        let mut attempts = JoinSet::new();
+        let mut last_err = None;
+
+        loop {
+            let has_remaining_addrs = addrs.len() > 0;
+
+            if attempts.is_empty() {
+                match addrs.next() {
+                    Some(addr) => self.spawn_connection_attempt(&mut attempts, addr),
+                    None => break,
+                }
+                continue;
+            }
+
+            let next = if has_remaining_addrs {
+                tokio::time::timeout(CONNECTION_ATTEMPT_DELAY, attempts.join_next()).await
+            } else {
+                Ok(attempts.join_next().await)
+            };
+
+            match next {
+                Ok(Some(Ok(Ok(tcp_stream)))) => return Ok(tcp_stream),
+                Ok(Some(Ok(Err(e)))) => last_err = Some(e),
+                Ok(Some(Err(join_error))) => last_err = Some(io::Error::other(join_error)),
+                Ok(None) => {}
+                Err(_) => {
+                    if let Some(addr) = addrs.next() {
+                        self.spawn_connection_attempt(&mut attempts, addr);
+                    }
+                }
+            }
+        }
+
+        Err(last_err.unwrap_or_else(no_addresses_error))
+    }
+
+    fn spawn_connection_attempt(
+        &self,
+        attempts: &mut JoinSet<io::Result<TcpStream>>,
+        addr: SocketAddr,
+    ) {
+        let timeout = self.connect_timeout;
+
+        attempts.spawn(async move {
+            match timeout {
+                Some(timeout) => tokio::time::timeout(timeout, TcpStream::connect(addr))
+                    .await
+                    .unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into())),
+                None => TcpStream::connect(addr).await,
+            }
+        });
+    }
+
+    /// Applies the configured options to a TCP stream and creates a new `Connection` from a
+    /// mutable reference to it.
+    pub fn tcp_stream<'a>(
+        &self,
+        tcp_stream: &'a mut TcpStream,
+    ) -> io::Result<Connection<BufReader<ReadHalf<'a>>, WriteHalf<'a>>> {
+        self.configure_tcp(SockRef::from(&*tcp_stream))?;
+        Connection::new_from_tcp_stream(tcp_stream)
+    }
+
+    /// Applies the configured options to a Unix domain socket stream and creates a new
+    /// `Connection` from a mutable reference to it.
+    #[cfg(unix)]
+    pub fn unix_stream<'a>(
+        &self,
+        unix_stream: &'a mut UnixStream,
+    ) -> io::Result<Connection<BufReader<unix::ReadHalf<'a>>, unix::WriteHalf<'a>>> {
+        self.configure(SockRef::from(&*unix_stream))?;
+        Connection::new_from_unix_stream(unix_stream)
+    }
+}
@@ -0,0 +1,83 @@
+use serde::Serialize;
+use serde_json::{Map, Number, Value};
+
+/// The largest magnitude below which every integer is exactly representable as an `f64`.
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_992.0;
+
+/// Rewrites a single line of JSON Lines into its canonical form: object keys sorted
+/// lexicographically at every level of nesting, floats with no fractional part written as
+/// integers, and no insignificant whitespace.
+///
+/// Two lines holding the same JSON value always canonicalize to the same string, so files whose
+/// lines have been canonicalized can be compared with line-based tools like `diff` and `sort`. The
+/// returned string does not end in a newline.
+pub fn canonicalize(line: &str) -> Result<String, serde_json::Error> {
+    let value: Value = serde_json::from_str(line)?;
+
+    serde_json::to_string(&canonical_value(value))
+}
+
+/// Serializes a value into the canonical form described in [`canonicalize`].
+///
+/// The output is byte-for-byte identical across runs, platforms and versions of this crate, so it
+/// is suitable for hashing and reproducible builds.
+pub fn to_canonical_string<T: Serialize>(t: &T) -> Result<String, serde_json::Error> {
+    serde_json::to_value(t).and_then(|value| serde_json::to_string(&canonical_value(value)))
+}
+
+/// Puts a JSON value into canonical form.
+pub(crate) fn canonical_value(value: Value) -> Value {
+    normalize_numbers(sort_keys(value))
+}
+
+/// Rewrites floats with no fractional part that fit exactly in an integer as that integer, so
+/// `1.0` and `1` (and `-0.0` and `0`) are written the same way.
+fn normalize_numbers(value: Value) -> Value {
+    match value {
+        Value::Array(values) => Value::Array(values.into_iter().map(normalize_numbers).collect()),
+        Value::Object(object) => Value::Object(
+            object
+                .into_iter()
+                .map(|(key, value)| (key, normalize_numbers(value)))
+                .collect(),
+        ),
+        Value::Number(n) => match n.as_f64() {
+            Some(f) if n.is_f64() && f.fract() == 0.0 && f.abs() < MAX_SAFE_INTEGER => {
+                Value::Number(Number::from(f as i64))
+            }
+            _ => Value::Number(n),
+        },
+        value => value,
+    }
+}
+
+/// Expands a single line of JSON Lines over multiple indented lines for human viewing, keeping
+/// object keys in their original order.
+///
+/// The result is no longer valid JSON Lines, so it should only be used for display.
+pub fn pretty_line(line: &str) -> Result<String, serde_json::Error> {
+    let value: Value = serde_json::from_str(line)?;
+
+    serde_json::to_string_pretty(&value)
+}
+
+/// Sorts object keys recursively. This is done explicitly rather than relying on [`Map`] being
+/// ordered, since that changes if any crate in the dependency graph enables `serde_json`’s
+/// `preserve_order` feature.
+fn sort_keys(value: Value) -> Value {
+    match value {
+        Value::Array(values) => Value::Array(values.into_iter().map(sort_keys).collect()),
+        Value::Object(object) => {
+            let mut entries: Vec<_> = object.into_iter().collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, sort_keys(value)))
+                    .collect::<Map<_, _>>(),
+            )
+        }
+        value => value,
+    }
+}
@@ -0,0 +1,268 @@
+use crate::{Connection, ReadError, WriteError};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+#[cfg(not(feature = "tokio"))]
+use std::io::{BufRead, Write};
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncBufRead as BufRead, AsyncWrite as Write};
+
+/// Which way a captured message went, from the point of view of the side that was recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// The message was read.
+    Inbound,
+    /// The message was written.
+    Outbound,
+}
+
+/// A single line of a capture file written by [`Recorder`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CaptureEntry {
+    /// The time since recording started, in microseconds.
+    pub elapsed_micros: u64,
+    pub direction: Direction,
+    pub message: Value,
+}
+
+/// Wraps a [`Connection`], recording every message read and written, with timestamps, to a
+/// capture file that can later be played back with [`Replayer`].
+///
+/// The capture is written with blocking IO whichever features are enabled, as with a log file. It
+/// is not flushed after every message, so wrap it in a `BufWriter` if needed and call
+/// [`Recorder::flush_capture`] when appropriate.
+#[derive(Debug)]
+pub struct Recorder<R: BufRead, W: Write, C> {
+    connection: Connection<R, W>,
+    capture: C,
+    start: Instant,
+}
+
+impl<R: BufRead, W: Write, C: io::Write> Recorder<R, W, C> {
+    /// Creates a new `Recorder` wrapping `connection` and writing the capture to `capture`.
+    pub fn new(connection: Connection<R, W>, capture: C) -> Self {
+        Self {
+            connection,
+            capture,
+            start: Instant::now(),
+        }
+    }
+
+    /// Flushes the capture.
+    pub fn flush_capture(&mut self) -> io::Result<()> {
+        self.capture.flush()
+    }
+
+    /// Consumes the `Recorder`, returning the wrapped connection and the capture.
+    pub fn into_parts(self) -> (Connection<R, W>, C) {
+        (self.connection, self.capture)
+    }
+
+    fn record(&mut self, direction: Direction, message: Value) -> Result<(), WriteError> {
+        let entry = CaptureEntry {
+            elapsed_micros: self.start.elapsed().as_micros() as u64,
+            direction,
+            message,
+        };
+
+        crate::blocking::write(&mut self.capture, &entry)
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl<R: BufRead, W: Write, C: io::Write> Recorder<R, W, C> {
+    /// Reads a line from the connection and deserializes it into a given type, recording it.
+    ///
+    /// A failure to write to the capture is reported as an IO error.
+    pub fn read<T: DeserializeOwned>(&mut self) -> Result<T, ReadError> {
+        let message: Value = self.connection.read()?;
+
+        self.record(Direction::Inbound, message.clone())
+            .map_err(|e| ReadError::Io(io::Error::other(e)))?;
+
+        Ok(serde_json::from_value(message)?)
+    }
+
+    /// Writes a given value to the connection, serializing it into JSON, and records it.
+    pub fn write<T: Serialize>(&mut self, t: &T) -> Result<(), WriteError> {
+        let message = serde_json::to_value(t)?;
+        self.connection.write(&message)?;
+        self.record(Direction::Outbound, message)
+    }
+
+    /// Flushes the connection’s writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.connection.flush()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R: BufRead + Unpin, W: Write + Unpin, C: io::Write> Recorder<R, W, C> {
+    /// Reads a line from the connection and deserializes it into a given type, recording it.
+    ///
+    /// A failure to write to the capture is reported as an IO error.
+    pub async fn read<T: DeserializeOwned>(&mut self) -> Result<T, ReadError> {
+        let message: Value = self.connection.read().await?;
+
+        self.record(Direction::Inbound, message.clone())
+            .map_err(|e| ReadError::Io(io::Error::other(e)))?;
+
+        Ok(serde_json::from_value(message)?)
+    }
+
+    /// Writes a given value to the connection, serializing it into JSON, and records it.
+    pub async fn write<T: Serialize>(&mut self, t: &T) -> Result<(), WriteError> {
+        let message = serde_json::to_value(t)?;
+        self.connection.write(&message).await?;
+        self.record(Direction::Outbound, message)
+    }
+
+    /// Flushes the connection’s writer.
+    pub async fn flush(&mut self) -> io::Result<()> {
+        self.connection.flush().await
+    }
+}
+
+/// How quickly a [`Replayer`] plays back a capture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Timing {
+    /// Waits between messages as long as was waited when the capture was recorded.
+    Original,
+    /// Sends every message as soon as possible.
+    AsFastAsPossible,
+}
+
+/// Plays back a capture recorded by [`Recorder`] against the code under test, taking the place of
+/// the peer of the recorded side.
+///
+/// Messages the recorded side read are written to the code under test, and wherever the recorded
+/// side wrote a message, a message is read from the code under test instead.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replayer {
+    entries: Vec<CaptureEntry>,
+    timing: Timing,
+}
+
+impl Replayer {
+    /// Loads the capture in the file at `path`, to be played back with the original timing.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Loads a capture from a reader, to be played back with the original timing.
+    pub fn from_reader<R: io::BufRead>(mut reader: R) -> io::Result<Self> {
+        let mut entries = Vec::new();
+
+        loop {
+            match crate::blocking::read(&mut reader) {
+                Ok(entry) => entries.push(entry),
+                Err(ReadError::Eof) => break,
+                Err(ReadError::Io(e)) => return Err(e),
+                Err(ReadError::Deserialize(e)) => return Err(e.into()),
+                Err(e @ ReadError::LineTooLong { .. }) => {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, e))
+                }
+            }
+        }
+
+        Ok(Self {
+            entries,
+            timing: Timing::Original,
+        })
+    }
+
+    /// Sets how quickly the capture is played back.
+    pub fn timing(mut self, timing: Timing) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    /// Returns the entries of the capture.
+    pub fn entries(&self) -> &[CaptureEntry] {
+        &self.entries
+    }
+
+    /// Returns how long to wait before the entry at `i`.
+    fn delay(&self, i: usize) -> Option<Duration> {
+        if self.timing == Timing::AsFastAsPossible || i == 0 {
+            return None;
+        }
+
+        let micros = self.entries[i]
+            .elapsed_micros
+            .saturating_sub(self.entries[i - 1].elapsed_micros);
+
+        Some(Duration::from_micros(micros))
+    }
+}
+
+/// An error that occurred while playing back a capture with [`Replayer`].
+#[derive(Debug, thiserror::Error)]
+pub enum ReplayError {
+    #[error("failed reading from code under test")]
+    Read(#[from] ReadError),
+    #[error("failed writing to code under test")]
+    Write(#[from] WriteError),
+}
+
+#[cfg(not(feature = "tokio"))]
+impl Replayer {
+    /// Plays the capture back over `connection`, returning the messages read from the code under
+    /// test in place of those the recorded side wrote, so that they can be compared.
+    pub fn replay<R: BufRead, W: Write>(
+        &self,
+        connection: &mut Connection<R, W>,
+    ) -> Result<Vec<Value>, ReplayError> {
+        let mut received = Vec::new();
+
+        for (i, entry) in self.entries.iter().enumerate() {
+            match entry.direction {
+                Direction::Inbound => {
+                    if let Some(delay) = self.delay(i) {
+                        std::thread::sleep(delay);
+                    }
+
+                    connection.write(&entry.message)?;
+                    connection.flush().map_err(WriteError::Io)?;
+                }
+                Direction::Outbound => received.push(connection.read()?),
+            }
+        }
+
+        Ok(received)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Replayer {
+    /// Plays the capture back over `connection`, returning the messages read from the code under
+    /// test in place of those the recorded side wrote, so that they can be compared.
+    pub async fn replay<R: BufRead + Unpin, W: Write + Unpin>(
+        &self,
+        connection: &mut Connection<R, W>,
+    ) -> Result<Vec<Value>, ReplayError> {
+        let mut received = Vec::new();
+
+        for (i, entry) in self.entries.iter().enumerate() {
+            match entry.direction {
+                Direction::Inbound => {
+                    if let Some(delay) = self.delay(i) {
+                        tokio::time::sleep(delay).await;
+                    }
+
+                    connection.write(&entry.message).await?;
+                    connection.flush().await.map_err(WriteError::Io)?;
+                }
+                Direction::Outbound => received.push(connection.read().await?),
+            }
+        }
+
+        Ok(received)
+    }
+}
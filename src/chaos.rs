@@ -0,0 +1,247 @@
+use std::io::{self, Read, Write};
+use std::thread;
+use std::time::Duration;
+
+/// A small, fast pseudorandom number generator (xorshift64*), so that the faults injected by
+/// [`Chaos`] are reproducible from a seed without depending on a particular version of `rand`.
+#[derive(Debug, Clone)]
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // Zero is a fixed point of xorshift, so it is mapped to an arbitrary nonzero state.
+        Self(if seed == 0 {
+            0x9e37_79b9_7f4a_7c15
+        } else {
+            seed
+        })
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Returns a number uniformly distributed in `[0, 1)`.
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        probability > 0.0 && self.next_f64() < probability
+    }
+}
+
+/// The distribution of the delay [`Chaos`] adds before each operation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Latency {
+    /// No delay.
+    None,
+    /// The same delay every time.
+    Fixed(Duration),
+    /// A delay uniformly distributed between the two bounds.
+    Uniform { min: Duration, max: Duration },
+    /// An exponentially distributed delay with the given mean, giving mostly short delays with an
+    /// occasional long one, as on a real network.
+    Exponential { mean: Duration },
+}
+
+impl Latency {
+    fn sample(&self, rng: &mut Rng) -> Duration {
+        match *self {
+            Self::None => Duration::ZERO,
+            Self::Fixed(delay) => delay,
+            Self::Uniform { min, max } => min + max.saturating_sub(min).mul_f64(rng.next_f64()),
+            Self::Exponential { mean } => mean.mul_f64(-(1.0 - rng.next_f64()).ln()),
+        }
+    }
+}
+
+/// Injects faults into readers and writers for testing how code copes with unreliable
+/// connections: added latency, lines delivered out of order, and disconnections.
+///
+/// Every fault is drawn from a pseudorandom sequence determined by the seed, so a failing test can
+/// be reproduced exactly by reusing its seed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chaos {
+    seed: u64,
+    latency: Latency,
+    reorder_probability: f64,
+    disconnect_probability: f64,
+}
+
+impl Chaos {
+    /// Creates a new `Chaos` with the given seed, which injects no faults until configured to.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            latency: Latency::None,
+            reorder_probability: 0.0,
+            disconnect_probability: 0.0,
+        }
+    }
+
+    /// Sets the delay added before every read and every line written.
+    pub fn latency(mut self, latency: Latency) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Sets the probability that a line written is held back and sent after the following line
+    /// instead. Held lines are always sent when the writer is flushed.
+    pub fn reorder_probability(mut self, probability: f64) -> Self {
+        self.reorder_probability = probability;
+        self
+    }
+
+    /// Sets the probability that any given read or line written fails with
+    /// [`io::ErrorKind::ConnectionReset`], after which every operation fails.
+    pub fn disconnect_probability(mut self, probability: f64) -> Self {
+        self.disconnect_probability = probability;
+        self
+    }
+
+    /// Wraps a reader so that reading from it suffers latency and disconnections.
+    pub fn reader<R: Read>(&self, reader: R) -> ChaosReader<R> {
+        ChaosReader {
+            reader,
+            rng: Rng::new(self.seed),
+            latency: self.latency,
+            disconnect_probability: self.disconnect_probability,
+            disconnected: false,
+        }
+    }
+
+    /// Wraps a writer so that lines written to it suffer latency, reordering and disconnections.
+    ///
+    /// The writer is seeded differently from readers created from the same `Chaos`, so that the
+    /// two do not fail in lockstep.
+    pub fn writer<W: Write>(&self, writer: W) -> ChaosWriter<W> {
+        ChaosWriter {
+            writer,
+            rng: Rng::new(self.seed.rotate_left(32) ^ 1),
+            latency: self.latency,
+            reorder_probability: self.reorder_probability,
+            disconnect_probability: self.disconnect_probability,
+            disconnected: false,
+            line: Vec::new(),
+            held: None,
+        }
+    }
+}
+
+fn disconnected() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::ConnectionReset,
+        "connection reset by fault injection",
+    )
+}
+
+/// A reader suffering the faults configured in [`Chaos`], created by [`Chaos::reader`].
+#[derive(Debug)]
+pub struct ChaosReader<R> {
+    reader: R,
+    rng: Rng,
+    latency: Latency,
+    disconnect_probability: f64,
+    disconnected: bool,
+}
+
+impl<R> ChaosReader<R> {
+    /// Consumes the `ChaosReader`, returning the wrapped reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Read for ChaosReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.disconnected || self.rng.chance(self.disconnect_probability) {
+            self.disconnected = true;
+            return Err(disconnected());
+        }
+
+        thread::sleep(self.latency.sample(&mut self.rng));
+        self.reader.read(buf)
+    }
+}
+
+/// A writer suffering the faults configured in [`Chaos`], created by [`Chaos::writer`].
+///
+/// Faults are applied to whole lines, which are passed on to the wrapped writer once complete.
+#[derive(Debug)]
+pub struct ChaosWriter<W> {
+    writer: W,
+    rng: Rng,
+    latency: Latency,
+    reorder_probability: f64,
+    disconnect_probability: f64,
+    disconnected: bool,
+    line: Vec<u8>,
+    held: Option<Vec<u8>>,
+}
+
+impl<W> ChaosWriter<W> {
+    /// Consumes the `ChaosWriter`, returning the wrapped writer. Any incomplete or held line is
+    /// discarded.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> ChaosWriter<W> {
+    fn write_line(&mut self, line: Vec<u8>) -> io::Result<()> {
+        if self.rng.chance(self.disconnect_probability) {
+            self.disconnected = true;
+            return Err(disconnected());
+        }
+
+        thread::sleep(self.latency.sample(&mut self.rng));
+
+        if self.held.is_none() && self.rng.chance(self.reorder_probability) {
+            self.held = Some(line);
+            return Ok(());
+        }
+
+        self.writer.write_all(&line)?;
+
+        if let Some(held) = self.held.take() {
+            self.writer.write_all(&held)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for ChaosWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.disconnected {
+            return Err(disconnected());
+        }
+
+        for &byte in buf {
+            self.line.push(byte);
+
+            if byte == b'\n' {
+                let line = std::mem::take(&mut self.line);
+                self.write_line(line)?;
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.disconnected {
+            return Err(disconnected());
+        }
+
+        if let Some(held) = self.held.take() {
+            self.writer.write_all(&held)?;
+        }
+
+        self.writer.flush()
+    }
+}
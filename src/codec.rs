@@ -0,0 +1,106 @@
+use crate::{encode_into_bytes, DecodeError, Decoder, WriteError};
+use bytes::BytesMut;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io;
+use std::marker::PhantomData;
+
+/// An error that occurred while decoding with [`JsonLinesCodec`].
+#[derive(Debug, thiserror::Error)]
+pub enum CodecError {
+    #[error("failed reading data from reader")]
+    Io(#[from] io::Error),
+    #[error("failed decoding line")]
+    Decode(#[from] DecodeError),
+}
+
+/// A [`tokio_util::codec`] codec for JSON Lines, which decodes lines into values of type `T` and
+/// encodes any serializable value into a line.
+///
+/// This lets JSON Lines be spoken over anything `Framed` can wrap, alongside the other codecs a
+/// server is built out of. Decoding is done with a [`Decoder`], so lines are checked against its
+/// limits before they are deserialized; the last line of a stream does not need a trailing newline.
+///
+/// ```
+/// use bytes::BytesMut;
+/// use jsonl::JsonLinesCodec;
+/// use serde_json::{json, Value};
+/// use tokio_util::codec::{Decoder, Encoder};
+///
+/// let mut codec = JsonLinesCodec::<Value>::new();
+/// let mut buf = BytesMut::new();
+///
+/// codec.encode(json!({ "id": 1 }), &mut buf)?;
+/// assert_eq!(&buf[..], b"{\"id\":1}\n");
+/// assert_eq!(codec.decode(&mut buf)?, Some(json!({ "id": 1 })));
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct JsonLinesCodec<T> {
+    decoder: Decoder,
+    _message: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for JsonLinesCodec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for JsonLinesCodec<T> {
+    fn clone(&self) -> Self {
+        Self::with_decoder(self.decoder.clone())
+    }
+}
+
+impl<T> JsonLinesCodec<T> {
+    /// Creates a new `JsonLinesCodec` that decodes with the default limits of [`Decoder::new`].
+    pub fn new() -> Self {
+        Self::with_decoder(Decoder::new())
+    }
+
+    /// Creates a new `JsonLinesCodec` that decodes with `decoder`, to change its limits or how
+    /// it handles malformed lines.
+    pub fn with_decoder(decoder: Decoder) -> Self {
+        Self {
+            decoder,
+            _message: PhantomData,
+        }
+    }
+}
+
+impl<T: DeserializeOwned> tokio_util::codec::Decoder for JsonLinesCodec<T> {
+    type Item = T;
+    type Error = CodecError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<T>, CodecError> {
+        match self.decoder.decode_from(src) {
+            Some(result) => Ok(Some(result?)),
+            None => Ok(None),
+        }
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<T>, CodecError> {
+        if let Some(t) = self.decode(src)? {
+            return Ok(Some(t));
+        }
+
+        // Whatever is left is a last line without a trailing newline, or the rest of a line that
+        // was too long, which the decoder discards.
+        let rest = src.split();
+        self.decoder.feed_bytes(&rest);
+
+        match self.decoder.finish() {
+            Some(result) => Ok(Some(result?)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<T, U: Serialize> tokio_util::codec::Encoder<U> for JsonLinesCodec<T> {
+    type Error = WriteError;
+
+    fn encode(&mut self, item: U, dst: &mut BytesMut) -> Result<(), WriteError> {
+        encode_into_bytes(&item, dst)
+    }
+}
@@ -1,20 +1,13 @@
-#[cfg(not(feature = "tokio"))]
-mod imports {
-    pub(super) use std::io::{self, BufRead, BufReader, Stdin, Stdout, Write};
-    pub(super) use std::net::{Shutdown, TcpStream};
-    pub(super) use std::process::{Child, ChildStdin, ChildStdout};
-}
-#[cfg(feature = "tokio")]
-mod imports {
-    pub(super) use tokio::io::{
-        self, AsyncBufRead as BufRead, AsyncWrite as Write, AsyncWriteExt, BufReader, Stdin, Stdout,
-    };
-    pub(super) use tokio::net::tcp::{ReadHalf, WriteHalf};
-    pub(super) use tokio::net::TcpStream;
-    pub(super) use tokio::process::{Child, ChildStdin, ChildStdout};
-}
-
-use imports::*;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{
+    self, AsyncBufRead as BufRead, AsyncWrite as Write, AsyncWriteExt, BufReader, Stdin, Stdout,
+};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf, ReadHalf, WriteHalf};
+use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::{unix, UnixStream};
+use tokio::process::{Child, ChildStdin, ChildStdout};
 
 /// Use this type when you have both a reader and writer, and want them to be grouped together.
 ///
@@ -24,18 +17,65 @@ use imports::*;
 /// when one is forgotten.
 ///
 /// `Connection` is internally a pair of a reader and a writer, and delegates to [`crate::read`] and
-/// [`crate::write`] for [`Connection::read`] and [`Connection::write`] respectively.
+/// [`crate::write`] for [`Connection::read`] and [`Connection::write`] respectively. It can be
+/// created from a reader and writer directly, or from any [`Transport`](crate::Transport) with
+/// [`Connection::from_transport`].
+///
+/// This is the asynchronous `Connection` used with the `tokio` feature. The `std`-based one stays
+/// available as [`blocking::Connection`](crate::blocking::Connection).
 ///
 /// [data clump]: https://youtu.be/DC-pQPq0acs?t=521
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
 pub struct Connection<R: BufRead, W: Write> {
     reader: R,
     writer: W,
+    max_line_len: Option<usize>,
+    poll: PollState,
+}
+
+/// The progress of a message partway through being read by [`Connection::poll_read`] or written by
+/// [`Connection::poll_write`].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+struct PollState {
+    read_buf: Vec<u8>,
+    write_buf: Vec<u8>,
+    write_pos: usize,
 }
 
 impl<R: BufRead, W: Write> Connection<R, W> {
     pub fn new(reader: R, writer: W) -> Self {
-        Self { reader, writer }
+        Self {
+            reader,
+            writer,
+            max_line_len: None,
+            poll: PollState::default(),
+        }
+    }
+
+    /// Sets the maximum length in bytes of the lines read, not including their newlines, beyond
+    /// which reads fail with [`ReadError::LineTooLong`](crate::ReadError::LineTooLong) instead of
+    /// buffering the rest of the line; see [`crate::read_with_limit`]. Lines are unlimited by
+    /// default, which is only safe when the peer is trusted.
+    pub fn max_line_len(mut self, max_bytes: usize) -> Self {
+        self.max_line_len = Some(max_bytes);
+        self
+    }
+
+    /// Returns a mutable reference to the contained reader, for transport-specific operations.
+    #[cfg_attr(not(feature = "fd-passing"), allow(dead_code))]
+    pub(crate) fn reader_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Returns a mutable reference to the contained writer, for transport-specific operations.
+    pub(crate) fn writer_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Consumes the `Connection`, returning the contained reader and writer. Any message partway
+    /// through being read or written with the poll-based methods is lost.
+    pub fn into_parts(self) -> (R, W) {
+        (self.reader, self.writer)
     }
 }
 
@@ -44,13 +84,7 @@ impl<'a> Connection<BufReader<&'a mut ChildStdout>, &'a mut ChildStdin> {
     /// child process’ `stdout` as the reader. This facilitates communication with this child process
     /// by passing data into its `stdin` and reading from its `stdout`.
     pub fn new_from_child(child: &'a mut Child) -> Option<Self> {
-        let stdin = child.stdin.as_mut()?;
-        let stdout = child.stdout.as_mut()?;
-
-        Some(Self {
-            reader: BufReader::new(stdout),
-            writer: stdin,
-        })
+        Self::from_transport(child).ok()
     }
 }
 
@@ -58,39 +92,26 @@ impl Connection<BufReader<Stdin>, Stdout> {
     /// Creates a new `Connection` from the stdio of the current process – `stdin` is used as the reader
     /// and `stdout` is used as the writer.
     pub fn new_from_stdio() -> Self {
-        Self {
-            reader: BufReader::new(io::stdin()),
-            writer: io::stdout(),
-        }
+        Self::from_infallible_transport(crate::StdioTransport)
     }
 }
 
-#[cfg(not(feature = "tokio"))]
-impl Connection<BufReader<TcpStream>, TcpStream> {
-    /// Creates a new `Connection` from a TCP stream.
-    pub fn new_from_tcp_stream(tcp_stream: TcpStream) -> io::Result<Self> {
-        Ok(Self {
-            reader: BufReader::new(tcp_stream.try_clone()?),
-            writer: tcp_stream,
-        })
+impl<'a> Connection<BufReader<ReadHalf<'a>>, WriteHalf<'a>> {
+    /// Creates a new `Connection` from a mutable reference to a TCP stream.
+    pub fn new_from_tcp_stream(tcp_stream: &'a mut TcpStream) -> io::Result<Self> {
+        Ok(Self::from_infallible_transport(tcp_stream))
     }
 
     /// Closes the TCP stream.
-    pub fn shutdown(self) -> io::Result<()> {
-        self.writer.shutdown(Shutdown::Both)
+    pub async fn shutdown(mut self) -> io::Result<()> {
+        self.writer.shutdown().await
     }
 }
 
-#[cfg(feature = "tokio")]
-impl<'a> Connection<BufReader<ReadHalf<'a>>, WriteHalf<'a>> {
-    /// Creates a new `Connection` from a mutable reference to a TCP stream.
-    pub fn new_from_tcp_stream(tcp_stream: &'a mut TcpStream) -> io::Result<Self> {
-        let (read_half, write_half) = tcp_stream.split();
-
-        Ok(Self {
-            reader: BufReader::new(read_half),
-            writer: write_half,
-        })
+impl Connection<BufReader<OwnedReadHalf>, OwnedWriteHalf> {
+    /// Creates a new `Connection` that takes ownership of a TCP stream.
+    pub fn new_from_owned_tcp_stream(tcp_stream: TcpStream) -> Self {
+        Self::from_infallible_transport(tcp_stream)
     }
 
     /// Closes the TCP stream.
@@ -99,29 +120,35 @@ impl<'a> Connection<BufReader<ReadHalf<'a>>, WriteHalf<'a>> {
     }
 }
 
-#[cfg(not(feature = "tokio"))]
-impl<R: BufRead, W: Write> Connection<R, W> {
-    /// Reads a line from the reader and deserializes it into a given type.
-    pub fn read<T: serde::de::DeserializeOwned>(&mut self) -> Result<T, crate::ReadError> {
-        crate::read(&mut self.reader)
-    }
-
-    /// Writes a given value to the writer, serializing it into JSON.
-    pub fn write<T: serde::Serialize>(&mut self, t: &T) -> Result<(), crate::WriteError> {
-        crate::write(&mut self.writer, t)
+#[cfg(unix)]
+impl<'a> Connection<BufReader<unix::ReadHalf<'a>>, unix::WriteHalf<'a>> {
+    /// Creates a new `Connection` from a mutable reference to a Unix domain socket stream.
+    pub fn new_from_unix_stream(unix_stream: &'a mut UnixStream) -> io::Result<Self> {
+        Ok(Self::from_infallible_transport(unix_stream))
     }
 
-    /// Flushes the contained writer’s buffer.
-    pub fn flush(&mut self) -> io::Result<()> {
-        self.writer.flush()
+    /// Closes the Unix domain socket stream.
+    pub async fn shutdown(mut self) -> io::Result<()> {
+        self.writer.shutdown().await
     }
 }
 
-#[cfg(feature = "tokio")]
 impl<R: BufRead + Unpin, W: Write + Unpin> Connection<R, W> {
     /// Reads a line from the reader and deserializes it into a given type.
     pub async fn read<T: serde::de::DeserializeOwned>(&mut self) -> Result<T, crate::ReadError> {
-        crate::read(&mut self.reader).await
+        self.read_with_len().await.map(|(t, _)| t)
+    }
+
+    /// Reads a line from the reader and deserializes it into a given type, returning `None` at EOF;
+    /// see [`crate::read_opt`].
+    pub async fn read_opt<T: serde::de::DeserializeOwned>(
+        &mut self,
+    ) -> Result<Option<T>, crate::ReadError> {
+        match self.read().await {
+            Ok(t) => Ok(Some(t)),
+            Err(crate::ReadError::Eof) => Ok(None),
+            Err(e) => Err(e),
+        }
     }
 
     /// Writes a given value to the writer, serializing it into JSON.
@@ -129,8 +156,165 @@ impl<R: BufRead + Unpin, W: Write + Unpin> Connection<R, W> {
         crate::write(&mut self.writer, t).await
     }
 
+    /// Reads a line from the reader and deserializes it into a given type, also returning the
+    /// length of the line in bytes, including its newline.
+    pub async fn read_with_len<T: serde::de::DeserializeOwned>(
+        &mut self,
+    ) -> Result<(T, usize), crate::ReadError> {
+        match self.max_line_len {
+            Some(max_bytes) => crate::read_with_len_and_limit(&mut self.reader, max_bytes).await,
+            None => crate::read_with_len(&mut self.reader).await,
+        }
+    }
+
+    /// Writes a given value to the writer, serializing it into JSON, and returns the length of the
+    /// line written in bytes, including its newline.
+    pub async fn write_with_len<T: serde::Serialize>(
+        &mut self,
+        t: &T,
+    ) -> Result<usize, crate::WriteError> {
+        crate::write_with_len(&mut self.writer, t).await
+    }
+
+    /// Writes a given value to the writer in canonical form; see [`crate::write_canonical`].
+    pub async fn write_canonical<T: serde::Serialize>(
+        &mut self,
+        t: &T,
+    ) -> Result<(), crate::WriteError> {
+        crate::write_canonical(&mut self.writer, t).await
+    }
+
+    /// Writes a given value to the writer with a custom formatter; see
+    /// [`crate::write_with_formatter`].
+    pub async fn write_with_formatter<T, F>(
+        &mut self,
+        t: &T,
+        formatter: F,
+    ) -> Result<(), crate::WriteError>
+    where
+        T: serde::Serialize,
+        F: serde_json::ser::Formatter,
+    {
+        crate::write_with_formatter(&mut self.writer, t, formatter).await
+    }
+
+    /// Writes every value yielded by `values` to the writer, returning how many were written. This
+    /// is a fallible counterpart to [`Extend::extend`].
+    pub async fn write_all<I>(&mut self, values: I) -> Result<usize, crate::WriteError>
+    where
+        I: IntoIterator,
+        I::Item: serde::Serialize,
+    {
+        crate::write_all(&mut self.writer, values).await
+    }
+
     /// Flushes the contained writer’s buffer.
     pub async fn flush(&mut self) -> io::Result<()> {
         self.writer.flush().await
     }
+
+    /// Attempts to read a line from the reader and deserialize it into a given type, for use when
+    /// implementing [`Future`](std::future::Future) by hand.
+    ///
+    /// If the line is not yet complete, the part read so far is kept in the `Connection` and
+    /// `Poll::Pending` is returned; call this again when woken to continue reading it. Do not mix
+    /// this with [`Connection::read`] while a line is partway through being read.
+    pub fn poll_read<T: serde::de::DeserializeOwned>(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<T, crate::ReadError>> {
+        loop {
+            let available = match Pin::new(&mut self.reader).poll_fill_buf(cx) {
+                Poll::Ready(Ok(available)) => available,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(crate::ReadError::Io(e))),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            let (num_bytes, is_line_complete) = match available.iter().position(|b| *b == b'\n') {
+                Some(i) => (i + 1, true),
+                None => (available.len(), available.is_empty()),
+            };
+
+            self.poll
+                .read_buf
+                .extend_from_slice(&available[..num_bytes]);
+            Pin::new(&mut self.reader).consume(num_bytes);
+
+            if let Some(max_bytes) = self.max_line_len {
+                let read_buf = &self.poll.read_buf;
+
+                if read_buf.len() - usize::from(read_buf.ends_with(b"\n")) > max_bytes {
+                    self.poll.read_buf.clear();
+                    return Poll::Ready(Err(crate::ReadError::LineTooLong { max: max_bytes }));
+                }
+            }
+
+            if is_line_complete {
+                break;
+            }
+        }
+
+        let buf = std::mem::take(&mut self.poll.read_buf);
+
+        // An empty buffer means the reader reached EOF without a partial line to finish.
+        if buf.is_empty() {
+            return Poll::Ready(Err(crate::ReadError::Eof));
+        }
+
+        let line = String::from_utf8(buf)
+            .map_err(|e| crate::ReadError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+
+        #[cfg(feature = "log")]
+        crate::wire_log::inbound(&line);
+
+        Poll::Ready(serde_json::from_str(&line).map_err(crate::ReadError::Deserialize))
+    }
+
+    /// Attempts to write a given value to the writer, serializing it into JSON, for use when
+    /// implementing [`Future`](std::future::Future) by hand.
+    ///
+    /// The value is serialized on the first call, and as much of it written as the writer accepts.
+    /// If some of it is left, it is kept in the `Connection` and `Poll::Pending` is returned; call
+    /// this again when woken to continue writing it. `t` is ignored on these later calls, so pass
+    /// the same value until `Poll::Ready` is returned.
+    pub fn poll_write<T: serde::Serialize>(
+        &mut self,
+        cx: &mut Context<'_>,
+        t: &T,
+    ) -> Poll<Result<(), crate::WriteError>> {
+        if self.poll.write_buf.is_empty() {
+            self.poll.write_buf = crate::format::encode_line(t)?;
+            self.poll.write_pos = 0;
+        }
+
+        while self.poll.write_pos < self.poll.write_buf.len() {
+            let remaining = &self.poll.write_buf[self.poll.write_pos..];
+
+            match Pin::new(&mut self.writer).poll_write(cx, remaining) {
+                Poll::Ready(Ok(0)) => {
+                    self.poll.write_buf.clear();
+                    return Poll::Ready(Err(crate::WriteError::Io(
+                        io::ErrorKind::WriteZero.into(),
+                    )));
+                }
+                Poll::Ready(Ok(num_bytes)) => self.poll.write_pos += num_bytes,
+                Poll::Ready(Err(e)) => {
+                    self.poll.write_buf.clear();
+                    return Poll::Ready(Err(crate::WriteError::Io(e)));
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        self.poll.write_buf.clear();
+        self.poll.write_pos = 0;
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Attempts to flush the contained writer’s buffer, for use when implementing
+    /// [`Future`](std::future::Future) by hand.
+    pub fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.writer).poll_flush(cx)
+    }
 }
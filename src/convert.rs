@@ -0,0 +1,369 @@
+use crate::ReadError;
+#[cfg(feature = "encryption")]
+use crate::{FieldCipher, WriteError};
+use std::cell::Cell;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// How a JSON Lines file is compressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum Compression {
+    None,
+    #[cfg(feature = "gzip")]
+    Gzip,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Compression {
+    /// Guesses the compression of a file from its extension: `.gz` for gzip and `.zst` for
+    /// Zstandard, if the matching feature is enabled. Anything else is taken to be uncompressed.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        match path.as_ref().extension().and_then(|e| e.to_str()) {
+            #[cfg(feature = "gzip")]
+            Some("gz") => Self::Gzip,
+            #[cfg(feature = "zstd")]
+            Some("zst") => Self::Zstd,
+            _ => Self::None,
+        }
+    }
+
+    /// Returns what is left of `path` once the extension of this compression is removed: the whole
+    /// path if it is uncompressed, or its file stem otherwise.
+    pub(crate) fn strip_extension(self, path: &Path) -> Option<&OsStr> {
+        match self {
+            Self::None => Some(path.as_os_str()),
+            #[cfg(feature = "gzip")]
+            Self::Gzip => path.file_stem(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => path.file_stem(),
+        }
+    }
+
+    /// Wraps `reader` so that it reads decompressed data.
+    pub(crate) fn decoder<'a, R: Read + 'a>(self, reader: R) -> io::Result<Box<dyn BufRead + 'a>> {
+        Ok(match self {
+            Self::None => Box::new(BufReader::new(reader)),
+            #[cfg(feature = "gzip")]
+            Self::Gzip => Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(reader))),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => Box::new(BufReader::new(zstd::stream::read::Decoder::new(reader)?)),
+        })
+    }
+
+    fn encoder<W: Write>(self, writer: W) -> io::Result<Encoder<W>> {
+        Ok(match self {
+            Self::None => Encoder::None(writer),
+            #[cfg(feature = "gzip")]
+            Self::Gzip => Encoder::Gzip(flate2::write::GzEncoder::new(
+                writer,
+                flate2::Compression::default(),
+            )),
+            #[cfg(feature = "zstd")]
+            Self::Zstd => Encoder::Zstd(zstd::stream::write::Encoder::new(writer, 0)?),
+        })
+    }
+}
+
+enum Encoder<W: Write> {
+    None(W),
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::write::GzEncoder<W>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+}
+
+impl<W: Write> Encoder<W> {
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            Self::None(writer) => writer,
+            #[cfg(feature = "gzip")]
+            Self::Gzip(writer) => writer,
+            #[cfg(feature = "zstd")]
+            Self::Zstd(writer) => writer,
+        }
+    }
+
+    fn finish(self) -> io::Result<W> {
+        let mut writer = match self {
+            Self::None(writer) => writer,
+            #[cfg(feature = "gzip")]
+            Self::Gzip(writer) => writer.finish()?,
+            #[cfg(feature = "zstd")]
+            Self::Zstd(writer) => writer.finish()?,
+        };
+
+        writer.flush()?;
+        Ok(writer)
+    }
+}
+
+/// An error that occurred while converting with [`Convert`].
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum ConvertError {
+    #[error("failed opening or creating file")]
+    File(#[source] io::Error),
+    #[error("failed reading line {line}")]
+    Read {
+        line: u64,
+        #[source]
+        source: ReadError,
+    },
+    #[error("failed writing line {line}")]
+    Write {
+        line: u64,
+        #[source]
+        source: io::Error,
+    },
+    #[cfg(feature = "encryption")]
+    #[error("failed re-encrypting line {line}")]
+    Cipher {
+        line: u64,
+        #[source]
+        source: crate::FieldCipherError,
+    },
+}
+
+/// How far a conversion has got, as reported to [`Convert::on_progress`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct ConvertProgress {
+    /// The number of lines converted.
+    pub num_lines: u64,
+    /// The number of bytes read from the source, before decompression. Compare this with the
+    /// source’s size to estimate how much is left.
+    pub bytes_read: u64,
+}
+
+/// Converts JSON Lines between storage encodings, such as from `.jsonl.gz` to `.jsonl.zst`, in a
+/// single streaming pass.
+///
+/// Records are decompressed, optionally have their fields decrypted with one [`FieldCipher`] and
+/// encrypted with another, and recompressed, one line at a time, so files of any size can be
+/// converted in constant memory. Lines are copied byte-for-byte unless they are re-encrypted.
+pub struct Convert<'a> {
+    from: Compression,
+    to: Compression,
+    #[cfg(feature = "encryption")]
+    decrypt: Option<&'a FieldCipher>,
+    #[cfg(feature = "encryption")]
+    encrypt: Option<&'a FieldCipher>,
+    on_progress: Option<Box<dyn FnMut(ConvertProgress) + 'a>>,
+}
+
+impl<'a> Convert<'a> {
+    /// Creates a new `Convert` that converts from one compression to another.
+    pub fn new(from: Compression, to: Compression) -> Self {
+        Self {
+            from,
+            to,
+            #[cfg(feature = "encryption")]
+            decrypt: None,
+            #[cfg(feature = "encryption")]
+            encrypt: None,
+            on_progress: None,
+        }
+    }
+
+    /// Decrypts the fields selected by `cipher` in every record read.
+    #[cfg(feature = "encryption")]
+    pub fn decrypt_with(mut self, cipher: &'a FieldCipher) -> Self {
+        self.decrypt = Some(cipher);
+        self
+    }
+
+    /// Encrypts the fields selected by `cipher` in every record written.
+    #[cfg(feature = "encryption")]
+    pub fn encrypt_with(mut self, cipher: &'a FieldCipher) -> Self {
+        self.encrypt = Some(cipher);
+        self
+    }
+
+    /// Calls `f` with the progress so far after every line converted.
+    pub fn on_progress<F: FnMut(ConvertProgress) + 'a>(mut self, f: F) -> Self {
+        self.on_progress = Some(Box::new(f));
+        self
+    }
+
+    /// Converts the file at `from` into a new file at `to`, replacing it if it exists. The
+    /// compressions given to [`Convert::new`] are used, not those implied by the paths; see
+    /// [`Compression::from_path`].
+    ///
+    /// The output is written to a temporary file beside `to` and only renamed over it once the
+    /// conversion has succeeded, so `from` and `to` may be the same file, and a failed conversion
+    /// leaves `to` as it was.
+    pub fn files<P: AsRef<Path>, Q: AsRef<Path>>(
+        self,
+        from: P,
+        to: Q,
+    ) -> Result<ConvertProgress, ConvertError> {
+        let to = to.as_ref();
+        let dir = match to.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+
+        let reader = File::open(from).map_err(ConvertError::File)?;
+        let writer = tempfile::NamedTempFile::new_in(dir).map_err(ConvertError::File)?;
+
+        let progress = self.streams(reader, BufWriter::new(writer.as_file()))?;
+        writer
+            .persist(to)
+            .map_err(|e| ConvertError::File(e.error))?;
+
+        Ok(progress)
+    }
+
+    /// Converts everything read from `reader` and writes it to `writer`, returning the final
+    /// progress.
+    pub fn streams<R: Read, W: Write>(
+        mut self,
+        reader: R,
+        writer: W,
+    ) -> Result<ConvertProgress, ConvertError> {
+        let bytes_read = Cell::new(0);
+        let mut reader = self
+            .from
+            .decoder(CountingReader::new(reader, &bytes_read))
+            .map_err(|e| read_error(1, ReadError::Io(e)))?;
+        let mut writer = self
+            .to
+            .encoder(writer)
+            .map_err(|source| ConvertError::Write { line: 1, source })?;
+
+        let mut progress = ConvertProgress::default();
+        let mut line = String::new();
+
+        loop {
+            line.clear();
+            let line_number = progress.num_lines + 1;
+
+            if reader
+                .read_line(&mut line)
+                .map_err(|e| read_error(line_number, ReadError::Io(e)))?
+                == 0
+            {
+                break;
+            }
+
+            self.convert_line(&line, line_number, writer.writer())?;
+
+            progress.num_lines = line_number;
+            progress.bytes_read = bytes_read.get();
+
+            if let Some(on_progress) = &mut self.on_progress {
+                on_progress(progress);
+            }
+        }
+
+        writer.finish().map_err(|source| ConvertError::Write {
+            line: progress.num_lines,
+            source,
+        })?;
+
+        Ok(progress)
+    }
+
+    #[cfg(feature = "encryption")]
+    fn convert_line(
+        &self,
+        line: &str,
+        line_number: u64,
+        writer: &mut dyn Write,
+    ) -> Result<(), ConvertError> {
+        let is_blank = line
+            .trim_matches(|c: char| c.is_ascii_whitespace())
+            .is_empty();
+
+        if is_blank || (self.decrypt.is_none() && self.encrypt.is_none()) {
+            return copy_line(line, line_number, writer);
+        }
+
+        let cipher_error = |source| ConvertError::Cipher {
+            line: line_number,
+            source,
+        };
+
+        let mut record: serde_json::Value = serde_json::from_str(line)
+            .map_err(|e| read_error(line_number, ReadError::Deserialize(e)))?;
+
+        if let Some(cipher) = self.decrypt {
+            cipher.decrypt_fields(&mut record).map_err(cipher_error)?;
+        }
+
+        if let Some(cipher) = self.encrypt {
+            cipher.encrypt_fields(&mut record).map_err(cipher_error)?;
+        }
+
+        crate::blocking::write(writer, &record).map_err(|e| match e {
+            WriteError::Io(source) => ConvertError::Write {
+                line: line_number,
+                source,
+            },
+            WriteError::Serialize(e) => cipher_error(e.into()),
+        })
+    }
+
+    #[cfg(not(feature = "encryption"))]
+    fn convert_line(
+        &self,
+        line: &str,
+        line_number: u64,
+        writer: &mut dyn Write,
+    ) -> Result<(), ConvertError> {
+        copy_line(line, line_number, writer)
+    }
+}
+
+impl fmt::Debug for Convert<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Convert")
+            .field("from", &self.from)
+            .field("to", &self.to)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Copies a line unchanged, adding a newline if it was the last line and had none.
+fn copy_line(line: &str, line_number: u64, writer: &mut dyn Write) -> Result<(), ConvertError> {
+    let write_error = |source| ConvertError::Write {
+        line: line_number,
+        source,
+    };
+
+    writer.write_all(line.as_bytes()).map_err(write_error)?;
+
+    if !line.ends_with('\n') {
+        writer.write_all(b"\n").map_err(write_error)?;
+    }
+
+    Ok(())
+}
+
+fn read_error(line: u64, source: ReadError) -> ConvertError {
+    ConvertError::Read { line, source }
+}
+
+/// A reader that counts the bytes read through it.
+pub(crate) struct CountingReader<'a, R> {
+    inner: R,
+    count: &'a Cell<u64>,
+}
+
+impl<'a, R> CountingReader<'a, R> {
+    pub(crate) fn new(inner: R, count: &'a Cell<u64>) -> Self {
+        Self { inner, count }
+    }
+}
+
+impl<R: Read> Read for CountingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let num_read = self.inner.read(buf)?;
+        self.count.set(self.count.get() + num_read as u64);
+        Ok(num_read)
+    }
+}
@@ -0,0 +1,479 @@
+use crate::convert::CountingReader;
+use crate::{Compression, ReadError};
+use serde::de::DeserializeOwned;
+use std::cell::Cell;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+/// How many lines of each shard [`Dataset::estimate_len`] reads to estimate its length.
+const ESTIMATE_SAMPLE_LINES: usize = 1000;
+
+/// An error that occurred while reading a [`Dataset`].
+#[derive(Debug, thiserror::Error)]
+pub enum DatasetError {
+    #[error("failed listing dataset directory")]
+    List(#[source] io::Error),
+    #[error("failed opening shard {}", path.display())]
+    Open {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("failed reading line {line} of shard {}", path.display())]
+    Read {
+        path: PathBuf,
+        line: u64,
+        #[source]
+        source: ReadError,
+    },
+}
+
+/// Which records of a dataset a split keeps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Split {
+    seed: u64,
+    validation_fraction: f64,
+    validation: bool,
+}
+
+impl Split {
+    /// Decides which side of the split a record falls on from its position alone, so the decision
+    /// is the same whatever order the records are read in.
+    fn keeps(&self, shard: usize, line: u64) -> bool {
+        let hash = splitmix64(self.seed ^ splitmix64(((shard as u64) << 40) ^ line));
+        // The top 53 bits of the hash, as a fraction uniformly distributed in [0, 1).
+        let fraction = (hash >> 11) as f64 / (1u64 << 53) as f64;
+        let is_validation = fraction < self.validation_fraction;
+
+        is_validation == self.validation
+    }
+}
+
+/// A dataset of records of type `T`, stored as a directory of JSON Lines files (shards), which may
+/// be compressed.
+///
+/// Shards are read in order of file name, or in a random order with [`Dataset::shuffled`]. Blank
+/// lines are skipped.
+#[derive(Debug, Clone)]
+pub struct Dataset<T> {
+    shards: Vec<PathBuf>,
+    split: Option<Split>,
+    _record: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned> Dataset<T> {
+    /// Opens the dataset in the directory at `dir`, whose shards are the files named `*.jsonl` or
+    /// `*.ndjson`, optionally followed by the extension of a supported [`Compression`].
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, DatasetError> {
+        let mut shards = Vec::new();
+
+        for entry in fs::read_dir(dir).map_err(DatasetError::List)? {
+            let path = entry.map_err(DatasetError::List)?.path();
+
+            if path.is_file() && is_shard(&path) {
+                shards.push(path);
+            }
+        }
+
+        shards.sort();
+
+        Ok(Self::from_shards(shards))
+    }
+
+    /// Creates a dataset from the given shards, which are read in the order given.
+    pub fn from_shards<I: IntoIterator<Item = PathBuf>>(shards: I) -> Self {
+        Self {
+            shards: shards.into_iter().collect(),
+            split: None,
+            _record: PhantomData,
+        }
+    }
+
+    /// Returns the paths of the dataset’s shards.
+    pub fn shards(&self) -> &[PathBuf] {
+        &self.shards
+    }
+
+    /// Iterates over every record, shard by shard.
+    pub fn iter(&self) -> DatasetIter<T> {
+        DatasetIter::new(self, (0..self.shards.len()).collect())
+    }
+
+    /// Iterates over every record in an order that is random but the same for a given `seed`.
+    ///
+    /// Shards are visited in a shuffled order, and the records of each are shuffled among
+    /// themselves, so only one shard is held in memory at a time. Keep shards small enough to fit
+    /// in memory, and numerous enough that shard order mixes the dataset well.
+    #[cfg(feature = "rand")]
+    pub fn shuffled(&self, seed: u64) -> DatasetIter<T> {
+        use rand::seq::SliceRandom;
+        use rand::SeedableRng;
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        let mut order: Vec<_> = (0..self.shards.len()).collect();
+        order.shuffle(&mut rng);
+
+        let mut iter = DatasetIter::new(self, order);
+        iter.rng = Some(rng);
+        iter
+    }
+
+    /// Splits the dataset into a training set and a validation set holding about
+    /// `validation_fraction` of the records, chosen at random but the same for a given `seed`.
+    ///
+    /// Each record’s side of the split depends only on the seed and where it is stored, so the
+    /// two sets never overlap, however they are iterated.
+    pub fn split(&self, validation_fraction: f64, seed: u64) -> (Self, Self) {
+        let split = |validation| Self {
+            shards: self.shards.clone(),
+            split: Some(Split {
+                seed,
+                validation_fraction,
+                validation,
+            }),
+            _record: PhantomData,
+        };
+
+        (split(false), split(true))
+    }
+
+    /// Estimates the number of records without reading the whole dataset, by measuring how many
+    /// bytes of each shard on disk the first lines take up and scaling that to the shard’s size.
+    /// Shards small enough to be read completely are counted exactly.
+    pub fn estimate_len(&self) -> Result<u64, DatasetError> {
+        let mut estimate = 0.0;
+
+        for path in &self.shards {
+            let open_error = |source| DatasetError::Open {
+                path: path.clone(),
+                source,
+            };
+
+            let file = File::open(path).map_err(open_error)?;
+            let file_len = file.metadata().map_err(open_error)?.len();
+            let bytes_read = Cell::new(0);
+            let mut reader = Compression::from_path(path)
+                .decoder(CountingReader::new(file, &bytes_read))
+                .map_err(open_error)?;
+
+            let mut num_lines = 0;
+            let mut is_complete = false;
+            let mut line = String::new();
+
+            while num_lines < ESTIMATE_SAMPLE_LINES {
+                line.clear();
+
+                match reader.read_line(&mut line) {
+                    Ok(0) => {
+                        is_complete = true;
+                        break;
+                    }
+                    Ok(_) if line.trim().is_empty() => {}
+                    Ok(_) => num_lines += 1,
+                    Err(source) => {
+                        return Err(DatasetError::Read {
+                            path: path.clone(),
+                            line: num_lines as u64 + 1,
+                            source: ReadError::Io(source),
+                        })
+                    }
+                }
+            }
+
+            // The reader may have read ahead of the lines sampled, but only by a buffer’s worth,
+            // which is small next to the sample.
+            let shard_estimate = match bytes_read.get() {
+                0 => 0.0,
+                _ if is_complete => num_lines as f64,
+                sampled_len => num_lines as f64 * file_len as f64 / sampled_len as f64,
+            };
+
+            estimate += match self.split {
+                Some(split) if split.validation => shard_estimate * split.validation_fraction,
+                Some(split) => shard_estimate * (1.0 - split.validation_fraction),
+                None => shard_estimate,
+            };
+        }
+
+        Ok(estimate.round() as u64)
+    }
+}
+
+impl<T: DeserializeOwned> IntoIterator for &Dataset<T> {
+    type Item = Result<T, DatasetError>;
+    type IntoIter = DatasetIter<T>;
+
+    fn into_iter(self) -> DatasetIter<T> {
+        self.iter()
+    }
+}
+
+/// An iterator over the records of a [`Dataset`], created by [`Dataset::iter`] or
+/// [`Dataset::shuffled`].
+pub struct DatasetIter<T> {
+    shards: Vec<PathBuf>,
+    split: Option<Split>,
+    order: std::vec::IntoIter<usize>,
+    current: Option<(usize, Box<dyn BufRead>)>,
+    line_number: u64,
+    line: String,
+    #[cfg(feature = "rand")]
+    rng: Option<rand::rngs::StdRng>,
+    #[cfg(feature = "rand")]
+    shuffled: Vec<T>,
+    _record: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned> DatasetIter<T> {
+    fn new(dataset: &Dataset<T>, order: Vec<usize>) -> Self {
+        Self {
+            shards: dataset.shards.clone(),
+            split: dataset.split,
+            order: order.into_iter(),
+            current: None,
+            line_number: 0,
+            line: String::new(),
+            #[cfg(feature = "rand")]
+            rng: None,
+            #[cfg(feature = "rand")]
+            shuffled: Vec::new(),
+            _record: PhantomData,
+        }
+    }
+
+    /// Opens the next shard to be read, returning `None` if there are none left.
+    fn open_next_shard(&mut self) -> Option<Result<(), DatasetError>> {
+        let shard = self.order.next()?;
+        let path = self.shards.get(shard)?;
+
+        match File::open(path).and_then(|file| Compression::from_path(path).decoder(file)) {
+            Ok(reader) => {
+                self.current = Some((shard, reader));
+                self.line_number = 0;
+                Some(Ok(()))
+            }
+            Err(source) => Some(Err(DatasetError::Open {
+                path: path.clone(),
+                source,
+            })),
+        }
+    }
+
+    /// Reads the next record kept by the split from the current shard, returning `None` once it
+    /// runs out.
+    fn read_current(&mut self) -> Option<Result<T, DatasetError>> {
+        let (shard, reader) = self.current.as_mut()?;
+        let shard = *shard;
+
+        loop {
+            self.line.clear();
+            self.line_number += 1;
+
+            let shards = &self.shards;
+            let line_number = self.line_number;
+            let read_error = |source| DatasetError::Read {
+                path: shards.get(shard).cloned().unwrap_or_default(),
+                line: line_number,
+                source,
+            };
+
+            match reader.read_line(&mut self.line) {
+                Ok(0) => {
+                    self.current = None;
+                    return None;
+                }
+                Ok(_) => {}
+                Err(e) => return Some(Err(read_error(ReadError::Io(e)))),
+            }
+
+            if self.line.trim().is_empty() {
+                continue;
+            }
+
+            if let Some(split) = &self.split {
+                if !split.keeps(shard, self.line_number) {
+                    continue;
+                }
+            }
+
+            return Some(
+                serde_json::from_str(&self.line).map_err(|e| read_error(ReadError::Deserialize(e))),
+            );
+        }
+    }
+
+    fn next_in_order(&mut self) -> Option<Result<T, DatasetError>> {
+        loop {
+            if self.current.is_none() {
+                if let Err(e) = self.open_next_shard()? {
+                    return Some(Err(e));
+                }
+            }
+
+            if let Some(result) = self.read_current() {
+                return Some(result);
+            }
+        }
+    }
+}
+
+impl<T: DeserializeOwned> Iterator for DatasetIter<T> {
+    type Item = Result<T, DatasetError>;
+
+    #[cfg(not(feature = "rand"))]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_in_order()
+    }
+
+    #[cfg(feature = "rand")]
+    fn next(&mut self) -> Option<Self::Item> {
+        use rand::seq::SliceRandom;
+
+        if self.rng.is_none() {
+            return self.next_in_order();
+        }
+
+        // Once the records loaded from one shard have all been yielded, the next is loaded whole
+        // and shuffled.
+        while self.shuffled.is_empty() {
+            if let Err(e) = self.open_next_shard()? {
+                return Some(Err(e));
+            }
+
+            while let Some(result) = self.read_current() {
+                match result {
+                    Ok(record) => self.shuffled.push(record),
+                    Err(e) => {
+                        self.current = None;
+                        return Some(Err(e));
+                    }
+                }
+            }
+
+            if let Some(rng) = &mut self.rng {
+                self.shuffled.shuffle(rng);
+            }
+        }
+
+        self.shuffled.pop().map(Ok)
+    }
+}
+
+impl<T> fmt::Debug for DatasetIter<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DatasetIter")
+            .field("shards", &self.shards)
+            .field("line_number", &self.line_number)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Returns whether `path` is named like a shard: `*.jsonl` or `*.ndjson`, followed by the
+/// extension of a supported compression if it is compressed.
+fn is_shard(path: &Path) -> bool {
+    let stem = Compression::from_path(path).strip_extension(path);
+
+    let extension = stem.and_then(|stem| Path::new(stem).extension());
+
+    matches!(extension.and_then(|e| e.to_str()), Some("jsonl" | "ndjson"))
+}
+
+/// The SplitMix64 finalizer, which scrambles an integer into a well-distributed hash.
+pub(crate) fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// An iterator that samples from several sources in proportion to their weights, created by
+/// [`interleave`].
+#[cfg(feature = "rand")]
+#[derive(Debug)]
+pub struct Interleave<I> {
+    sources: Vec<(I, f64)>,
+    rng: rand::rngs::StdRng,
+}
+
+/// Mixes several sources of records, such as [`DatasetIter`]s, into a single stream, taking each
+/// record from a source chosen at random in proportion to its weight. The order is the same for a
+/// given `seed`.
+///
+/// Weights are relative, so `[3.0, 1.0]` takes three records from the first source for every one
+/// from the second. When a source runs out, the rest carry on in proportion to their weights, and
+/// the stream ends when all have run out. Sources with a weight that is not positive, or with no
+/// weight at all, are never read from.
+#[cfg(feature = "rand")]
+pub fn interleave<S, W>(sources: S, weights: W, seed: u64) -> Interleave<S::Item>
+where
+    S: IntoIterator,
+    S::Item: Iterator,
+    W: IntoIterator<Item = f64>,
+{
+    use rand::SeedableRng;
+
+    let mut sources: Vec<_> = sources
+        .into_iter()
+        .zip(weights)
+        .filter(|(_, weight)| *weight > 0.0 && weight.is_finite())
+        .collect();
+
+    // Scale the weights so that the largest is one, so that their sum cannot overflow to infinity
+    // however large they are. A weight so much smaller than the largest that it scales to zero
+    // would never have been chosen anyway.
+    let max_weight = sources
+        .iter()
+        .map(|(_, weight)| *weight)
+        .fold(0.0, f64::max);
+    for (_, weight) in &mut sources {
+        *weight /= max_weight;
+    }
+    sources.retain(|(_, weight)| *weight > 0.0);
+
+    Interleave {
+        sources,
+        rng: rand::rngs::StdRng::seed_from_u64(seed),
+    }
+}
+
+#[cfg(feature = "rand")]
+impl<I: Iterator> Iterator for Interleave<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        use rand::Rng;
+
+        loop {
+            if self.sources.is_empty() {
+                return None;
+            }
+
+            let total: f64 = self.sources.iter().map(|(_, weight)| weight).sum();
+
+            let mut choice = self.rng.gen_range(0.0..total);
+            let i = self
+                .sources
+                .iter()
+                .position(|(_, weight)| {
+                    choice -= weight;
+                    choice < 0.0
+                })
+                .unwrap_or(self.sources.len() - 1);
+
+            let next = self
+                .sources
+                .get_mut(i)
+                .and_then(|(source, _)| source.next());
+
+            match next {
+                Some(item) => return Some(item),
+                None => {
+                    self.sources.remove(i);
+                }
+            }
+        }
+    }
+}
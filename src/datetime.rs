@@ -0,0 +1,305 @@
+use crate::{ReadError, WriteError};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+/// Epoch timestamps at or above this magnitude are taken to be in milliseconds by
+/// [`EpochUnit::Auto`]. In seconds it is the year 5138; in milliseconds, March 1973.
+const AUTO_MILLIS_THRESHOLD: f64 = 100_000_000_000.0;
+
+/// The number of seconds from the Unix epoch to the end of the year 9999, the last that RFC 3339
+/// can represent.
+const MAX_SECS: f64 = 253_402_300_800.0;
+
+/// An error that occurred while normalizing timestamps with [`TimestampNormalizer`].
+#[derive(Debug, thiserror::Error)]
+pub enum TimestampError {
+    #[error("failed reading record")]
+    Read(#[from] ReadError),
+    #[error("failed writing record")]
+    Write(#[from] WriteError),
+    #[error("failed converting record to or from JSON")]
+    Json(#[from] serde_json::Error),
+    #[error("the field at {0} is not a recognized timestamp")]
+    Invalid(String),
+}
+
+/// The unit of numeric timestamps read by [`TimestampNormalizer`], counted from the Unix epoch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum EpochUnit {
+    Seconds,
+    Milliseconds,
+    /// Guesses from the magnitude of each timestamp, taking anything that would be after the year
+    /// 5000 in seconds to be in milliseconds.
+    #[default]
+    Auto,
+}
+
+/// Rewrites selected timestamp fields of records as RFC 3339 timestamps in UTC, such as
+/// `2024-05-01T12:00:00Z`, whatever format they arrived in.
+///
+/// Fields are selected by [JSON pointer](https://www.rfc-editor.org/rfc/rfc6901). RFC 3339
+/// timestamps with any offset are converted to UTC, and numbers are read as seconds or
+/// milliseconds since the Unix epoch according to [`TimestampNormalizer::epoch_unit`]. Fractional
+/// seconds are kept, with trailing zeros removed. Fields that are missing or `null` are skipped,
+/// while anything else fails with [`TimestampError::Invalid`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TimestampNormalizer {
+    pointers: Vec<String>,
+    epoch_unit: EpochUnit,
+}
+
+impl TimestampNormalizer {
+    /// Creates a new `TimestampNormalizer` for the fields at `pointers`.
+    pub fn new<I, S>(pointers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            pointers: pointers.into_iter().map(Into::into).collect(),
+            epoch_unit: EpochUnit::default(),
+        }
+    }
+
+    /// Sets the unit numeric timestamps are in.
+    pub fn epoch_unit(mut self, unit: EpochUnit) -> Self {
+        self.epoch_unit = unit;
+        self
+    }
+
+    /// Normalizes the selected fields of a record in place.
+    pub fn normalize_fields(&self, record: &mut Value) -> Result<(), TimestampError> {
+        for pointer in &self.pointers {
+            let field = match record.pointer_mut(pointer) {
+                Some(Value::Null) | None => continue,
+                Some(field) => field,
+            };
+
+            let (secs, nanos) = match &*field {
+                Value::String(s) => parse_rfc3339(s),
+                Value::Number(n) => n.as_f64().and_then(|n| self.split_epoch(n)),
+                _ => None,
+            }
+            .ok_or_else(|| TimestampError::Invalid(pointer.clone()))?;
+
+            *field = Value::String(format_rfc3339(secs, nanos));
+        }
+
+        Ok(())
+    }
+
+    /// Splits a numeric timestamp into whole seconds and nanoseconds since the epoch.
+    fn split_epoch(&self, n: f64) -> Option<(i64, u32)> {
+        let is_millis = match self.epoch_unit {
+            EpochUnit::Seconds => false,
+            EpochUnit::Milliseconds => true,
+            EpochUnit::Auto => n.abs() >= AUTO_MILLIS_THRESHOLD,
+        };
+        let secs = if is_millis { n / 1000.0 } else { n };
+
+        if !secs.is_finite() || secs.abs() >= MAX_SECS {
+            return None;
+        }
+
+        let whole = secs.floor();
+        // Rounded to the microsecond, since that is all the precision an f64 has left for
+        // present-day timestamps.
+        let micros = ((secs - whole) * 1_000_000.0).round() as u32;
+
+        Some((whole as i64, micros.min(999_999) * 1000))
+    }
+
+    fn normalize_value<T: serde::Serialize>(&self, t: &T) -> Result<Value, TimestampError> {
+        let mut record = serde_json::to_value(t)?;
+        self.normalize_fields(&mut record)?;
+        Ok(record)
+    }
+
+    fn deserialize_normalized<T: DeserializeOwned>(
+        &self,
+        mut record: Value,
+    ) -> Result<T, TimestampError> {
+        self.normalize_fields(&mut record)?;
+        Ok(serde_json::from_value(record)?)
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl TimestampNormalizer {
+    /// Reads a line from the reader, normalizes its selected fields and deserializes it into a
+    /// given type.
+    pub fn read<R: std::io::BufRead, T: DeserializeOwned>(
+        &self,
+        reader: R,
+    ) -> Result<T, TimestampError> {
+        let record: Value = crate::read(reader)?;
+        self.deserialize_normalized(record)
+    }
+
+    /// Serializes a given value, normalizes its selected fields and writes it to the writer.
+    pub fn write<W: std::io::Write, T: serde::Serialize>(
+        &self,
+        writer: W,
+        t: &T,
+    ) -> Result<(), TimestampError> {
+        let record = self.normalize_value(t)?;
+        Ok(crate::write(writer, &record)?)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl TimestampNormalizer {
+    /// Reads a line from the reader, normalizes its selected fields and deserializes it into a
+    /// given type.
+    pub async fn read<R: tokio::io::AsyncBufRead + Unpin, T: DeserializeOwned>(
+        &self,
+        reader: R,
+    ) -> Result<T, TimestampError> {
+        let record: Value = crate::read(reader).await?;
+        self.deserialize_normalized(record)
+    }
+
+    /// Serializes a given value, normalizes its selected fields and writes it to the writer.
+    pub async fn write<W: tokio::io::AsyncWrite + Unpin, T: serde::Serialize>(
+        &self,
+        writer: W,
+        t: &T,
+    ) -> Result<(), TimestampError> {
+        let record = self.normalize_value(t)?;
+        Ok(crate::write(writer, &record).await?)
+    }
+}
+
+/// Formats a time given in seconds and nanoseconds since the Unix epoch as an RFC 3339 timestamp in
+/// UTC, with as many fractional digits as needed in groups of three.
+pub(crate) fn format_rfc3339(secs: i64, nanos: u32) -> String {
+    let (days, secs_of_day) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+    let (year, month, day) = civil_from_days(days);
+
+    let mut formatted = format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60,
+    );
+
+    if nanos != 0 {
+        let fraction = format!("{:09}", nanos);
+        let num_digits = fraction.trim_end_matches('0').len().div_ceil(3) * 3;
+        formatted.push('.');
+        formatted.push_str(fraction.get(..num_digits).unwrap_or(&fraction));
+    }
+
+    formatted.push('Z');
+    formatted
+}
+
+/// Parses an RFC 3339 timestamp with any offset, returning the seconds and nanoseconds since the
+/// Unix epoch.
+pub(crate) fn parse_rfc3339(s: &str) -> Option<(i64, u32)> {
+    let digits = |range: std::ops::Range<usize>| -> Option<i64> {
+        let digits = s.get(range)?;
+        if digits.bytes().all(|b| b.is_ascii_digit()) {
+            digits.parse().ok()
+        } else {
+            None
+        }
+    };
+    let byte = |i: usize| s.as_bytes().get(i).copied();
+
+    if byte(4)? != b'-' || byte(7)? != b'-' || !matches!(byte(10)?, b'T' | b't' | b' ') {
+        return None;
+    }
+    if byte(13)? != b':' || byte(16)? != b':' {
+        return None;
+    }
+
+    let (year, month, day) = (digits(0..4)?, digits(5..7)?, digits(8..10)?);
+    let (hour, minute, second) = (digits(11..13)?, digits(14..16)?, digits(17..19)?);
+
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+    // A leap second is folded into the second before it.
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    let mut rest = s.get(19..)?;
+    let mut nanos = 0;
+
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let num_digits = fraction.bytes().take_while(u8::is_ascii_digit).count();
+        if num_digits == 0 {
+            return None;
+        }
+
+        for (i, digit) in fraction.bytes().take(num_digits.min(9)).enumerate() {
+            nanos += u32::from(digit - b'0') * 10u32.pow(8 - i as u32);
+        }
+        rest = fraction.get(num_digits..)?;
+    }
+
+    let offset = match rest.as_bytes() {
+        [b'Z'] | [b'z'] => 0,
+        [sign @ (b'+' | b'-'), h1, h2, b':', m1, m2]
+            if [h1, h2, m1, m2].iter().all(|b| b.is_ascii_digit()) =>
+        {
+            let hours = i64::from((h1 - b'0') * 10 + (h2 - b'0'));
+            let minutes = i64::from((m1 - b'0') * 10 + (m2 - b'0'));
+            let offset = hours * 3600 + minutes * 60;
+
+            if *sign == b'+' {
+                offset
+            } else {
+                -offset
+            }
+        }
+        _ => return None,
+    };
+
+    let days = days_from_civil(year, month, day);
+    let secs = days * 86_400 + hour * 3600 + minute * 60 + second.min(59) - offset;
+
+    Some((secs, nanos))
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// The conversions between days since the epoch and civil dates are Howard Hinnant’s
+// (https://howardhinnant.github.io/date_algorithms.html).
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
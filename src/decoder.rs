@@ -0,0 +1,727 @@
+use crate::WriteError;
+#[cfg(feature = "bytes")]
+use bytes::{Buf, BufMut, BytesMut};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::convert::TryFrom;
+
+/// The default for [`Decoder::max_line_len`].
+const DEFAULT_MAX_LINE_LEN: usize = 16 * 1024 * 1024;
+
+/// The default for [`Decoder::max_depth`], which matches the recursion limit of `serde_json`.
+const DEFAULT_MAX_DEPTH: usize = 128;
+
+/// An error that occurred while decoding a line with [`Decoder`].
+///
+/// After any of these errors the decoder carries on with the next line, so one bad line does not
+/// prevent the rest of the input from being read.
+#[derive(Debug, thiserror::Error)]
+pub enum DecodeError {
+    #[error("line of at least {len} bytes is longer than the maximum of {max}")]
+    LineTooLong { len: usize, max: usize },
+    #[error("line nests arrays and objects more than {max} levels deep")]
+    TooDeep { max: usize },
+    #[error(
+        "line contains a Unicode line or paragraph separator outside a string at offset {offset}"
+    )]
+    UnicodeSeparator { offset: usize },
+    #[error("line contains a NUL byte at offset {offset}")]
+    NulByte { offset: usize },
+    #[error("line contains the raw control character {byte:#04x} at offset {offset}")]
+    ControlCharacter { offset: usize, byte: u8 },
+    #[error("line is not valid UTF-8")]
+    InvalidUtf8(#[from] std::str::Utf8Error),
+    #[error("failed deserializing JSON")]
+    Deserialize(#[from] serde_json::Error),
+}
+
+/// What [`Decoder`] does with the Unicode line separator (U+2028) and paragraph separator (U+2029)
+/// when they appear outside a string.
+///
+/// Only `\n` ends a line, so these never split a record, and inside strings they are ordinary
+/// characters. Some producers, though, emit them between records, where they are not JSON
+/// whitespace and so would otherwise fail deserialization with an unhelpful error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum UnicodeSeparators {
+    /// Fails the line with [`DecodeError::UnicodeSeparator`].
+    #[default]
+    Reject,
+    /// Treats them as whitespace, so a line holding nothing else is skipped as blank.
+    Ignore,
+}
+
+/// What [`Decoder`] does with raw control characters (U+0000 to U+001F) on a line, other than the
+/// tabs and carriage returns that JSON allows as whitespace between values.
+///
+/// JSON only allows control characters inside strings in escaped form, so any that appear raw come
+/// from a malformed producer. Whatever the policy, one straight after a backslash in a string fails
+/// the line with [`DecodeError::ControlCharacter`], since it cannot be removed or escaped without
+/// changing what the string holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum ControlCharacters {
+    /// Fails the line with [`DecodeError::ControlCharacter`], or [`DecodeError::NulByte`] for
+    /// NUL.
+    #[default]
+    Reject,
+    /// Removes them before deserializing.
+    Strip,
+    /// Escapes them as `\u00XX` inside strings, keeping what they encode, and removes them
+    /// elsewhere.
+    Escape,
+}
+
+/// A decoder for JSON Lines that does no IO of its own, for use with any source of bytes: feed it
+/// bytes as they arrive with [`Decoder::feed_bytes`], and take decoded values out with
+/// [`Decoder::decode`].
+///
+/// `Decoder` is built to be safe on untrusted input, and is a convenient entry point for fuzzing.
+/// None of its methods panic, whatever bytes it is fed. Memory use is bounded by the maximum line
+/// length: the rest of a line that is too long is discarded as it arrives rather than buffered.
+/// Lines are checked for nesting depth and control characters before being deserialized, and blank
+/// lines are skipped.
+///
+/// For the sending side, [`encode_into`] serializes lines into a buffer the caller owns.
+#[derive(Debug, Clone)]
+pub struct Decoder {
+    buf: Vec<u8>,
+    scanned: usize,
+    /// The length of the incomplete line at the end of `buf`.
+    partial_len: usize,
+    discarding: bool,
+    max_line_len: usize,
+    max_depth: usize,
+    unicode_separators: UnicodeSeparators,
+    control_characters: ControlCharacters,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder {
+    /// Creates a new `Decoder` with the default limits: lines of up to 16 MiB, nested up to 128
+    /// levels deep.
+    pub fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            scanned: 0,
+            partial_len: 0,
+            discarding: false,
+            max_line_len: DEFAULT_MAX_LINE_LEN,
+            max_depth: DEFAULT_MAX_DEPTH,
+            unicode_separators: UnicodeSeparators::default(),
+            control_characters: ControlCharacters::default(),
+        }
+    }
+
+    /// Sets the maximum length of a line in bytes, not including its newline.
+    pub fn max_line_len(mut self, max_line_len: usize) -> Self {
+        self.max_line_len = max_line_len;
+        self
+    }
+
+    /// Sets how many levels deep arrays and objects may be nested.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Sets what is done with Unicode line and paragraph separators outside strings. They are
+    /// rejected by default.
+    ///
+    /// ```
+    /// use jsonl::{Decoder, UnicodeSeparators};
+    /// use serde_json::{json, Value};
+    ///
+    /// let input = "{\"text\":\"a\u{2028}b\"}\u{2028}\n\u{2029}\n";
+    ///
+    /// let mut decoder = Decoder::new();
+    /// decoder.feed_bytes(input.as_bytes());
+    /// assert!(decoder.decode::<Value>().is_some_and(|result| result.is_err()));
+    ///
+    /// let mut decoder = Decoder::new().unicode_separators(UnicodeSeparators::Ignore);
+    /// decoder.feed_bytes(input.as_bytes());
+    /// let value = decoder.decode::<Value>().transpose()?;
+    /// assert_eq!(value, Some(json!({ "text": "a\u{2028}b" })));
+    /// assert!(decoder.decode::<Value>().is_none());
+    /// # Ok::<_, jsonl::DecodeError>(())
+    /// ```
+    pub fn unicode_separators(mut self, unicode_separators: UnicodeSeparators) -> Self {
+        self.unicode_separators = unicode_separators;
+        self
+    }
+
+    /// Sets what is done with raw control characters. They are rejected by default.
+    pub fn control_characters(mut self, control_characters: ControlCharacters) -> Self {
+        self.control_characters = control_characters;
+        self
+    }
+
+    /// Adds bytes to the end of the input.
+    ///
+    /// Once a line grows past the maximum length, the rest of it is dropped rather than buffered,
+    /// and [`Decoder::decode`] fails it with [`DecodeError::LineTooLong`].
+    pub fn feed_bytes(&mut self, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            if self.discarding {
+                match bytes.iter().position(|b| *b == b'\n') {
+                    Some(i) => {
+                        self.discarding = false;
+                        bytes = bytes.get(i + 1..).unwrap_or_default();
+                        continue;
+                    }
+                    None => return,
+                }
+            }
+
+            let (len, ends_line) = match bytes.iter().position(|b| *b == b'\n') {
+                Some(i) => (i, true),
+                None => (bytes.len(), false),
+            };
+
+            if self.partial_len.saturating_add(len) > self.max_line_len {
+                // Keep one byte more than the maximum and end the line there, so that decoding it
+                // fails as too long, then drop everything up to the real end of the line.
+                let kept = self
+                    .max_line_len
+                    .saturating_add(1)
+                    .saturating_sub(self.partial_len);
+                self.buf
+                    .extend_from_slice(bytes.get(..kept).unwrap_or_default());
+                self.buf.push(b'\n');
+                self.partial_len = 0;
+                self.discarding = true;
+                bytes = bytes.get(len..).unwrap_or_default();
+                continue;
+            }
+
+            if ends_line {
+                self.buf
+                    .extend_from_slice(bytes.get(..=len).unwrap_or_default());
+                self.partial_len = 0;
+                bytes = bytes.get(len + 1..).unwrap_or_default();
+            } else {
+                self.buf.extend_from_slice(bytes);
+                self.partial_len += len;
+                bytes = &[];
+            }
+        }
+    }
+
+    /// Returns the number of bytes buffered that have not yet been decoded.
+    pub fn buffered_len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Decodes the next complete line, or returns `None` if there is no complete line buffered.
+    pub fn decode<T: DeserializeOwned>(&mut self) -> Option<Result<T, DecodeError>> {
+        loop {
+            let unscanned = self.buf.get(self.scanned..).unwrap_or_default();
+
+            let end = match unscanned.iter().position(|b| *b == b'\n') {
+                Some(i) => self.scanned + i,
+                None => {
+                    self.scanned = self.buf.len();
+
+                    if self.buf.len() > self.max_line_len {
+                        let len = self.buf.len();
+                        self.buf.clear();
+                        self.scanned = 0;
+                        self.partial_len = 0;
+                        self.discarding = true;
+
+                        return Some(Err(DecodeError::LineTooLong {
+                            len,
+                            max: self.max_line_len,
+                        }));
+                    }
+
+                    return None;
+                }
+            };
+
+            let line: Vec<u8> = self.buf.drain(..=end).collect();
+            self.scanned = 0;
+
+            let line = line.get(..end).unwrap_or_default();
+
+            if let Some(result) = self.decode_line(line) {
+                return Some(result);
+            }
+        }
+    }
+
+    /// Decodes whatever is left in the buffer once the input has ended, in case the last line has
+    /// no trailing newline. Returns `None` if there is nothing left to decode.
+    pub fn finish<T: DeserializeOwned>(&mut self) -> Option<Result<T, DecodeError>> {
+        let line = std::mem::take(&mut self.buf);
+        self.scanned = 0;
+        self.partial_len = 0;
+
+        if self.discarding {
+            self.discarding = false;
+            return None;
+        }
+
+        self.decode_line(&line)
+    }
+
+    /// Decodes the next complete line straight out of `src`, removing it, or returns `None` if `src`
+    /// holds no complete line.
+    ///
+    /// Unlike [`Decoder::decode`], the bytes are never copied into the decoder’s own buffer, which
+    /// suits network stacks built on [`bytes`], such as Tokio codecs and `quinn`. Incomplete lines
+    /// are left in `src` for the caller to add to. Do not mix this with [`Decoder::feed_bytes`] on
+    /// one decoder, and keep passing the same buffer, since the decoder remembers how much of it has
+    /// already been searched for a newline.
+    #[cfg(feature = "bytes")]
+    pub fn decode_from<T: DeserializeOwned>(
+        &mut self,
+        src: &mut BytesMut,
+    ) -> Option<Result<T, DecodeError>> {
+        loop {
+            if self.discarding {
+                match src.iter().position(|b| *b == b'\n') {
+                    Some(i) => {
+                        src.advance(i + 1);
+                        self.discarding = false;
+                    }
+                    None => {
+                        src.clear();
+                        return None;
+                    }
+                }
+            }
+
+            let start = self.scanned.min(src.len());
+            let unscanned = src.get(start..).unwrap_or_default();
+
+            let end = match unscanned.iter().position(|b| *b == b'\n') {
+                Some(i) => start + i,
+                None => {
+                    self.scanned = src.len();
+
+                    if src.len() > self.max_line_len {
+                        let len = src.len();
+                        src.clear();
+                        self.scanned = 0;
+                        self.discarding = true;
+
+                        return Some(Err(DecodeError::LineTooLong {
+                            len,
+                            max: self.max_line_len,
+                        }));
+                    }
+
+                    return None;
+                }
+            };
+
+            let line = src.split_to(end + 1);
+            self.scanned = 0;
+
+            if let Some(result) = self.decode_line(line.get(..end).unwrap_or_default()) {
+                return Some(result);
+            }
+        }
+    }
+
+    /// Checks and deserializes a single line without its newline, returning `None` if it is blank.
+    fn decode_line<T: DeserializeOwned>(&self, line: &[u8]) -> Option<Result<T, DecodeError>> {
+        let line = match self.check_line(line) {
+            Ok(line) => line,
+            Err(e) => return Some(Err(e)),
+        };
+
+        if line
+            .trim_matches(|c: char| c.is_ascii_whitespace())
+            .is_empty()
+        {
+            return None;
+        }
+
+        Some(serde_json::from_str(&line).map_err(DecodeError::Deserialize))
+    }
+
+    fn check_line<'a>(&self, line: &'a [u8]) -> Result<Cow<'a, str>, DecodeError> {
+        if line.len() > self.max_line_len {
+            return Err(DecodeError::LineTooLong {
+                len: line.len(),
+                max: self.max_line_len,
+            });
+        }
+
+        if self.control_characters == ControlCharacters::Reject {
+            if let Some(offset) = line.iter().position(|b| *b == 0) {
+                return Err(DecodeError::NulByte { offset });
+            }
+        }
+
+        let line = std::str::from_utf8(line)?;
+
+        let mut depth: usize = 0;
+        let mut in_string = false;
+        let mut escaped = false;
+        // Replacements to make before deserializing, as the offset and length of what to replace,
+        // in order.
+        let mut edits: Vec<(usize, usize, String)> = Vec::new();
+
+        for (offset, c) in line.char_indices() {
+            let b = match u8::try_from(c) {
+                Ok(b) => b,
+                Err(_) if in_string => continue,
+                Err(_) => {
+                    if c == '\u{2028}' || c == '\u{2029}' {
+                        match self.unicode_separators {
+                            UnicodeSeparators::Reject => {
+                                return Err(DecodeError::UnicodeSeparator { offset })
+                            }
+                            UnicodeSeparators::Ignore => {
+                                edits.push((offset, c.len_utf8(), " ".to_string()))
+                            }
+                        }
+                    }
+                    continue;
+                }
+            };
+
+            let is_whitespace = !in_string && (b == b'\t' || b == b'\r');
+
+            if b < 0x20 && !is_whitespace {
+                // A backslash cannot escape a control character, and removing or escaping the
+                // character would leave the backslash escaping whatever comes next instead.
+                if escaped {
+                    return Err(DecodeError::ControlCharacter { offset, byte: b });
+                }
+
+                match self.control_characters {
+                    ControlCharacters::Reject => {
+                        return Err(DecodeError::ControlCharacter { offset, byte: b })
+                    }
+                    ControlCharacters::Escape if in_string => {
+                        edits.push((offset, 1, format!("\\u{:04x}", b)))
+                    }
+                    ControlCharacters::Strip | ControlCharacters::Escape => {
+                        edits.push((offset, 1, String::new()))
+                    }
+                }
+                continue;
+            }
+
+            if in_string {
+                match b {
+                    _ if escaped => escaped = false,
+                    b'\\' => escaped = true,
+                    b'"' => in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+
+            match b {
+                b'"' => in_string = true,
+                b'[' | b'{' => {
+                    depth = depth.saturating_add(1);
+
+                    if depth > self.max_depth {
+                        return Err(DecodeError::TooDeep {
+                            max: self.max_depth,
+                        });
+                    }
+                }
+                b']' | b'}' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+
+        if edits.is_empty() {
+            return Ok(Cow::Borrowed(line));
+        }
+
+        let mut edited = String::with_capacity(line.len());
+        let mut copied = 0;
+
+        for (offset, len, replacement) in edits {
+            edited.push_str(line.get(copied..offset).unwrap_or_default());
+            edited.push_str(&replacement);
+            copied = offset + len;
+        }
+
+        edited.push_str(line.get(copied..).unwrap_or_default());
+
+        Ok(Cow::Owned(edited))
+    }
+}
+
+/// Serializes `t` into JSON and appends it to `dst` as a line, without an intermediate buffer.
+///
+/// Nothing is allocated beyond what `dst` needs to grow, so clearing and reusing one buffer
+/// across messages avoids allocating at all once it is large enough. If serialization fails,
+/// `dst` is left as it was.
+///
+/// ```
+/// # fn main() -> Result<(), jsonl::WriteError> {
+/// let mut buf = Vec::new();
+///
+/// for n in 1..=3 {
+///     jsonl::encode_into(&n, &mut buf)?;
+/// }
+///
+/// assert_eq!(buf, b"1\n2\n3\n");
+/// # Ok(())
+/// # }
+/// ```
+pub fn encode_into<T: Serialize>(t: &T, dst: &mut Vec<u8>) -> Result<(), WriteError> {
+    let len = dst.len();
+
+    if let Err(e) = serde_json::to_writer(&mut *dst, t) {
+        dst.truncate(len);
+        return Err(WriteError::Serialize(e));
+    }
+
+    dst.push(b'\n');
+
+    Ok(())
+}
+
+/// Serializes `t` into JSON and appends it to `dst` as a line, without an intermediate buffer, as
+/// with [`encode_into`].
+#[cfg(feature = "bytes")]
+pub fn encode_into_bytes<T: Serialize>(t: &T, dst: &mut BytesMut) -> Result<(), WriteError> {
+    let len = dst.len();
+
+    if let Err(e) = serde_json::to_writer((&mut *dst).writer(), t) {
+        dst.truncate(len);
+        return Err(WriteError::Serialize(e));
+    }
+
+    dst.put_u8(b'\n');
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    /// Feeds `input` to `decoder` in chunks of `chunk_len` bytes, then decodes every line.
+    fn decode_all(
+        mut decoder: Decoder,
+        input: &[u8],
+        chunk_len: usize,
+    ) -> Vec<Result<Value, DecodeError>> {
+        let mut results = Vec::new();
+
+        for chunk in input.chunks(chunk_len) {
+            decoder.feed_bytes(chunk);
+            while let Some(result) = decoder.decode() {
+                results.push(result);
+            }
+        }
+
+        results.extend(decoder.finish());
+        results
+    }
+
+    #[test]
+    fn long_lines_are_rejected_and_decoding_carries_on() {
+        let input = b"[1,2,3,4,5,6,7,8,9]\n[1]\n";
+
+        for chunk_len in 1..=input.len() {
+            let decoder = Decoder::new().max_line_len(8);
+            let results = decode_all(decoder, input, chunk_len);
+
+            assert!(
+                matches!(
+                    &results[..],
+                    [Err(DecodeError::LineTooLong { max: 8, .. }), Ok(value)] if *value == json!([1])
+                ),
+                "chunks of {} bytes",
+                chunk_len
+            );
+        }
+    }
+
+    #[test]
+    fn long_lines_are_not_buffered() {
+        let mut decoder = Decoder::new().max_line_len(8);
+
+        for _ in 0..1000 {
+            decoder.feed_bytes(b"[1,2,3,4,5,6,7,8,9]");
+            assert!(decoder.buffered_len() <= 10);
+        }
+
+        assert!(matches!(
+            decoder.decode::<Value>(),
+            Some(Err(DecodeError::LineTooLong { max: 8, .. }))
+        ));
+        decoder.feed_bytes(b"]\n[1]\n");
+        assert!(matches!(decoder.decode::<Value>(), Some(Ok(value)) if value == json!([1])));
+        assert!(decoder.decode::<Value>().is_none());
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn long_lines_are_rejected_by_decode_from() {
+        let mut decoder = Decoder::new().max_line_len(8);
+        let mut src = BytesMut::new();
+        let mut results = Vec::new();
+
+        for chunk in b"[1,2,3,4,5,6,7,8,9]\n[1]\n".chunks(3) {
+            src.put_slice(chunk);
+            while let Some(result) = decoder.decode_from::<Value>(&mut src) {
+                results.push(result);
+            }
+            assert!(src.len() <= 9);
+        }
+
+        assert!(matches!(
+            &results[..],
+            [Err(DecodeError::LineTooLong { max: 8, .. }), Ok(value)] if *value == json!([1])
+        ));
+    }
+
+    #[test]
+    fn deep_nesting_is_rejected() {
+        let input = b"[[[1]]]\n[[[[1]]]]\n{\"a\":\"[[[[\"}\n";
+        let decoder = Decoder::new().max_depth(3);
+        let results = decode_all(decoder, input, input.len());
+
+        assert!(matches!(
+            &results[..],
+            [Ok(_), Err(DecodeError::TooDeep { max: 3 }), Ok(_)]
+        ));
+    }
+
+    #[test]
+    fn nul_bytes_and_invalid_utf8_are_rejected() {
+        let input = b"\"a\x00\"\n\"\xff\"\n1\n";
+        let results = decode_all(Decoder::new(), input, input.len());
+
+        assert!(matches!(
+            &results[..],
+            [
+                Err(DecodeError::NulByte { offset: 2 }),
+                Err(DecodeError::InvalidUtf8(_)),
+                Ok(_),
+            ]
+        ));
+    }
+
+    #[test]
+    fn blank_lines_and_a_missing_final_newline() {
+        let input = b"\n \t\r\n1\n\n2";
+        let results = decode_all(Decoder::new(), input, 1);
+
+        assert!(matches!(
+            &results[..],
+            [Ok(a), Ok(b)] if *a == json!(1) && *b == json!(2)
+        ));
+    }
+
+    #[test]
+    fn control_characters_are_escaped_inside_strings() {
+        let input = b"{\"text\":\"a\x01b\\n\"}\x02\n";
+        let decoder = Decoder::new().control_characters(ControlCharacters::Escape);
+        let results = decode_all(decoder, input, input.len());
+
+        assert!(matches!(
+            &results[..],
+            [Ok(value)] if *value == json!({ "text": "a\u{1}b\n" })
+        ));
+    }
+
+    #[test]
+    fn control_characters_after_a_backslash_are_rejected() {
+        let input = b"{\"text\":\"a\\\x01\"}\n{\"text\":\"a\\\x01\\\"\"}\n";
+
+        for policy in [
+            ControlCharacters::Reject,
+            ControlCharacters::Strip,
+            ControlCharacters::Escape,
+        ] {
+            let decoder = Decoder::new().control_characters(policy);
+            let results = decode_all(decoder, input, input.len());
+
+            assert!(matches!(
+                &results[..],
+                [
+                    Err(DecodeError::ControlCharacter {
+                        offset: 11,
+                        byte: 1
+                    }),
+                    Err(DecodeError::ControlCharacter {
+                        offset: 11,
+                        byte: 1
+                    }),
+                ]
+            ));
+        }
+    }
+
+    #[test]
+    fn unicode_separators_inside_strings_are_kept() {
+        let input = "{\"text\":\"a\u{2028}b\u{2029}c\"}\n";
+
+        for policy in [UnicodeSeparators::Reject, UnicodeSeparators::Ignore] {
+            let decoder = Decoder::new().unicode_separators(policy);
+            let results = decode_all(decoder, input.as_bytes(), input.len());
+
+            assert_eq!(results.len(), 1);
+            assert!(
+                matches!(&results[..], [Ok(value)] if *value == json!({ "text": "a\u{2028}b\u{2029}c" }))
+            );
+        }
+    }
+
+    #[test]
+    fn unicode_separators_outside_strings_are_rejected() {
+        let input = "{\"a\":1}\u{2028}\n\u{2029}{\"b\":2}\n{\"c\":3}\n";
+        let results = decode_all(Decoder::new(), input.as_bytes(), input.len());
+
+        assert!(matches!(
+            &results[..],
+            [
+                Err(DecodeError::UnicodeSeparator { offset: 7 }),
+                Err(DecodeError::UnicodeSeparator { offset: 0 }),
+                Ok(_),
+            ]
+        ));
+    }
+
+    #[test]
+    fn unicode_separators_outside_strings_can_be_ignored() {
+        let input = "{\"a\":1}\u{2028}\n\u{2029}\n\u{2029}{\"b\":2}\n";
+        let decoder = Decoder::new().unicode_separators(UnicodeSeparators::Ignore);
+        let results = decode_all(decoder, input.as_bytes(), input.len());
+
+        assert!(matches!(
+            &results[..],
+            [Ok(a), Ok(b)] if *a == json!({ "a": 1 }) && *b == json!({ "b": 2 })
+        ));
+    }
+
+    #[test]
+    fn unicode_separators_split_across_chunks() {
+        let input = "\u{2028}{\"text\":\"\u{2029}\"}\u{2028}\n";
+
+        for chunk_len in 1..=4 {
+            let results = decode_all(Decoder::new(), input.as_bytes(), chunk_len);
+            assert!(matches!(
+                &results[..],
+                [Err(DecodeError::UnicodeSeparator { offset: 0 })]
+            ));
+
+            let decoder = Decoder::new().unicode_separators(UnicodeSeparators::Ignore);
+            let results = decode_all(decoder, input.as_bytes(), chunk_len);
+            assert!(matches!(
+                &results[..],
+                [Ok(value)] if *value == json!({ "text": "\u{2029}" })
+            ));
+        }
+    }
+}
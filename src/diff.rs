@@ -0,0 +1,212 @@
+use crate::ReadError;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::io::BufRead;
+use std::vec;
+
+/// A difference between two JSON Lines inputs, as reported by [`diff`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordDiff<K> {
+    /// A record with this key is only present in the second input.
+    Added { key: K, record: Value },
+    /// A record with this key is only present in the first input.
+    Removed { key: K, record: Value },
+    /// Records with this key are present in both inputs but differ.
+    Changed { key: K, changes: Vec<FieldChange> },
+}
+
+/// A single difference between two records with the same key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    /// The [JSON pointer](https://www.rfc-editor.org/rfc/rfc6901) to the value that differs. This is
+    /// the empty string if the records differ as a whole, for instance if they are not objects.
+    pub pointer: String,
+    /// The value in the first input, or `None` if it was added.
+    pub old: Option<Value>,
+    /// The value in the second input, or `None` if it was removed.
+    pub new: Option<Value>,
+}
+
+/// An iterator over the differences between two JSON Lines inputs, created by [`diff`].
+#[derive(Debug)]
+pub struct Diff<A, B, K, F> {
+    a: A,
+    b: B,
+    key_fn: F,
+    a_done: bool,
+    b_done: bool,
+    read_a_next: bool,
+    num_records: usize,
+    pending_a: HashMap<K, (usize, Value)>,
+    pending_b: HashMap<K, (usize, Value)>,
+    leftovers: Option<vec::IntoIter<RecordDiff<K>>>,
+}
+
+/// Compares the records of two JSON Lines inputs, aligning them by the key returned by `key_fn`.
+///
+/// Both inputs are read alternately as the returned iterator is advanced, and a record is only held
+/// in memory until the record with the same key is found in the other input. Inputs whose records
+/// are in roughly the same order can therefore be compared in little memory, however large they
+/// are. Records that are identical in both inputs are not reported. Once both inputs are exhausted
+/// the remaining unmatched records are reported as removed or added, in the order they were read.
+///
+/// Keys are expected to be unique within each input. Objects are compared field by field, while
+/// arrays and all other values are compared as a whole.
+pub fn diff<A, B, K, F>(a: A, b: B, key_fn: F) -> Diff<A, B, K, F>
+where
+    A: BufRead,
+    B: BufRead,
+    K: Eq + Hash,
+    F: FnMut(&Value) -> K,
+{
+    Diff {
+        a,
+        b,
+        key_fn,
+        a_done: false,
+        b_done: false,
+        read_a_next: true,
+        num_records: 0,
+        pending_a: HashMap::new(),
+        pending_b: HashMap::new(),
+        leftovers: None,
+    }
+}
+
+impl<A, B, K, F> Iterator for Diff<A, B, K, F>
+where
+    A: BufRead,
+    B: BufRead,
+    K: Eq + Hash,
+    F: FnMut(&Value) -> K,
+{
+    type Item = Result<RecordDiff<K>, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.a_done || !self.b_done {
+            let read_a = self.b_done || (self.read_a_next && !self.a_done);
+            self.read_a_next = !read_a;
+
+            let result = if read_a {
+                crate::blocking::read(&mut self.a)
+            } else {
+                crate::blocking::read(&mut self.b)
+            };
+
+            let record: Value = match result {
+                Ok(record) => record,
+                Err(ReadError::Eof) => {
+                    if read_a {
+                        self.a_done = true;
+                    } else {
+                        self.b_done = true;
+                    }
+                    continue;
+                }
+                Err(e) => return Some(Err(e)),
+            };
+
+            let key = (self.key_fn)(&record);
+            let (pending, other_pending) = if read_a {
+                (&mut self.pending_a, &mut self.pending_b)
+            } else {
+                (&mut self.pending_b, &mut self.pending_a)
+            };
+
+            match other_pending.remove(&key) {
+                Some((_, other)) => {
+                    let (old, new) = if read_a {
+                        (&record, &other)
+                    } else {
+                        (&other, &record)
+                    };
+
+                    let mut changes = Vec::new();
+                    diff_values(&mut String::new(), old, new, &mut changes);
+
+                    if !changes.is_empty() {
+                        return Some(Ok(RecordDiff::Changed { key, changes }));
+                    }
+                }
+                None => {
+                    pending.insert(key, (self.num_records, record));
+                    self.num_records += 1;
+                }
+            }
+        }
+
+        let pending_a = &mut self.pending_a;
+        let pending_b = &mut self.pending_b;
+
+        self.leftovers
+            .get_or_insert_with(|| {
+                let mut leftovers: Vec<_> = pending_a
+                    .drain()
+                    .map(|(key, (i, record))| (i, RecordDiff::Removed { key, record }))
+                    .chain(
+                        pending_b
+                            .drain()
+                            .map(|(key, (i, record))| (i, RecordDiff::Added { key, record })),
+                    )
+                    .collect();
+                leftovers.sort_by_key(|(i, _)| *i);
+
+                leftovers
+                    .into_iter()
+                    .map(|(_, diff)| diff)
+                    .collect::<Vec<_>>()
+                    .into_iter()
+            })
+            .next()
+            .map(Ok)
+    }
+}
+
+fn diff_values(pointer: &mut String, old: &Value, new: &Value, changes: &mut Vec<FieldChange>) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            for (key, old_value) in old {
+                let len = pointer.len();
+                push_token(pointer, key);
+
+                match new.get(key) {
+                    Some(new_value) => diff_values(pointer, old_value, new_value, changes),
+                    None => changes.push(FieldChange {
+                        pointer: pointer.clone(),
+                        old: Some(old_value.clone()),
+                        new: None,
+                    }),
+                }
+
+                pointer.truncate(len);
+            }
+
+            for (key, new_value) in new {
+                if !old.contains_key(key) {
+                    let len = pointer.len();
+                    push_token(pointer, key);
+
+                    changes.push(FieldChange {
+                        pointer: pointer.clone(),
+                        old: None,
+                        new: Some(new_value.clone()),
+                    });
+
+                    pointer.truncate(len);
+                }
+            }
+        }
+        (old, new) if old != new => changes.push(FieldChange {
+            pointer: pointer.clone(),
+            old: Some(old.clone()),
+            new: Some(new.clone()),
+        }),
+        _ => {}
+    }
+}
+
+fn push_token(pointer: &mut String, key: &str) {
+    pointer.push('/');
+    pointer.push_str(&key.replace('~', "~0").replace('/', "~1"));
+}
@@ -0,0 +1,214 @@
+#[cfg(not(feature = "tokio"))]
+mod imports {
+    pub(super) use std::io::{self, BufReader, Read};
+    pub(super) use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+}
+#[cfg(feature = "tokio")]
+mod imports {
+    pub(super) use std::pin::Pin;
+    pub(super) use std::process::Stdio;
+    pub(super) use std::task::{ready, Context, Poll};
+    pub(super) use tokio::io::{self, AsyncRead, BufReader, ReadBuf};
+    pub(super) use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+}
+
+use crate::Connection;
+use imports::*;
+use std::cmp;
+use std::convert::TryFrom;
+
+const HEADER_LEN: usize = 8;
+const STREAM_STDIN: u8 = 0;
+const STREAM_STDOUT: u8 = 1;
+const STREAM_STDERR: u8 = 2;
+
+/// The size of the buffer frames are read through.
+const CHUNK_LEN: usize = 8 * 1024;
+
+impl Connection<BufReader<ChildStdout>, ChildStdin> {
+    /// Runs `command` inside the running container `container` using the system’s `docker` client,
+    /// and creates a new `Connection` that writes to the command’s `stdin` and reads from its
+    /// `stdout`. The `docker` child process is returned alongside the `Connection` so that it can
+    /// be waited on or killed.
+    ///
+    /// No pseudoterminal is allocated, so the data passing through is never mangled. The
+    /// command’s `stderr` is inherited.
+    pub fn new_from_docker_exec(container: &str, command: &[&str]) -> io::Result<(Self, Child)> {
+        let mut child = Command::new("docker")
+            .args(["exec", "--interactive", "--", container])
+            .args(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+
+        let missing = || io::Error::other("docker child process is missing piped stdio");
+        let stdin = child.stdin.take().ok_or_else(missing)?;
+        let stdout = child.stdout.take().ok_or_else(missing)?;
+
+        Ok((Connection::new(BufReader::new(stdout), stdin), child))
+    }
+}
+
+/// Demultiplexes the stream framing the Docker Engine API uses on hijacked attach and exec
+/// connections when no TTY is allocated.
+///
+/// Docker prefixes each chunk of output with an eight-byte header saying whether it came from
+/// `stdout` or `stderr` and how long it is. `DockerDemux` strips this framing, yielding only the
+/// `stdout` data from its reader and passing `stderr` data on to a separate writer, so that
+/// ```text
+/// Connection::new(BufReader::new(DockerDemux::new(read_half)), write_half)
+/// ```
+/// speaks JSON Lines with the process in the container. Data written to the connection needs no
+/// framing.
+#[derive(Debug)]
+pub struct DockerDemux<R, E = std::io::Sink> {
+    inner: R,
+    stderr: E,
+    header: [u8; HEADER_LEN],
+    header_len: usize,
+    stream: u8,
+    remaining: usize,
+}
+
+impl<R> DockerDemux<R> {
+    /// Creates a new `DockerDemux` that discards anything written to `stderr`.
+    pub fn new(inner: R) -> Self {
+        Self::with_stderr(inner, std::io::sink())
+    }
+}
+
+impl<R, E: std::io::Write> DockerDemux<R, E> {
+    /// Creates a new `DockerDemux` that writes anything written to `stderr` to the given writer.
+    pub fn with_stderr(inner: R, stderr: E) -> Self {
+        Self {
+            inner,
+            stderr,
+            header: [0; HEADER_LEN],
+            header_len: 0,
+            stream: STREAM_STDOUT,
+            remaining: 0,
+        }
+    }
+
+    /// Consumes the `DockerDemux`, returning the wrapped reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Parses a complete frame header, preparing to read the frame it describes.
+    fn start_frame(&mut self) -> io::Result<()> {
+        let [stream, _, _, _, a, b, c, d] = self.header;
+
+        if !matches!(stream, STREAM_STDIN | STREAM_STDOUT | STREAM_STDERR) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid stream type in Docker frame header",
+            ));
+        }
+
+        self.stream = stream;
+        self.remaining = usize::try_from(u32::from_be_bytes([a, b, c, d]))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.header_len = 0;
+
+        Ok(())
+    }
+
+    /// Handles a chunk freshly read from the current frame, returning whether it belongs in the
+    /// output.
+    fn consume(&mut self, chunk: &[u8]) -> io::Result<bool> {
+        if chunk.is_empty() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        self.remaining -= chunk.len();
+
+        if self.stream == STREAM_STDERR {
+            self.stderr.write_all(chunk)?;
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+}
+
+fn truncated_header() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "stream ended in the middle of a Docker frame header",
+    )
+}
+
+#[cfg(not(feature = "tokio"))]
+impl<R: Read, E: std::io::Write> Read for DockerDemux<R, E> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            while self.remaining == 0 {
+                while self.header_len < HEADER_LEN {
+                    match self.inner.read(&mut self.header[self.header_len..])? {
+                        0 if self.header_len == 0 => return Ok(0),
+                        0 => return Err(truncated_header()),
+                        num_bytes => self.header_len += num_bytes,
+                    }
+                }
+
+                self.start_frame()?;
+            }
+
+            let mut chunk = [0; CHUNK_LEN];
+            let max_len = cmp::min(self.remaining, cmp::min(buf.len(), CHUNK_LEN));
+            let num_bytes = self.inner.read(&mut chunk[..max_len])?;
+
+            if self.consume(&chunk[..num_bytes])? {
+                buf[..num_bytes].copy_from_slice(&chunk[..num_bytes]);
+                return Ok(num_bytes);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R: AsyncRead + Unpin, E: std::io::Write + Unpin> AsyncRead for DockerDemux<R, E> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        loop {
+            while this.remaining == 0 {
+                while this.header_len < HEADER_LEN {
+                    let mut header = ReadBuf::new(&mut this.header[this.header_len..]);
+                    ready!(Pin::new(&mut this.inner).poll_read(cx, &mut header))?;
+
+                    match header.filled().len() {
+                        0 if this.header_len == 0 => return Poll::Ready(Ok(())),
+                        0 => return Poll::Ready(Err(truncated_header())),
+                        num_bytes => this.header_len += num_bytes,
+                    }
+                }
+
+                this.start_frame()?;
+            }
+
+            let mut chunk = [0; CHUNK_LEN];
+            let max_len = cmp::min(this.remaining, cmp::min(buf.remaining(), CHUNK_LEN));
+            let mut chunk = ReadBuf::new(&mut chunk[..max_len]);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk))?;
+
+            if this.consume(chunk.filled())? {
+                buf.put_slice(chunk.filled());
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+}
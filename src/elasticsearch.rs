@@ -0,0 +1,204 @@
+use crate::WriteError;
+use serde::Serialize;
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Action<'a> {
+    Index(Metadata<'a>),
+    Create(Metadata<'a>),
+    Update(Metadata<'a>),
+    Delete(Metadata<'a>),
+}
+
+#[derive(Serialize)]
+struct Metadata<'a> {
+    #[serde(rename = "_index")]
+    index: &'a str,
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<&'a str>,
+}
+
+/// Writes request bodies for the Elasticsearch and OpenSearch `_bulk` API, which consist of an
+/// action line, usually followed by a document line, for every operation.
+///
+/// Send everything written to the underlying writer as the body of a `POST /_bulk` request with
+/// a `Content-Type` of `application/x-ndjson`. Every line, including the last, is terminated by a
+/// newline as the API requires.
+#[derive(Debug)]
+pub struct BulkWriter<W> {
+    writer: W,
+    num_actions: usize,
+}
+
+impl<W> BulkWriter<W> {
+    /// Creates a new `BulkWriter` that writes to the given writer.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            num_actions: 0,
+        }
+    }
+
+    /// Returns how many actions have been written so far, which is useful for splitting large jobs
+    /// into several requests.
+    pub fn num_actions(&self) -> usize {
+        self.num_actions
+    }
+
+    /// Consumes the `BulkWriter`, returning the wrapped writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl<W: std::io::Write> BulkWriter<W> {
+    /// Indexes `document` into `index`, replacing any existing document with the same ID. The
+    /// server generates an ID if `id` is `None`.
+    pub fn index<T: Serialize>(
+        &mut self,
+        index: &str,
+        id: Option<&str>,
+        document: &T,
+    ) -> Result<(), WriteError> {
+        self.write_action(&Action::Index(Metadata { index, id }), Some(document))
+    }
+
+    /// Indexes `document` into `index`, failing if a document with the same ID already exists.
+    /// The server generates an ID if `id` is `None`.
+    pub fn create<T: Serialize>(
+        &mut self,
+        index: &str,
+        id: Option<&str>,
+        document: &T,
+    ) -> Result<(), WriteError> {
+        self.write_action(&Action::Create(Metadata { index, id }), Some(document))
+    }
+
+    /// Updates the document with the given ID in `index`. `body` is the full update body, such as
+    /// `{"doc": {...}}` for a partial document or `{"script": {...}}` for a scripted update.
+    pub fn update<T: Serialize>(
+        &mut self,
+        index: &str,
+        id: &str,
+        body: &T,
+    ) -> Result<(), WriteError> {
+        self.write_action(
+            &Action::Update(Metadata {
+                index,
+                id: Some(id),
+            }),
+            Some(body),
+        )
+    }
+
+    /// Deletes the document with the given ID from `index`.
+    pub fn delete(&mut self, index: &str, id: &str) -> Result<(), WriteError> {
+        self.write_action::<()>(
+            &Action::Delete(Metadata {
+                index,
+                id: Some(id),
+            }),
+            None,
+        )
+    }
+
+    fn write_action<T: Serialize>(
+        &mut self,
+        action: &Action<'_>,
+        source: Option<&T>,
+    ) -> Result<(), WriteError> {
+        crate::write(&mut self.writer, action)?;
+
+        if let Some(source) = source {
+            crate::write(&mut self.writer, source)?;
+        }
+
+        self.num_actions += 1;
+
+        Ok(())
+    }
+
+    /// Flushes the contained writer’s buffer.
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<W: tokio::io::AsyncWrite + Unpin> BulkWriter<W> {
+    /// Indexes `document` into `index`, replacing any existing document with the same ID. The
+    /// server generates an ID if `id` is `None`.
+    pub async fn index<T: Serialize>(
+        &mut self,
+        index: &str,
+        id: Option<&str>,
+        document: &T,
+    ) -> Result<(), WriteError> {
+        self.write_action(&Action::Index(Metadata { index, id }), Some(document))
+            .await
+    }
+
+    /// Indexes `document` into `index`, failing if a document with the same ID already exists.
+    /// The server generates an ID if `id` is `None`.
+    pub async fn create<T: Serialize>(
+        &mut self,
+        index: &str,
+        id: Option<&str>,
+        document: &T,
+    ) -> Result<(), WriteError> {
+        self.write_action(&Action::Create(Metadata { index, id }), Some(document))
+            .await
+    }
+
+    /// Updates the document with the given ID in `index`. `body` is the full update body, such as
+    /// `{"doc": {...}}` for a partial document or `{"script": {...}}` for a scripted update.
+    pub async fn update<T: Serialize>(
+        &mut self,
+        index: &str,
+        id: &str,
+        body: &T,
+    ) -> Result<(), WriteError> {
+        self.write_action(
+            &Action::Update(Metadata {
+                index,
+                id: Some(id),
+            }),
+            Some(body),
+        )
+        .await
+    }
+
+    /// Deletes the document with the given ID from `index`.
+    pub async fn delete(&mut self, index: &str, id: &str) -> Result<(), WriteError> {
+        self.write_action::<()>(
+            &Action::Delete(Metadata {
+                index,
+                id: Some(id),
+            }),
+            None,
+        )
+        .await
+    }
+
+    async fn write_action<T: Serialize>(
+        &mut self,
+        action: &Action<'_>,
+        source: Option<&T>,
+    ) -> Result<(), WriteError> {
+        crate::write(&mut self.writer, action).await?;
+
+        if let Some(source) = source {
+            crate::write(&mut self.writer, source).await?;
+        }
+
+        self.num_actions += 1;
+
+        Ok(())
+    }
+
+    /// Flushes the contained writer’s buffer.
+    pub async fn flush(&mut self) -> std::io::Result<()> {
+        tokio::io::AsyncWriteExt::flush(&mut self.writer).await
+    }
+}
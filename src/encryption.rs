@@ -0,0 +1,191 @@
+use crate::{ReadError, WriteError};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::fmt;
+
+/// The prefix marking a string as a field encrypted by [`FieldCipher`].
+const PREFIX: &str = "enc:v1:";
+
+/// The length in bytes of an XChaCha20-Poly1305 nonce.
+const NONCE_LEN: usize = 24;
+
+/// An error that occurred while encrypting or decrypting fields with [`FieldCipher`].
+#[derive(Debug, thiserror::Error)]
+pub enum FieldCipherError {
+    #[error("failed reading record")]
+    Read(#[from] ReadError),
+    #[error("failed writing record")]
+    Write(#[from] WriteError),
+    #[error("failed converting record to or from JSON")]
+    Json(#[from] serde_json::Error),
+    #[error("failed encrypting the field at {0}")]
+    Encrypt(String),
+    #[error("failed decrypting the field at {0}")]
+    Decrypt(String),
+}
+
+/// Encrypts and decrypts selected fields of records, leaving the rest of each record readable.
+///
+/// Fields are selected by [JSON pointer](https://www.rfc-editor.org/rfc/rfc6901), such as
+/// `/user/ssn`. Each selected field is serialized, encrypted with XChaCha20-Poly1305 using a fresh
+/// random nonce and replaced with a string of the form `enc:v1:<base64>`, so that records remain
+/// valid JSON and every other field can still be queried. The pointer is bound to the ciphertext,
+/// so an encrypted value cannot be moved to a different field without failing to decrypt.
+///
+/// Fields that are missing from a record are skipped, as are fields that are not encrypted when
+/// decrypting.
+pub struct FieldCipher {
+    cipher: XChaCha20Poly1305,
+    pointers: Vec<String>,
+}
+
+impl fmt::Debug for FieldCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FieldCipher")
+            .field("pointers", &self.pointers)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FieldCipher {
+    /// Creates a new `FieldCipher` encrypting the fields at `pointers` with the given 256-bit key.
+    pub fn new<I, S>(key: &[u8; 32], pointers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            cipher: XChaCha20Poly1305::new(key.into()),
+            pointers: pointers.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Encrypts the selected fields of a record in place.
+    pub fn encrypt_fields(&self, record: &mut Value) -> Result<(), FieldCipherError> {
+        for pointer in &self.pointers {
+            let field = match record.pointer_mut(pointer) {
+                Some(field) => field,
+                None => continue,
+            };
+
+            let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+            let plaintext = serde_json::to_vec(field)?;
+            let ciphertext = self
+                .cipher
+                .encrypt(
+                    &nonce,
+                    Payload {
+                        msg: &plaintext,
+                        aad: pointer.as_bytes(),
+                    },
+                )
+                .map_err(|_| FieldCipherError::Encrypt(pointer.clone()))?;
+
+            let mut sealed = nonce.to_vec();
+            sealed.extend_from_slice(&ciphertext);
+
+            *field = Value::String(format!("{}{}", PREFIX, STANDARD.encode(sealed)));
+        }
+
+        Ok(())
+    }
+
+    /// Decrypts the selected fields of a record in place.
+    pub fn decrypt_fields(&self, record: &mut Value) -> Result<(), FieldCipherError> {
+        for pointer in &self.pointers {
+            let field = match record.pointer_mut(pointer) {
+                Some(field) => field,
+                None => continue,
+            };
+
+            let encoded = match field.as_str().and_then(|s| s.strip_prefix(PREFIX)) {
+                Some(encoded) => encoded,
+                None => continue,
+            };
+
+            let decrypt_error = || FieldCipherError::Decrypt(pointer.clone());
+            let sealed = STANDARD.decode(encoded).map_err(|_| decrypt_error())?;
+
+            if sealed.len() < NONCE_LEN {
+                return Err(decrypt_error());
+            }
+
+            let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+            let plaintext = self
+                .cipher
+                .decrypt(
+                    XNonce::from_slice(nonce),
+                    Payload {
+                        msg: ciphertext,
+                        aad: pointer.as_bytes(),
+                    },
+                )
+                .map_err(|_| decrypt_error())?;
+
+            *field = serde_json::from_slice(&plaintext)?;
+        }
+
+        Ok(())
+    }
+
+    fn encrypt_value<T: serde::Serialize>(&self, t: &T) -> Result<Value, FieldCipherError> {
+        let mut record = serde_json::to_value(t)?;
+        self.encrypt_fields(&mut record)?;
+        Ok(record)
+    }
+
+    fn decrypt_value<T: DeserializeOwned>(&self, mut record: Value) -> Result<T, FieldCipherError> {
+        self.decrypt_fields(&mut record)?;
+        Ok(serde_json::from_value(record)?)
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl FieldCipher {
+    /// Reads a line from the reader, decrypts its selected fields and deserializes it into a given
+    /// type.
+    pub fn read<R: std::io::BufRead, T: DeserializeOwned>(
+        &self,
+        reader: R,
+    ) -> Result<T, FieldCipherError> {
+        let record: Value = crate::read(reader)?;
+        self.decrypt_value(record)
+    }
+
+    /// Serializes a given value, encrypts its selected fields and writes it to the writer.
+    pub fn write<W: std::io::Write, T: serde::Serialize>(
+        &self,
+        writer: W,
+        t: &T,
+    ) -> Result<(), FieldCipherError> {
+        let record = self.encrypt_value(t)?;
+        Ok(crate::write(writer, &record)?)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl FieldCipher {
+    /// Reads a line from the reader, decrypts its selected fields and deserializes it into a given
+    /// type.
+    pub async fn read<R: tokio::io::AsyncBufRead + Unpin, T: DeserializeOwned>(
+        &self,
+        reader: R,
+    ) -> Result<T, FieldCipherError> {
+        let record: Value = crate::read(reader).await?;
+        self.decrypt_value(record)
+    }
+
+    /// Serializes a given value, encrypts its selected fields and writes it to the writer.
+    pub async fn write<W: tokio::io::AsyncWrite + Unpin, T: serde::Serialize>(
+        &self,
+        writer: W,
+        t: &T,
+    ) -> Result<(), FieldCipherError> {
+        let record = self.encrypt_value(t)?;
+        Ok(crate::write(writer, &record).await?)
+    }
+}
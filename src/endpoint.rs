@@ -0,0 +1,146 @@
+#[cfg(not(feature = "tokio"))]
+use std::io::{self, BufRead, Write};
+#[cfg(feature = "tokio")]
+use tokio::io::{self, AsyncBufRead as BufRead, AsyncWrite as Write, AsyncWriteExt};
+
+use crate::{Connection, ReadError, WriteError};
+use std::fmt;
+use std::marker::PhantomData;
+
+/// The sending half of a pipe that only ever carries messages of type `T` in one direction,
+/// written to `W`.
+///
+/// Where [`Connection`] leaves the type of each message up to the call site, a `Sender` fixes it
+/// once, so a mismatched message is a compile error rather than a confused peer. Named after
+/// [`std::sync::mpsc::Sender`], which it mirrors across a process boundary.
+pub struct Sender<T, W: Write> {
+    writer: W,
+    _message: PhantomData<fn(&T)>,
+}
+
+/// The receiving half of a pipe that only ever carries messages of type `T` in one direction,
+/// read from `R`. See [`Sender`].
+pub struct Receiver<T, R: BufRead> {
+    reader: R,
+    _message: PhantomData<fn() -> T>,
+}
+
+impl<T, W: Write> Sender<T, W> {
+    /// Creates a new `Sender` that writes messages to `writer`.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            _message: PhantomData,
+        }
+    }
+
+    /// Returns a reference to the contained writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Consumes the `Sender`, returning the contained writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<T, R: BufRead> Receiver<T, R> {
+    /// Creates a new `Receiver` that reads messages from `reader`.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            _message: PhantomData,
+        }
+    }
+
+    /// Returns a reference to the contained reader.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Consumes the `Receiver`, returning the contained reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: BufRead, W: Write> Connection<R, W> {
+    /// Splits the `Connection` into a [`Receiver`] of `In` messages and a [`Sender`] of `Out`
+    /// messages, for when each direction only ever carries one type.
+    pub fn into_endpoints<In, Out>(self) -> (Receiver<In, R>, Sender<Out, W>) {
+        let (reader, writer) = self.into_parts();
+        (Receiver::new(reader), Sender::new(writer))
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl<T: serde::Serialize, W: Write> Sender<T, W> {
+    /// Writes a message to the writer, serializing it into JSON.
+    pub fn send(&mut self, t: &T) -> Result<(), WriteError> {
+        crate::write(&mut self.writer, t)
+    }
+
+    /// Flushes the contained writer’s buffer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl<T: serde::de::DeserializeOwned, R: BufRead> Receiver<T, R> {
+    /// Reads a line from the reader and deserializes it into a message.
+    pub fn recv(&mut self) -> Result<T, ReadError> {
+        crate::read(&mut self.reader)
+    }
+}
+
+/// Yields messages until the reader reaches EOF.
+#[cfg(not(feature = "tokio"))]
+impl<T: serde::de::DeserializeOwned, R: BufRead> Iterator for Receiver<T, R> {
+    type Item = Result<T, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.recv() {
+            Err(ReadError::Eof) => None,
+            result => Some(result),
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<T: serde::Serialize, W: Write + Unpin> Sender<T, W> {
+    /// Writes a message to the writer, serializing it into JSON.
+    pub async fn send(&mut self, t: &T) -> Result<(), WriteError> {
+        crate::write(&mut self.writer, t).await
+    }
+
+    /// Flushes the contained writer’s buffer.
+    pub async fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush().await
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<T: serde::de::DeserializeOwned, R: BufRead + Unpin> Receiver<T, R> {
+    /// Reads a line from the reader and deserializes it into a message.
+    pub async fn recv(&mut self) -> Result<T, ReadError> {
+        crate::read(&mut self.reader).await
+    }
+}
+
+impl<T, W: Write + fmt::Debug> fmt::Debug for Sender<T, W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender")
+            .field("writer", &self.writer)
+            .finish()
+    }
+}
+
+impl<T, R: BufRead + fmt::Debug> fmt::Debug for Receiver<T, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver")
+            .field("reader", &self.reader)
+            .finish()
+    }
+}
@@ -9,6 +9,8 @@ pub enum ReadError {
     Deserialize(#[from] serde_json::Error),
     #[error("reader has reached EOF")]
     Eof,
+    #[error("line is longer than the maximum of {max} bytes")]
+    LineTooLong { max: usize },
 }
 
 /// An error that occurred during writing.
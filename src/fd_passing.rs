@@ -0,0 +1,434 @@
+#[cfg(not(feature = "tokio"))]
+mod imports {
+    pub(super) use std::io::{self, BufRead, Read, Write};
+    pub(super) use std::os::unix::net::UnixStream;
+
+    pub(super) type ReadStream = UnixStream;
+}
+#[cfg(feature = "tokio")]
+mod imports {
+    pub(super) use std::pin::Pin;
+    pub(super) use std::task::{Context, Poll};
+    pub(super) use tokio::io::{self, AsyncBufRead, AsyncRead, AsyncWriteExt, Interest, ReadBuf};
+    pub(super) use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+    pub(super) use tokio::net::UnixStream;
+
+    pub(super) type ReadStream = OwnedReadHalf;
+}
+
+use crate::{Connection, ReadError, WriteError};
+use imports::*;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::os::unix::io::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::{mem, ptr};
+
+/// The most file descriptors that can be sent with a single line.
+pub const MAX_FDS_PER_LINE: usize = 32;
+
+const BUF_SIZE: usize = 8 * 1024;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const RECV_FLAGS: libc::c_int = libc::MSG_CMSG_CLOEXEC;
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const RECV_FLAGS: libc::c_int = 0;
+
+/// Reads from a Unix domain socket while keeping the file descriptors sent alongside the data, for
+/// [`Connection::read_with_fds`].
+///
+/// File descriptors are kept with the position in the stream of the line they were sent with.
+/// Those sent with a line that is read some other way, such as with [`Connection::read`], are
+/// closed the next time [`Connection::read_with_fds`] is called.
+#[derive(Debug)]
+pub struct FdReader {
+    stream: ReadStream,
+    buf: Vec<u8>,
+    start: usize,
+    end: usize,
+    /// The position in the stream of `buf[start]`.
+    pos: u64,
+    fds: VecDeque<(u64, Vec<OwnedFd>)>,
+}
+
+impl FdReader {
+    fn new(stream: ReadStream) -> Self {
+        Self {
+            stream,
+            buf: vec![0; BUF_SIZE],
+            start: 0,
+            end: 0,
+            pos: 0,
+            fds: VecDeque::new(),
+        }
+    }
+
+    /// Keeps the file descriptors received with bytes starting at the current end of the buffer.
+    fn received(&mut self, num_bytes: usize, fds: Vec<OwnedFd>) {
+        if !fds.is_empty() {
+            self.fds.push_back((self.pos, fds));
+        }
+
+        self.start = 0;
+        self.end = num_bytes;
+    }
+
+    /// Takes the file descriptors sent with the bytes from `start` up to `end` in the stream,
+    /// closing any sent with bytes before them.
+    fn take_fds(&mut self, start: u64, end: u64) -> Vec<OwnedFd> {
+        let mut taken = Vec::new();
+
+        while let Some((pos, _)) = self.fds.front() {
+            if *pos >= end {
+                break;
+            }
+
+            if let Some((pos, fds)) = self.fds.pop_front() {
+                if pos >= start {
+                    taken.extend(fds);
+                }
+            }
+        }
+
+        taken
+    }
+
+    fn buffered(&self) -> &[u8] {
+        self.buf.get(self.start..self.end).unwrap_or_default()
+    }
+
+    fn advance(&mut self, amt: usize) {
+        let amt = amt.min(self.end - self.start);
+        self.start += amt;
+        self.pos += amt as u64;
+    }
+}
+
+/// Sends `bytes` with `fds` attached, returning how many bytes were sent. The file descriptors
+/// are sent along with the first byte, however many bytes are sent.
+fn send_with_fds(socket: RawFd, bytes: &[u8], fds: &[RawFd]) -> std::io::Result<usize> {
+    let data_len = mem::size_of_val(fds);
+    // SAFETY: CMSG_SPACE only does arithmetic.
+    let space = unsafe { libc::CMSG_SPACE(data_len as u32) } as usize;
+    // Stored as u64s so that the control message headers are suitably aligned.
+    let mut control = vec![0u64; space.div_ceil(8)];
+
+    let mut iov = libc::iovec {
+        iov_base: bytes.as_ptr() as *mut libc::c_void,
+        iov_len: bytes.len(),
+    };
+
+    // SAFETY: msghdr is a plain C struct for which all zeroes is a valid value.
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+
+    if !fds.is_empty() {
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = space as _;
+
+        // SAFETY: the control buffer is large enough for one control message holding every file
+        // descriptor, so CMSG_FIRSTHDR returns a pointer into it, which we check anyway.
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+
+            if cmsg.is_null() {
+                return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput));
+            }
+
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(data_len as u32) as _;
+            ptr::copy_nonoverlapping(fds.as_ptr() as *const u8, libc::CMSG_DATA(cmsg), data_len);
+        }
+    }
+
+    // SAFETY: every pointer in msg is valid for the duration of the call.
+    let num_bytes = unsafe { libc::sendmsg(socket, &msg, 0) };
+
+    if num_bytes < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(num_bytes as usize)
+}
+
+/// Receives into `buf`, adding any file descriptors that came with the bytes to `fds`.
+fn recv_with_fds(socket: RawFd, buf: &mut [u8], fds: &mut Vec<OwnedFd>) -> std::io::Result<usize> {
+    // SAFETY: CMSG_SPACE only does arithmetic.
+    let space =
+        unsafe { libc::CMSG_SPACE(mem::size_of::<[RawFd; MAX_FDS_PER_LINE]>() as u32) } as usize;
+    let mut control = vec![0u64; space.div_ceil(8)];
+
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+
+    // SAFETY: msghdr is a plain C struct for which all zeroes is a valid value.
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = space as _;
+
+    let num_fds = fds.len();
+
+    // SAFETY: every pointer in msg is valid for the duration of the call.
+    let num_bytes = unsafe { libc::recvmsg(socket, &mut msg, RECV_FLAGS) };
+
+    if num_bytes < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    // SAFETY: recvmsg has filled in the control buffer, and CMSG_FIRSTHDR and CMSG_NXTHDR only
+    // return pointers to complete control messages inside it. The file descriptors in an
+    // SCM_RIGHTS message are newly opened in this process, so we take ownership of them.
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let data = libc::CMSG_DATA(cmsg);
+                let data_len =
+                    ((*cmsg).cmsg_len as usize).saturating_sub(data as usize - cmsg as usize);
+
+                for i in 0..data_len / mem::size_of::<RawFd>() {
+                    let fd = ptr::read_unaligned((data as *const RawFd).add(i));
+                    fds.push(OwnedFd::from_raw_fd(fd));
+                }
+            }
+
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+
+    // The kernel closes whatever file descriptors did not fit, so the ones that did cannot be
+    // matched up with the lines they were sent with.
+    if msg.msg_flags & libc::MSG_CTRUNC != 0 {
+        fds.truncate(num_fds);
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "file descriptors sent with a line were truncated",
+        ));
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    for fd in fds.iter() {
+        // SAFETY: fd is a valid file descriptor.
+        if unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+
+    Ok(num_bytes as usize)
+}
+
+/// Serializes `t` into a line, checking that `fds` can be sent with it.
+fn encode_line<T: Serialize>(t: &T, fds: &[BorrowedFd<'_>]) -> Result<Vec<u8>, WriteError> {
+    if fds.len() > MAX_FDS_PER_LINE {
+        return Err(WriteError::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "too many file descriptors for one line",
+        )));
+    }
+
+    crate::format::encode_line(t)
+}
+
+#[cfg(not(feature = "tokio"))]
+impl Read for FdReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let num_bytes = available.len().min(buf.len());
+
+        buf[..num_bytes].copy_from_slice(&available[..num_bytes]);
+        self.consume(num_bytes);
+
+        Ok(num_bytes)
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl BufRead for FdReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.start == self.end {
+            let mut fds = Vec::new();
+
+            let num_bytes = loop {
+                match recv_with_fds(self.stream.as_raw_fd(), &mut self.buf, &mut fds) {
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    result => break result?,
+                }
+            };
+
+            self.received(num_bytes, fds);
+        }
+
+        Ok(self.buffered())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.advance(amt);
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncRead for FdReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let available = match self.as_mut().poll_fill_buf(cx) {
+            Poll::Ready(Ok(available)) => available,
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Pending => return Poll::Pending,
+        };
+        let num_bytes = available.len().min(buf.remaining());
+
+        buf.put_slice(&available[..num_bytes]);
+        self.consume(num_bytes);
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncBufRead for FdReader {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+
+        while this.start == this.end {
+            let stream: &UnixStream = this.stream.as_ref();
+
+            match stream.poll_read_ready(cx) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+
+            let buf = &mut this.buf;
+            let mut fds = Vec::new();
+
+            match stream.try_io(Interest::READABLE, || {
+                recv_with_fds(stream.as_raw_fd(), buf, &mut fds)
+            }) {
+                Ok(num_bytes) => {
+                    this.received(num_bytes, fds);
+                    break;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+
+        Poll::Ready(Ok(this.buffered()))
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        self.get_mut().advance(amt);
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl Connection<FdReader, UnixStream> {
+    /// Creates a new `Connection` from a Unix domain socket stream that can send and receive file
+    /// descriptors alongside lines, with [`Connection::write_with_fds`] and
+    /// [`Connection::read_with_fds`].
+    pub fn new_from_unix_stream_with_fds(unix_stream: UnixStream) -> io::Result<Self> {
+        Ok(Self::new(
+            FdReader::new(unix_stream.try_clone()?),
+            unix_stream,
+        ))
+    }
+
+    /// Writes a given value to the writer, serializing it into JSON, and sends `fds` with it.
+    ///
+    /// The receiver gets its own copies of the file descriptors, which refer to the same open
+    /// files or sockets, so this can hand over anything from a file to a listening socket without
+    /// copying any data. At most [`MAX_FDS_PER_LINE`] can be sent with one line.
+    pub fn write_with_fds<T: Serialize>(
+        &mut self,
+        t: &T,
+        fds: &[BorrowedFd<'_>],
+    ) -> Result<(), WriteError> {
+        let line = encode_line(t, fds)?;
+        let raw_fds: Vec<_> = fds.iter().map(AsRawFd::as_raw_fd).collect();
+        let writer = self.writer_mut();
+
+        let sent = loop {
+            match send_with_fds(writer.as_raw_fd(), &line, &raw_fds) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                result => break result.map_err(WriteError::Io)?,
+            }
+        };
+
+        writer
+            .write_all(line.get(sent..).unwrap_or_default())
+            .map_err(WriteError::Io)
+    }
+
+    /// Reads a line from the reader and deserializes it into a given type, also returning the file
+    /// descriptors sent with it, if any.
+    pub fn read_with_fds<T: DeserializeOwned>(&mut self) -> Result<(T, Vec<OwnedFd>), ReadError> {
+        let start = self.reader_mut().pos;
+        let (t, len) = crate::read_with_len(self.reader_mut())?;
+        let fds = self.reader_mut().take_fds(start, start + len as u64);
+
+        Ok((t, fds))
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Connection<FdReader, OwnedWriteHalf> {
+    /// Creates a new `Connection` that takes ownership of a Unix domain socket stream and can send
+    /// and receive file descriptors alongside lines, with [`Connection::write_with_fds`] and
+    /// [`Connection::read_with_fds`].
+    pub fn new_from_unix_stream_with_fds(unix_stream: UnixStream) -> Self {
+        let (read_half, write_half) = unix_stream.into_split();
+
+        Self::new(FdReader::new(read_half), write_half)
+    }
+
+    /// Writes a given value to the writer, serializing it into JSON, and sends `fds` with it.
+    ///
+    /// The receiver gets its own copies of the file descriptors, which refer to the same open
+    /// files or sockets, so this can hand over anything from a file to a listening socket without
+    /// copying any data. At most [`MAX_FDS_PER_LINE`] can be sent with one line.
+    pub async fn write_with_fds<T: Serialize>(
+        &mut self,
+        t: &T,
+        fds: &[BorrowedFd<'_>],
+    ) -> Result<(), WriteError> {
+        let line = encode_line(t, fds)?;
+        let raw_fds: Vec<_> = fds.iter().map(AsRawFd::as_raw_fd).collect();
+        let writer = self.writer_mut();
+        let stream: &UnixStream = writer.as_ref();
+
+        let sent = stream
+            .async_io(Interest::WRITABLE, || {
+                send_with_fds(stream.as_raw_fd(), &line, &raw_fds)
+            })
+            .await
+            .map_err(WriteError::Io)?;
+
+        writer
+            .write_all(line.get(sent..).unwrap_or_default())
+            .await
+            .map_err(WriteError::Io)
+    }
+
+    /// Reads a line from the reader and deserializes it into a given type, also returning the file
+    /// descriptors sent with it, if any.
+    pub async fn read_with_fds<T: DeserializeOwned>(
+        &mut self,
+    ) -> Result<(T, Vec<OwnedFd>), ReadError> {
+        let start = self.reader_mut().pos;
+        let (t, len) = crate::read_with_len(self.reader_mut()).await?;
+        let fds = self.reader_mut().take_fds(start, start + len as u64);
+
+        Ok((t, fds))
+    }
+}
@@ -0,0 +1,88 @@
+use crate::WriteError;
+use serde::Serialize;
+use serde_json::ser::Formatter;
+
+/// Serializes `t` into JSON with `formatter`, checking that the result is valid UTF-8 and fits on
+/// one line.
+pub(crate) fn to_string_with_formatter<T, F>(t: &T, formatter: F) -> Result<String, WriteError>
+where
+    T: Serialize + ?Sized,
+    F: Formatter,
+{
+    let mut buf = Vec::new();
+    let mut serializer = serde_json::Serializer::with_formatter(&mut buf, formatter);
+    t.serialize(&mut serializer)
+        .map_err(WriteError::Serialize)?;
+
+    // Formatters can write arbitrary bytes, so unlike the output of serde_json’s own formatters
+    // this must be checked against the JSON Lines specification (https://jsonlines.org).
+    let json = String::from_utf8(buf).map_err(|_| invalid_output("invalid UTF-8"))?;
+
+    if json.contains('\n') {
+        return Err(invalid_output("a newline"));
+    }
+
+    Ok(json)
+}
+
+/// Serializes `t` into JSON followed by a newline, ready to be written as one line, logging it if
+/// wire logging is enabled.
+pub(crate) fn encode_line<T: Serialize + ?Sized>(t: &T) -> Result<Vec<u8>, WriteError> {
+    // We use to_string here instead of to_vec because it verifies that the JSON is valid UTF-8,
+    // which is required by the JSON Lines specification (https://jsonlines.org).
+    let json = serde_json::to_string(t).map_err(WriteError::Serialize)?;
+
+    #[cfg(feature = "log")]
+    crate::wire_log::outbound(&json);
+
+    let mut line = json.into_bytes();
+    line.push(b'\n');
+
+    Ok(line)
+}
+
+fn invalid_output(what: &str) -> WriteError {
+    WriteError::Serialize(<serde_json::Error as serde::ser::Error>::custom(
+        format_args!("formatter wrote {}", what),
+    ))
+}
+
+/// A [`Formatter`] that escapes every non-ASCII character in strings as `\uXXXX`, for consumers
+/// that cannot handle UTF-8.
+///
+/// Characters outside the Basic Multilingual Plane are written as a surrogate pair, as JSON
+/// requires. The escaping happens as the value is serialized, so it costs no extra pass over the
+/// output. Otherwise the output is the same as [`write`](crate::write)’s.
+///
+/// ```
+/// let mut buf = Vec::new();
+/// jsonl::blocking::write_with_formatter(&mut buf, &"café ☕", jsonl::AsciiFormatter).unwrap();
+/// assert_eq!(buf, b"\"caf\\u00e9 \\u2615\"\n");
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AsciiFormatter;
+
+impl Formatter for AsciiFormatter {
+    fn write_string_fragment<W>(&mut self, writer: &mut W, fragment: &str) -> std::io::Result<()>
+    where
+        W: ?Sized + std::io::Write,
+    {
+        let mut rest = fragment;
+
+        while let Some(i) = rest.find(|c: char| !c.is_ascii()) {
+            let (ascii, non_ascii) = rest.split_at(i);
+            writer.write_all(ascii.as_bytes())?;
+
+            let c = non_ascii.chars().next().unwrap_or_default();
+            let mut utf16 = [0; 2];
+
+            for unit in c.encode_utf16(&mut utf16) {
+                write!(writer, "\\u{:04x}", unit)?;
+            }
+
+            rest = non_ascii.get(c.len_utf8()..).unwrap_or_default();
+        }
+
+        writer.write_all(rest.as_bytes())
+    }
+}
@@ -0,0 +1,147 @@
+use crate::{ReadError, WriteError};
+use ::geojson::Feature;
+
+/// The record separator that begins every GeoJSON text in an
+/// [RFC 8142](https://www.rfc-editor.org/rfc/rfc8142) GeoJSON text sequence.
+const RECORD_SEPARATOR: u8 = 0x1e;
+
+/// How GeoJSON features are separated from one another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum GeoJsonFraming {
+    /// One feature per line, as in GeoJSONL and newline-delimited GeoJSON.
+    Lines,
+    /// One feature per line, with each line prefixed by an ASCII record separator, as in
+    /// [RFC 8142](https://www.rfc-editor.org/rfc/rfc8142) GeoJSON text sequences
+    /// (`application/geo+json-seq`).
+    TextSequence,
+}
+
+/// Reads GeoJSON features one at a time from newline-delimited GeoJSON or from a GeoJSON text
+/// sequence.
+///
+/// Both framings are accepted without needing to be told which one is in use: a leading record
+/// separator is stripped from every line if present, and blank lines are skipped.
+#[derive(Debug)]
+pub struct FeatureReader<R> {
+    reader: R,
+}
+
+impl<R> FeatureReader<R> {
+    /// Creates a new `FeatureReader` reading from the given reader.
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
+
+    /// Consumes the `FeatureReader`, returning the wrapped reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+/// Strips record separators and surrounding whitespace from a line, returning `None` if nothing
+/// is left.
+fn strip_framing(line: &[u8]) -> Option<&[u8]> {
+    let start = line
+        .iter()
+        .position(|b| *b != RECORD_SEPARATOR && !b.is_ascii_whitespace())?;
+    let end = line.iter().rposition(|b| !b.is_ascii_whitespace())? + 1;
+
+    Some(&line[start..end])
+}
+
+/// Writes GeoJSON features one per line, using either framing.
+#[derive(Debug)]
+pub struct FeatureWriter<W> {
+    writer: W,
+    framing: GeoJsonFraming,
+}
+
+impl<W> FeatureWriter<W> {
+    /// Creates a new `FeatureWriter` writing to the given writer with the given framing.
+    pub fn new(writer: W, framing: GeoJsonFraming) -> Self {
+        Self { writer, framing }
+    }
+
+    /// Consumes the `FeatureWriter`, returning the wrapped writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl<R: std::io::BufRead> FeatureReader<R> {
+    /// Reads the next feature.
+    pub fn read(&mut self) -> Result<Feature, ReadError> {
+        let mut buf = Vec::new();
+
+        loop {
+            buf.clear();
+
+            if self.reader.read_until(b'\n', &mut buf)? == 0 {
+                return Err(ReadError::Eof);
+            }
+
+            if let Some(json) = strip_framing(&buf) {
+                return Ok(serde_json::from_slice(json)?);
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl<W: std::io::Write> FeatureWriter<W> {
+    /// Writes a feature.
+    pub fn write(&mut self, feature: &Feature) -> Result<(), WriteError> {
+        if self.framing == GeoJsonFraming::TextSequence {
+            self.writer.write_all(&[RECORD_SEPARATOR])?;
+        }
+
+        crate::write(&mut self.writer, feature)
+    }
+
+    /// Flushes the contained writer’s buffer.
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R: tokio::io::AsyncBufRead + Unpin> FeatureReader<R> {
+    /// Reads the next feature.
+    pub async fn read(&mut self) -> Result<Feature, ReadError> {
+        use tokio::io::AsyncBufReadExt;
+
+        let mut buf = Vec::new();
+
+        loop {
+            buf.clear();
+
+            if self.reader.read_until(b'\n', &mut buf).await? == 0 {
+                return Err(ReadError::Eof);
+            }
+
+            if let Some(json) = strip_framing(&buf) {
+                return Ok(serde_json::from_slice(json)?);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<W: tokio::io::AsyncWrite + Unpin> FeatureWriter<W> {
+    /// Writes a feature.
+    pub async fn write(&mut self, feature: &Feature) -> Result<(), WriteError> {
+        use tokio::io::AsyncWriteExt;
+
+        if self.framing == GeoJsonFraming::TextSequence {
+            self.writer.write_all(&[RECORD_SEPARATOR]).await?;
+        }
+
+        crate::write(&mut self.writer, feature).await
+    }
+
+    /// Flushes the contained writer’s buffer.
+    pub async fn flush(&mut self) -> std::io::Result<()> {
+        tokio::io::AsyncWriteExt::flush(&mut self.writer).await
+    }
+}
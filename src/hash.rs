@@ -0,0 +1,221 @@
+use crate::canonical::canonical_value;
+use crate::ReadError;
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+/// A SHA-256 digest of the contents of a JSON Lines stream.
+pub type ContentHash = [u8; 32];
+
+/// Whether the order of lines affects a [`ContentHash`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HashMode {
+    /// Streams holding the same lines in a different order hash differently.
+    Ordered,
+    /// Streams holding the same lines, including any duplicates, hash the same whatever their
+    /// order. Useful for datasets that are shuffled or produced in parallel.
+    Unordered,
+}
+
+/// An error that occurred while hashing with [`hash_file`] or [`hash_reader`].
+#[derive(Debug, thiserror::Error)]
+pub enum HashError {
+    #[error("failed opening file")]
+    Open(#[source] io::Error),
+    #[error("failed reading line {line}")]
+    Read {
+        line: usize,
+        #[source]
+        source: ReadError,
+    },
+}
+
+/// Computes a digest over the canonical form of each line, so that streams holding the same
+/// values hash the same however they were formatted.
+///
+/// Lines are canonicalized as in [`canonicalize`](crate::canonicalize). Blank lines are ignored.
+#[derive(Debug, Clone)]
+pub struct ContentHasher {
+    mode: HashMode,
+    ordered: Sha256,
+    sum: ContentHash,
+    num_lines: u64,
+}
+
+impl ContentHasher {
+    /// Creates a new `ContentHasher` that has hashed nothing yet.
+    pub fn new(mode: HashMode) -> Self {
+        Self {
+            mode,
+            ordered: Sha256::new(),
+            sum: [0; 32],
+            num_lines: 0,
+        }
+    }
+
+    /// Hashes a single line of JSON Lines.
+    pub fn update_line(&mut self, line: &str) -> Result<(), serde_json::Error> {
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+
+        let value: Value = serde_json::from_str(line)?;
+        self.update_value(value)
+    }
+
+    /// Hashes a value as if it had been written as a line.
+    pub fn update<T: Serialize>(&mut self, t: &T) -> Result<(), serde_json::Error> {
+        self.update_value(serde_json::to_value(t)?)
+    }
+
+    fn update_value(&mut self, value: Value) -> Result<(), serde_json::Error> {
+        let mut line = serde_json::to_vec(&canonical_value(value))?;
+        line.push(b'\n');
+
+        match self.mode {
+            HashMode::Ordered => self.ordered.update(&line),
+            HashMode::Unordered => add_assign(&mut self.sum, &Sha256::digest(&line).into()),
+        }
+
+        self.num_lines += 1;
+
+        Ok(())
+    }
+
+    /// Returns the number of lines hashed so far.
+    pub fn num_lines(&self) -> u64 {
+        self.num_lines
+    }
+
+    /// Returns the digest of everything hashed.
+    pub fn finish(self) -> ContentHash {
+        match self.mode {
+            HashMode::Ordered => self.ordered.finalize().into(),
+            // The sum of the lines’ digests is the same whatever order they are added in. Hashing
+            // it along with the number of lines keeps the result the same length as an ordered
+            // digest and distinct from it.
+            HashMode::Unordered => Sha256::new()
+                .chain_update(b"unordered\n")
+                .chain_update(self.num_lines.to_be_bytes())
+                .chain_update(self.sum)
+                .finalize()
+                .into(),
+        }
+    }
+}
+
+/// Adds `rhs` to `lhs` as 256-bit big-endian integers, wrapping on overflow.
+fn add_assign(lhs: &mut ContentHash, rhs: &ContentHash) {
+    let mut carry = 0;
+
+    for (l, r) in lhs.iter_mut().zip(rhs).rev() {
+        let sum = u16::from(*l) + u16::from(*r) + carry;
+        *l = sum.to_be_bytes()[1];
+        carry = sum >> 8;
+    }
+}
+
+/// A writer that hashes every complete line passing through it on the way to the inner writer.
+///
+/// Writes fail with [`io::ErrorKind::InvalidData`] if a line is not valid JSON, after it has been
+/// passed on.
+#[derive(Debug)]
+pub struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: ContentHasher,
+    partial_line: Vec<u8>,
+}
+
+impl<W: Write> HashingWriter<W> {
+    /// Creates a new `HashingWriter` that writes to `inner`.
+    pub fn new(inner: W, mode: HashMode) -> Self {
+        Self {
+            inner,
+            hasher: ContentHasher::new(mode),
+            partial_line: Vec::new(),
+        }
+    }
+
+    /// Consumes the `HashingWriter`, returning the inner writer and the digest of every line
+    /// written. A final line without a trailing newline is hashed too.
+    pub fn finish(mut self) -> io::Result<(W, ContentHash)> {
+        if !self.partial_line.is_empty() {
+            let line = std::mem::take(&mut self.partial_line);
+            self.hash_line(&line)?;
+        }
+
+        Ok((self.inner, self.hasher.finish()))
+    }
+
+    fn hash_line(&mut self, line: &[u8]) -> io::Result<()> {
+        let line =
+            std::str::from_utf8(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        self.hasher
+            .update_line(line)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let num_written = self.inner.write(buf)?;
+        let mut written = buf.get(..num_written).unwrap_or_default();
+
+        while let Some(newline) = written.iter().position(|b| *b == b'\n') {
+            let (line, rest) = written.split_at(newline);
+            self.partial_line.extend_from_slice(line);
+            written = rest.get(1..).unwrap_or_default();
+
+            let line = std::mem::take(&mut self.partial_line);
+            self.hash_line(&line)?;
+        }
+
+        self.partial_line.extend_from_slice(written);
+
+        Ok(num_written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Hashes every line read from `reader`.
+pub fn hash_reader<R: BufRead>(mut reader: R, mode: HashMode) -> Result<ContentHash, HashError> {
+    let mut hasher = ContentHasher::new(mode);
+    let mut line = String::new();
+    let mut line_number = 0;
+
+    loop {
+        line.clear();
+        line_number += 1;
+
+        let read_error = |source| HashError::Read {
+            line: line_number,
+            source,
+        };
+
+        if reader
+            .read_line(&mut line)
+            .map_err(|e| read_error(ReadError::Io(e)))?
+            == 0
+        {
+            return Ok(hasher.finish());
+        }
+
+        hasher
+            .update_line(&line)
+            .map_err(|e| read_error(ReadError::Deserialize(e)))?;
+    }
+}
+
+/// Hashes every line of the JSON Lines file at `path`, for verifying a dataset’s integrity after
+/// a transfer or rebuild.
+pub fn hash_file<P: AsRef<Path>>(path: P, mode: HashMode) -> Result<ContentHash, HashError> {
+    let file = File::open(path).map_err(HashError::Open)?;
+    hash_reader(BufReader::new(file), mode)
+}
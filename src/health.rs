@@ -0,0 +1,313 @@
+#[cfg(not(feature = "tokio"))]
+mod imports {
+    pub(super) use std::io::{self, BufRead, BufReader, Write};
+    pub(super) use std::net::TcpStream;
+    #[cfg(unix)]
+    pub(super) use std::os::unix::net::UnixStream;
+}
+#[cfg(feature = "tokio")]
+mod imports {
+    pub(super) use tokio::io::{AsyncBufRead as BufRead, AsyncWrite as Write};
+}
+
+use crate::{Connection, ReadError, WriteError};
+use imports::*;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The field holding the nonce in both health checks and their replies.
+const HEALTH_CHECK_FIELD: &str = "health_check";
+
+static NEXT_NONCE: AtomicU64 = AtomicU64::new(1);
+
+/// An error that occurred while checking the health of a peer, or while answering a check.
+#[derive(Debug, thiserror::Error)]
+pub enum HealthError {
+    #[error("failed reading health check reply")]
+    Read(#[from] ReadError),
+    #[error("failed writing health check")]
+    Write(#[from] WriteError),
+    #[error("peer did not reply to health check within {0:?}")]
+    TimedOut(Duration),
+    #[error("peer replied with something other than a health check reply")]
+    UnexpectedReply(Value),
+    #[error("peer is not serving")]
+    NotServing,
+}
+
+/// Whether a peer is ready to handle messages, as reported in reply to a health check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    Serving,
+    NotServing,
+}
+
+/// A health check, written as `{"health_check":<nonce>}`.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Ping {
+    health_check: u64,
+}
+
+/// The reply to a health check, written as `{"health_check":<nonce>,"status":"serving"}`.
+#[derive(Serialize, Deserialize)]
+struct Pong {
+    health_check: u64,
+    status: HealthStatus,
+}
+
+impl Ping {
+    fn new() -> Self {
+        Self {
+            health_check: NEXT_NONCE.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// Returns a new health check as a complete line, ready to be written to a stream directly.
+    pub(crate) fn line() -> Vec<u8> {
+        let mut line = format!(
+            "{{\"{}\":{}}}",
+            HEALTH_CHECK_FIELD,
+            Self::new().health_check
+        );
+        line.push('\n');
+        line.into_bytes()
+    }
+
+    /// Returns the health check `value` holds, if it is one rather than an ordinary message.
+    fn from_value(value: &Value) -> Option<Self> {
+        let object = value.as_object()?;
+
+        if object.len() != 1 || !object.contains_key(HEALTH_CHECK_FIELD) {
+            return None;
+        }
+
+        serde_json::from_value(value.clone()).ok()
+    }
+
+    /// Returns whether `value` is the reply to a health check, rather than an ordinary message.
+    fn is_reply(value: &Value) -> bool {
+        match value.as_object() {
+            Some(object) => {
+                object.len() == 2
+                    && object.contains_key(HEALTH_CHECK_FIELD)
+                    && serde_json::from_value::<Pong>(value.clone()).is_ok()
+            }
+            None => false,
+        }
+    }
+
+    /// Checks that `value` is the reply to this health check, and that it reports the peer serving.
+    fn check_reply(&self, value: Value) -> Result<(), HealthError> {
+        match serde_json::from_value::<Pong>(value.clone()) {
+            Ok(pong) if pong.health_check == self.health_check => match pong.status {
+                HealthStatus::Serving => Ok(()),
+                HealthStatus::NotServing => Err(HealthError::NotServing),
+            },
+            _ => Err(HealthError::UnexpectedReply(value)),
+        }
+    }
+}
+
+/// Answers health checks sent by [`Connection::health_check`] on the server side of a connection.
+///
+/// Read with [`Connection::read_answering_health_checks`] in place of [`Connection::read`], and
+/// health checks are answered as they arrive, never reaching the rest of the program. Replies to
+/// health checks sent from this side, such as those sent by the [`Server`](crate::Server)
+/// watchdog, are skipped in the same way. Clones share
+/// the same status, so one kept by whatever supervises the server can mark every connection as not
+/// serving while it drains.
+#[derive(Debug, Clone)]
+pub struct HealthResponder {
+    serving: Arc<AtomicBool>,
+}
+
+impl Default for HealthResponder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HealthResponder {
+    /// Creates a new `HealthResponder` that reports [`HealthStatus::Serving`].
+    pub fn new() -> Self {
+        Self {
+            serving: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    /// Returns the status reported in reply to health checks.
+    pub fn status(&self) -> HealthStatus {
+        if self.serving.load(Ordering::Relaxed) {
+            HealthStatus::Serving
+        } else {
+            HealthStatus::NotServing
+        }
+    }
+
+    /// Sets the status reported in reply to health checks by this responder and all its clones.
+    pub fn set_status(&self, status: HealthStatus) {
+        self.serving
+            .store(status == HealthStatus::Serving, Ordering::Relaxed);
+    }
+
+    fn reply(&self, ping: &Ping) -> Pong {
+        Pong {
+            health_check: ping.health_check,
+            status: self.status(),
+        }
+    }
+}
+
+/// Maps a read that gave up because the socket’s read timeout elapsed to
+/// [`HealthError::TimedOut`].
+#[cfg(not(feature = "tokio"))]
+fn timed_out(e: ReadError, timeout: Duration) -> HealthError {
+    match e {
+        ReadError::Io(e)
+            if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+        {
+            HealthError::TimedOut(timeout)
+        }
+        e => HealthError::Read(e),
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl<R: BufRead, W: Write> Connection<R, W> {
+    /// Reads a line from the reader and deserializes it into a given type, first answering any
+    /// health checks that arrive before it on behalf of `responder`.
+    pub fn read_answering_health_checks<T: DeserializeOwned>(
+        &mut self,
+        responder: &HealthResponder,
+    ) -> Result<T, HealthError> {
+        loop {
+            let value: Value = self.read()?;
+
+            match Ping::from_value(&value) {
+                Some(ping) => {
+                    self.write(&responder.reply(&ping))?;
+                    self.flush().map_err(WriteError::Io)?;
+                }
+                None if Ping::is_reply(&value) => {}
+                None => {
+                    return serde_json::from_value(value).map_err(|e| ReadError::from(e).into())
+                }
+            }
+        }
+    }
+
+    /// Sends a health check and reads the reply, with the socket’s read timeout already set.
+    fn exchange_health_check(&mut self, timeout: Duration) -> Result<Duration, HealthError> {
+        let ping = Ping::new();
+        let start = Instant::now();
+
+        self.write(&ping)?;
+        self.flush().map_err(WriteError::Io)?;
+
+        let reply = self.read().map_err(|e| timed_out(e, timeout))?;
+        ping.check_reply(reply)?;
+
+        Ok(start.elapsed())
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl Connection<BufReader<TcpStream>, TcpStream> {
+    /// Sends a health check to the peer and waits up to `timeout` for a reply saying it is serving,
+    /// returning the round-trip time.
+    ///
+    /// The peer must answer with [`Connection::read_answering_health_checks`] or by sending the
+    /// equivalent reply itself, and nothing else may be in flight from the peer while the check is
+    /// made. After [`HealthError::TimedOut`] a late reply may still arrive, or part of one may have
+    /// been consumed, so the connection should be closed.
+    pub fn health_check(&mut self, timeout: Duration) -> Result<Duration, HealthError> {
+        let previous = self.writer_mut().read_timeout().map_err(ReadError::Io)?;
+        self.writer_mut()
+            .set_read_timeout(Some(timeout))
+            .map_err(ReadError::Io)?;
+
+        let result = self.exchange_health_check(timeout);
+
+        self.writer_mut()
+            .set_read_timeout(previous)
+            .map_err(ReadError::Io)?;
+
+        result
+    }
+}
+
+#[cfg(all(unix, not(feature = "tokio")))]
+impl Connection<BufReader<UnixStream>, UnixStream> {
+    /// Sends a health check to the peer and waits up to `timeout` for a reply saying it is serving,
+    /// returning the round-trip time. See the TCP version of this method for details.
+    pub fn health_check(&mut self, timeout: Duration) -> Result<Duration, HealthError> {
+        let previous = self.writer_mut().read_timeout().map_err(ReadError::Io)?;
+        self.writer_mut()
+            .set_read_timeout(Some(timeout))
+            .map_err(ReadError::Io)?;
+
+        let result = self.exchange_health_check(timeout);
+
+        self.writer_mut()
+            .set_read_timeout(previous)
+            .map_err(ReadError::Io)?;
+
+        result
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R: BufRead + Unpin, W: Write + Unpin> Connection<R, W> {
+    /// Reads a line from the reader and deserializes it into a given type, first answering any
+    /// health checks that arrive before it on behalf of `responder`.
+    pub async fn read_answering_health_checks<T: DeserializeOwned>(
+        &mut self,
+        responder: &HealthResponder,
+    ) -> Result<T, HealthError> {
+        loop {
+            let value: Value = self.read().await?;
+
+            match Ping::from_value(&value) {
+                Some(ping) => {
+                    self.write(&responder.reply(&ping)).await?;
+                    self.flush().await.map_err(WriteError::Io)?;
+                }
+                None if Ping::is_reply(&value) => {}
+                None => {
+                    return serde_json::from_value(value).map_err(|e| ReadError::from(e).into())
+                }
+            }
+        }
+    }
+
+    /// Sends a health check to the peer and waits up to `timeout` for a reply saying it is serving,
+    /// returning the round-trip time.
+    ///
+    /// The peer must answer with [`Connection::read_answering_health_checks`] or by sending the
+    /// equivalent reply itself, and nothing else may be in flight from the peer while the check is
+    /// made. After [`HealthError::TimedOut`] a late reply may still arrive, or part of one may have
+    /// been consumed, so the connection should be closed.
+    pub async fn health_check(&mut self, timeout: Duration) -> Result<Duration, HealthError> {
+        let ping = Ping::new();
+        let start = Instant::now();
+
+        let exchange = async {
+            self.write(&ping).await?;
+            self.flush().await.map_err(WriteError::Io)?;
+            Ok::<Value, HealthError>(self.read().await?)
+        };
+
+        let reply = tokio::time::timeout(timeout, exchange)
+            .await
+            .map_err(|_| HealthError::TimedOut(timeout))??;
+        ping.check_reply(reply)?;
+
+        Ok(start.elapsed())
+    }
+}
@@ -0,0 +1,243 @@
+use crate::WriteError;
+use flate2::write::{DeflateEncoder, GzEncoder};
+use flate2::Compression;
+use serde::Serialize;
+use std::io::{self, Write};
+
+/// The largest chunk [`NdjsonResponse`] buffers before sending it, even if no flush is due.
+const MAX_CHUNK_LEN: usize = 64 * 1024;
+
+/// The content encoding applied to the body of an [`NdjsonResponse`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ContentEncoding {
+    Identity,
+    Gzip,
+    Deflate,
+}
+
+impl ContentEncoding {
+    /// Picks the best encoding the client accepts, given the value of its `Accept-Encoding`
+    /// header. Quality values are honoured only to the extent of excluding encodings with `q=0`.
+    pub fn negotiate(accept_encoding: &str) -> Self {
+        let accepted = |name: &str| {
+            accept_encoding.split(',').any(|part| {
+                let mut params = part.split(';').map(str::trim);
+                let coding = params.next().unwrap_or_default();
+                let is_refused = params.any(|param| {
+                    param
+                        .strip_prefix("q=")
+                        .and_then(|q| q.parse::<f32>().ok())
+                        .is_some_and(|q| q == 0.0)
+                });
+
+                (coding.eq_ignore_ascii_case(name) || coding == "*") && !is_refused
+            })
+        };
+
+        if accepted("gzip") {
+            Self::Gzip
+        } else if accepted("deflate") {
+            Self::Deflate
+        } else {
+            Self::Identity
+        }
+    }
+
+    fn header_value(self) -> Option<&'static str> {
+        match self {
+            Self::Identity => None,
+            Self::Gzip => Some("gzip"),
+            Self::Deflate => Some("deflate"),
+        }
+    }
+}
+
+/// Writes the body of an HTTP/1.1 response in chunked transfer encoding, sending a chunk
+/// whenever it is flushed or has grown large.
+#[derive(Debug)]
+struct ChunkedWriter<W: Write> {
+    inner: W,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> ChunkedWriter<W> {
+    fn send_chunk(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+
+        write!(self.inner, "{:x}\r\n", self.buf.len())?;
+        self.inner.write_all(&self.buf)?;
+        self.inner.write_all(b"\r\n")?;
+        self.buf.clear();
+
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<W> {
+        self.send_chunk()?;
+        self.inner.write_all(b"0\r\n\r\n")?;
+        self.inner.flush()?;
+
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for ChunkedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+
+        if self.buf.len() >= MAX_CHUNK_LEN {
+            self.send_chunk()?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send_chunk()?;
+        self.inner.flush()
+    }
+}
+
+#[derive(Debug)]
+enum Body<W: Write> {
+    Identity(ChunkedWriter<W>),
+    Gzip(GzEncoder<ChunkedWriter<W>>),
+    Deflate(DeflateEncoder<ChunkedWriter<W>>),
+}
+
+impl<W: Write> Body<W> {
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            Self::Identity(writer) => writer,
+            Self::Gzip(writer) => writer,
+            Self::Deflate(writer) => writer,
+        }
+    }
+
+    fn finish(self) -> io::Result<W> {
+        match self {
+            Self::Identity(writer) => writer.finish(),
+            Self::Gzip(writer) => writer.finish()?.finish(),
+            Self::Deflate(writer) => writer.finish()?.finish(),
+        }
+    }
+}
+
+/// Streams JSON Lines as the response to an HTTP/1.1 request, written straight to the client’s
+/// connection.
+///
+/// Buffering by HTTP servers and compressors would hold records back, defeating the point of
+/// streaming NDJSON, so the body is sent in chunked transfer encoding with a chunk for every line
+/// by default, and a compressor is flushed at the same points so each line can be decompressed as
+/// soon as it arrives. [`NdjsonResponse::flush_every`] trades that latency for fewer, larger
+/// chunks.
+///
+/// The status line and headers are written along with the first record.
+#[derive(Debug)]
+pub struct NdjsonResponse<W: Write> {
+    writer: Option<W>,
+    body: Option<Body<W>>,
+    encoding: ContentEncoding,
+    flush_every: Option<usize>,
+    num_unflushed: usize,
+}
+
+impl<W: Write> NdjsonResponse<W> {
+    /// Creates a new `NdjsonResponse` that writes an uncompressed response to `writer`, flushing
+    /// after every line.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: Some(writer),
+            body: None,
+            encoding: ContentEncoding::Identity,
+            flush_every: Some(1),
+            num_unflushed: 0,
+        }
+    }
+
+    /// Sets the content encoding of the body, which should be one the client accepts; see
+    /// [`ContentEncoding::negotiate`].
+    pub fn content_encoding(mut self, encoding: ContentEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Sets how many lines are written between automatic flushes, or `None` to only flush when
+    /// [`NdjsonResponse::flush`] is called. Defaults to one, flushing after every line.
+    pub fn flush_every(mut self, num_lines: Option<usize>) -> Self {
+        self.flush_every = num_lines.filter(|num_lines| *num_lines > 0);
+        self
+    }
+
+    /// Writes a given value as a line of the response, serializing it into JSON.
+    pub fn write<T: Serialize>(&mut self, t: &T) -> Result<(), WriteError> {
+        crate::blocking::write(self.body()?.writer(), t)?;
+        self.num_unflushed += 1;
+
+        if self.flush_every.is_some_and(|n| self.num_unflushed >= n) {
+            self.flush().map_err(WriteError::Io)?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends every line written so far to the client.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.num_unflushed = 0;
+        self.body()?.writer().flush()
+    }
+
+    /// Ends the response, returning the writer it was written to.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.body()?;
+
+        match self.body.take() {
+            Some(body) => body.finish(),
+            None => Err(io::ErrorKind::BrokenPipe.into()),
+        }
+    }
+
+    /// Returns the body, writing the status line and headers first if they have not been yet.
+    fn body(&mut self) -> io::Result<&mut Body<W>> {
+        if let Some(mut writer) = self.writer.take() {
+            write!(
+                writer,
+                "HTTP/1.1 200 OK\r\n\
+                 Content-Type: application/x-ndjson\r\n\
+                 Transfer-Encoding: chunked\r\n\
+                 Cache-Control: no-cache\r\n"
+            )?;
+
+            if let Some(encoding) = self.encoding.header_value() {
+                write!(
+                    writer,
+                    "Content-Encoding: {}\r\nVary: Accept-Encoding\r\n",
+                    encoding
+                )?;
+            }
+
+            writer.write_all(b"\r\n")?;
+
+            let chunked = ChunkedWriter {
+                inner: writer,
+                buf: Vec::new(),
+            };
+
+            self.body = Some(match self.encoding {
+                ContentEncoding::Identity => Body::Identity(chunked),
+                ContentEncoding::Gzip => {
+                    Body::Gzip(GzEncoder::new(chunked, Compression::default()))
+                }
+                ContentEncoding::Deflate => {
+                    Body::Deflate(DeflateEncoder::new(chunked, Compression::default()))
+                }
+            });
+        }
+
+        self.body
+            .as_mut()
+            .ok_or_else(|| io::ErrorKind::BrokenPipe.into())
+    }
+}
@@ -0,0 +1,95 @@
+#[cfg(not(feature = "tokio"))]
+mod imports {
+    pub(super) use std::io::{self, BufRead, BufReader, Write};
+}
+#[cfg(feature = "tokio")]
+mod imports {
+    pub(super) use socket2::Domain;
+    pub(super) use tokio::io::{self, AsyncBufRead as BufRead, AsyncWrite as Write, BufReader};
+    pub(super) use tokio::net::{TcpStream, UnixStream};
+}
+
+use crate::Connection;
+use imports::*;
+use socket2::{SockRef, Socket};
+use std::io::IsTerminal;
+
+/// What the standard input of the current process is connected to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum StdinKind {
+    /// A socket, which usually means the process was launched by inetd, systemd with `Accept=yes`
+    /// or a similar superserver. The same socket is also used as standard output.
+    Socket,
+    /// An interactive terminal.
+    Terminal,
+    /// Anything else, such as a pipe or a regular file.
+    Other,
+}
+
+/// Detects what the standard input of the current process is connected to.
+pub fn stdin_kind() -> StdinKind {
+    let stdin = std::io::stdin();
+
+    if SockRef::from(&stdin).r#type().is_ok() {
+        StdinKind::Socket
+    } else if stdin.is_terminal() {
+        StdinKind::Terminal
+    } else {
+        StdinKind::Other
+    }
+}
+
+/// A `Connection` whose reader and writer are chosen at runtime, as created by
+/// [`Connection::new_from_stdio_or_socket`].
+#[cfg(not(feature = "tokio"))]
+pub type DynConnection = Connection<Box<dyn BufRead + Send>, Box<dyn Write + Send>>;
+
+/// A `Connection` whose reader and writer are chosen at runtime, as created by
+/// [`Connection::new_from_stdio_or_socket`].
+#[cfg(feature = "tokio")]
+pub type DynConnection = Connection<Box<dyn BufRead + Send + Unpin>, Box<dyn Write + Send + Unpin>>;
+
+impl DynConnection {
+    /// Creates a new `Connection` that speaks over the socket on standard input if the process was
+    /// launched inetd-style (see [`stdin_kind`]), and over the stdio of the current process
+    /// otherwise, as with [`Connection::new_from_stdio`].
+    ///
+    /// This lets one binary serve both as a stdio-based tool and as a socket-activated service.
+    pub fn new_from_stdio_or_socket() -> io::Result<Self> {
+        if stdin_kind() != StdinKind::Socket {
+            let connection = Connection::new_from_stdio();
+            let (reader, writer) = connection.into_parts();
+            return Ok(Connection::new(Box::new(reader), Box::new(writer)));
+        }
+
+        let socket = SockRef::from(&std::io::stdin()).try_clone()?;
+        socket.set_cloexec(true)?;
+
+        Self::new_from_socket(socket)
+    }
+
+    #[cfg(not(feature = "tokio"))]
+    fn new_from_socket(socket: Socket) -> io::Result<Self> {
+        let reader = BufReader::new(socket.try_clone()?);
+        Ok(Connection::new(Box::new(reader), Box::new(socket)))
+    }
+
+    #[cfg(feature = "tokio")]
+    fn new_from_socket(socket: Socket) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+
+        if socket.local_addr()?.domain() == Domain::UNIX {
+            let (read_half, write_half) = UnixStream::from_std(socket.into())?.into_split();
+            Ok(Connection::new(
+                Box::new(BufReader::new(read_half)),
+                Box::new(write_half),
+            ))
+        } else {
+            let (read_half, write_half) = TcpStream::from_std(socket.into())?.into_split();
+            Ok(Connection::new(
+                Box::new(BufReader::new(read_half)),
+                Box::new(write_half),
+            ))
+        }
+    }
+}
@@ -0,0 +1,315 @@
+use crate::{DirectoryTail, ReadError};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// How far an [`Ingest`] has got through its source: the file being read and the offset just past
+/// the last record processed from it.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub path: PathBuf,
+    pub offset: u64,
+}
+
+/// Somewhere to persist the [`Checkpoint`]s of an [`Ingest`], so that it can pick up where it left
+/// off after a restart.
+pub trait CheckpointStore {
+    /// Returns the most recently saved checkpoint, if any.
+    fn load(&mut self) -> io::Result<Option<Checkpoint>>;
+
+    /// Saves a checkpoint. This must not return until the checkpoint is durable.
+    fn save(&mut self, checkpoint: &Checkpoint) -> io::Result<()>;
+}
+
+/// A [`CheckpointStore`] keeping the checkpoint as JSON in a file.
+///
+/// Checkpoints are written to a temporary file next to the checkpoint file, synced and then renamed
+/// over it, and the directory is synced after the rename, so that a crash never leaves a partially
+/// written checkpoint behind or loses one that was saved.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FileCheckpointStore {
+    path: PathBuf,
+}
+
+impl FileCheckpointStore {
+    /// Creates a new `FileCheckpointStore` keeping the checkpoint in the file at `path`.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl CheckpointStore for FileCheckpointStore {
+    fn load(&mut self) -> io::Result<Option<Checkpoint>> {
+        match fs::read(&self.path) {
+            Ok(json) => Ok(Some(serde_json::from_slice(&json)?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn save(&mut self, checkpoint: &Checkpoint) -> io::Result<()> {
+        let mut temp_path = self.path.clone().into_os_string();
+        temp_path.push(".tmp");
+
+        let mut file = File::create(&temp_path)?;
+        file.write_all(&serde_json::to_vec(checkpoint)?)?;
+        file.sync_all()?;
+        drop(file);
+
+        fs::rename(&temp_path, &self.path)?;
+
+        // The rename itself is only durable once the directory containing the checkpoint is synced.
+        #[cfg(unix)]
+        {
+            let dir = match self.path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            File::open(dir)?.sync_all()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An error that occurred while running an [`Ingest`].
+#[derive(Debug, thiserror::Error)]
+pub enum IngestError<E: std::error::Error + 'static> {
+    #[error("failed reading a record")]
+    Read(#[from] ReadError),
+    #[error("failed loading or saving a checkpoint")]
+    Checkpoint(#[source] io::Error),
+    #[error("failed processing a record")]
+    Process(#[source] E),
+    #[error("failed writing a malformed line to the dead letter file")]
+    DeadLetter(#[source] io::Error),
+}
+
+/// What an [`Ingest`] does with a line that is not valid UTF-8 or cannot be deserialized into a
+/// record.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum MalformedPolicy {
+    /// Stops ingesting, returning the error. Since the checkpoint stays before the line, a
+    /// restarted `Ingest` fails on the same line again until it is fixed or removed.
+    #[default]
+    Fail,
+    /// Skips the line, so that the checkpoint moves past it.
+    Skip,
+    /// Appends the line, byte for byte, to the file at the given path and skips it, so that the
+    /// checkpoint moves past it. The line is synced to the file before the checkpoint can move past
+    /// it.
+    DeadLetter(PathBuf),
+}
+
+/// Feeds every record of a [`DirectoryTail`] through a processing closure, saving a [`Checkpoint`]
+/// as it goes so that a restarted process carries on after the last record it processed.
+///
+/// The closure is expected to hand each record to a durable sink (a database, a queue, another
+/// file) before returning. Since a checkpoint is only saved after the closure returns, every record
+/// is processed at least once, and only records processed after the last checkpoint are processed
+/// again after a crash. Sinks that can recognize records they have already seen (for instance by a
+/// unique ID) therefore see every record exactly once.
+#[derive(Debug)]
+pub struct Ingest<S> {
+    source: DirectoryTail,
+    store: S,
+    checkpoint_every: usize,
+    on_malformed: MalformedPolicy,
+}
+
+impl<S: CheckpointStore> Ingest<S> {
+    /// Creates a new `Ingest` reading from `source` and saving checkpoints to `store`.
+    pub fn new(source: DirectoryTail, store: S) -> Self {
+        Self {
+            source,
+            store,
+            checkpoint_every: 1,
+            on_malformed: MalformedPolicy::Fail,
+        }
+    }
+
+    /// Sets how many records are processed between checkpoints. Defaults to one, which saves a
+    /// checkpoint after every record; larger values trade more records processed again after a
+    /// crash for fewer writes to the checkpoint store.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `checkpoint_every` is zero.
+    pub fn checkpoint_every(mut self, checkpoint_every: usize) -> Self {
+        assert!(checkpoint_every > 0, "checkpoint interval must be nonzero");
+        self.checkpoint_every = checkpoint_every;
+        self
+    }
+
+    /// Sets what is done with lines that cannot be deserialized into a record. Defaults to
+    /// [`MalformedPolicy::Fail`].
+    pub fn on_malformed(mut self, on_malformed: MalformedPolicy) -> Self {
+        self.on_malformed = on_malformed;
+        self
+    }
+
+    /// Resumes from the last saved checkpoint, if any, and processes records with `process` as they
+    /// become available. This only returns if an error occurs.
+    pub fn run<T, E, F>(mut self, mut process: F) -> Result<(), IngestError<E>>
+    where
+        T: DeserializeOwned,
+        E: std::error::Error + 'static,
+        F: FnMut(T) -> Result<(), E>,
+    {
+        let mut source = match self.store.load().map_err(IngestError::Checkpoint)? {
+            Some(checkpoint) => self
+                .source
+                .resume_at(checkpoint.path, checkpoint.offset)
+                .map_err(IngestError::Checkpoint)?,
+            None => self.source,
+        };
+        let mut dead_letter = match &self.on_malformed {
+            MalformedPolicy::DeadLetter(path) => Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(IngestError::DeadLetter)?,
+            ),
+            MalformedPolicy::Fail | MalformedPolicy::Skip => None,
+        };
+        let mut num_unsaved = 0;
+
+        loop {
+            let line = source.read_line()?;
+            let record = match crate::tail::decode_line(&line) {
+                Ok(line) => serde_json::from_str::<T>(line).map_err(ReadError::Deserialize),
+                Err(e) => Err(ReadError::Io(e)),
+            };
+
+            match record {
+                Ok(record) => process(record).map_err(IngestError::Process)?,
+                Err(e) => match (&self.on_malformed, &mut dead_letter) {
+                    (MalformedPolicy::Skip, _) => {}
+                    (MalformedPolicy::DeadLetter(_), Some(file)) => {
+                        write_dead_letter(file, &line).map_err(IngestError::DeadLetter)?
+                    }
+                    _ => return Err(e.into()),
+                },
+            }
+            num_unsaved += 1;
+
+            if num_unsaved == self.checkpoint_every {
+                if let Some((path, offset)) = source.position() {
+                    let checkpoint = Checkpoint {
+                        path: path.to_path_buf(),
+                        offset,
+                    };
+                    self.store
+                        .save(&checkpoint)
+                        .map_err(IngestError::Checkpoint)?;
+                }
+
+                num_unsaved = 0;
+            }
+        }
+    }
+}
+
+/// Appends `line` to the dead letter file and syncs it, so that it is durable before the checkpoint
+/// moves past it.
+fn write_dead_letter(file: &mut File, line: &[u8]) -> io::Result<()> {
+    file.write_all(line)?;
+    if !line.ends_with(b"\n") {
+        file.write_all(b"\n")?;
+    }
+    file.sync_data()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ingests `contents` with `policy`, stopping at the record `3`, and returns the records
+    /// processed and how the run ended.
+    fn ingest(
+        contents: &[u8],
+        policy: MalformedPolicy,
+    ) -> Result<(Vec<u32>, IngestError<io::Error>), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let source = dir.path().join("source");
+        fs::create_dir(&source)?;
+        fs::write(source.join("0.jsonl"), contents)?;
+
+        let store = FileCheckpointStore::new(dir.path().join("checkpoint"));
+        let mut records = Vec::new();
+        let e = Ingest::new(DirectoryTail::new(&source), store)
+            .on_malformed(policy)
+            .run(|record: u32| {
+                records.push(record);
+                match record {
+                    3 => Err(io::Error::other("stop")),
+                    _ => Ok(()),
+                }
+            });
+
+        match e {
+            Ok(()) => Err("ingest returned without an error".into()),
+            Err(e) => Ok((records, e)),
+        }
+    }
+
+    #[test]
+    fn fail_stops_on_malformed_lines() -> Result<(), Box<dyn std::error::Error>> {
+        let (records, e) = ingest(b"1\n\"\xff\"\n3\n", MalformedPolicy::Fail)?;
+        assert_eq!(records, [1]);
+        assert!(matches!(e, IngestError::Read(ReadError::Io(_))));
+
+        let (records, e) = ingest(b"1\n{\n3\n", MalformedPolicy::Fail)?;
+        assert_eq!(records, [1]);
+        assert!(matches!(e, IngestError::Read(ReadError::Deserialize(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn skip_moves_past_malformed_lines() -> Result<(), Box<dyn std::error::Error>> {
+        let (records, e) = ingest(b"1\n\"\xff\"\n{\n3\n", MalformedPolicy::Skip)?;
+        assert_eq!(records, [1, 3]);
+        assert!(matches!(e, IngestError::Process(_)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn dead_letter_keeps_malformed_lines() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let dead_letter = dir.path().join("dead");
+
+        let (records, e) = ingest(
+            b"1\n\"\xff\"\n{\n3\n",
+            MalformedPolicy::DeadLetter(dead_letter.clone()),
+        )?;
+        assert_eq!(records, [1, 3]);
+        assert!(matches!(e, IngestError::Process(_)));
+        assert_eq!(fs::read(dead_letter)?, b"\"\xff\"\n{\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn checkpoints_survive_a_restart() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("checkpoint");
+        let checkpoint = Checkpoint {
+            path: dir.path().join("0.jsonl"),
+            offset: 42,
+        };
+
+        FileCheckpointStore::new(&path).save(&checkpoint)?;
+
+        assert_eq!(FileCheckpointStore::new(&path).load()?, Some(checkpoint));
+        assert!(!dir.path().join("checkpoint.tmp").exists());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+use std::io::{self, BufRead, Read};
+
+/// A reader that accepts JSON typed by a human, as created by [`Connection::interactive`].
+///
+/// Each line is rewritten into strict JSON before it is parsed: single-quoted strings become
+/// double-quoted ones, and trailing commas before a closing bracket or brace are dropped. Lines
+/// that are already strict JSON pass through unchanged.
+///
+/// [`Connection::interactive`]: crate::blocking::Connection::interactive
+#[derive(Debug)]
+pub struct LenientReader<R: BufRead> {
+    inner: R,
+    line: String,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: BufRead> LenientReader<R> {
+    /// Creates a new `LenientReader` that rewrites the lines read from `inner`.
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            line: String::new(),
+            buf: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// Consumes the `LenientReader`, returning the wrapped reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: BufRead> Read for LenientReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+
+        Ok(len)
+    }
+}
+
+impl<R: BufRead> BufRead for LenientReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos >= self.buf.len() {
+            self.line.clear();
+            self.inner.read_line(&mut self.line)?;
+            self.buf = relax(&self.line).into_bytes();
+            self.pos = 0;
+        }
+
+        Ok(self.buf.get(self.pos..).unwrap_or_default())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = self.pos.saturating_add(amt).min(self.buf.len());
+    }
+}
+
+/// Rewrites relaxed JSON into strict JSON, converting single-quoted strings and removing trailing
+/// commas. Invalid input is left for the JSON parser to reject.
+pub(crate) fn relax(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                out.push('"');
+
+                while let Some(c) = chars.next() {
+                    out.push(c);
+
+                    match c {
+                        '\\' => out.extend(chars.next()),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '\'' => {
+                out.push('"');
+
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => match chars.next() {
+                            Some('\'') => out.push('\''),
+                            Some(c) => {
+                                out.push('\\');
+                                out.push(c);
+                            }
+                            None => {}
+                        },
+                        '"' => out.push_str("\\\""),
+                        '\'' => break,
+                        c => out.push(c),
+                    }
+                }
+
+                out.push('"');
+            }
+            ',' => {
+                let rest = chars.clone().find(|c| !c.is_whitespace());
+
+                if !matches!(rest, Some(']') | Some('}')) {
+                    out.push(',');
+                }
+            }
+            c => out.push(c),
+        }
+    }
+
+    out
+}
@@ -0,0 +1,227 @@
+use crate::pipeline::{partition_of, NUM_SPILL_PARTITIONS};
+use crate::{PipelineError, ReadError};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::Hash;
+use std::io::{self, BufRead, BufReader, BufWriter, Seek};
+use std::vec;
+
+/// The memory budget used by [`join`].
+const DEFAULT_MEMORY_BUDGET: usize = 256 * 1024 * 1024;
+
+#[derive(Debug)]
+enum LeftSource<L> {
+    Reader(L),
+    Partition(BufReader<File>),
+    Done,
+}
+
+/// An iterator over the merged records produced by [`join`] and [`join_with_memory_budget`].
+#[derive(Debug)]
+pub struct Join<L, K, F> {
+    left: LeftSource<L>,
+    key_fn: F,
+    table: HashMap<K, Vec<Value>>,
+    matches: vec::IntoIter<Value>,
+    partitions: Vec<(File, File)>,
+}
+
+/// Joins the records of `left` with the records of `right` that have the same key, as returned by
+/// `key_fn`, using a memory budget of 256 MiB. See [`join_with_memory_budget`] for details.
+pub fn join<L, R, K, F>(left: L, right: R, key_fn: F) -> Result<Join<L, K, F>, PipelineError>
+where
+    L: BufRead,
+    R: BufRead,
+    K: Eq + Hash,
+    F: FnMut(&Value) -> K,
+{
+    join_with_memory_budget(left, right, key_fn, DEFAULT_MEMORY_BUDGET)
+}
+
+/// Joins the records of `left` with the records of `right` that have the same key, as returned by
+/// `key_fn`, yielding one merged record for every matching pair. Records without a match on the
+/// other side are dropped, as in an SQL inner join.
+///
+/// When both records of a pair are objects they are merged into a single object holding the fields
+/// of both, with the fields of the left record taking precedence; otherwise the pair is yielded as
+/// a two-element array.
+///
+/// Every record of `right` is read up front into a hash table, after which `left` is streamed
+/// through it; put the smaller input on the right. If the right input takes up more than
+/// `memory_budget` bytes of JSON, both inputs are instead split by key across temporary files and
+/// joined one pair of files at a time, which requires each part of the right input to fit in
+/// memory.
+pub fn join_with_memory_budget<L, R, K, F>(
+    left: L,
+    mut right: R,
+    mut key_fn: F,
+    memory_budget: usize,
+) -> Result<Join<L, K, F>, PipelineError>
+where
+    L: BufRead,
+    R: BufRead,
+    K: Eq + Hash,
+    F: FnMut(&Value) -> K,
+{
+    let mut table: HashMap<K, Vec<Value>> = HashMap::new();
+    let mut size = 0;
+    let mut buf = String::new();
+
+    while let Some(record) = read_record(&mut right, &mut buf)? {
+        size += buf.len();
+        table.entry(key_fn(&record)).or_default().push(record);
+
+        if size > memory_budget {
+            return spill(left, right, key_fn, table);
+        }
+    }
+
+    Ok(Join {
+        left: LeftSource::Reader(left),
+        key_fn,
+        table,
+        matches: Vec::new().into_iter(),
+        partitions: Vec::new(),
+    })
+}
+
+/// Splits both inputs across partition files by the hash of their key, starting with the records
+/// of the right input already read into `table`.
+fn spill<L, R, K, F>(
+    mut left: L,
+    mut right: R,
+    mut key_fn: F,
+    table: HashMap<K, Vec<Value>>,
+) -> Result<Join<L, K, F>, PipelineError>
+where
+    L: BufRead,
+    R: BufRead,
+    K: Eq + Hash,
+    F: FnMut(&Value) -> K,
+{
+    let mut left_partitions = create_partitions()?;
+    let mut right_partitions = create_partitions()?;
+    let mut buf = String::new();
+
+    for (key, records) in table {
+        let partition = &mut right_partitions[partition_of(&key, NUM_SPILL_PARTITIONS)];
+
+        for record in records {
+            crate::blocking::write(&mut *partition, &record)?;
+        }
+    }
+
+    while let Some(record) = read_record(&mut right, &mut buf)? {
+        let partition = &mut right_partitions[partition_of(&key_fn(&record), NUM_SPILL_PARTITIONS)];
+        crate::blocking::write(partition, &record)?;
+    }
+
+    while let Some(record) = read_record(&mut left, &mut buf)? {
+        let partition = &mut left_partitions[partition_of(&key_fn(&record), NUM_SPILL_PARTITIONS)];
+        crate::blocking::write(partition, &record)?;
+    }
+
+    let partitions = left_partitions
+        .into_iter()
+        .zip(right_partitions)
+        .map(|(left, right)| Ok((finish_partition(left)?, finish_partition(right)?)))
+        .collect::<io::Result<_>>()?;
+
+    Ok(Join {
+        left: LeftSource::Done,
+        key_fn,
+        table: HashMap::new(),
+        matches: Vec::new().into_iter(),
+        partitions,
+    })
+}
+
+fn create_partitions() -> io::Result<Vec<BufWriter<File>>> {
+    (0..NUM_SPILL_PARTITIONS)
+        .map(|_| Ok(BufWriter::new(tempfile::tempfile()?)))
+        .collect()
+}
+
+fn finish_partition(partition: BufWriter<File>) -> io::Result<File> {
+    let mut file = partition.into_inner().map_err(|e| e.into_error())?;
+    file.rewind()?;
+    Ok(file)
+}
+
+/// Reads a single record, leaving its line in `buf` so that its size can be measured.
+fn read_record<R: BufRead>(reader: &mut R, buf: &mut String) -> Result<Option<Value>, ReadError> {
+    buf.clear();
+
+    if reader.read_line(buf)? == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(serde_json::from_str(buf)?))
+}
+
+fn merge(left: &Value, right: &Value) -> Value {
+    match (left, right) {
+        (Value::Object(left), Value::Object(right)) => {
+            let mut merged = right.clone();
+            merged.extend(left.iter().map(|(key, value)| (key.clone(), value.clone())));
+            Value::Object(merged)
+        }
+        (left, right) => Value::Array(vec![left.clone(), right.clone()]),
+    }
+}
+
+impl<L, K, F> Iterator for Join<L, K, F>
+where
+    L: BufRead,
+    K: Eq + Hash,
+    F: FnMut(&Value) -> K,
+{
+    type Item = Result<Value, PipelineError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(record) = self.matches.next() {
+                return Some(Ok(record));
+            }
+
+            let result = match &mut self.left {
+                LeftSource::Reader(reader) => crate::blocking::read(reader),
+                LeftSource::Partition(reader) => crate::blocking::read(reader),
+                LeftSource::Done => Err(ReadError::Eof),
+            };
+
+            match result {
+                Ok(left) => {
+                    if let Some(matches) = self.table.get(&(self.key_fn)(&left)) {
+                        self.matches = matches
+                            .iter()
+                            .map(|right| merge(&left, right))
+                            .collect::<Vec<_>>()
+                            .into_iter();
+                    }
+                }
+                Err(ReadError::Eof) => {
+                    let (left, right) = self.partitions.pop()?;
+                    self.left = LeftSource::Partition(BufReader::new(left));
+                    self.table.clear();
+
+                    let mut right = BufReader::new(right);
+                    loop {
+                        let record: Value = match crate::blocking::read(&mut right) {
+                            Ok(record) => record,
+                            Err(ReadError::Eof) => break,
+                            Err(e) => return Some(Err(e.into())),
+                        };
+
+                        self.table
+                            .entry((self.key_fn)(&record))
+                            .or_default()
+                            .push(record);
+                    }
+                }
+                Err(e) => return Some(Err(e.into())),
+            }
+        }
+    }
+}
@@ -1,4 +1,12 @@
 #![warn(rust_2018_idioms, missing_debug_implementations)]
+#![deny(
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic,
+    clippy::unreachable,
+    clippy::todo,
+    clippy::unimplemented
+)]
 
 //! An implementation of JSON Lines for Rust.
 //!
@@ -9,72 +17,485 @@
 //! See [`Connection`] for situations in which you have both a reader and a writer and would like to
 //! bundle them up together.
 //!
-//! Enable the `tokio` feature to replace the usages of `std` IO primitives with those from Tokio.
+//! # Panics
+//!
+//! Malformed or hostile input is always reported as an error rather than a panic, so this crate is
+//! safe to point at untrusted data. The only panics are those documented on individual functions
+//! for invalid arguments, such as a batch size of zero, and panics raised by your own closures.
+//!
+//! # Features
+//!
+//! - `tokio`: makes the top-level API asynchronous, built on Tokio’s IO primitives rather than
+//!   `std`’s. The `std`-based API stays available in [`blocking`], so code that uses it from there
+//!   is unaffected by whether this feature is enabled. [`ConnectionBuilder`] is the exception: it
+//!   only builds top-level connections, so with this feature there is no blocking equivalent.
+//! - `arbitrary-precision`: keeps numbers read into a [`serde_json::Value`] exactly as written;
+//!   see [`NumberPolicy`].
+//! - `arrow`: converts between JSON Lines and Arrow record batches with [`read_record_batches`]
+//!   and [`write_record_batches`].
+//! - `blob`: sends large binary payloads as chunked base64 lines with [`write_blob`], and reads
+//!   them back with [`BlobReader`].
+//! - `bytes`: decodes from and encodes into [`bytes::BytesMut`] buffers without copying, with
+//!   [`Decoder::decode_from`] and [`encode_into_bytes`].
+//! - `codec`: provides [`JsonLinesCodec`], a `tokio_util` codec for using JSON Lines with
+//!   `Framed`.
+//! - `derive`: generates typed RPC clients and servers from a trait with [`macro@rpc`], and
+//!   protocol message enums with [`macro@message`].
+//! - `docker`: runs commands inside containers with [`Connection::new_from_docker_exec`], and
+//!   demultiplexes the Docker Engine API’s stream framing with [`DockerDemux`].
+//! - `elasticsearch`: writes Elasticsearch and OpenSearch `_bulk` request bodies with
+//!   [`BulkWriter`].
+//! - `encryption`: encrypts selected fields of records with [`FieldCipher`].
+//! - `fd-passing`: sends file descriptors alongside lines over Unix domain sockets with
+//!   [`Connection::write_with_fds`] and [`Connection::read_with_fds`].
+//! - `geojson`: reads and writes newline-delimited GeoJSON features and GeoJSON text sequences
+//!   with [`FeatureReader`] and [`FeatureWriter`].
+//! - `gzip`: reads and writes gzip-compressed JSON Lines; see [`Compression`].
+//! - `hash`: computes digests of JSON Lines streams and files that ignore formatting, and
+//!   optionally line order, with [`hash_file`] and [`HashingWriter`].
+//! - `http`: streams JSON Lines as an HTTP response, optionally compressed, with
+//!   [`NdjsonResponse`].
+//! - `kubernetes`: reads typed events from Kubernetes watch streams with [`WatchStream`].
+//! - `log`: logs every line read and written, with optional redaction, once turned on with
+//!   [`enable_wire_logging`] or the `JSONL_WIRE_LOG` environment variable.
+//! - `object-store`: streams JSON Lines from and to object storage such as Amazon S3 with
+//!   [`ObjectReader`] and [`ObjectWriter`].
+//! - `proptest`: generates valid and adversarial JSON Lines streams for property tests with the
+//!   strategies in [`strategies`].
+//! - `proxy`: connects through SOCKS5 and HTTP proxies with
+//!   [`ConnectionBuilder::connect_tcp_via_proxy`].
+//! - `pty`: talks to child processes through a pseudoterminal with [`Connection::new_from_pty`].
+//! - `quinn`: speaks JSON Lines over QUIC streams with [`Connection::open_quic_stream`] and
+//!   [`Connection::open_quic_channel`]. Enables `tokio`.
+//! - `rand`: picks a uniformly random sample of records with [`sample_reservoir`], and shuffles
+//!   records with [`shuffle`] and [`Dataset::shuffled`], and mixes sources by weight with
+//!   [`interleave`].
+//! - `repl`: speaks to a server interactively from a line-editing prompt with [`Repl`].
+//! - `shm`: speaks JSON Lines between processes on the same host through shared memory with
+//!   [`ShmTransport`].
+//! - `sqlite`: loads JSON Lines into SQLite tables with [`to_sqlite`] and turns the results of
+//!   SQLite queries back into JSON with [`from_sqlite`].
+//! - `sse`: reads JSON from server-sent event streams, as used by LLM APIs, with
+//!   [`EventStreamReader`].
+//! - `ssh`: runs commands on remote hosts with [`Connection::new_from_ssh`].
+//! - `stream`: adapts an asynchronous [`Connection`] into a `futures` `Stream` and `Sink` of
+//!   messages with [`Connection::into_stream_sink`]. Enables `tokio`.
+//! - `types`: provides serde adapters in [`types`] for `u128`s, decimals, timestamps and UUIDs
+//!   that other JSON Lines tools can read. Those for `rust_decimal` and `uuid` types also need
+//!   the features of the same names.
+//! - `wal`: keeps a crash-safe write-ahead log of records with [`WalWriter`] and [`recover`].
+//! - `zstd`: reads and writes Zstandard-compressed JSON Lines; see [`Compression`].
 
+#[cfg(target_os = "linux")]
+mod activation;
+#[cfg(feature = "arrow")]
+mod arrow;
+mod balance;
+mod batch;
+#[cfg(feature = "blob")]
+mod blob;
+pub mod blocking;
+mod breaker;
+mod builder;
+mod canonical;
+mod capture;
+mod chaos;
+#[cfg(feature = "codec")]
+mod codec;
+#[cfg(feature = "tokio")]
 mod connection;
+mod convert;
+mod dataset;
+mod datetime;
+mod decoder;
+mod diff;
+#[cfg(feature = "docker")]
+mod docker;
+#[cfg(feature = "elasticsearch")]
+mod elasticsearch;
+#[cfg(feature = "encryption")]
+mod encryption;
+mod endpoint;
 mod errors;
+#[cfg(all(unix, feature = "fd-passing"))]
+mod fd_passing;
+mod format;
+#[cfg(feature = "geojson")]
+mod geo;
+#[cfg(feature = "hash")]
+mod hash;
+mod health;
+#[cfg(feature = "http")]
+mod http;
+#[cfg(unix)]
+mod inetd;
+mod ingest;
+mod interactive;
+mod join;
+#[cfg(feature = "kubernetes")]
+mod kubernetes;
+mod lifecycle;
+mod map;
+mod message;
+mod middleware;
+mod multiline;
+mod mux;
+mod number;
+#[cfg(feature = "object-store")]
+mod object;
+mod pipeline;
+mod provenance;
+#[cfg(feature = "proxy")]
+mod proxy;
+#[cfg(all(unix, feature = "pty"))]
+mod pty;
+#[cfg(feature = "quinn")]
+mod quic;
+#[cfg(feature = "repl")]
+mod repl;
+mod resume;
+mod retention;
+mod retry;
+mod router;
+mod rpc;
+mod sample;
+mod server;
+mod session;
+#[cfg(all(unix, feature = "shm"))]
+mod shm;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+#[cfg(feature = "sse")]
+mod sse;
+#[cfg(feature = "ssh")]
+mod ssh;
+#[cfg(feature = "proptest")]
+pub mod strategies;
+#[cfg(feature = "stream")]
+mod stream;
+mod tail;
+mod terminal;
+pub mod testing;
+mod time_range;
+mod transcode;
+#[cfg(feature = "tokio")]
+mod transport;
+#[cfg(feature = "types")]
+pub mod types;
+mod version;
+#[cfg(feature = "wal")]
+mod wal;
+#[cfg(feature = "log")]
+mod wire_log;
+mod write_batch;
 
+#[cfg(target_os = "linux")]
+pub use activation::{activated_sockets, ActivatedSocket};
+#[cfg(feature = "arrow")]
+pub use arrow::{
+    read_record_batches, read_record_batches_with_schema, write_record_batches, RecordBatches,
+};
+pub use balance::{Balance, MultiEndpointClient};
+pub use batch::{BatchSink, ColumnBatch, RecordBatcher};
+#[cfg(feature = "blob")]
+pub use blob::{write_blob, BlobManifest, BlobReader, BLOB_CHUNK_SIZE};
+pub use blocking::StdioTransport;
+pub use breaker::{CircuitBreaker, CircuitError, CircuitMetrics, CircuitOpen, CircuitState};
+pub use builder::ConnectionBuilder;
+pub use canonical::{canonicalize, pretty_line, to_canonical_string};
+pub use capture::{CaptureEntry, Direction, Recorder, ReplayError, Replayer, Timing};
+pub use chaos::{Chaos, ChaosReader, ChaosWriter, Latency};
+#[cfg(feature = "codec")]
+pub use codec::{CodecError, JsonLinesCodec};
+#[cfg(feature = "tokio")]
 pub use connection::Connection;
+pub use convert::{Compression, Convert, ConvertError, ConvertProgress};
+#[cfg(feature = "rand")]
+pub use dataset::{interleave, Interleave};
+pub use dataset::{Dataset, DatasetError, DatasetIter};
+pub use datetime::{EpochUnit, TimestampError, TimestampNormalizer};
+#[cfg(feature = "bytes")]
+pub use decoder::encode_into_bytes;
+pub use decoder::{encode_into, ControlCharacters, DecodeError, Decoder, UnicodeSeparators};
+pub use diff::{diff, Diff, FieldChange, RecordDiff};
+#[cfg(feature = "docker")]
+pub use docker::DockerDemux;
+#[cfg(feature = "elasticsearch")]
+pub use elasticsearch::BulkWriter;
+#[cfg(feature = "encryption")]
+pub use encryption::{FieldCipher, FieldCipherError};
+pub use endpoint::{Receiver, Sender};
 pub use errors::{ReadError, WriteError};
+#[cfg(all(unix, feature = "fd-passing"))]
+pub use fd_passing::{FdReader, MAX_FDS_PER_LINE};
+pub use format::AsciiFormatter;
+#[cfg(feature = "geojson")]
+pub use geo::{FeatureReader, FeatureWriter, GeoJsonFraming};
+#[cfg(feature = "geojson")]
+pub use geojson;
+#[cfg(feature = "hash")]
+pub use hash::{
+    hash_file, hash_reader, ContentHash, ContentHasher, HashError, HashMode, HashingWriter,
+};
+pub use health::{HealthError, HealthResponder, HealthStatus};
+#[cfg(feature = "http")]
+pub use http::{ContentEncoding, NdjsonResponse};
+#[cfg(unix)]
+pub use inetd::{stdin_kind, DynConnection, StdinKind};
+pub use ingest::{
+    Checkpoint, CheckpointStore, FileCheckpointStore, Ingest, IngestError, MalformedPolicy,
+};
+pub use interactive::LenientReader;
+pub use join::{join, join_with_memory_budget, Join};
+#[cfg(feature = "derive")]
+pub use jsonl_macros::{message, rpc};
+#[cfg(feature = "kubernetes")]
+pub use kubernetes::{WatchEvent, WatchStatus, WatchStream};
+pub use lifecycle::{ConnectionState, InvalidTransition, Lifecycle};
+pub use map::MappedConnection;
+pub use message::{
+    read_or_unknown, Incoming, Message, MessageError, MessageReader, MessageWriter,
+    UnknownMessages, VERSION_FIELD,
+};
+pub use middleware::{
+    CatchPanic, CatchPanicService, Filter, FilterService, Inspect, InspectService, Layer,
+    RateLimit, RateLimitService,
+};
+pub use multiline::{
+    MultiLineError, MultiLineReader, MultiLineRecord, MultiLineWriter, RecordFraming,
+};
+pub use mux::{Demux, Mux};
+pub use number::{
+    deserialize_non_finite, NonFinitePolicy, NumberError, NumberPolicy, OverflowPolicy,
+};
+#[cfg(feature = "object-store")]
+pub use object::{ObjectReader, ObjectWriter};
+#[cfg(feature = "object-store")]
+pub use object_store;
+pub use pipeline::{
+    iter, Aggregate, GroupBy, Groups, ParallelMap, Pipeline, PipelineError, Records,
+};
+pub use provenance::{ProvenanceMode, ProvenanceWriter};
+#[cfg(feature = "proxy")]
+pub use proxy::Proxy;
+#[cfg(all(unix, feature = "pty"))]
+pub use pty::PtyMaster;
+#[cfg(feature = "quinn")]
+pub use quic::{QuicError, QuicTransport};
+#[cfg(feature = "repl")]
+pub use repl::{Repl, ReplError};
+pub use resume::{ResumableConnection, ResumeError};
+pub use retention::{Retention, RetentionReport};
+pub use retry::{Jitter, RetryPolicy};
+pub use router::{Router, RouterError};
+pub use rpc::{
+    current_deadline, serve, serve_with_policy, BidiCall, Client, Dispatcher, ErrorObject,
+    ErrorPolicy, ErrorResponse, ItemSink, ItemSource, LatencyStats, Request, Response,
+    ResponseStream, RpcError, Service, StreamFrame,
+};
+pub use sample::{head, stride, Head, Stride};
+#[cfg(feature = "rand")]
+pub use sample::{sample_reservoir, shuffle, Shuffle};
+#[cfg(unix)]
+pub use server::LISTENER_FD_ENV;
+pub use server::{Broadcaster, Rejection, Server, ServerConnection, ServerStream, ShutdownNotice};
+pub use session::Session;
+#[cfg(all(unix, feature = "shm"))]
+pub use shm::{ShmReader, ShmTransport, ShmWriter};
+#[cfg(feature = "sqlite")]
+pub use sqlite::{from_sqlite, to_sqlite, SqliteError, SqliteLayout, SqliteRows};
+#[cfg(feature = "sse")]
+pub use sse::EventStreamReader;
+#[cfg(feature = "stream")]
+pub use stream::MessageStream;
+pub use tail::{DirectoryTail, FileOrder, TailLag, TailMetrics};
+pub use terminal::{TerminalMode, TerminalWriter};
+pub use time_range::TimeRange;
+pub use transcode::{transcode, TranscodeError};
+#[cfg(feature = "tokio")]
+pub use transport::Transport;
+pub use version::{VersionError, Versioning};
+#[cfg(feature = "wal")]
+pub use wal::{recover, Recovery, WalReader, WalWriter};
+#[cfg(feature = "log")]
+pub use wire_log::{disable_wire_logging, enable_wire_logging, WIRE_LOG_ENV, WIRE_LOG_REDACT_ENV};
+pub use write_batch::WriteBatch;
+#[cfg(feature = "arrow")]
+pub use {arrow_array, arrow_schema};
+#[cfg(feature = "derive")]
+#[doc(hidden)]
+pub use {serde, serde_json};
 
 #[cfg(not(feature = "tokio"))]
+pub use blocking::{
+    read, read_opt, read_with_len, read_with_limit, write, write_all, write_canonical,
+    write_with_formatter, write_with_len, Connection, Transport,
+};
+
+#[cfg(feature = "tokio")]
 mod imp {
     use super::*;
-    use std::io::{BufRead, Write};
+    use tokio::io::{AsyncBufRead as BufRead, AsyncBufReadExt, AsyncWrite as Write, AsyncWriteExt};
 
     /// Reads a line from the reader and deserializes it into a given type.
-    pub fn read<R: BufRead, T: serde::de::DeserializeOwned>(mut reader: R) -> Result<T, ReadError> {
+    ///
+    /// The whole line is read into memory however long it is, so use [`read_with_limit`] when the
+    /// reader is not trusted.
+    pub async fn read<R: BufRead + Unpin, T: serde::de::DeserializeOwned>(
+        reader: R,
+    ) -> Result<T, ReadError> {
+        read_with_len(reader).await.map(|(t, _)| t)
+    }
+
+    /// Reads a line from the reader and deserializes it into a given type, returning `None`
+    /// instead of [`ReadError::Eof`] if the reader has already reached EOF, so that a loop over
+    /// every line can stop there without matching on the error.
+    pub async fn read_opt<R: BufRead + Unpin, T: serde::de::DeserializeOwned>(
+        reader: R,
+    ) -> Result<Option<T>, ReadError> {
+        match read(reader).await {
+            Ok(t) => Ok(Some(t)),
+            Err(ReadError::Eof) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Reads a line from the reader and deserializes it into a given type, also returning the
+    /// length of the line in bytes, including its newline.
+    pub async fn read_with_len<R: BufRead + Unpin, T: serde::de::DeserializeOwned>(
+        mut reader: R,
+    ) -> Result<(T, usize), ReadError> {
         let mut buf = String::new();
-        let num_bytes_read = reader.read_line(&mut buf).map_err(ReadError::Io)?;
+        let num_bytes_read = reader.read_line(&mut buf).await.map_err(ReadError::Io)?;
 
         if num_bytes_read == 0 {
             return Err(ReadError::Eof);
         }
 
-        Ok(serde_json::from_str(&buf).map_err(ReadError::Deserialize)?)
-    }
+        #[cfg(feature = "log")]
+        wire_log::inbound(&buf);
 
-    /// Writes a given value to the writer, serializing it into JSON.
-    pub fn write<W: Write, T: serde::Serialize>(mut writer: W, t: &T) -> Result<(), WriteError> {
-        // We use to_string here instead of to_vec because it verifies that the JSON is valid UTF-8,
-        // which is required by the JSON Lines specification (https://jsonlines.org).
-        let json = serde_json::to_string(t).map_err(WriteError::Serialize)?;
-
-        writer.write_all(json.as_bytes()).map_err(WriteError::Io)?;
-        writer.write_all(b"\n").map_err(WriteError::Io)?;
+        let t = serde_json::from_str(&buf).map_err(ReadError::Deserialize)?;
 
-        Ok(())
+        Ok((t, num_bytes_read))
     }
-}
 
-#[cfg(feature = "tokio")]
-mod imp {
-    use super::*;
-    use tokio::io::{AsyncBufRead as BufRead, AsyncBufReadExt, AsyncWrite as Write, AsyncWriteExt};
+    /// Reads a line from the reader and deserializes it into a given type, failing with
+    /// [`ReadError::LineTooLong`] as soon as more than `max_bytes` have been read without reaching
+    /// the end of the line, not counting its newline.
+    ///
+    /// The line is read in chunks, so no more than `max_bytes` and what the reader has buffered is
+    /// ever held in memory. The rest of a line that is too long is left unread, so the reader
+    /// should be abandoned after this error rather than read from again.
+    pub async fn read_with_limit<R: BufRead + Unpin, T: serde::de::DeserializeOwned>(
+        reader: R,
+        max_bytes: usize,
+    ) -> Result<T, ReadError> {
+        read_with_len_and_limit(reader, max_bytes)
+            .await
+            .map(|(t, _)| t)
+    }
 
-    /// Reads a line from the reader and deserializes it into a given type.
-    pub async fn read<R: BufRead + Unpin, T: serde::de::DeserializeOwned>(
+    /// Reads a line like [`read_with_limit`], also returning its length as [`read_with_len`] does.
+    pub(crate) async fn read_with_len_and_limit<
+        R: BufRead + Unpin,
+        T: serde::de::DeserializeOwned,
+    >(
         mut reader: R,
-    ) -> Result<T, ReadError> {
-        let mut buf = String::new();
-        let num_bytes_read = reader.read_line(&mut buf).await.map_err(ReadError::Io)?;
+        max_bytes: usize,
+    ) -> Result<(T, usize), ReadError> {
+        let mut buf = Vec::new();
 
-        if num_bytes_read == 0 {
+        loop {
+            let available = reader.fill_buf().await.map_err(ReadError::Io)?;
+
+            let (num_bytes, is_line_complete) = match available.iter().position(|b| *b == b'\n') {
+                Some(i) => (i + 1, true),
+                None => (available.len(), available.is_empty()),
+            };
+
+            buf.extend_from_slice(&available[..num_bytes]);
+            reader.consume(num_bytes);
+
+            if buf.len() - usize::from(buf.ends_with(b"\n")) > max_bytes {
+                return Err(ReadError::LineTooLong { max: max_bytes });
+            }
+
+            if is_line_complete {
+                break;
+            }
+        }
+
+        if buf.is_empty() {
             return Err(ReadError::Eof);
         }
 
-        Ok(serde_json::from_str(&buf).map_err(ReadError::Deserialize)?)
+        let num_bytes_read = buf.len();
+        let line = String::from_utf8(buf)
+            .map_err(|e| ReadError::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, e)))?;
+
+        #[cfg(feature = "log")]
+        wire_log::inbound(&line);
+
+        let t = serde_json::from_str(&line).map_err(ReadError::Deserialize)?;
+
+        Ok((t, num_bytes_read))
     }
 
     /// Writes a given value to the writer, serializing it into JSON.
     pub async fn write<W: Write + Unpin, T: serde::Serialize>(
-        mut writer: W,
+        writer: W,
         t: &T,
     ) -> Result<(), WriteError> {
+        write_with_len(writer, t).await.map(|_| ())
+    }
+
+    /// Writes a given value to the writer, serializing it into JSON, and returns the length of the
+    /// line written in bytes, including its newline.
+    pub async fn write_with_len<W: Write + Unpin, T: serde::Serialize>(
+        mut writer: W,
+        t: &T,
+    ) -> Result<usize, WriteError> {
         // We use to_string here instead of to_vec because it verifies that the JSON is valid UTF-8,
         // which is required by the JSON Lines specification (https://jsonlines.org).
         let json = serde_json::to_string(t).map_err(WriteError::Serialize)?;
 
+        #[cfg(feature = "log")]
+        wire_log::outbound(&json);
+
+        writer
+            .write_all(json.as_bytes())
+            .await
+            .map_err(WriteError::Io)?;
+
+        writer.write_all(b"\n").await.map_err(WriteError::Io)?;
+
+        Ok(json.len() + 1)
+    }
+
+    /// Writes a given value to the writer, serializing it into JSON with a custom
+    /// [`Formatter`](serde_json::ser::Formatter), such as one that escapes differently to match
+    /// what a downstream tool expects. [`AsciiFormatter`](crate::AsciiFormatter), for
+    /// example, escapes every non-ASCII character.
+    ///
+    /// The formatter must write everything on one line, so formatters that add line breaks, such
+    /// as [`PrettyFormatter`](serde_json::ser::PrettyFormatter), fail with
+    /// [`WriteError::Serialize`], as do formatters that write invalid UTF-8.
+    pub async fn write_with_formatter<W, T, F>(
+        mut writer: W,
+        t: &T,
+        formatter: F,
+    ) -> Result<(), WriteError>
+    where
+        W: Write + Unpin,
+        T: serde::Serialize,
+        F: serde_json::ser::Formatter,
+    {
+        let json = format::to_string_with_formatter(t, formatter)?;
+
+        #[cfg(feature = "log")]
+        wire_log::outbound(&json);
+
         writer
             .write_all(json.as_bytes())
             .await
@@ -84,6 +505,35 @@ mod imp {
 
         Ok(())
     }
+
+    /// Writes a given value to the writer in the canonical form described in [`canonicalize`], so
+    /// that equal values are always written as identical bytes.
+    pub async fn write_canonical<W: Write + Unpin, T: serde::Serialize>(
+        writer: W,
+        t: &T,
+    ) -> Result<(), WriteError> {
+        let value = serde_json::to_value(t).map_err(WriteError::Serialize)?;
+        write(writer, &canonical::canonical_value(value)).await
+    }
+
+    /// Writes every value yielded by `values` to the writer, serializing each into JSON, and
+    /// returns how many were written. Writing stops at the first error.
+    pub async fn write_all<W, I>(mut writer: W, values: I) -> Result<usize, WriteError>
+    where
+        W: Write + Unpin,
+        I: IntoIterator,
+        I::Item: serde::Serialize,
+    {
+        let mut num_written = 0;
+
+        for value in values {
+            write(&mut writer, &value).await?;
+            num_written += 1;
+        }
+
+        Ok(num_written)
+    }
 }
 
+#[cfg(feature = "tokio")]
 pub use imp::*;
@@ -0,0 +1,114 @@
+use std::fmt;
+
+/// A phase in the lifecycle of a connection.
+///
+/// A connection normally moves through the phases in order, though it may skip handshaking if its
+/// protocol has none, and may close at any point. A closed connection can be reopened, either from
+/// scratch or by handshaking over a new transport that is already connected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ConnectionState {
+    /// The transport is being connected.
+    Connecting,
+    /// The transport is connected, and the peers are exchanging whatever they need to before
+    /// messages can flow.
+    Handshaking,
+    /// Messages can be read and written.
+    Established,
+    /// No more messages will be written, but those still in flight are being read.
+    Draining,
+    /// The connection is not usable.
+    Closed,
+}
+
+impl ConnectionState {
+    /// Returns whether a connection may move from this state to `to`.
+    pub fn can_transition_to(self, to: Self) -> bool {
+        use ConnectionState::*;
+
+        matches!(
+            (self, to),
+            (Connecting, Handshaking)
+                | (Connecting, Established)
+                | (Handshaking, Established)
+                | (Established, Draining)
+                | (Closed, Connecting)
+                | (Closed, Handshaking)
+        ) || (to == Closed && self != Closed)
+    }
+}
+
+/// An error returned by [`Lifecycle::transition`] for a transition between states that is not
+/// allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("connection cannot go from {from:?} to {to:?}")]
+pub struct InvalidTransition {
+    pub from: ConnectionState,
+    pub to: ConnectionState,
+}
+
+type Callback = Box<dyn FnMut(ConnectionState, ConnectionState) + Send>;
+
+/// The current [`ConnectionState`] of a connection, along with callbacks run whenever it changes.
+///
+/// Helpers that manage a connection, such as reconnecting, draining or keepalives, record and
+/// check their progress here rather than each keeping their own flags, so that they agree on what
+/// state the connection is in.
+pub struct Lifecycle {
+    state: ConnectionState,
+    callbacks: Vec<Callback>,
+}
+
+impl fmt::Debug for Lifecycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Lifecycle")
+            .field("state", &self.state)
+            .field("num_callbacks", &self.callbacks.len())
+            .finish()
+    }
+}
+
+impl Default for Lifecycle {
+    fn default() -> Self {
+        Self::new(ConnectionState::Closed)
+    }
+}
+
+impl Lifecycle {
+    /// Creates a new `Lifecycle` starting in the given state.
+    pub fn new(state: ConnectionState) -> Self {
+        Self {
+            state,
+            callbacks: Vec::new(),
+        }
+    }
+
+    /// Returns the current state.
+    pub fn state(&self) -> ConnectionState {
+        self.state
+    }
+
+    /// Adds a callback run with the old and new states after every transition.
+    pub fn on_change<F: FnMut(ConnectionState, ConnectionState) + Send + 'static>(
+        &mut self,
+        callback: F,
+    ) {
+        self.callbacks.push(Box::new(callback));
+    }
+
+    /// Moves to the state `to`, running every callback, if the transition is allowed.
+    pub fn transition(&mut self, to: ConnectionState) -> Result<(), InvalidTransition> {
+        let from = self.state;
+
+        if !from.can_transition_to(to) {
+            return Err(InvalidTransition { from, to });
+        }
+
+        self.state = to;
+
+        for callback in &mut self.callbacks {
+            callback(from, to);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,143 @@
+#[cfg(not(feature = "tokio"))]
+use std::io::{self, BufRead, Write};
+#[cfg(feature = "tokio")]
+use tokio::io::{self, AsyncBufRead as BufRead, AsyncWrite as Write};
+
+use crate::{Connection, ReadError, WriteError};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::value::RawValue;
+use std::fmt;
+
+pub(crate) type ReadMap<In> = Box<dyn FnMut(&RawValue) -> serde_json::Result<In> + Send>;
+pub(crate) type WriteMap<Out> = Box<dyn FnMut(&Out) -> serde_json::Result<Box<RawValue>> + Send>;
+
+/// A [`Connection`] that reads `In` messages and writes `Out` messages, converting between them
+/// and the types sent over the wire with functions given to [`MappedConnection::map_read`] and
+/// [`MappedConnection::map_write`].
+///
+/// This lets the wire types of a protocol differ from the domain types used by the rest of a
+/// program, say to translate between versions of the protocol or convert units, without wrapping
+/// every call site. Create one with [`Connection::map_messages`].
+pub struct MappedConnection<R: BufRead, W: Write, In: 'static, Out: 'static> {
+    connection: Connection<R, W>,
+    read_map: ReadMap<In>,
+    write_map: WriteMap<Out>,
+}
+
+impl<R: BufRead, W: Write> Connection<R, W> {
+    /// Fixes the types of messages read and written to `In` and `Out`, which are at first sent
+    /// over the wire as they are. See [`MappedConnection`].
+    pub fn map_messages<In, Out>(self) -> MappedConnection<R, W, In, Out>
+    where
+        In: DeserializeOwned + 'static,
+        Out: Serialize + 'static,
+    {
+        MappedConnection {
+            connection: self,
+            read_map: Box::new(|raw| serde_json::from_str(raw.get())),
+            write_map: Box::new(serde_json::value::to_raw_value),
+        }
+    }
+}
+
+impl<R: BufRead, W: Write, In: 'static, Out: 'static> MappedConnection<R, W, In, Out> {
+    /// Reads messages as `Wire` and converts each with `f`, replacing any previous conversion.
+    pub fn map_read<Wire, T, F>(self, mut f: F) -> MappedConnection<R, W, T, Out>
+    where
+        Wire: DeserializeOwned,
+        T: 'static,
+        F: FnMut(Wire) -> T + Send + 'static,
+    {
+        MappedConnection {
+            connection: self.connection,
+            read_map: Box::new(move |raw| serde_json::from_str(raw.get()).map(&mut f)),
+            write_map: self.write_map,
+        }
+    }
+
+    /// Converts each message written with `f` and writes the result as `Wire`, replacing any
+    /// previous conversion.
+    pub fn map_write<Wire, T, F>(self, mut f: F) -> MappedConnection<R, W, In, T>
+    where
+        Wire: Serialize,
+        T: 'static,
+        F: FnMut(&T) -> Wire + Send + 'static,
+    {
+        MappedConnection {
+            connection: self.connection,
+            read_map: self.read_map,
+            write_map: Box::new(move |t| serde_json::value::to_raw_value(&f(t))),
+        }
+    }
+
+    /// Creates a `MappedConnection` with fallible conversions, for building other adapters on.
+    pub(crate) fn from_parts(
+        connection: Connection<R, W>,
+        read_map: ReadMap<In>,
+        write_map: WriteMap<Out>,
+    ) -> Self {
+        Self {
+            connection,
+            read_map,
+            write_map,
+        }
+    }
+
+    /// Consumes the `MappedConnection`, returning the contained `Connection`.
+    pub fn into_inner(self) -> Connection<R, W> {
+        self.connection
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl<R: BufRead, W: Write, In: 'static, Out: 'static> MappedConnection<R, W, In, Out> {
+    /// Reads a line from the reader and converts it into a message.
+    pub fn read(&mut self) -> Result<In, ReadError> {
+        let raw: Box<RawValue> = self.connection.read()?;
+        (self.read_map)(&raw).map_err(ReadError::Deserialize)
+    }
+
+    /// Converts a message and writes it to the writer.
+    pub fn write(&mut self, t: &Out) -> Result<(), WriteError> {
+        let raw = (self.write_map)(t).map_err(WriteError::Serialize)?;
+        self.connection.write(&raw)
+    }
+
+    /// Flushes the contained writer’s buffer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.connection.flush()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R: BufRead + Unpin, W: Write + Unpin, In: 'static, Out: 'static>
+    MappedConnection<R, W, In, Out>
+{
+    /// Reads a line from the reader and converts it into a message.
+    pub async fn read(&mut self) -> Result<In, ReadError> {
+        let raw: Box<RawValue> = self.connection.read().await?;
+        (self.read_map)(&raw).map_err(ReadError::Deserialize)
+    }
+
+    /// Converts a message and writes it to the writer.
+    pub async fn write(&mut self, t: &Out) -> Result<(), WriteError> {
+        let raw = (self.write_map)(t).map_err(WriteError::Serialize)?;
+        self.connection.write(&raw).await
+    }
+
+    /// Flushes the contained writer’s buffer.
+    pub async fn flush(&mut self) -> io::Result<()> {
+        self.connection.flush().await
+    }
+}
+
+impl<R: BufRead + fmt::Debug, W: Write + fmt::Debug, In: 'static, Out: 'static> fmt::Debug
+    for MappedConnection<R, W, In, Out>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MappedConnection")
+            .field("connection", &self.connection)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,234 @@
+use crate::{ReadError, WriteError};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::value::RawValue;
+use serde_json::Value;
+
+/// The field holding the protocol version in messages written by [`MessageWriter`].
+pub const VERSION_FIELD: &str = "version";
+
+/// A protocol message type, usually implemented with the `message` attribute macro.
+pub trait Message: Serialize + DeserializeOwned {
+    /// The name of the field holding the message type.
+    const TAG: &'static str;
+
+    /// The version of the protocol this type implements.
+    const VERSION: u64;
+
+    /// Returns whether this is the catch-all variant standing in for a message of a type this
+    /// version of the protocol does not know.
+    fn is_unknown(&self) -> bool;
+}
+
+/// What a [`MessageReader`] does with messages of a type it does not know.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum UnknownMessages {
+    /// Return them as the message type’s catch-all variant, if it has one.
+    CatchAll,
+    /// Return [`MessageError::UnknownType`].
+    Error,
+}
+
+/// An error that occurred while reading or writing [`Message`]s.
+#[derive(Debug, thiserror::Error)]
+pub enum MessageError {
+    #[error("failed reading message")]
+    Read(#[from] ReadError),
+    #[error("failed writing message")]
+    Write(#[from] WriteError),
+    #[error("message has unknown type `{0}`")]
+    UnknownType(String),
+    #[error("message has version {found}, but only versions up to {supported} are supported")]
+    UnsupportedVersion { found: u64, supported: u64 },
+}
+
+/// Reads [`Message`]s, checking their protocol version.
+///
+/// Messages without a version field are assumed to be of the reader’s version. Messages of a newer
+/// version than the message type implements result in [`MessageError::UnsupportedVersion`].
+#[derive(Debug)]
+pub struct MessageReader<R> {
+    reader: R,
+    unknown: UnknownMessages,
+}
+
+impl<R> MessageReader<R> {
+    /// Creates a new `MessageReader` reading from the given reader, which returns messages of
+    /// unknown types as the catch-all variant.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            unknown: UnknownMessages::CatchAll,
+        }
+    }
+
+    /// Sets what to do with messages of unknown types.
+    pub fn unknown(mut self, unknown: UnknownMessages) -> Self {
+        self.unknown = unknown;
+        self
+    }
+
+    /// Consumes the `MessageReader`, returning the wrapped reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn decode<T: Message>(&self, mut message: Value) -> Result<T, MessageError> {
+        if let Some(object) = message.as_object_mut() {
+            if let Some(version) = object.remove(VERSION_FIELD).and_then(|v| v.as_u64()) {
+                if version > T::VERSION {
+                    return Err(MessageError::UnsupportedVersion {
+                        found: version,
+                        supported: T::VERSION,
+                    });
+                }
+            }
+        }
+
+        let tag = message
+            .get(T::TAG)
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+
+        let message: T = serde_json::from_value(message).map_err(ReadError::Deserialize)?;
+
+        if message.is_unknown() && self.unknown == UnknownMessages::Error {
+            return Err(MessageError::UnknownType(tag));
+        }
+
+        Ok(message)
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl<R: std::io::BufRead> MessageReader<R> {
+    /// Reads the next message.
+    pub fn read<T: Message>(&mut self) -> Result<T, MessageError> {
+        let message = crate::read(&mut self.reader)?;
+        self.decode(message)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R: tokio::io::AsyncBufRead + Unpin> MessageReader<R> {
+    /// Reads the next message.
+    pub async fn read<T: Message>(&mut self) -> Result<T, MessageError> {
+        let message = crate::read(&mut self.reader).await?;
+        self.decode(message)
+    }
+}
+
+/// Writes [`Message`]s, adding the protocol version to each one.
+#[derive(Debug)]
+pub struct MessageWriter<W> {
+    writer: W,
+}
+
+impl<W> MessageWriter<W> {
+    /// Creates a new `MessageWriter` writing to the given writer.
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Consumes the `MessageWriter`, returning the wrapped writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+fn encode<T: Message>(message: &T) -> Result<Value, WriteError> {
+    let mut message = serde_json::to_value(message)?;
+
+    if let Some(object) = message.as_object_mut() {
+        object.insert(VERSION_FIELD.to_string(), Value::from(T::VERSION));
+    }
+
+    Ok(message)
+}
+
+#[cfg(not(feature = "tokio"))]
+impl<W: std::io::Write> MessageWriter<W> {
+    /// Writes a message.
+    pub fn write<T: Message>(&mut self, message: &T) -> Result<(), WriteError> {
+        crate::write(&mut self.writer, &encode(message)?)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<W: tokio::io::AsyncWrite + Unpin> MessageWriter<W> {
+    /// Writes a message.
+    pub async fn write<T: Message>(&mut self, message: &T) -> Result<(), WriteError> {
+        crate::write(&mut self.writer, &encode(message)?).await
+    }
+}
+
+/// A message read by [`read_or_unknown`].
+#[derive(Debug, Clone)]
+pub enum Incoming<T> {
+    /// A message of a type known to `T`.
+    Known(T),
+    /// A message whose type tag is not one of the variants of `T`, kept as its original JSON.
+    Unknown(Box<RawValue>),
+}
+
+impl<T> Incoming<T> {
+    /// Returns the known message, or `None` if it was of an unknown type.
+    pub fn known(self) -> Option<T> {
+        match self {
+            Self::Known(t) => Some(t),
+            Self::Unknown(_) => None,
+        }
+    }
+}
+
+/// Deserializes a line into an enum, returning [`Incoming::Unknown`] rather than an error if the
+/// line’s tag does not name one of the enum’s variants.
+fn decode_incoming<T: DeserializeOwned>(line: &str) -> Result<Incoming<T>, ReadError> {
+    match serde_json::from_str(line) {
+        Ok(t) => Ok(Incoming::Known(t)),
+        Err(e) if e.is_data() && e.to_string().starts_with("unknown variant") => {
+            let raw = RawValue::from_string(line.trim_end().to_string())?;
+            Ok(Incoming::Unknown(raw))
+        }
+        Err(e) => Err(ReadError::Deserialize(e)),
+    }
+}
+
+/// Reads a line from the reader and deserializes it into an enum, yielding [`Incoming::Unknown`]
+/// holding the raw JSON of the line if its tag names a variant the enum does not have.
+///
+/// This keeps old clients working when servers add new message types, without the enum needing a
+/// catch-all variant. Any other deserialization failure is still an error.
+#[cfg(not(feature = "tokio"))]
+pub fn read_or_unknown<R: std::io::BufRead, T: DeserializeOwned>(
+    mut reader: R,
+) -> Result<Incoming<T>, ReadError> {
+    let mut buf = String::new();
+
+    if reader.read_line(&mut buf)? == 0 {
+        return Err(ReadError::Eof);
+    }
+
+    decode_incoming(&buf)
+}
+
+/// Reads a line from the reader and deserializes it into an enum, yielding [`Incoming::Unknown`]
+/// holding the raw JSON of the line if its tag names a variant the enum does not have.
+///
+/// This keeps old clients working when servers add new message types, without the enum needing a
+/// catch-all variant. Any other deserialization failure is still an error.
+#[cfg(feature = "tokio")]
+pub async fn read_or_unknown<R: tokio::io::AsyncBufRead + Unpin, T: DeserializeOwned>(
+    mut reader: R,
+) -> Result<Incoming<T>, ReadError> {
+    use tokio::io::AsyncBufReadExt;
+
+    let mut buf = String::new();
+
+    if reader.read_line(&mut buf).await? == 0 {
+        return Err(ReadError::Eof);
+    }
+
+    decode_incoming(&buf)
+}
@@ -0,0 +1,315 @@
+use crate::{ErrorObject, ItemSink, ItemSource, Service};
+use serde_json::Value;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::{Duration, Instant};
+
+/// Middleware that wraps a [`Service`] in another, adding behaviour common to every method such as
+/// authentication, logging or rate limiting.
+///
+/// Layers are applied with [`Service::layer`]. The last layer applied is the outermost, and so is
+/// the first to see each call:
+///
+/// ```
+/// use jsonl::{CatchPanic, Dispatcher, ErrorObject, Filter, RateLimit, Service};
+/// use std::time::Duration;
+///
+/// let service = Dispatcher::new()
+///     .method("add", |(a, b): (i64, i64)| Ok(a + b))
+///     .layer(CatchPanic)
+///     .layer(RateLimit::new(100, Duration::from_secs(1)))
+///     .layer(Filter::new(|method: &str, _: &serde_json::Value| {
+///         if method.starts_with("admin.") {
+///             Err(ErrorObject::new(-32003, "forbidden"))
+///         } else {
+///             Ok(())
+///         }
+///     }));
+/// ```
+pub trait Layer<S> {
+    /// The service produced by wrapping `S`.
+    type Service: Service;
+
+    /// Wraps `inner` in this layer’s middleware.
+    fn layer(self, inner: S) -> Self::Service;
+}
+
+/// Implements every method of [`Service`] by passing the call through `$self.around`, which runs
+/// the middleware and then the wrapped service.
+macro_rules! impl_service_around {
+    () => {
+        fn call(&mut self, method: &str, params: Value) -> Result<Value, ErrorObject> {
+            self.around(method, params, |inner, params| inner.call(method, params))
+        }
+
+        fn call_stream(
+            &mut self,
+            method: &str,
+            params: Value,
+            items: &mut ItemSink<'_>,
+        ) -> Result<(), ErrorObject> {
+            self.around(method, params, |inner, params| {
+                inner.call_stream(method, params, items)
+            })
+        }
+
+        fn call_client_stream(
+            &mut self,
+            method: &str,
+            params: Value,
+            items: &mut ItemSource<'_>,
+        ) -> Result<Value, ErrorObject> {
+            self.around(method, params, |inner, params| {
+                inner.call_client_stream(method, params, items)
+            })
+        }
+
+        fn call_bidi(
+            &mut self,
+            method: &str,
+            params: Value,
+            incoming: &mut ItemSource<'_>,
+            outgoing: &mut ItemSink<'_>,
+        ) -> Result<(), ErrorObject> {
+            self.around(method, params, |inner, params| {
+                inner.call_bidi(method, params, incoming, outgoing)
+            })
+        }
+    };
+}
+
+/// A [`Layer`] that rejects calls for which a function returns an error, before they reach the
+/// wrapped service. This is the building block for authentication and authorization checks.
+#[derive(Debug, Clone)]
+pub struct Filter<F> {
+    check: F,
+}
+
+impl<F> Filter<F> {
+    /// Creates a new `Filter` that passes each call’s method and parameters to `check`, rejecting
+    /// it with the error `check` returns, if any.
+    pub fn new(check: F) -> Self {
+        Self { check }
+    }
+}
+
+impl<S, F> Layer<S> for Filter<F>
+where
+    S: Service,
+    F: FnMut(&str, &Value) -> Result<(), ErrorObject>,
+{
+    type Service = FilterService<S, F>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        FilterService {
+            inner,
+            check: self.check,
+        }
+    }
+}
+
+/// The service produced by the [`Filter`] layer.
+#[derive(Debug, Clone)]
+pub struct FilterService<S, F> {
+    inner: S,
+    check: F,
+}
+
+impl<S, F> FilterService<S, F>
+where
+    S: Service,
+    F: FnMut(&str, &Value) -> Result<(), ErrorObject>,
+{
+    fn around<T>(
+        &mut self,
+        method: &str,
+        params: Value,
+        call: impl FnOnce(&mut S, Value) -> Result<T, ErrorObject>,
+    ) -> Result<T, ErrorObject> {
+        (self.check)(method, &params)?;
+        call(&mut self.inner, params)
+    }
+}
+
+impl<S, F> Service for FilterService<S, F>
+where
+    S: Service,
+    F: FnMut(&str, &Value) -> Result<(), ErrorObject>,
+{
+    impl_service_around!();
+}
+
+/// A [`Layer`] that passes the method name, duration and error, if any, of every call to a
+/// function once it finishes, for logging and metrics.
+#[derive(Debug, Clone)]
+pub struct Inspect<F> {
+    inspect: F,
+}
+
+impl<F> Inspect<F> {
+    /// Creates a new `Inspect` that passes every finished call to `inspect`.
+    pub fn new(inspect: F) -> Self {
+        Self { inspect }
+    }
+}
+
+impl<S, F> Layer<S> for Inspect<F>
+where
+    S: Service,
+    F: FnMut(&str, Duration, Option<&ErrorObject>),
+{
+    type Service = InspectService<S, F>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        InspectService {
+            inner,
+            inspect: self.inspect,
+        }
+    }
+}
+
+/// The service produced by the [`Inspect`] layer.
+#[derive(Debug, Clone)]
+pub struct InspectService<S, F> {
+    inner: S,
+    inspect: F,
+}
+
+impl<S, F> InspectService<S, F>
+where
+    S: Service,
+    F: FnMut(&str, Duration, Option<&ErrorObject>),
+{
+    fn around<T>(
+        &mut self,
+        method: &str,
+        params: Value,
+        call: impl FnOnce(&mut S, Value) -> Result<T, ErrorObject>,
+    ) -> Result<T, ErrorObject> {
+        let start = Instant::now();
+        let outcome = call(&mut self.inner, params);
+        (self.inspect)(method, start.elapsed(), outcome.as_ref().err());
+
+        outcome
+    }
+}
+
+impl<S, F> Service for InspectService<S, F>
+where
+    S: Service,
+    F: FnMut(&str, Duration, Option<&ErrorObject>),
+{
+    impl_service_around!();
+}
+
+/// A [`Layer`] that limits how often the wrapped service is called, rejecting calls over the limit
+/// with [`ErrorObject::RATE_LIMITED`].
+///
+/// Calls are limited with a token bucket, which allows short bursts of up to the full limit as
+/// long as the average rate stays within it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    num_calls: u32,
+    per: Duration,
+}
+
+impl RateLimit {
+    /// Creates a new `RateLimit` allowing `num_calls` calls every `per`.
+    pub fn new(num_calls: u32, per: Duration) -> Self {
+        Self { num_calls, per }
+    }
+}
+
+impl<S: Service> Layer<S> for RateLimit {
+    type Service = RateLimitService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        let capacity = f64::from(self.num_calls);
+
+        RateLimitService {
+            inner,
+            capacity,
+            tokens: capacity,
+            tokens_per_second: capacity / self.per.as_secs_f64(),
+            last_refill: Instant::now(),
+        }
+    }
+}
+
+/// The service produced by the [`RateLimit`] layer.
+#[derive(Debug, Clone)]
+pub struct RateLimitService<S> {
+    inner: S,
+    capacity: f64,
+    tokens: f64,
+    tokens_per_second: f64,
+    last_refill: Instant,
+}
+
+impl<S: Service> RateLimitService<S> {
+    fn around<T>(
+        &mut self,
+        _method: &str,
+        params: Value,
+        call: impl FnOnce(&mut S, Value) -> Result<T, ErrorObject>,
+    ) -> Result<T, ErrorObject> {
+        let now = Instant::now();
+        let refill = now.duration_since(self.last_refill).as_secs_f64() * self.tokens_per_second;
+
+        // A zero `per` gives an infinite rate, and a NaN refill, which `min` discards in favour of a
+        // full bucket.
+        self.tokens = (self.tokens + refill).min(self.capacity);
+        self.last_refill = now;
+
+        if self.tokens < 1.0 {
+            return Err(ErrorObject::rate_limited());
+        }
+
+        self.tokens -= 1.0;
+        call(&mut self.inner, params)
+    }
+}
+
+impl<S: Service> Service for RateLimitService<S> {
+    impl_service_around!();
+}
+
+/// A [`Layer`] that turns a panic in the wrapped service into an
+/// [`ErrorObject::internal_error`], so that one faulty handler does not take the server down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct CatchPanic;
+
+impl<S: Service> Layer<S> for CatchPanic {
+    type Service = CatchPanicService<S>;
+
+    fn layer(self, inner: S) -> Self::Service {
+        CatchPanicService { inner }
+    }
+}
+
+/// The service produced by the [`CatchPanic`] layer.
+#[derive(Debug, Clone)]
+pub struct CatchPanicService<S> {
+    inner: S,
+}
+
+impl<S: Service> CatchPanicService<S> {
+    fn around<T>(
+        &mut self,
+        method: &str,
+        params: Value,
+        call: impl FnOnce(&mut S, Value) -> Result<T, ErrorObject>,
+    ) -> Result<T, ErrorObject> {
+        let inner = &mut self.inner;
+
+        panic::catch_unwind(AssertUnwindSafe(|| call(inner, params))).unwrap_or_else(|_| {
+            Err(ErrorObject::internal_error(format_args!(
+                "handler for `{}` panicked",
+                method
+            )))
+        })
+    }
+}
+
+impl<S: Service> Service for CatchPanicService<S> {
+    impl_service_around!();
+}
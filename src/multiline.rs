@@ -0,0 +1,331 @@
+use crate::{ReadError, WriteError};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::convert::TryFrom;
+
+/// How the end of a [`MultiLineRecord`] is found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordFraming {
+    /// The data lines are followed by a terminator line equal to the given value.
+    Terminated(Value),
+    /// The header is an object whose field with the given name holds the number of data lines.
+    /// [`MultiLineWriter`] fills this field in.
+    Counted(String),
+}
+
+/// A logical message made up of a header line followed by any number of data lines.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct MultiLineRecord<H, T> {
+    pub header: H,
+    pub lines: Vec<T>,
+}
+
+/// An error that occurred while reading a [`MultiLineRecord`].
+#[derive(Debug, thiserror::Error)]
+pub enum MultiLineError {
+    #[error(transparent)]
+    Read(#[from] ReadError),
+    #[error("header has no line count in field `{0}`")]
+    MissingCount(String),
+    #[error("record has more than {0} data lines")]
+    TooManyLines(usize),
+    #[error("record is longer than {0} bytes")]
+    TooLarge(usize),
+    #[error("reader reached EOF partway through a record")]
+    Truncated,
+}
+
+/// Reads [`MultiLineRecord`]s, for protocols in which one message spans several lines, such as a
+/// header line followed by a line per item and then a terminator.
+///
+/// Every record is checked against the limits set with [`MultiLineReader::max_lines`] and
+/// [`MultiLineReader::max_bytes`] as it is read, so a peer cannot make the reader buffer an
+/// unbounded amount by never ending a record. [`ReadError::Eof`] is returned if the reader reaches
+/// EOF between records, while [`MultiLineError::Truncated`] is returned if it does so partway
+/// through one.
+#[derive(Debug)]
+pub struct MultiLineReader<R> {
+    reader: R,
+    framing: RecordFraming,
+    max_lines: Option<usize>,
+    max_bytes: Option<usize>,
+}
+
+impl<R> MultiLineReader<R> {
+    /// Creates a new `MultiLineReader` reading records with the given framing from the given
+    /// reader, without any limits.
+    pub fn new(reader: R, framing: RecordFraming) -> Self {
+        Self {
+            reader,
+            framing,
+            max_lines: None,
+            max_bytes: None,
+        }
+    }
+
+    /// Sets the most data lines a record may have.
+    pub fn max_lines(mut self, max_lines: usize) -> Self {
+        self.max_lines = Some(max_lines);
+        self
+    }
+
+    /// Sets the most bytes a record may take up, including its header, terminator and newlines.
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Consumes the `MultiLineReader`, returning the wrapped reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Returns the number of data lines the header says follow it, if the framing is counted.
+    fn expected_lines(&self, header: &Value) -> Result<Option<usize>, MultiLineError> {
+        let field = match &self.framing {
+            RecordFraming::Terminated(_) => return Ok(None),
+            RecordFraming::Counted(field) => field,
+        };
+
+        let num_lines = header
+            .get(field)
+            .and_then(Value::as_u64)
+            .and_then(|n| usize::try_from(n).ok())
+            .ok_or_else(|| MultiLineError::MissingCount(field.clone()))?;
+        self.check_lines(num_lines)?;
+
+        Ok(Some(num_lines))
+    }
+
+    fn check_lines(&self, num_lines: usize) -> Result<(), MultiLineError> {
+        match self.max_lines {
+            Some(max_lines) if num_lines > max_lines => {
+                Err(MultiLineError::TooManyLines(max_lines))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_bytes(&self, num_bytes: usize) -> Result<(), MultiLineError> {
+        match self.max_bytes {
+            Some(max_bytes) if num_bytes > max_bytes => Err(MultiLineError::TooLarge(max_bytes)),
+            _ => Ok(()),
+        }
+    }
+
+    fn is_terminator(&self, line: &Value) -> bool {
+        matches!(&self.framing, RecordFraming::Terminated(terminator) if terminator == line)
+    }
+}
+
+/// Turns the raw lines of a record into its header and data lines.
+fn assemble<H, T>(header: Value, lines: Vec<Value>) -> Result<MultiLineRecord<H, T>, ReadError>
+where
+    H: DeserializeOwned,
+    T: DeserializeOwned,
+{
+    Ok(MultiLineRecord {
+        header: serde_json::from_value(header)?,
+        lines: lines
+            .into_iter()
+            .map(serde_json::from_value)
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+/// Treats EOF partway through a record as a truncated record.
+fn truncated(e: MultiLineError) -> MultiLineError {
+    match e {
+        MultiLineError::Read(ReadError::Eof) => MultiLineError::Truncated,
+        e => e,
+    }
+}
+
+/// Treats a line too long for what is left of the byte budget as a record that is too large.
+fn too_large(e: ReadError, max_bytes: usize) -> MultiLineError {
+    match e {
+        ReadError::LineTooLong { .. } => MultiLineError::TooLarge(max_bytes),
+        e => MultiLineError::Read(e),
+    }
+}
+
+/// Writes [`MultiLineRecord`]s in the form read by [`MultiLineReader`].
+#[derive(Debug)]
+pub struct MultiLineWriter<W> {
+    writer: W,
+    framing: RecordFraming,
+}
+
+impl<W> MultiLineWriter<W> {
+    /// Creates a new `MultiLineWriter` writing records with the given framing to the given writer.
+    pub fn new(writer: W, framing: RecordFraming) -> Self {
+        Self { writer, framing }
+    }
+
+    /// Consumes the `MultiLineWriter`, returning the wrapped writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Serializes `header`, filling in the line count if the framing is counted.
+    fn header<H: Serialize>(&self, header: &H, num_lines: usize) -> Result<Value, WriteError> {
+        let mut header = serde_json::to_value(header)?;
+
+        if let RecordFraming::Counted(field) = &self.framing {
+            let object = header.as_object_mut().ok_or_else(|| {
+                <serde_json::Error as serde::ser::Error>::custom(
+                    "header of a counted record must be an object",
+                )
+            })?;
+            object.insert(field.clone(), Value::from(num_lines));
+        }
+
+        Ok(header)
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl<R: std::io::BufRead> MultiLineReader<R> {
+    /// Reads the next record.
+    pub fn read<H, T>(&mut self) -> Result<MultiLineRecord<H, T>, MultiLineError>
+    where
+        H: DeserializeOwned,
+        T: DeserializeOwned,
+    {
+        let (header, mut num_bytes) = self.read_line(0)?;
+        self.check_bytes(num_bytes)?;
+        let expected_lines = self.expected_lines(&header)?;
+
+        let mut lines = Vec::new();
+
+        while expected_lines.is_none_or(|expected| lines.len() < expected) {
+            let (line, len) = self.read_line(num_bytes).map_err(truncated)?;
+            num_bytes += len;
+            self.check_bytes(num_bytes)?;
+
+            if self.is_terminator(&line) {
+                break;
+            }
+
+            self.check_lines(lines.len() + 1)?;
+            lines.push(line);
+        }
+
+        Ok(assemble(header, lines)?)
+    }
+
+    /// Reads a line of a record of which `num_bytes` have been read already, failing as soon as
+    /// the line takes the record past the byte limit rather than once it has been read in full.
+    fn read_line(&mut self, num_bytes: usize) -> Result<(Value, usize), MultiLineError> {
+        match self.max_bytes {
+            Some(max_bytes) => crate::blocking::read_with_len_and_limit(
+                &mut self.reader,
+                max_bytes.saturating_sub(num_bytes),
+            )
+            .map_err(|e| too_large(e, max_bytes)),
+            None => Ok(crate::read_with_len(&mut self.reader)?),
+        }
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl<W: std::io::Write> MultiLineWriter<W> {
+    /// Writes a record, returning the number of bytes written.
+    pub fn write<H, T>(&mut self, header: &H, lines: &[T]) -> Result<usize, WriteError>
+    where
+        H: Serialize,
+        T: Serialize,
+    {
+        let header = self.header(header, lines.len())?;
+        let mut num_bytes = crate::write_with_len(&mut self.writer, &header)?;
+
+        for line in lines {
+            num_bytes += crate::write_with_len(&mut self.writer, line)?;
+        }
+
+        if let RecordFraming::Terminated(terminator) = &self.framing {
+            num_bytes += crate::write_with_len(&mut self.writer, terminator)?;
+        }
+
+        Ok(num_bytes)
+    }
+
+    /// Flushes the contained writer’s buffer.
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R: tokio::io::AsyncBufRead + Unpin> MultiLineReader<R> {
+    /// Reads the next record.
+    pub async fn read<H, T>(&mut self) -> Result<MultiLineRecord<H, T>, MultiLineError>
+    where
+        H: DeserializeOwned,
+        T: DeserializeOwned,
+    {
+        let (header, mut num_bytes) = self.read_line(0).await?;
+        self.check_bytes(num_bytes)?;
+        let expected_lines = self.expected_lines(&header)?;
+
+        let mut lines = Vec::new();
+
+        while expected_lines.is_none_or(|expected| lines.len() < expected) {
+            let (line, len) = self.read_line(num_bytes).await.map_err(truncated)?;
+            num_bytes += len;
+            self.check_bytes(num_bytes)?;
+
+            if self.is_terminator(&line) {
+                break;
+            }
+
+            self.check_lines(lines.len() + 1)?;
+            lines.push(line);
+        }
+
+        Ok(assemble(header, lines)?)
+    }
+
+    /// Reads a line of a record of which `num_bytes` have been read already, failing as soon as
+    /// the line takes the record past the byte limit rather than once it has been read in full.
+    async fn read_line(&mut self, num_bytes: usize) -> Result<(Value, usize), MultiLineError> {
+        match self.max_bytes {
+            Some(max_bytes) => crate::read_with_len_and_limit(
+                &mut self.reader,
+                max_bytes.saturating_sub(num_bytes),
+            )
+            .await
+            .map_err(|e| too_large(e, max_bytes)),
+            None => Ok(crate::read_with_len(&mut self.reader).await?),
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<W: tokio::io::AsyncWrite + Unpin> MultiLineWriter<W> {
+    /// Writes a record, returning the number of bytes written.
+    pub async fn write<H, T>(&mut self, header: &H, lines: &[T]) -> Result<usize, WriteError>
+    where
+        H: Serialize,
+        T: Serialize,
+    {
+        let header = self.header(header, lines.len())?;
+        let mut num_bytes = crate::write_with_len(&mut self.writer, &header).await?;
+
+        for line in lines {
+            num_bytes += crate::write_with_len(&mut self.writer, line).await?;
+        }
+
+        if let RecordFraming::Terminated(terminator) = &self.framing {
+            num_bytes += crate::write_with_len(&mut self.writer, terminator).await?;
+        }
+
+        Ok(num_bytes)
+    }
+
+    /// Flushes the contained writer’s buffer.
+    pub async fn flush(&mut self) -> std::io::Result<()> {
+        tokio::io::AsyncWriteExt::flush(&mut self.writer).await
+    }
+}
@@ -0,0 +1,164 @@
+use crate::{ReadError, WriteError};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+
+/// A record tagged with the ID of the logical stream it belongs to, written as a two-element array
+/// so that the tag costs only a few bytes per line: `["orders",{"id":1}]`.
+#[derive(Serialize)]
+struct Frame<'a, T>(&'a str, &'a T);
+
+/// Interleaves records from many logical streams over a single writer, tagging each line with the
+/// ID of the stream it belongs to. Use [`Demux`] to separate the streams again on the other side.
+///
+/// This lets many streams share one file or connection, rather than needing one each.
+#[derive(Debug)]
+pub struct Mux<W> {
+    writer: W,
+}
+
+impl<W> Mux<W> {
+    /// Creates a new `Mux` writing to the given writer.
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Consumes the `Mux`, returning the wrapped writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl<W: std::io::Write> Mux<W> {
+    /// Writes a given value to the stream with the given ID, serializing it into JSON.
+    pub fn write<T: Serialize>(&mut self, stream: &str, t: &T) -> Result<(), WriteError> {
+        crate::write(&mut self.writer, &Frame(stream, t))
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<W: tokio::io::AsyncWrite + Unpin> Mux<W> {
+    /// Writes a given value to the stream with the given ID, serializing it into JSON.
+    pub async fn write<T: Serialize>(&mut self, stream: &str, t: &T) -> Result<(), WriteError> {
+        crate::write(&mut self.writer, &Frame(stream, t)).await
+    }
+}
+
+/// Separates the logical streams interleaved by a [`Mux`].
+///
+/// Records can be read from any stream as they arrive with [`Demux::read_any`], or from one
+/// particular stream with [`Demux::read`]. Reading from one stream queues the records of every
+/// other stream read along the way until they are asked for, so every stream should eventually be
+/// read to avoid unbounded memory use.
+#[derive(Debug)]
+pub struct Demux<R> {
+    reader: R,
+    queues: HashMap<String, VecDeque<Value>>,
+}
+
+impl<R> Demux<R> {
+    /// Creates a new `Demux` reading from the given reader.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            queues: HashMap::new(),
+        }
+    }
+
+    /// Returns the number of records queued for the stream with the given ID.
+    pub fn num_queued(&self, stream: &str) -> usize {
+        self.queues.get(stream).map_or(0, VecDeque::len)
+    }
+
+    /// Consumes the `Demux`, returning the wrapped reader. Any queued records are discarded.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn dequeue(&mut self, stream: &str) -> Option<Value> {
+        let queue = self.queues.get_mut(stream)?;
+        let record = queue.pop_front();
+
+        if queue.is_empty() {
+            self.queues.remove(stream);
+        }
+
+        record
+    }
+
+    fn dequeue_any(&mut self) -> Option<(String, Value)> {
+        let stream = self.queues.keys().next()?.clone();
+        let record = self.dequeue(&stream)?;
+        Some((stream, record))
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl<R: std::io::BufRead> Demux<R> {
+    /// Reads the next record from the stream with the given ID, deserializing it into a given type.
+    ///
+    /// Returns [`ReadError::Eof`] once the reader is exhausted without another record for this
+    /// stream.
+    pub fn read<T: DeserializeOwned>(&mut self, stream: &str) -> Result<T, ReadError> {
+        if let Some(record) = self.dequeue(stream) {
+            return Ok(serde_json::from_value(record)?);
+        }
+
+        loop {
+            let (id, record): (String, Value) = crate::read(&mut self.reader)?;
+
+            if id == stream {
+                return Ok(serde_json::from_value(record)?);
+            }
+
+            self.queues.entry(id).or_default().push_back(record);
+        }
+    }
+
+    /// Reads the next record from any stream, returning the ID of its stream along with the record
+    /// deserialized into a given type. Queued records are returned first.
+    pub fn read_any<T: DeserializeOwned>(&mut self) -> Result<(String, T), ReadError> {
+        let (id, record) = match self.dequeue_any() {
+            Some(queued) => queued,
+            None => crate::read(&mut self.reader)?,
+        };
+
+        Ok((id, serde_json::from_value(record)?))
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R: tokio::io::AsyncBufRead + Unpin> Demux<R> {
+    /// Reads the next record from the stream with the given ID, deserializing it into a given type.
+    ///
+    /// Returns [`ReadError::Eof`] once the reader is exhausted without another record for this
+    /// stream.
+    pub async fn read<T: DeserializeOwned>(&mut self, stream: &str) -> Result<T, ReadError> {
+        if let Some(record) = self.dequeue(stream) {
+            return Ok(serde_json::from_value(record)?);
+        }
+
+        loop {
+            let (id, record): (String, Value) = crate::read(&mut self.reader).await?;
+
+            if id == stream {
+                return Ok(serde_json::from_value(record)?);
+            }
+
+            self.queues.entry(id).or_default().push_back(record);
+        }
+    }
+
+    /// Reads the next record from any stream, returning the ID of its stream along with the record
+    /// deserialized into a given type. Queued records are returned first.
+    pub async fn read_any<T: DeserializeOwned>(&mut self) -> Result<(String, T), ReadError> {
+        let (id, record) = match self.dequeue_any() {
+            Some(queued) => queued,
+            None => crate::read(&mut self.reader).await?,
+        };
+
+        Ok((id, serde_json::from_value(record)?))
+    }
+}
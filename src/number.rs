@@ -0,0 +1,682 @@
+use crate::{ReadError, WriteError};
+use serde::de::{self, DeserializeOwned, Deserializer, Visitor};
+use serde::ser::{self, Serialize, Serializer};
+use std::cell::Cell;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{BufRead, Write};
+
+/// What [`NumberPolicy`] does with floats that are infinite or NaN, which JSON cannot represent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum NonFinitePolicy {
+    /// Fails with [`NumberError::NonFinite`].
+    Error,
+    /// Writes `null`, as `serde_json` does on its own.
+    #[default]
+    Null,
+    /// Writes `"NaN"`, `"Infinity"` or `"-Infinity"`, which can be read back with
+    /// [`deserialize_non_finite`].
+    String,
+}
+
+/// What [`NumberPolicy`] does with integers outside the range of `i64` and `u64`, which most JSON
+/// parsers, including `serde_json` without its `arbitrary_precision` feature, can only read as
+/// floats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum OverflowPolicy {
+    /// Fails with [`NumberError::Overflow`].
+    Error,
+    /// Writes them exactly, as `serde_json` can, and reads them as the nearest float, losing
+    /// precision.
+    #[default]
+    Lossy,
+}
+
+/// An error that occurred while reading or writing with a [`NumberPolicy`].
+#[derive(Debug, thiserror::Error)]
+pub enum NumberError {
+    #[error("cannot write non-finite float {value} as JSON")]
+    NonFinite { value: f64 },
+    #[error("integer {literal} does not fit in 64 bits")]
+    Overflow { literal: String },
+    #[error("failed reading line")]
+    Read(#[from] ReadError),
+    #[error("failed writing line")]
+    Write(#[from] WriteError),
+}
+
+/// Decides how the numeric edge cases that JSON handles poorly are read and written, so that they
+/// fail with a clear error or are dealt with predictably, rather than being silently mangled or
+/// failing deep inside `serde`.
+///
+/// Enabling the `arbitrary-precision` feature keeps every number read into a
+/// [`serde_json::Value`] exactly as written, so integers of any size and decimals round-trip
+/// losslessly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct NumberPolicy {
+    non_finite: NonFinitePolicy,
+    overflow: OverflowPolicy,
+}
+
+impl NumberPolicy {
+    /// Creates a new `NumberPolicy` that behaves as `serde_json` does: non-finite floats are
+    /// written as `null`, and out-of-range integers are read as floats.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets what is done with non-finite floats.
+    pub fn non_finite(mut self, policy: NonFinitePolicy) -> Self {
+        self.non_finite = policy;
+        self
+    }
+
+    /// Sets what is done with integers outside the range of `i64` and `u64`.
+    pub fn overflow(mut self, policy: OverflowPolicy) -> Self {
+        self.overflow = policy;
+        self
+    }
+
+    /// Reads a line from the reader and deserializes it into a given type, applying the policy.
+    pub fn read<R: BufRead, T: DeserializeOwned>(&self, mut reader: R) -> Result<T, NumberError> {
+        let mut line = String::new();
+
+        if reader.read_line(&mut line).map_err(ReadError::Io)? == 0 {
+            return Err(ReadError::Eof.into());
+        }
+
+        self.from_str(&line)
+    }
+
+    /// Deserializes a single line, applying the policy.
+    pub fn from_str<T: DeserializeOwned>(&self, line: &str) -> Result<T, NumberError> {
+        if self.overflow == OverflowPolicy::Error {
+            if let Some(literal) = find_overflowing_integer(line) {
+                return Err(NumberError::Overflow {
+                    literal: literal.to_string(),
+                });
+            }
+        }
+
+        serde_json::from_str(line).map_err(|e| ReadError::Deserialize(e).into())
+    }
+
+    /// Writes a given value to the writer, serializing it into JSON and applying the policy.
+    pub fn write<W: Write, T: Serialize>(&self, writer: W, t: &T) -> Result<(), NumberError> {
+        let json = self.to_string(t)?;
+        let raw = serde_json::value::RawValue::from_string(json).map_err(WriteError::Serialize)?;
+
+        crate::blocking::write(writer, &raw).map_err(NumberError::Write)
+    }
+
+    /// Serializes a value into a single line, without a trailing newline, applying the policy.
+    pub fn to_string<T: Serialize>(&self, t: &T) -> Result<String, NumberError> {
+        let state = State {
+            policy: *self,
+            violation: Cell::new(None),
+        };
+
+        serde_json::to_string(&Checked {
+            value: t,
+            state: &state,
+        })
+        .map_err(|e| {
+            state
+                .violation
+                .take()
+                .unwrap_or(NumberError::Write(WriteError::Serialize(e)))
+        })
+    }
+}
+
+/// Returns the first integer literal outside of a string in `line` that does not fit in an `i64`
+/// or `u64`.
+fn find_overflowing_integer(line: &str) -> Option<&str> {
+    let bytes = line.as_bytes();
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut i = 0;
+
+    while let Some(&b) = bytes.get(i) {
+        if in_string {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            i += 1;
+            continue;
+        }
+
+        match b {
+            b'"' => in_string = true,
+            b'-' | b'0'..=b'9' => {
+                let len = bytes
+                    .get(i..)
+                    .unwrap_or_default()
+                    .iter()
+                    .take_while(|b| matches!(b, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9'))
+                    .count();
+                let literal = line.get(i..i + len).unwrap_or_default();
+                let is_integer = !literal.contains(['.', 'e', 'E']);
+
+                if is_integer && literal.parse::<i64>().is_err() && literal.parse::<u64>().is_err()
+                {
+                    return Some(literal);
+                }
+
+                i += len;
+                continue;
+            }
+            _ => {}
+        }
+
+        i += 1;
+    }
+
+    None
+}
+
+/// Deserializes an `f64` written under [`NonFinitePolicy::String`] or [`NonFinitePolicy::Null`],
+/// for use with `#[serde(deserialize_with = "jsonl::deserialize_non_finite")]`.
+///
+/// Numbers are read as usual, `"NaN"`, `"Infinity"` and `"-Infinity"` as the values they name,
+/// and `null` as NaN.
+pub fn deserialize_non_finite<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    struct NonFiniteVisitor;
+
+    impl<'de> Visitor<'de> for NonFiniteVisitor {
+        type Value = f64;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a number, null, \"NaN\", \"Infinity\" or \"-Infinity\"")
+        }
+
+        fn visit_f64<E: de::Error>(self, v: f64) -> Result<f64, E> {
+            Ok(v)
+        }
+
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<f64, E> {
+            Ok(v as f64)
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<f64, E> {
+            Ok(v as f64)
+        }
+
+        fn visit_unit<E: de::Error>(self) -> Result<f64, E> {
+            Ok(f64::NAN)
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<f64, E> {
+            match v {
+                "NaN" => Ok(f64::NAN),
+                "Infinity" => Ok(f64::INFINITY),
+                "-Infinity" => Ok(f64::NEG_INFINITY),
+                _ => Err(E::invalid_value(de::Unexpected::Str(v), &self)),
+            }
+        }
+    }
+
+    deserializer.deserialize_any(NonFiniteVisitor)
+}
+
+/// The policy being applied during serialization, and the first violation of it, which is turned
+/// back into a [`NumberError`] once `serde_json` has given up.
+struct State {
+    policy: NumberPolicy,
+    violation: Cell<Option<NumberError>>,
+}
+
+impl State {
+    fn violate<E: ser::Error>(&self, error: NumberError) -> E {
+        let e = E::custom(&error);
+        self.violation.set(Some(error));
+        e
+    }
+}
+
+/// A value whose numbers are checked against the policy as it is serialized.
+struct Checked<'a, T: ?Sized> {
+    value: &'a T,
+    state: &'a State,
+}
+
+impl<T: Serialize + ?Sized> Serialize for Checked<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.value.serialize(CheckedSerializer {
+            inner: serializer,
+            state: self.state,
+        })
+    }
+}
+
+struct CheckedSerializer<'a, S> {
+    inner: S,
+    state: &'a State,
+}
+
+impl<'a, S: Serializer> CheckedSerializer<'a, S> {
+    fn checked<'b, T: ?Sized>(&self, value: &'b T) -> Checked<'b, T>
+    where
+        'a: 'b,
+    {
+        Checked {
+            value,
+            state: self.state,
+        }
+    }
+
+    fn serialize_float(self, v: f64) -> Result<S::Ok, S::Error> {
+        if v.is_finite() {
+            return self.inner.serialize_f64(v);
+        }
+
+        match self.state.policy.non_finite {
+            NonFinitePolicy::Error => Err(self.state.violate(NumberError::NonFinite { value: v })),
+            NonFinitePolicy::Null => self.inner.serialize_unit(),
+            NonFinitePolicy::String if v.is_nan() => self.inner.serialize_str("NaN"),
+            NonFinitePolicy::String if v > 0.0 => self.inner.serialize_str("Infinity"),
+            NonFinitePolicy::String => self.inner.serialize_str("-Infinity"),
+        }
+    }
+
+    /// Fails with [`NumberError::Overflow`] if the policy does not allow integers outside the
+    /// range of `i64` and `u64`.
+    fn check_overflowing<T: fmt::Display>(&self, v: T) -> Result<(), S::Error> {
+        match self.state.policy.overflow {
+            OverflowPolicy::Error => Err(self.state.violate(NumberError::Overflow {
+                literal: v.to_string(),
+            })),
+            OverflowPolicy::Lossy => Ok(()),
+        }
+    }
+}
+
+impl<'a, S: Serializer> Serializer for CheckedSerializer<'a, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = Compound<'a, S::SerializeSeq>;
+    type SerializeTuple = Compound<'a, S::SerializeTuple>;
+    type SerializeTupleStruct = Compound<'a, S::SerializeTupleStruct>;
+    type SerializeTupleVariant = Compound<'a, S::SerializeTupleVariant>;
+    type SerializeMap = Compound<'a, S::SerializeMap>;
+    type SerializeStruct = Compound<'a, S::SerializeStruct>;
+    type SerializeStructVariant = Compound<'a, S::SerializeStructVariant>;
+
+    fn serialize_bool(self, v: bool) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_bool(v)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_i8(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_i16(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_i32(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_i64(v)
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<S::Ok, S::Error> {
+        if i64::try_from(v).is_err() && u64::try_from(v).is_err() {
+            self.check_overflowing(v)?;
+        }
+
+        self.inner.serialize_i128(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_u8(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_u16(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_u32(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_u64(v)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<S::Ok, S::Error> {
+        if u64::try_from(v).is_err() {
+            self.check_overflowing(v)?;
+        }
+
+        self.inner.serialize_u128(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<S::Ok, S::Error> {
+        if v.is_finite() {
+            self.inner.serialize_f32(v)
+        } else {
+            self.serialize_float(f64::from(v))
+        }
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<S::Ok, S::Error> {
+        self.serialize_float(v)
+    }
+
+    fn serialize_char(self, v: char) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_char(v)
+    }
+
+    fn serialize_str(self, v: &str) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_str(v)
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_bytes(v)
+    }
+
+    fn serialize_none(self) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_none()
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<S::Ok, S::Error> {
+        let value = self.checked(value);
+        self.inner.serialize_some(&value)
+    }
+
+    fn serialize_unit(self) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit()
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit_struct(name)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<S::Ok, S::Error> {
+        self.inner
+            .serialize_unit_variant(name, variant_index, variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        let value = self.checked(value);
+        self.inner.serialize_newtype_struct(name, &value)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        let value = self.checked(value);
+        self.inner
+            .serialize_newtype_variant(name, variant_index, variant, &value)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, S::Error> {
+        let state = self.state;
+        let inner = self.inner.serialize_seq(len)?;
+        Ok(Compound { inner, state })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, S::Error> {
+        let state = self.state;
+        let inner = self.inner.serialize_tuple(len)?;
+        Ok(Compound { inner, state })
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, S::Error> {
+        let state = self.state;
+        let inner = self.inner.serialize_tuple_struct(name, len)?;
+        Ok(Compound { inner, state })
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, S::Error> {
+        let state = self.state;
+        let inner = self
+            .inner
+            .serialize_tuple_variant(name, variant_index, variant, len)?;
+        Ok(Compound { inner, state })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, S::Error> {
+        let state = self.state;
+        let inner = self.inner.serialize_map(len)?;
+        Ok(Compound { inner, state })
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, S::Error> {
+        let state = self.state;
+        let inner = self.inner.serialize_struct(name, len)?;
+        Ok(Compound { inner, state })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, S::Error> {
+        let state = self.state;
+        let inner = self
+            .inner
+            .serialize_struct_variant(name, variant_index, variant, len)?;
+        Ok(Compound { inner, state })
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+/// A sequence, tuple, map or struct partway through being serialized by [`CheckedSerializer`],
+/// which checks each of its elements in turn.
+struct Compound<'a, C> {
+    inner: C,
+    state: &'a State,
+}
+
+impl<C: ser::SerializeSeq> ser::SerializeSeq for Compound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = Checked {
+            value,
+            state: self.state,
+        };
+        self.inner.serialize_element(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: ser::SerializeTuple> ser::SerializeTuple for Compound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = Checked {
+            value,
+            state: self.state,
+        };
+        self.inner.serialize_element(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: ser::SerializeTupleStruct> ser::SerializeTupleStruct for Compound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = Checked {
+            value,
+            state: self.state,
+        };
+        self.inner.serialize_field(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: ser::SerializeTupleVariant> ser::SerializeTupleVariant for Compound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = Checked {
+            value,
+            state: self.state,
+        };
+        self.inner.serialize_field(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: ser::SerializeMap> ser::SerializeMap for Compound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), C::Error> {
+        let key = Checked {
+            value: key,
+            state: self.state,
+        };
+        self.inner.serialize_key(&key)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = Checked {
+            value,
+            state: self.state,
+        };
+        self.inner.serialize_value(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: ser::SerializeStruct> ser::SerializeStruct for Compound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), C::Error> {
+        let value = Checked {
+            value,
+            state: self.state,
+        };
+        self.inner.serialize_field(key, &value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: ser::SerializeStructVariant> ser::SerializeStructVariant for Compound<'_, C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), C::Error> {
+        let value = Checked {
+            value,
+            state: self.state,
+        };
+        self.inner.serialize_field(key, &value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lossy_writes_128_bit_integers_exactly() {
+        let policy = NumberPolicy::new().overflow(OverflowPolicy::Lossy);
+
+        assert_eq!(
+            policy.to_string(&u128::MAX).ok().as_deref(),
+            Some("340282366920938463463374607431768211455")
+        );
+        assert_eq!(
+            policy.to_string(&i128::MIN).ok().as_deref(),
+            Some("-170141183460469231731687303715884105728")
+        );
+        assert_eq!(policy.to_string(&5u128).ok().as_deref(), Some("5"));
+        assert_eq!(policy.to_string(&-5i128).ok().as_deref(), Some("-5"));
+    }
+
+    #[test]
+    fn error_rejects_integers_beyond_64_bits() {
+        let policy = NumberPolicy::new().overflow(OverflowPolicy::Error);
+
+        assert!(matches!(
+            policy.to_string(&u128::MAX),
+            Err(NumberError::Overflow { .. })
+        ));
+        assert!(matches!(
+            policy.to_string(&i128::MIN),
+            Err(NumberError::Overflow { .. })
+        ));
+        assert_eq!(
+            policy.to_string(&u128::from(u64::MAX)).ok(),
+            Some(u64::MAX.to_string())
+        );
+    }
+}
@@ -0,0 +1,250 @@
+use crate::{ReadError, RetryPolicy, WriteError};
+use ::object_store::path::Path;
+use ::object_store::{GetOptions, GetRange, ObjectStore, WriteMultipart};
+use futures::stream::{BoxStream, StreamExt};
+use serde::de::DeserializeOwned;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The number of parts [`ObjectWriter`] uploads at once.
+const MAX_CONCURRENT_PARTS: usize = 8;
+
+/// Reads JSON Lines from an object in an object store (such as Amazon S3, Google Cloud Storage or
+/// Azure Blob Storage), streaming it rather than downloading it first.
+///
+/// If the connection fails partway through the object, it is reopened with a ranged request
+/// starting after the data already received, backing off between attempts as set by
+/// [`ObjectReader::retry_policy`]. The [offset](ObjectReader::offset) of the last record
+/// read can also be saved and passed to [`ObjectReader::resume_at`] to carry on from the same place
+/// in a later run.
+pub struct ObjectReader {
+    store: Arc<dyn ObjectStore>,
+    path: Path,
+    stream: Option<BoxStream<'static, ::object_store::Result<bytes::Bytes>>>,
+    buf: Vec<u8>,
+    offset: usize,
+    fetched: usize,
+    size: Option<usize>,
+    retry_policy: RetryPolicy<::object_store::Error>,
+    failed_attempts: u32,
+}
+
+impl fmt::Debug for ObjectReader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ObjectReader")
+            .field("store", &self.store)
+            .field("path", &self.path)
+            .field("offset", &self.offset)
+            .field("retry_policy", &self.retry_policy)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ObjectReader {
+    /// Creates a new `ObjectReader` reading the object at `path` from the beginning.
+    pub fn new(store: Arc<dyn ObjectStore>, path: Path) -> Self {
+        Self::resume_at(store, path, 0)
+    }
+
+    /// Creates a new `ObjectReader` reading the object at `path` from `offset` bytes in, which
+    /// should be an offset previously returned by [`ObjectReader::offset`].
+    pub fn resume_at(store: Arc<dyn ObjectStore>, path: Path, offset: usize) -> Self {
+        Self {
+            store,
+            path,
+            stream: None,
+            buf: Vec::new(),
+            offset,
+            fetched: offset,
+            size: None,
+            retry_policy: RetryPolicy::new(),
+            failed_attempts: 0,
+        }
+    }
+
+    /// Sets when and how often a failed request is retried before giving up. Defaults to
+    /// [`RetryPolicy::new`].
+    pub fn retry_policy(mut self, policy: RetryPolicy<::object_store::Error>) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Returns the offset in bytes just past the last record read.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Reads a line from the object and deserializes it into a given type.
+    pub async fn read<T: DeserializeOwned>(&mut self) -> Result<T, ReadError> {
+        loop {
+            if let Some(i) = self.buf.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = self.buf.drain(..=i).collect();
+                self.offset += line.len();
+
+                return Ok(serde_json::from_slice(&line)?);
+            }
+
+            let stream = match &mut self.stream {
+                Some(stream) => stream,
+                None => {
+                    // A ranged request starting at the end of the object fails rather than
+                    // returning nothing, so the size is checked first.
+                    if self.fetched > 0 {
+                        let size = match self.size {
+                            Some(size) => size,
+                            None => match self.store.head(&self.path).await {
+                                Ok(meta) => *self.size.insert(meta.size),
+                                Err(e) => {
+                                    self.fail(e).await?;
+                                    continue;
+                                }
+                            },
+                        };
+
+                        if self.fetched >= size {
+                            return self.read_last_line();
+                        }
+                    }
+
+                    let options = GetOptions {
+                        range: (self.fetched > 0).then_some(GetRange::Offset(self.fetched)),
+                        ..GetOptions::default()
+                    };
+
+                    match self.store.get_opts(&self.path, options).await {
+                        Ok(result) => {
+                            self.size = Some(result.meta.size);
+                            self.stream = Some(result.into_stream());
+                        }
+                        Err(e) => self.fail(e).await?,
+                    }
+                    continue;
+                }
+            };
+
+            let next = stream.next().await;
+
+            match next {
+                Some(Ok(bytes)) => {
+                    self.buf.extend_from_slice(&bytes);
+                    self.fetched += bytes.len();
+                    self.failed_attempts = 0;
+                }
+                Some(Err(e)) => {
+                    self.stream = None;
+                    self.fail(e).await?;
+                }
+                None => return self.read_last_line(),
+            }
+        }
+    }
+
+    /// Deserializes what is left in the buffer once the whole object has been fetched, since an
+    /// object that does not end in a newline has a last line without one.
+    fn read_last_line<T: DeserializeOwned>(&mut self) -> Result<T, ReadError> {
+        if self.buf.is_empty() {
+            return Err(ReadError::Eof);
+        }
+
+        let line = std::mem::take(&mut self.buf);
+        self.offset += line.len();
+
+        Ok(serde_json::from_slice(&line)?)
+    }
+
+    /// Records a failed request, waiting before the next one is made if the retry policy allows
+    /// it and returning the error otherwise.
+    async fn fail(&mut self, e: ::object_store::Error) -> Result<(), ReadError> {
+        self.failed_attempts += 1;
+
+        if !self.retry_policy.should_retry(self.failed_attempts, &e) {
+            return Err(ReadError::Io(io::Error::from(e)));
+        }
+
+        sleep(self.retry_policy.delay(self.failed_attempts)).await;
+
+        Ok(())
+    }
+}
+
+/// Waits for `duration` without blocking the executor.
+#[cfg(feature = "tokio")]
+async fn sleep(duration: Duration) {
+    tokio::time::sleep(duration).await;
+}
+
+/// Waits for `duration` without blocking the executor. Without the `tokio` feature there is no
+/// timer to wait on, so a thread is spawned to wake the reader up, which is fine for the few
+/// retries a reader makes.
+#[cfg(not(feature = "tokio"))]
+async fn sleep(duration: Duration) {
+    let (sender, receiver) = futures::channel::oneshot::channel();
+
+    std::thread::spawn(move || {
+        std::thread::sleep(duration);
+        let _ = sender.send(());
+    });
+
+    let _ = receiver.await;
+}
+
+/// Writes JSON Lines to an object in an object store using a multipart upload, so that objects of
+/// any size can be written without being held in memory.
+///
+/// Parts are uploaded in the background as enough data is written to fill them. The object only
+/// appears in the store once [`ObjectWriter::finish`] has been called; an `ObjectWriter` that is
+/// dropped without finishing leaves an incomplete upload behind, which should be cleaned up with
+/// [`ObjectWriter::abort`] instead.
+#[derive(Debug)]
+pub struct ObjectWriter {
+    upload: WriteMultipart,
+}
+
+impl ObjectWriter {
+    /// Starts a multipart upload to the object at `path`.
+    pub async fn new(store: Arc<dyn ObjectStore>, path: Path) -> Result<Self, WriteError> {
+        let upload = store
+            .put_multipart(&path)
+            .await
+            .map_err(|e| WriteError::Io(e.into()))?;
+
+        Ok(Self {
+            upload: WriteMultipart::new(upload),
+        })
+    }
+
+    /// Writes a given value to the object, serializing it into JSON.
+    ///
+    /// This waits if too many parts are already being uploaded.
+    pub async fn write<T: serde::Serialize>(&mut self, t: &T) -> Result<(), WriteError> {
+        let json = serde_json::to_string(t)?;
+
+        self.upload.write(json.as_bytes());
+        self.upload.write(b"\n");
+
+        self.upload
+            .wait_for_capacity(MAX_CONCURRENT_PARTS)
+            .await
+            .map_err(|e| WriteError::Io(e.into()))
+    }
+
+    /// Uploads any remaining data and completes the upload, making the object visible.
+    pub async fn finish(self) -> Result<(), WriteError> {
+        self.upload
+            .finish()
+            .await
+            .map_err(|e| WriteError::Io(e.into()))?;
+
+        Ok(())
+    }
+
+    /// Aborts the upload, discarding everything written so far.
+    pub async fn abort(self) -> Result<(), WriteError> {
+        self.upload
+            .abort()
+            .await
+            .map_err(|e| WriteError::Io(e.into()))
+    }
+}
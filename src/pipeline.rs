@@ -0,0 +1,613 @@
+use crate::{ReadError, WriteError};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, BufReader, BufWriter, Seek, Write};
+use std::marker::PhantomData;
+use std::{panic, thread, vec};
+
+/// The number of records each thread processes at a time in [`Pipeline::map_parallel`].
+const PARALLEL_CHUNK_LEN: usize = 64;
+
+/// The default for [`GroupBy::max_groups_in_memory`].
+const DEFAULT_MAX_GROUPS_IN_MEMORY: usize = 1_000_000;
+
+/// The number of files groups are spread across when spilled to disk.
+pub(crate) const NUM_SPILL_PARTITIONS: usize = 16;
+
+/// An error that occurred while running a [`Pipeline`].
+#[derive(Debug, thiserror::Error)]
+pub enum PipelineError {
+    #[error("failed reading records")]
+    Read(#[from] ReadError),
+    #[error("failed writing records")]
+    Write(#[from] WriteError),
+    #[error("failed spilling records to disk")]
+    Spill(#[from] io::Error),
+    #[error("group was accumulated for a different aggregate")]
+    AggregateMismatch,
+}
+
+/// An iterator over every record of a JSON Lines reader, stopping at EOF, created by [`iter`].
+#[derive(Debug)]
+pub struct Records<R, T> {
+    reader: R,
+    done: bool,
+    _record: PhantomData<fn() -> T>,
+}
+
+/// Yields every record from `reader`, ending cleanly once it reaches EOF.
+///
+/// A line that fails to deserialize is yielded as an error, after which iteration carries on with
+/// the next line. This always reads with `std`, even when the `tokio` feature is enabled.
+///
+/// ```
+/// # fn main() -> Result<(), jsonl::ReadError> {
+/// let input = b"1\n2\n3\n" as &[u8];
+/// let numbers = jsonl::iter::<u32, _>(input).collect::<Result<Vec<_>, _>>()?;
+///
+/// assert_eq!(numbers, [1, 2, 3]);
+/// # Ok(())
+/// # }
+/// ```
+pub fn iter<T: DeserializeOwned, R: BufRead>(reader: R) -> Records<R, T> {
+    Records {
+        reader,
+        done: false,
+        _record: PhantomData,
+    }
+}
+
+impl<R: BufRead, T: DeserializeOwned> Iterator for Records<R, T> {
+    type Item = Result<T, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match crate::blocking::read(&mut self.reader) {
+            Err(ReadError::Eof) => {
+                self.done = true;
+                None
+            }
+            result => Some(result),
+        }
+    }
+}
+
+/// A chain of operations over the records of a JSON Lines reader, in the spirit of `jq`.
+///
+/// Records are read, transformed and written one at a time as the pipeline is driven by
+/// [`Pipeline::write_to`] or by iterating over it, so inputs of any size can be processed.
+/// Records can be deserialized into your own types, or into [`serde_json::Value`] to work with
+/// arbitrary JSON. Errors from reading are passed through every stage untouched, so a pipeline
+/// stops at the first malformed record when written out.
+///
+/// ```no_run
+/// # fn main() -> Result<(), jsonl::PipelineError> {
+/// use jsonl::Pipeline;
+/// use serde_json::Value;
+/// use std::io;
+///
+/// Pipeline::new(io::stdin().lock())
+///     .filter(|record: &Value| record["level"] == "error")
+///     .map(|record| record["message"].clone())
+///     .take(10)
+///     .write_to(io::stdout())?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Pipeline<I> {
+    records: I,
+}
+
+impl<R: BufRead, T: DeserializeOwned> Pipeline<Records<R, T>> {
+    /// Creates a new `Pipeline` over the records read from the given reader.
+    pub fn new(reader: R) -> Self {
+        Self {
+            records: iter(reader),
+        }
+    }
+}
+
+impl<I, T> Pipeline<I>
+where
+    I: Iterator<Item = Result<T, ReadError>>,
+{
+    /// Creates a new `Pipeline` over records from an existing source, such as another reader
+    /// utility in this crate.
+    pub fn from_records(records: I) -> Self {
+        Self { records }
+    }
+
+    /// Keeps only the records for which `predicate` returns `true`.
+    pub fn filter<P>(self, mut predicate: P) -> Pipeline<impl Iterator<Item = Result<T, ReadError>>>
+    where
+        P: FnMut(&T) -> bool,
+    {
+        Pipeline {
+            records: self
+                .records
+                .filter(move |record| record.as_ref().map_or(true, &mut predicate)),
+        }
+    }
+
+    /// Transforms every record with `f`.
+    pub fn map<U, F>(self, mut f: F) -> Pipeline<impl Iterator<Item = Result<U, ReadError>>>
+    where
+        F: FnMut(T) -> U,
+    {
+        Pipeline {
+            records: self.records.map(move |record| record.map(&mut f)),
+        }
+    }
+
+    /// Transforms every record with `f`, spreading the work across `num_threads` threads. The
+    /// order of records is preserved.
+    ///
+    /// Records are read on the current thread in chunks and then transformed in parallel, so this
+    /// is only worthwhile when `f` is expensive compared to deserializing a record.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_threads` is zero.
+    pub fn map_parallel<U, F>(self, num_threads: usize, f: F) -> Pipeline<ParallelMap<I, F, U>>
+    where
+        T: Send,
+        U: Send,
+        F: Fn(T) -> U + Sync,
+    {
+        assert!(num_threads > 0, "a pipeline needs at least one thread");
+
+        Pipeline {
+            records: ParallelMap {
+                records: self.records,
+                f,
+                num_threads,
+                buffer: Vec::new().into_iter(),
+            },
+        }
+    }
+
+    /// Stops after the first `n` records.
+    pub fn take(self, n: usize) -> Pipeline<impl Iterator<Item = Result<T, ReadError>>> {
+        Pipeline {
+            records: self.records.take(n),
+        }
+    }
+
+    /// Groups records by the key returned by `key_fn`, ready to be aggregated.
+    pub fn group_by<K, F>(self, key_fn: F) -> GroupBy<I, F>
+    where
+        F: FnMut(&T) -> K,
+    {
+        GroupBy {
+            records: self.records,
+            key_fn,
+            max_groups_in_memory: DEFAULT_MAX_GROUPS_IN_MEMORY,
+        }
+    }
+
+    /// Runs the pipeline, writing every resulting record to `writer` as JSON Lines. Returns the
+    /// number of records written.
+    pub fn write_to<W: Write>(self, mut writer: W) -> Result<usize, PipelineError>
+    where
+        T: serde::Serialize,
+    {
+        let mut num_records = 0;
+
+        for record in self.records {
+            crate::blocking::write(&mut writer, &record?)?;
+            num_records += 1;
+        }
+
+        writer.flush().map_err(WriteError::Io)?;
+
+        Ok(num_records)
+    }
+}
+
+impl<I, T> Iterator for Pipeline<I>
+where
+    I: Iterator<Item = Result<T, ReadError>>,
+{
+    type Item = Result<T, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.records.next()
+    }
+}
+
+/// The stage of a [`Pipeline`] created by [`Pipeline::map_parallel`].
+#[derive(Debug)]
+pub struct ParallelMap<I, F, U> {
+    records: I,
+    f: F,
+    num_threads: usize,
+    buffer: vec::IntoIter<Result<U, ReadError>>,
+}
+
+impl<I, T, F, U> Iterator for ParallelMap<I, F, U>
+where
+    I: Iterator<Item = Result<T, ReadError>>,
+    T: Send,
+    U: Send,
+    F: Fn(T) -> U + Sync,
+{
+    type Item = Result<U, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(record) = self.buffer.next() {
+            return Some(record);
+        }
+
+        let mut batch: Vec<_> = self
+            .records
+            .by_ref()
+            .take(self.num_threads * PARALLEL_CHUNK_LEN)
+            .collect();
+
+        if batch.is_empty() {
+            return None;
+        }
+
+        let chunk_len = batch.len().div_ceil(self.num_threads);
+        let mut chunks = Vec::with_capacity(self.num_threads);
+
+        while !batch.is_empty() {
+            let rest = batch.split_off(chunk_len.min(batch.len()));
+            chunks.push(batch);
+            batch = rest;
+        }
+
+        let f = &self.f;
+        let results: Vec<_> = thread::scope(|scope| {
+            let handles: Vec<_> = chunks
+                .into_iter()
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        chunk
+                            .into_iter()
+                            .map(|record| record.map(f))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+                .collect()
+        });
+
+        self.buffer = results.into_iter();
+        self.buffer.next()
+    }
+}
+
+/// How the records in each group are combined by [`GroupBy::aggregate`].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Aggregate {
+    /// The number of records in the group.
+    Count,
+    /// The sum of the numbers found at the given
+    /// [JSON pointer](https://www.rfc-editor.org/rfc/rfc6901) in each record of the group. Records
+    /// without a number there are skipped.
+    Sum(String),
+    /// An array of every record in the group, in the order they were read.
+    Collect,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum Accumulator {
+    Count(u64),
+    Sum(f64),
+    Collect(Vec<Value>),
+}
+
+impl Accumulator {
+    fn new(aggregate: &Aggregate) -> Self {
+        match aggregate {
+            Aggregate::Count => Self::Count(0),
+            Aggregate::Sum(_) => Self::Sum(0.0),
+            Aggregate::Collect => Self::Collect(Vec::new()),
+        }
+    }
+
+    fn add<T: Serialize>(
+        &mut self,
+        aggregate: &Aggregate,
+        record: &T,
+    ) -> Result<(), PipelineError> {
+        match (self, aggregate) {
+            (Self::Count(count), Aggregate::Count) => *count += 1,
+            (Self::Sum(sum), Aggregate::Sum(pointer)) => {
+                let record = serde_json::to_value(record).map_err(WriteError::from)?;
+
+                if let Some(n) = record.pointer(pointer).and_then(Value::as_f64) {
+                    *sum += n;
+                }
+            }
+            (Self::Collect(records), Aggregate::Collect) => {
+                records.push(serde_json::to_value(record).map_err(WriteError::from)?)
+            }
+            _ => return Err(PipelineError::AggregateMismatch),
+        }
+
+        Ok(())
+    }
+
+    fn merge(&mut self, other: Self) -> Result<(), PipelineError> {
+        match (self, other) {
+            (Self::Count(a), Self::Count(b)) => *a += b,
+            (Self::Sum(a), Self::Sum(b)) => *a += b,
+            (Self::Collect(a), Self::Collect(b)) => a.extend(b),
+            _ => return Err(PipelineError::AggregateMismatch),
+        }
+
+        Ok(())
+    }
+
+    fn into_value(self) -> Value {
+        match self {
+            Self::Count(count) => Value::from(count),
+            Self::Sum(sum) => Number::from_f64(sum).map_or(Value::Null, Value::Number),
+            Self::Collect(records) => Value::Array(records),
+        }
+    }
+}
+
+/// A [`Pipeline`] whose records have been grouped by key with [`Pipeline::group_by`].
+#[derive(Debug)]
+pub struct GroupBy<I, F> {
+    records: I,
+    key_fn: F,
+    max_groups_in_memory: usize,
+}
+
+impl<I, T, K, F> GroupBy<I, F>
+where
+    I: Iterator<Item = Result<T, ReadError>>,
+    T: Serialize,
+    K: Eq + Hash + Serialize + DeserializeOwned,
+    F: FnMut(&T) -> K,
+{
+    /// Sets how many groups may be held in memory at once before they are spilled to temporary
+    /// files, which are read back one at a time once every record has been read. Defaults to one
+    /// million.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_groups_in_memory` is zero.
+    pub fn max_groups_in_memory(mut self, max_groups_in_memory: usize) -> Self {
+        assert!(
+            max_groups_in_memory > 0,
+            "at least one group must fit in memory"
+        );
+        self.max_groups_in_memory = max_groups_in_memory;
+        self
+    }
+
+    /// Counts the records in each group.
+    pub fn count(self) -> Result<Groups<K>, PipelineError> {
+        self.aggregate(Aggregate::Count)
+    }
+
+    /// Sums the numbers at the given JSON pointer across the records in each group.
+    pub fn sum(self, pointer: &str) -> Result<Groups<K>, PipelineError> {
+        self.aggregate(Aggregate::Sum(pointer.to_string()))
+    }
+
+    /// Collects the records in each group into an array.
+    pub fn collect(self) -> Result<Groups<K>, PipelineError> {
+        self.aggregate(Aggregate::Collect)
+    }
+
+    /// Reads every record, combining the records in each group as described by `aggregate`.
+    ///
+    /// Groups are yielded in no particular order once every record has been read.
+    pub fn aggregate(mut self, aggregate: Aggregate) -> Result<Groups<K>, PipelineError> {
+        let mut groups: HashMap<K, Accumulator> = HashMap::new();
+        let mut partitions: Vec<BufWriter<File>> = Vec::new();
+
+        for record in self.records {
+            let record = record?;
+            let key = (self.key_fn)(&record);
+
+            if !groups.contains_key(&key) && groups.len() == self.max_groups_in_memory {
+                spill(&mut groups, &mut partitions)?;
+            }
+
+            groups
+                .entry(key)
+                .or_insert_with(|| Accumulator::new(&aggregate))
+                .add(&aggregate, &record)?;
+        }
+
+        if partitions.is_empty() {
+            return Ok(Groups {
+                current: groups.into_iter().collect::<Vec<_>>().into_iter(),
+                partitions: Vec::new(),
+            });
+        }
+
+        spill(&mut groups, &mut partitions)?;
+
+        let partitions = partitions
+            .into_iter()
+            .map(|partition| {
+                let mut file = partition.into_inner().map_err(|e| e.into_error())?;
+                file.rewind()?;
+                Ok(file)
+            })
+            .collect::<io::Result<_>>()?;
+
+        Ok(Groups {
+            current: Vec::new().into_iter(),
+            partitions,
+        })
+    }
+}
+
+/// Writes every group to the partition its key hashes to, leaving `groups` empty.
+fn spill<K: Hash + Serialize>(
+    groups: &mut HashMap<K, Accumulator>,
+    partitions: &mut Vec<BufWriter<File>>,
+) -> Result<(), PipelineError> {
+    if partitions.is_empty() {
+        for _ in 0..NUM_SPILL_PARTITIONS {
+            partitions.push(BufWriter::new(tempfile::tempfile()?));
+        }
+    }
+
+    for (key, accumulator) in groups.drain() {
+        let partition = &mut partitions[partition_of(&key, NUM_SPILL_PARTITIONS)];
+        crate::blocking::write(partition, &(key, accumulator))?;
+    }
+
+    Ok(())
+}
+
+pub(crate) fn partition_of<K: Hash + ?Sized>(key: &K, num_partitions: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    (hasher.finish() % num_partitions as u64) as usize
+}
+
+/// An iterator over the aggregated groups of a [`GroupBy`], yielding each key along with its
+/// aggregated value.
+#[derive(Debug)]
+pub struct Groups<K> {
+    current: vec::IntoIter<(K, Accumulator)>,
+    partitions: Vec<File>,
+}
+
+impl<K: Eq + Hash + DeserializeOwned> Iterator for Groups<K> {
+    type Item = Result<(K, Value), PipelineError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((key, accumulator)) = self.current.next() {
+                return Some(Ok((key, accumulator.into_value())));
+            }
+
+            let mut reader = BufReader::new(self.partitions.pop()?);
+            let mut groups: HashMap<K, Accumulator> = HashMap::new();
+
+            loop {
+                let (key, accumulator) = match crate::blocking::read(&mut reader) {
+                    Ok(group) => group,
+                    Err(ReadError::Eof) => break,
+                    Err(e) => return Some(Err(e.into())),
+                };
+
+                match groups.get_mut(&key) {
+                    Some(existing) => {
+                        if let Err(e) = existing.merge(accumulator) {
+                            return Some(Err(e));
+                        }
+                    }
+                    None => {
+                        groups.insert(key, accumulator);
+                    }
+                }
+            }
+
+            self.current = groups.into_iter().collect::<Vec<_>>().into_iter();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[derive(Debug, Deserialize)]
+    struct Point {
+        #[allow(dead_code)]
+        x: i64,
+    }
+
+    fn read_all(input: &[u8]) -> Vec<Result<Point, ReadError>> {
+        iter(input).collect()
+    }
+
+    #[test]
+    fn truncated_line_is_an_error() {
+        assert!(matches!(
+            crate::blocking::read::<_, Point>(&b"{\"x\":1"[..]),
+            Err(ReadError::Deserialize(_))
+        ));
+        assert!(matches!(
+            read_all(b"{\"x\":1}\n{\"x\":").as_slice(),
+            [Ok(_), Err(ReadError::Deserialize(_))]
+        ));
+    }
+
+    #[test]
+    fn oversized_line_is_an_error() {
+        let line = format!("{{\"x\":{}}}\n", "1".repeat(64));
+
+        assert!(matches!(
+            crate::blocking::read_with_limit::<_, Value>(line.as_bytes(), 16),
+            Err(ReadError::LineTooLong { max: 16 })
+        ));
+    }
+
+    #[test]
+    fn invalid_utf8_is_an_error() {
+        let input = b"{\"x\":\"\xff\xfe\"}\n";
+
+        assert!(matches!(
+            crate::blocking::read::<_, Value>(&input[..]),
+            Err(ReadError::Io(_))
+        ));
+        assert!(matches!(
+            crate::blocking::read_with_limit::<_, Value>(&input[..], 1024),
+            Err(ReadError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn wrong_shape_is_an_error() {
+        assert!(matches!(
+            read_all(b"{\"x\":1}\n[1,2]\n{\"y\":1}\n{\"x\":2}\n").as_slice(),
+            [
+                Ok(_),
+                Err(ReadError::Deserialize(_)),
+                Err(ReadError::Deserialize(_)),
+                Ok(_)
+            ]
+        ));
+    }
+
+    #[test]
+    fn read_error_stops_group_by() {
+        let result = Pipeline::new(&b"{\"x\":1}\nnot json\n"[..])
+            .group_by(|point: &Value| point["x"].to_string())
+            .count();
+
+        assert!(matches!(result, Err(PipelineError::Read(_))));
+    }
+
+    #[test]
+    fn mismatched_accumulator_is_an_error() {
+        let mut accumulator = Accumulator::new(&Aggregate::Count);
+
+        assert!(matches!(
+            accumulator.add(&Aggregate::Collect, &json!({})),
+            Err(PipelineError::AggregateMismatch)
+        ));
+        assert!(matches!(
+            accumulator.merge(Accumulator::Sum(1.0)),
+            Err(PipelineError::AggregateMismatch)
+        ));
+    }
+}
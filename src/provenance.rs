@@ -0,0 +1,148 @@
+use crate::WriteError;
+use serde::ser::Error as _;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::convert::TryFrom;
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Where [`ProvenanceWriter`] puts the provenance of each record.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ProvenanceMode {
+    /// Adds an object holding the provenance to the record itself under the given key. Records
+    /// that are not objects cannot be written in this mode.
+    Inline(String),
+    /// Wraps each record in an envelope: `{"record": ..., "provenance": {...}}`.
+    Envelope,
+}
+
+/// A writer that records where each record came from alongside it: the file it was read from, its
+/// line number there, when it was ingested and the version of the pipeline that wrote it.
+///
+/// Provenance is configured once on the writer, rather than being added to every record by the
+/// application. Only the fields that have been configured are written; the line number is always
+/// included.
+#[derive(Debug)]
+pub struct ProvenanceWriter<W: Write> {
+    inner: W,
+    mode: ProvenanceMode,
+    source: Option<String>,
+    pipeline_version: Option<String>,
+    ingested_at: bool,
+    next_line: u64,
+}
+
+impl<W: Write> ProvenanceWriter<W> {
+    /// Creates a new `ProvenanceWriter` that writes to `inner`, putting provenance in an object
+    /// under the `_provenance` key of each record.
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            mode: ProvenanceMode::Inline("_provenance".to_string()),
+            source: None,
+            pipeline_version: None,
+            ingested_at: false,
+            next_line: 1,
+        }
+    }
+
+    /// Sets where provenance is put in each record.
+    pub fn mode(mut self, mode: ProvenanceMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets the name of the file or other source records are being read from, and restarts line
+    /// numbering from one.
+    pub fn source(mut self, source: impl Into<String>) -> Self {
+        self.source = Some(source.into());
+        self.next_line = 1;
+        self
+    }
+
+    /// Sets the version of the pipeline writing the records, such as a release or commit hash.
+    pub fn pipeline_version(mut self, version: impl Into<String>) -> Self {
+        self.pipeline_version = Some(version.into());
+        self
+    }
+
+    /// Sets whether the time each record is written is recorded, as an RFC 3339 timestamp in UTC.
+    pub fn ingested_at(mut self, ingested_at: bool) -> Self {
+        self.ingested_at = ingested_at;
+        self
+    }
+
+    /// Writes a record with its provenance, taking its line number to be one more than the last
+    /// record’s, as is the case when every record read is written.
+    pub fn write<T: Serialize>(&mut self, t: &T) -> Result<(), WriteError> {
+        self.write_at(self.next_line, t)
+    }
+
+    /// Writes a record with its provenance, giving its line number in the source explicitly, for
+    /// when records are filtered or reordered on the way through. Later records read on from it.
+    pub fn write_at<T: Serialize>(&mut self, line: u64, t: &T) -> Result<(), WriteError> {
+        let record = serde_json::to_value(t)?;
+        let provenance = self.provenance(line);
+
+        let record = match &self.mode {
+            ProvenanceMode::Inline(key) => match record {
+                Value::Object(mut object) => {
+                    object.insert(key.clone(), provenance);
+                    Value::Object(object)
+                }
+                _ => {
+                    return Err(WriteError::Serialize(serde_json::Error::custom(
+                        "only objects can have provenance added inline",
+                    )))
+                }
+            },
+            ProvenanceMode::Envelope => {
+                serde_json::json!({ "record": record, "provenance": provenance })
+            }
+        };
+
+        crate::blocking::write(&mut self.inner, &record)?;
+        self.next_line = line.saturating_add(1);
+
+        Ok(())
+    }
+
+    /// Flushes the inner writer’s buffer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    /// Consumes the `ProvenanceWriter`, returning the inner writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    fn provenance(&self, line: u64) -> Value {
+        let mut provenance = Map::new();
+
+        if let Some(source) = &self.source {
+            provenance.insert("source".to_string(), source.clone().into());
+        }
+
+        provenance.insert("line".to_string(), line.into());
+
+        if self.ingested_at {
+            provenance.insert("ingested_at".to_string(), rfc3339(SystemTime::now()).into());
+        }
+
+        if let Some(version) = &self.pipeline_version {
+            provenance.insert("pipeline_version".to_string(), version.clone().into());
+        }
+
+        Value::Object(provenance)
+    }
+}
+
+/// Formats a time as an RFC 3339 timestamp in UTC with millisecond precision. Times before the
+/// Unix epoch are clamped to it.
+fn rfc3339(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = i64::try_from(since_epoch.as_secs()).unwrap_or(i64::MAX);
+
+    crate::datetime::format_rfc3339(secs, since_epoch.subsec_millis() * 1_000_000)
+}
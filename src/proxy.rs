@@ -0,0 +1,394 @@
+use base64::Engine;
+use std::convert::TryFrom;
+use std::io;
+use std::net::IpAddr;
+
+/// A proxy server to tunnel TCP connections through.
+///
+/// Pass this to [`crate::ConnectionBuilder::connect_tcp_via_proxy`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Proxy {
+    /// A [SOCKS5](https://www.rfc-editor.org/rfc/rfc1928) proxy, optionally authenticated with a
+    /// username and password.
+    Socks5 {
+        addr: String,
+        credentials: Option<(String, String)>,
+    },
+    /// An HTTP proxy that supports the `CONNECT` method, optionally authenticated with a username
+    /// and password using Basic authentication.
+    Http {
+        addr: String,
+        credentials: Option<(String, String)>,
+    },
+}
+
+impl Proxy {
+    /// Creates an unauthenticated SOCKS5 proxy at the given address (for example
+    /// `"proxy.example.com:1080"`).
+    pub fn socks5(addr: impl Into<String>) -> Self {
+        Self::Socks5 {
+            addr: addr.into(),
+            credentials: None,
+        }
+    }
+
+    /// Creates an unauthenticated HTTP proxy at the given address (for example
+    /// `"proxy.example.com:3128"`).
+    pub fn http(addr: impl Into<String>) -> Self {
+        Self::Http {
+            addr: addr.into(),
+            credentials: None,
+        }
+    }
+
+    /// Authenticates with the proxy using the given username and password.
+    pub fn with_credentials(
+        mut self,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        let new_credentials = Some((username.into(), password.into()));
+
+        match &mut self {
+            Self::Socks5 { credentials, .. } | Self::Http { credentials, .. } => {
+                *credentials = new_credentials
+            }
+        }
+
+        self
+    }
+
+    pub(crate) fn addr(&self) -> &str {
+        match self {
+            Self::Socks5 { addr, .. } | Self::Http { addr, .. } => addr,
+        }
+    }
+}
+
+const SOCKS_VERSION: u8 = 5;
+const SOCKS_NO_AUTHENTICATION: u8 = 0x00;
+const SOCKS_USERNAME_PASSWORD: u8 = 0x02;
+const SOCKS_NO_ACCEPTABLE_METHODS: u8 = 0xff;
+const SOCKS_CONNECT: u8 = 0x01;
+const SOCKS_IPV4: u8 = 0x01;
+const SOCKS_DOMAIN_NAME: u8 = 0x03;
+const SOCKS_IPV6: u8 = 0x04;
+
+fn proxy_error(message: impl Into<String>) -> io::Error {
+    io::Error::other(message.into())
+}
+
+fn socks5_greeting(credentials: &Option<(String, String)>) -> Vec<u8> {
+    match credentials {
+        Some(_) => vec![
+            SOCKS_VERSION,
+            2,
+            SOCKS_NO_AUTHENTICATION,
+            SOCKS_USERNAME_PASSWORD,
+        ],
+        None => vec![SOCKS_VERSION, 1, SOCKS_NO_AUTHENTICATION],
+    }
+}
+
+fn socks5_check_method(reply: [u8; 2], credentials: &Option<(String, String)>) -> io::Result<bool> {
+    match (reply, credentials) {
+        ([SOCKS_VERSION, SOCKS_NO_AUTHENTICATION], _) => Ok(false),
+        ([SOCKS_VERSION, SOCKS_USERNAME_PASSWORD], Some(_)) => Ok(true),
+        ([SOCKS_VERSION, SOCKS_NO_ACCEPTABLE_METHODS], _) => Err(proxy_error(
+            "SOCKS5 proxy did not accept any of the offered authentication methods",
+        )),
+        _ => Err(proxy_error("SOCKS5 proxy sent an invalid method selection")),
+    }
+}
+
+fn socks5_authentication(username: &str, password: &str) -> io::Result<Vec<u8>> {
+    let username_len = u8::try_from(username.len())
+        .map_err(|_| proxy_error("SOCKS5 username is longer than 255 bytes"))?;
+    let password_len = u8::try_from(password.len())
+        .map_err(|_| proxy_error("SOCKS5 password is longer than 255 bytes"))?;
+
+    let mut request = vec![1, username_len];
+    request.extend_from_slice(username.as_bytes());
+    request.push(password_len);
+    request.extend_from_slice(password.as_bytes());
+
+    Ok(request)
+}
+
+fn socks5_check_authentication(reply: [u8; 2]) -> io::Result<()> {
+    match reply {
+        [1, 0] => Ok(()),
+        _ => Err(proxy_error("SOCKS5 proxy rejected the given credentials")),
+    }
+}
+
+fn socks5_connect_request(host: &str, port: u16) -> io::Result<Vec<u8>> {
+    let mut request = vec![SOCKS_VERSION, SOCKS_CONNECT, 0];
+
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(SOCKS_IPV4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(SOCKS_IPV6);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            let host_len = u8::try_from(host.len())
+                .map_err(|_| proxy_error("hostname is longer than 255 bytes"))?;
+            request.push(SOCKS_DOMAIN_NAME);
+            request.push(host_len);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+
+    request.extend_from_slice(&port.to_be_bytes());
+
+    Ok(request)
+}
+
+/// Checks the fixed-size head of a SOCKS5 connect reply, returning how many more bytes of bound
+/// address and port follow it, excluding the length byte of a domain name.
+fn socks5_check_connect_reply(head: [u8; 4]) -> io::Result<Option<usize>> {
+    let [version, reply, _, address_type] = head;
+
+    if version != SOCKS_VERSION {
+        return Err(proxy_error("SOCKS5 proxy sent an invalid reply"));
+    }
+
+    let message = match reply {
+        0x00 => {
+            return match address_type {
+                SOCKS_IPV4 => Ok(Some(4 + 2)),
+                SOCKS_IPV6 => Ok(Some(16 + 2)),
+                SOCKS_DOMAIN_NAME => Ok(None),
+                _ => Err(proxy_error("SOCKS5 proxy sent an invalid address type")),
+            }
+        }
+        0x01 => "general SOCKS server failure",
+        0x02 => "connection not allowed by ruleset",
+        0x03 => "network unreachable",
+        0x04 => "host unreachable",
+        0x05 => "connection refused",
+        0x06 => "TTL expired",
+        0x07 => "command not supported",
+        0x08 => "address type not supported",
+        _ => "unknown SOCKS5 error",
+    };
+
+    Err(proxy_error(format!(
+        "SOCKS5 proxy failed to connect: {}",
+        message
+    )))
+}
+
+fn http_connect_request(host: &str, port: u16, credentials: &Option<(String, String)>) -> String {
+    let authority = match host.parse::<IpAddr>() {
+        Ok(IpAddr::V6(_)) => format!("[{}]:{}", host, port),
+        _ => format!("{}:{}", host, port),
+    };
+
+    let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", authority);
+
+    if let Some((username, password)) = credentials {
+        let token =
+            base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", username, password));
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", token));
+    }
+
+    request.push_str("\r\n");
+    request
+}
+
+fn http_check_connect_response(response: &[u8]) -> io::Result<()> {
+    let status_line = response
+        .split(|b| *b == b'\n')
+        .next()
+        .map(String::from_utf8_lossy)
+        .unwrap_or_default();
+
+    let mut parts = status_line.split_whitespace();
+    let is_http = parts.next().is_some_and(|v| v.starts_with("HTTP/1."));
+    let status = parts.next();
+
+    match (is_http, status) {
+        (true, Some(status)) if status.starts_with('2') => Ok(()),
+        (true, Some(_)) => Err(proxy_error(format!(
+            "HTTP proxy refused to connect: {}",
+            status_line.trim_end()
+        ))),
+        _ => Err(proxy_error("HTTP proxy sent an invalid response")),
+    }
+}
+
+/// The maximum size of the response headers accepted from an HTTP proxy.
+const MAX_HTTP_RESPONSE_LEN: usize = 16 * 1024;
+
+#[cfg(not(feature = "tokio"))]
+mod imp {
+    use super::*;
+    use std::io::{Read, Write};
+
+    impl Proxy {
+        pub(crate) fn handshake<S: Read + Write>(
+            &self,
+            stream: &mut S,
+            host: &str,
+            port: u16,
+        ) -> io::Result<()> {
+            match self {
+                Self::Socks5 { credentials, .. } => {
+                    socks5_handshake(stream, credentials, host, port)
+                }
+                Self::Http { credentials, .. } => http_handshake(stream, credentials, host, port),
+            }
+        }
+    }
+
+    fn socks5_handshake<S: Read + Write>(
+        stream: &mut S,
+        credentials: &Option<(String, String)>,
+        host: &str,
+        port: u16,
+    ) -> io::Result<()> {
+        stream.write_all(&socks5_greeting(credentials))?;
+
+        let mut reply = [0; 2];
+        stream.read_exact(&mut reply)?;
+
+        if let (true, Some((username, password))) =
+            (socks5_check_method(reply, credentials)?, credentials)
+        {
+            stream.write_all(&socks5_authentication(username, password)?)?;
+            stream.read_exact(&mut reply)?;
+            socks5_check_authentication(reply)?;
+        }
+
+        stream.write_all(&socks5_connect_request(host, port)?)?;
+
+        let mut head = [0; 4];
+        stream.read_exact(&mut head)?;
+
+        let remaining = match socks5_check_connect_reply(head)? {
+            Some(remaining) => remaining,
+            None => {
+                let mut len = [0; 1];
+                stream.read_exact(&mut len)?;
+                usize::from(len[0]) + 2
+            }
+        };
+
+        stream.read_exact(&mut vec![0; remaining])
+    }
+
+    fn http_handshake<S: Read + Write>(
+        stream: &mut S,
+        credentials: &Option<(String, String)>,
+        host: &str,
+        port: u16,
+    ) -> io::Result<()> {
+        stream.write_all(http_connect_request(host, port, credentials).as_bytes())?;
+
+        // Read one byte at a time so we never consume data belonging to the tunnelled stream.
+        let mut response = Vec::new();
+        let mut byte = [0; 1];
+
+        while !response.ends_with(b"\r\n\r\n") {
+            if response.len() == MAX_HTTP_RESPONSE_LEN {
+                return Err(proxy_error("HTTP proxy response headers are too long"));
+            }
+
+            stream.read_exact(&mut byte)?;
+            response.push(byte[0]);
+        }
+
+        http_check_connect_response(&response)
+    }
+}
+
+#[cfg(feature = "tokio")]
+mod imp {
+    use super::*;
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+    impl Proxy {
+        pub(crate) async fn handshake<S: AsyncRead + AsyncWrite + Unpin>(
+            &self,
+            stream: &mut S,
+            host: &str,
+            port: u16,
+        ) -> io::Result<()> {
+            match self {
+                Self::Socks5 { credentials, .. } => {
+                    socks5_handshake(stream, credentials, host, port).await
+                }
+                Self::Http { credentials, .. } => {
+                    http_handshake(stream, credentials, host, port).await
+                }
+            }
+        }
+    }
+
+    async fn socks5_handshake<S: AsyncRead + AsyncWrite + Unpin>(
+        stream: &mut S,
+        credentials: &Option<(String, String)>,
+        host: &str,
+        port: u16,
+    ) -> io::Result<()> {
+        stream.write_all(&socks5_greeting(credentials)).await?;
+
+        let mut reply = [0; 2];
+        stream.read_exact(&mut reply).await?;
+
+        if let (true, Some((username, password))) =
+            (socks5_check_method(reply, credentials)?, credentials)
+        {
+            stream
+                .write_all(&socks5_authentication(username, password)?)
+                .await?;
+            stream.read_exact(&mut reply).await?;
+            socks5_check_authentication(reply)?;
+        }
+
+        stream
+            .write_all(&socks5_connect_request(host, port)?)
+            .await?;
+
+        let mut head = [0; 4];
+        stream.read_exact(&mut head).await?;
+
+        let remaining = match socks5_check_connect_reply(head)? {
+            Some(remaining) => remaining,
+            None => usize::from(stream.read_u8().await?) + 2,
+        };
+
+        stream.read_exact(&mut vec![0; remaining]).await?;
+
+        Ok(())
+    }
+
+    async fn http_handshake<S: AsyncRead + AsyncWrite + Unpin>(
+        stream: &mut S,
+        credentials: &Option<(String, String)>,
+        host: &str,
+        port: u16,
+    ) -> io::Result<()> {
+        stream
+            .write_all(http_connect_request(host, port, credentials).as_bytes())
+            .await?;
+
+        // Read one byte at a time so we never consume data belonging to the tunnelled stream.
+        let mut response = Vec::new();
+
+        while !response.ends_with(b"\r\n\r\n") {
+            if response.len() == MAX_HTTP_RESPONSE_LEN {
+                return Err(proxy_error("HTTP proxy response headers are too long"));
+            }
+
+            response.push(stream.read_u8().await?);
+        }
+
+        http_check_connect_response(&response)
+    }
+}
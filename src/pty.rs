@@ -0,0 +1,207 @@
+#[cfg(not(feature = "tokio"))]
+mod imports {
+    pub(super) use std::fs::File;
+    pub(super) use std::io::{self, BufReader, Read, Write};
+    pub(super) use std::process::{Child, Command};
+}
+#[cfg(feature = "tokio")]
+mod imports {
+    pub(super) use std::pin::Pin;
+    pub(super) use std::task::{Context, Poll};
+    pub(super) use tokio::fs::File;
+    pub(super) use tokio::io::{self, AsyncRead, AsyncWrite, BufReader, ReadBuf};
+    pub(super) use tokio::process::{Child, Command};
+}
+
+use crate::{Connection, Transport};
+use imports::*;
+use std::os::unix::io::{AsFd, AsRawFd, FromRawFd, OwnedFd};
+#[cfg(not(feature = "tokio"))]
+use std::os::unix::process::CommandExt as _;
+use std::{mem, ptr};
+
+/// The master side of a pseudoterminal, as used by [`Connection::new_from_pty`].
+///
+/// Once the child process exits and the terminal is hung up, reads return end of file instead of
+/// the `EIO` error that Linux reports.
+#[derive(Debug)]
+pub struct PtyMaster {
+    file: File,
+}
+
+impl Connection<BufReader<PtyMaster>, PtyMaster> {
+    /// Spawns `command` with a pseudoterminal as its `stdin`, `stdout` and `stderr`, and creates a
+    /// new `Connection` that communicates with it through the master side of the terminal. This is
+    /// useful for programs that refuse to work unless they are attached to a terminal.
+    ///
+    /// The terminal is put into raw mode before the child starts, so that the messages written to
+    /// it are not echoed back, no line editing or signal characters are interpreted, and newlines
+    /// are not translated into `\r\n`. Anything the child writes to `stderr` is interleaved with
+    /// its `stdout`, since both are the same terminal.
+    ///
+    /// The child is made the leader of a new session with the terminal as its controlling
+    /// terminal.
+    pub fn new_from_pty(mut command: Command) -> io::Result<(Self, Child)> {
+        let (master, slave) = open_raw_pty()?;
+
+        command
+            .stdin(slave.try_clone()?)
+            .stdout(slave.try_clone()?)
+            .stderr(slave);
+
+        // SAFETY: setsid and ioctl are async-signal-safe.
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setsid() == -1 || libc::ioctl(0, libc::TIOCSCTTY as _, 0) == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        let child = command.spawn()?;
+
+        // Close our copies of the slave side, so that reads from the master report end of file
+        // once the child exits.
+        drop(command);
+
+        Ok((Connection::from_transport(PtyMaster::new(master))?, child))
+    }
+}
+
+/// The master side of a pseudoterminal, read from and written to through two handles on the same
+/// terminal.
+impl Transport for PtyMaster {
+    type Reader = BufReader<PtyMaster>;
+    type Writer = PtyMaster;
+    type Error = io::Error;
+
+    fn split(self) -> io::Result<(Self::Reader, Self::Writer)> {
+        let reader = PtyMaster::new(self.file.as_fd().try_clone_to_owned()?);
+        Ok((BufReader::new(reader), self))
+    }
+}
+
+fn open_raw_pty() -> io::Result<(OwnedFd, OwnedFd)> {
+    let mut master = -1;
+    let mut slave = -1;
+
+    let mut size: libc::winsize = unsafe { mem::zeroed() };
+    size.ws_row = 24;
+    size.ws_col = 80;
+
+    // SAFETY: the pointers passed are valid for the duration of the call, and openpty returns
+    // newly-opened file descriptors that we take ownership of.
+    let (master, slave) = unsafe {
+        if libc::openpty(
+            &mut master,
+            &mut slave,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            &size as *const libc::winsize as _,
+        ) == -1
+        {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave))
+    };
+
+    // SAFETY: master is a valid file descriptor and termios is initialized by tcgetattr before
+    // cfmakeraw and tcsetattr read it.
+    unsafe {
+        if libc::fcntl(master.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        let mut termios: libc::termios = mem::zeroed();
+
+        if libc::tcgetattr(slave.as_raw_fd(), &mut termios) == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        libc::cfmakeraw(&mut termios);
+
+        if libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &termios) == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+
+    Ok((master, slave))
+}
+
+fn is_hangup(e: &io::Error) -> bool {
+    e.raw_os_error() == Some(libc::EIO)
+}
+
+#[cfg(not(feature = "tokio"))]
+impl PtyMaster {
+    fn new(fd: OwnedFd) -> Self {
+        Self {
+            file: File::from(fd),
+        }
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl Read for PtyMaster {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.file.read(buf) {
+            Err(e) if is_hangup(&e) => Ok(0),
+            result => result,
+        }
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl Write for PtyMaster {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl PtyMaster {
+    fn new(fd: OwnedFd) -> Self {
+        Self {
+            file: File::from_std(std::fs::File::from(fd)),
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncRead for PtyMaster {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match Pin::new(&mut self.file).poll_read(cx, buf) {
+            Poll::Ready(Err(e)) if is_hangup(&e) => Poll::Ready(Ok(())),
+            poll => poll,
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncWrite for PtyMaster {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.file).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.file).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.file).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,107 @@
+use crate::{Connection, ReadError, Transport, WriteError};
+use quinn::{RecvStream, SendStream};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use tokio::io::BufReader;
+
+/// An error that occurred while opening or accepting a QUIC stream.
+#[derive(Debug, thiserror::Error)]
+pub enum QuicError {
+    #[error("QUIC connection failed")]
+    Connection(#[from] quinn::ConnectionError),
+    #[error("failed reading channel name")]
+    Read(#[from] ReadError),
+    #[error("failed writing channel name")]
+    Write(#[from] WriteError),
+}
+
+/// The first line sent on a stream opened with [`Connection::open_quic_channel`].
+#[derive(Serialize, Deserialize)]
+struct ChannelHeader {
+    channel: String,
+}
+
+/// A QUIC bidirectional stream as a [`Transport`], as opened with `quinn::Connection::open_bi`
+/// and accepted with `quinn::Connection::accept_bi`.
+#[derive(Debug)]
+pub struct QuicTransport {
+    send: SendStream,
+    recv: RecvStream,
+}
+
+impl QuicTransport {
+    /// Creates a new `QuicTransport` from the two halves of a bidirectional stream.
+    pub fn new(send: SendStream, recv: RecvStream) -> Self {
+        Self { send, recv }
+    }
+}
+
+impl Transport for QuicTransport {
+    type Reader = BufReader<RecvStream>;
+    type Writer = SendStream;
+    type Error = Infallible;
+
+    fn split(self) -> Result<(Self::Reader, Self::Writer), Infallible> {
+        Ok((BufReader::new(self.recv), self.send))
+    }
+}
+
+impl Connection<BufReader<RecvStream>, SendStream> {
+    /// Creates a new `Connection` from the two halves of a QUIC bidirectional stream.
+    pub fn new_from_quic_stream(send: SendStream, recv: RecvStream) -> Self {
+        Self::from_infallible_transport(QuicTransport::new(send, recv))
+    }
+
+    /// Opens a new bidirectional stream on a QUIC connection.
+    ///
+    /// QUIC only tells the peer about a stream once data is sent on it, so the peer’s
+    /// [`Connection::accept_quic_stream`] does not return until the first message is written.
+    pub async fn open_quic_stream(connection: &quinn::Connection) -> Result<Self, QuicError> {
+        let (send, recv) = connection.open_bi().await?;
+        Ok(Self::new_from_quic_stream(send, recv))
+    }
+
+    /// Accepts the next bidirectional stream opened by the peer on a QUIC connection.
+    pub async fn accept_quic_stream(connection: &quinn::Connection) -> Result<Self, QuicError> {
+        let (send, recv) = connection.accept_bi().await?;
+        Ok(Self::new_from_quic_stream(send, recv))
+    }
+
+    /// Opens a new bidirectional stream on a QUIC connection for the logical channel called
+    /// `channel`, which the peer learns from [`Connection::accept_quic_channel`].
+    ///
+    /// Each stream is independently flow-controlled and never blocked by packet loss on the
+    /// others, so giving every logical channel its own stream keeps a busy channel from holding up
+    /// the rest.
+    pub async fn open_quic_channel(
+        connection: &quinn::Connection,
+        channel: &str,
+    ) -> Result<Self, QuicError> {
+        let mut stream = Self::open_quic_stream(connection).await?;
+
+        stream
+            .write(&ChannelHeader {
+                channel: channel.to_string(),
+            })
+            .await?;
+
+        Ok(stream)
+    }
+
+    /// Accepts the next stream opened by the peer with [`Connection::open_quic_channel`],
+    /// returning the name of its channel alongside it.
+    pub async fn accept_quic_channel(
+        connection: &quinn::Connection,
+    ) -> Result<(String, Self), QuicError> {
+        let mut stream = Self::accept_quic_stream(connection).await?;
+        let header: ChannelHeader = stream.read().await?;
+
+        Ok((header.channel, stream))
+    }
+
+    /// Closes the sending half of the stream, telling the peer that no more messages will be
+    /// written. Messages can still be read until the peer does the same.
+    pub fn finish(&mut self) -> Result<(), quinn::ClosedStream> {
+        self.writer_mut().finish()
+    }
+}
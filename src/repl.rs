@@ -0,0 +1,153 @@
+use crate::{ReadError, TerminalMode, TerminalWriter, WriteError};
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use serde_json::Value;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+type InvalidRequestCallback = Box<dyn FnMut(&ReplError)>;
+
+/// An error that ended a [`Repl`] session.
+#[derive(Debug, thiserror::Error)]
+pub enum ReplError {
+    #[error("failed reading a line from the terminal")]
+    Readline(#[from] ReadlineError),
+    #[error("failed reading a response")]
+    Read(#[from] ReadError),
+    #[error("failed writing a request")]
+    Write(#[from] WriteError),
+    #[error("failed printing a response")]
+    Print(#[from] io::Error),
+    #[error("request is not valid JSON")]
+    InvalidRequest(#[source] serde_json::Error),
+}
+
+/// An interactive prompt for speaking JSON Lines to a server by hand while developing it.
+///
+/// Each line typed is sent as a request and the next line read back is printed as the response,
+/// expanded and highlighted when stdout is a terminal. Requests may use the relaxed syntax of
+/// [`LenientReader`](crate::LenientReader), and a line that is not valid JSON is not sent, and
+/// instead its error is printed in place of a response or handed to
+/// [`Repl::on_invalid_request`]. The session ends on Ctrl-C, Ctrl-D, or when the server closes the
+/// connection.
+///
+/// ```no_run
+/// use jsonl::Repl;
+/// use std::io::BufReader;
+/// use std::net::TcpStream;
+///
+/// let stream = TcpStream::connect("127.0.0.1:8080")?;
+/// Repl::new(BufReader::new(stream.try_clone()?), stream).run()?;
+/// # Ok::<_, Box<dyn std::error::Error>>(())
+/// ```
+pub struct Repl<R: BufRead, W: Write> {
+    reader: R,
+    writer: W,
+    prompt: String,
+    history_path: Option<PathBuf>,
+    on_invalid_request: Option<InvalidRequestCallback>,
+}
+
+impl<R: BufRead + fmt::Debug, W: Write + fmt::Debug> fmt::Debug for Repl<R, W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Repl")
+            .field("reader", &self.reader)
+            .field("writer", &self.writer)
+            .field("prompt", &self.prompt)
+            .field("history_path", &self.history_path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<R: BufRead, W: Write> Repl<R, W> {
+    /// Creates a new `Repl` that sends requests to `writer` and reads responses from `reader`.
+    pub fn new(reader: R, writer: W) -> Self {
+        Self {
+            reader,
+            writer,
+            prompt: "> ".to_string(),
+            history_path: None,
+            on_invalid_request: None,
+        }
+    }
+
+    /// Sets the prompt shown before each request. Defaults to `"> "`.
+    pub fn prompt(mut self, prompt: &str) -> Self {
+        self.prompt = prompt.to_string();
+        self
+    }
+
+    /// Loads history from the file at `path` when the session starts, and saves it there when the
+    /// session ends.
+    pub fn history_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.history_path = Some(path.into());
+        self
+    }
+
+    /// Calls `f` with [`ReplError::InvalidRequest`] whenever a line typed is not valid JSON,
+    /// instead of printing the error. The session carries on either way.
+    pub fn on_invalid_request<F: FnMut(&ReplError) + 'static>(mut self, f: F) -> Self {
+        self.on_invalid_request = Some(Box::new(f));
+        self
+    }
+
+    /// Runs the session until it is ended by the user or the server.
+    pub fn run(mut self) -> Result<(), ReplError> {
+        let mut editor = DefaultEditor::new()?;
+
+        if let Some(path) = &self.history_path {
+            // A missing history file just means this is the first session.
+            let _ = editor.load_history(path);
+        }
+
+        let result = self.run_with(&mut editor);
+
+        if let Some(path) = &self.history_path {
+            editor.save_history(path)?;
+        }
+
+        result
+    }
+
+    fn run_with(&mut self, editor: &mut DefaultEditor) -> Result<(), ReplError> {
+        let mut stdout = TerminalWriter::new(io::stdout(), TerminalMode::Colorize);
+
+        loop {
+            let line = match editor.readline(&self.prompt) {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => return Ok(()),
+                Err(e) => return Err(e.into()),
+            };
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            editor.add_history_entry(line.as_str())?;
+
+            let request: Value = match serde_json::from_str(&crate::interactive::relax(&line)) {
+                Ok(request) => request,
+                Err(source) => {
+                    match &mut self.on_invalid_request {
+                        Some(on_invalid_request) => {
+                            on_invalid_request(&ReplError::InvalidRequest(source))
+                        }
+                        None => writeln!(stdout, "invalid JSON: {}", source)?,
+                    }
+                    continue;
+                }
+            };
+
+            crate::blocking::write(&mut self.writer, &request)?;
+
+            let response: Value = match crate::blocking::read(&mut self.reader) {
+                Ok(response) => response,
+                Err(ReadError::Eof) => return Ok(()),
+                Err(e) => return Err(e.into()),
+            };
+
+            crate::blocking::write(&mut stdout, &response)?;
+        }
+    }
+}
@@ -0,0 +1,414 @@
+use crate::{Connection, ConnectionState, Lifecycle, ReadError, RetryPolicy, WriteError};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::io;
+
+#[cfg(not(feature = "tokio"))]
+use std::io::{BufRead, Write};
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncBufRead as BufRead, AsyncWrite as Write};
+
+/// Sent by both sides when a connection is (re)established, holding the sequence number of the
+/// last message received, or zero if none have been.
+#[derive(Serialize, Deserialize)]
+struct Hello {
+    resume: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope<T> {
+    seq: u64,
+    msg: T,
+}
+
+/// An error that occurred while using a [`ResumableConnection`].
+#[derive(Debug, thiserror::Error)]
+pub enum ResumeError {
+    #[error("failed reading from connection")]
+    Read(#[from] ReadError),
+    #[error("failed writing to connection")]
+    Write(#[from] WriteError),
+    #[error("peer resumed from message {requested}, but only messages from {oldest} are buffered")]
+    Gap { requested: u64, oldest: u64 },
+    #[error("connection has been lost and not yet resumed")]
+    Disconnected,
+    #[error("failed connecting to peer")]
+    Connect(#[source] io::Error),
+}
+
+impl ResumeError {
+    /// Returns whether a new connection could get past this error.
+    fn is_retryable(&self) -> bool {
+        !matches!(self, Self::Gap { .. })
+    }
+}
+
+/// A connection that delivers every message exactly once and in order, even across reconnects.
+///
+/// Every message written is numbered and kept in a replay buffer. Whenever a new underlying
+/// [`Connection`] is handed to [`ResumableConnection::resume`], both sides exchange the sequence
+/// number of the last message they received, and each replays whatever the other missed. Messages
+/// received twice because of a replay are dropped.
+///
+/// Both sides of the connection must use a `ResumableConnection`. When a read or write fails, the
+/// underlying connection is dropped; open a new one (for a client, by reconnecting, perhaps with
+/// [`ResumableConnection::reconnect`]; for a server, by accepting the client’s next connection)
+/// and pass it to `resume`. Messages written while
+/// disconnected are buffered and sent on resumption, so they must not be written again.
+///
+/// The connection is [`ConnectionState::Closed`] until resumed, [`ConnectionState::Handshaking`]
+/// while exchanging sequence numbers, and [`ConnectionState::Established`] once messages can flow.
+#[derive(Debug)]
+pub struct ResumableConnection<R: BufRead, W: Write> {
+    connection: Option<Connection<R, W>>,
+    lifecycle: Lifecycle,
+    next_seq: u64,
+    last_received: u64,
+    replay: VecDeque<(u64, Value)>,
+    replay_capacity: usize,
+}
+
+impl<R: BufRead, W: Write> ResumableConnection<R, W> {
+    /// Creates a new `ResumableConnection`, not yet connected, that buffers up to
+    /// `replay_capacity` sent messages for replay. Call [`ResumableConnection::resume`] to connect
+    /// it.
+    ///
+    /// If the peer misses more than `replay_capacity` messages, the connection cannot be resumed.
+    pub fn new(replay_capacity: usize) -> Self {
+        Self {
+            connection: None,
+            lifecycle: Lifecycle::default(),
+            next_seq: 1,
+            last_received: 0,
+            replay: VecDeque::new(),
+            replay_capacity,
+        }
+    }
+
+    /// Returns whether an underlying connection is currently attached.
+    pub fn is_connected(&self) -> bool {
+        self.connection.is_some()
+    }
+
+    /// Returns the current state of the connection.
+    pub fn state(&self) -> ConnectionState {
+        self.lifecycle.state()
+    }
+
+    /// Adds a callback run with the old and new states whenever the state of the connection
+    /// changes, such as to start reconnecting as soon as it is lost.
+    pub fn on_state_change<F: FnMut(ConnectionState, ConnectionState) + Send + 'static>(
+        &mut self,
+        callback: F,
+    ) {
+        self.lifecycle.on_change(callback);
+    }
+
+    fn enter(&mut self, state: ConnectionState) {
+        // Every transition made by `ResumableConnection` is allowed by construction.
+        let _ = self.lifecycle.transition(state);
+    }
+
+    /// Drops the underlying connection, if any, after it has failed or is being replaced.
+    fn disconnect(&mut self) {
+        self.connection = None;
+
+        if !matches!(
+            self.lifecycle.state(),
+            ConnectionState::Closed | ConnectionState::Connecting
+        ) {
+            self.enter(ConnectionState::Closed);
+        }
+    }
+
+    /// Returns the sequence number of the last message received.
+    pub fn last_received(&self) -> u64 {
+        self.last_received
+    }
+
+    /// Numbers a message and adds it to the replay buffer, returning its sequence number.
+    fn buffer<T: Serialize>(&mut self, t: &T) -> Result<u64, WriteError> {
+        let seq = self.next_seq;
+        self.replay.push_back((seq, serde_json::to_value(t)?));
+        self.next_seq += 1;
+
+        while self.replay.len() > self.replay_capacity {
+            self.replay.pop_front();
+        }
+
+        Ok(seq)
+    }
+
+    /// Drops the messages the peer has confirmed receiving from the replay buffer, checking that
+    /// every message it has not received is still buffered.
+    fn acknowledge(&mut self, peer_last_received: u64) -> Result<(), ResumeError> {
+        while self
+            .replay
+            .front()
+            .is_some_and(|(seq, _)| *seq <= peer_last_received)
+        {
+            self.replay.pop_front();
+        }
+
+        let oldest = self.replay.front().map_or(self.next_seq, |(seq, _)| *seq);
+
+        if peer_last_received + 1 < oldest {
+            return Err(ResumeError::Gap {
+                requested: peer_last_received + 1,
+                oldest,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl<R: BufRead, W: Write> ResumableConnection<R, W> {
+    /// Attaches a new underlying connection, exchanging sequence numbers with the peer and
+    /// replaying the messages it missed.
+    pub fn resume(&mut self, mut connection: Connection<R, W>) -> Result<(), ResumeError> {
+        self.disconnect();
+        self.enter(ConnectionState::Handshaking);
+
+        if let Err(e) = self.handshake(&mut connection) {
+            self.enter(ConnectionState::Closed);
+            return Err(e);
+        }
+
+        self.connection = Some(connection);
+        self.enter(ConnectionState::Established);
+
+        Ok(())
+    }
+
+    fn handshake(&mut self, connection: &mut Connection<R, W>) -> Result<(), ResumeError> {
+        connection.write(&Hello {
+            resume: self.last_received,
+        })?;
+        connection.flush().map_err(WriteError::Io)?;
+
+        let hello: Hello = connection.read()?;
+        self.acknowledge(hello.resume)?;
+
+        for (seq, msg) in &self.replay {
+            connection.write(&Envelope { seq: *seq, msg })?;
+        }
+        connection.flush().map_err(WriteError::Io)?;
+
+        Ok(())
+    }
+
+    /// Opens a new underlying connection with `connect` and resumes over it, retrying both as
+    /// `policy` allows. A [`ResumeError::Gap`] is never retried, since no new connection can
+    /// recover the messages lost.
+    ///
+    /// The connection is [`ConnectionState::Connecting`] while `connect` runs.
+    pub fn reconnect<F>(
+        &mut self,
+        policy: &RetryPolicy<ResumeError>,
+        mut connect: F,
+    ) -> Result<(), ResumeError>
+    where
+        F: FnMut() -> io::Result<Connection<R, W>>,
+    {
+        let mut num_attempts = 0;
+
+        loop {
+            num_attempts += 1;
+
+            self.disconnect();
+            self.enter(ConnectionState::Connecting);
+
+            let outcome = match connect() {
+                Ok(connection) => self.resume(connection),
+                Err(e) => {
+                    self.enter(ConnectionState::Closed);
+                    Err(ResumeError::Connect(e))
+                }
+            };
+
+            match outcome {
+                Err(e) if e.is_retryable() && policy.should_retry(num_attempts, &e) => {
+                    std::thread::sleep(policy.delay(num_attempts));
+                }
+                outcome => return outcome,
+            }
+        }
+    }
+
+    /// Reads the next message and deserializes it into a given type.
+    pub fn read<T: serde::de::DeserializeOwned>(&mut self) -> Result<T, ResumeError> {
+        let connection = self.connection.as_mut().ok_or(ResumeError::Disconnected)?;
+
+        loop {
+            let envelope: Envelope<Value> = match connection.read() {
+                Ok(envelope) => envelope,
+                Err(e) => {
+                    self.disconnect();
+                    return Err(e.into());
+                }
+            };
+
+            if envelope.seq > self.last_received {
+                self.last_received = envelope.seq;
+                return Ok(serde_json::from_value(envelope.msg).map_err(ReadError::Deserialize)?);
+            }
+        }
+    }
+
+    /// Writes a given value to the connection, serializing it into JSON.
+    ///
+    /// The message is buffered even if this fails, and will be sent when the connection is
+    /// resumed.
+    pub fn write<T: Serialize>(&mut self, t: &T) -> Result<(), ResumeError> {
+        let seq = self.buffer(t)?;
+        let connection = self.connection.as_mut().ok_or(ResumeError::Disconnected)?;
+
+        if let Err(e) = connection.write(&Envelope { seq, msg: t }) {
+            self.disconnect();
+            return Err(e.into());
+        }
+
+        Ok(())
+    }
+
+    /// Flushes the underlying writer’s buffer.
+    pub fn flush(&mut self) -> Result<(), ResumeError> {
+        let connection = self.connection.as_mut().ok_or(ResumeError::Disconnected)?;
+
+        if let Err(e) = connection.flush() {
+            self.disconnect();
+            return Err(WriteError::Io(e).into());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R: BufRead + Unpin, W: Write + Unpin> ResumableConnection<R, W> {
+    /// Attaches a new underlying connection, exchanging sequence numbers with the peer and
+    /// replaying the messages it missed.
+    pub async fn resume(&mut self, mut connection: Connection<R, W>) -> Result<(), ResumeError> {
+        self.disconnect();
+        self.enter(ConnectionState::Handshaking);
+
+        if let Err(e) = self.handshake(&mut connection).await {
+            self.enter(ConnectionState::Closed);
+            return Err(e);
+        }
+
+        self.connection = Some(connection);
+        self.enter(ConnectionState::Established);
+
+        Ok(())
+    }
+
+    /// Opens a new underlying connection with the future returned by `connect` and resumes over
+    /// it, retrying both as `policy` allows. A [`ResumeError::Gap`] is never retried, since no new
+    /// connection can recover the messages lost.
+    ///
+    /// The connection is [`ConnectionState::Connecting`] while `connect` runs.
+    pub async fn reconnect<F, Fut>(
+        &mut self,
+        policy: &RetryPolicy<ResumeError>,
+        mut connect: F,
+    ) -> Result<(), ResumeError>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = io::Result<Connection<R, W>>>,
+    {
+        let mut num_attempts = 0;
+
+        loop {
+            num_attempts += 1;
+
+            self.disconnect();
+            self.enter(ConnectionState::Connecting);
+
+            let outcome = match connect().await {
+                Ok(connection) => self.resume(connection).await,
+                Err(e) => {
+                    self.enter(ConnectionState::Closed);
+                    Err(ResumeError::Connect(e))
+                }
+            };
+
+            match outcome {
+                Err(e) if e.is_retryable() && policy.should_retry(num_attempts, &e) => {
+                    tokio::time::sleep(policy.delay(num_attempts)).await;
+                }
+                outcome => return outcome,
+            }
+        }
+    }
+
+    async fn handshake(&mut self, connection: &mut Connection<R, W>) -> Result<(), ResumeError> {
+        connection
+            .write(&Hello {
+                resume: self.last_received,
+            })
+            .await?;
+        connection.flush().await.map_err(WriteError::Io)?;
+
+        let hello: Hello = connection.read().await?;
+        self.acknowledge(hello.resume)?;
+
+        for (seq, msg) in &self.replay {
+            connection.write(&Envelope { seq: *seq, msg }).await?;
+        }
+        connection.flush().await.map_err(WriteError::Io)?;
+
+        Ok(())
+    }
+
+    /// Reads the next message and deserializes it into a given type.
+    pub async fn read<T: serde::de::DeserializeOwned>(&mut self) -> Result<T, ResumeError> {
+        let connection = self.connection.as_mut().ok_or(ResumeError::Disconnected)?;
+
+        loop {
+            let envelope: Envelope<Value> = match connection.read().await {
+                Ok(envelope) => envelope,
+                Err(e) => {
+                    self.disconnect();
+                    return Err(e.into());
+                }
+            };
+
+            if envelope.seq > self.last_received {
+                self.last_received = envelope.seq;
+                return Ok(serde_json::from_value(envelope.msg).map_err(ReadError::Deserialize)?);
+            }
+        }
+    }
+
+    /// Writes a given value to the connection, serializing it into JSON.
+    ///
+    /// The message is buffered even if this fails, and will be sent when the connection is
+    /// resumed.
+    pub async fn write<T: Serialize>(&mut self, t: &T) -> Result<(), ResumeError> {
+        let seq = self.buffer(t)?;
+        let connection = self.connection.as_mut().ok_or(ResumeError::Disconnected)?;
+
+        if let Err(e) = connection.write(&Envelope { seq, msg: t }).await {
+            self.disconnect();
+            return Err(e.into());
+        }
+
+        Ok(())
+    }
+
+    /// Flushes the underlying writer’s buffer.
+    pub async fn flush(&mut self) -> Result<(), ResumeError> {
+        let connection = self.connection.as_mut().ok_or(ResumeError::Disconnected)?;
+
+        if let Err(e) = connection.flush().await {
+            self.disconnect();
+            return Err(WriteError::Io(e).into());
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,277 @@
+use serde_json::Value;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// The extension of the temporary files that [`Retention::enforce_by_record`] writes rewritten
+/// files to before renaming them over the originals.
+const TEMP_EXTENSION: &str = "retain";
+
+/// What was removed by [`Retention::enforce`] or [`Retention::enforce_by_record`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct RetentionReport {
+    /// The number of files deleted.
+    pub num_files_removed: usize,
+    /// The number of records dropped from files that were rewritten rather than deleted.
+    pub num_records_removed: usize,
+    /// The number of bytes of disk space freed.
+    pub num_bytes_freed: u64,
+}
+
+/// Limits how much data a directory of rotated JSON Lines files holds, by age and by size.
+///
+/// The file currently being written, set with [`Retention::active_file`], is never modified or
+/// deleted. If it is not set, the files are taken to be rotated in order of file name, as expected
+/// by [`DirectoryTail`], and the file that sorts last is taken to be the one being written. Every
+/// other file is assumed to be complete, so retention can safely be enforced while writing
+/// continues, for instance on a timer. The temporary `.retain` files made while rewriting files
+/// are always ignored.
+///
+/// [`DirectoryTail`]: crate::DirectoryTail
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Retention {
+    dir: PathBuf,
+    extension: Option<OsString>,
+    active_file: Option<PathBuf>,
+    max_age: Option<Duration>,
+    max_total_size: Option<u64>,
+}
+
+impl Retention {
+    /// Creates a new `Retention` for the files in `dir`, which removes nothing until limits are
+    /// set.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            extension: None,
+            active_file: None,
+            max_age: None,
+            max_total_size: None,
+        }
+    }
+
+    /// Only considers files with the given extension (such as `"jsonl"`), ignoring all others.
+    pub fn extension<S: Into<OsString>>(mut self, extension: S) -> Self {
+        self.extension = Some(extension.into());
+        self
+    }
+
+    /// Sets the file currently being written, which is never modified or deleted. Only its file
+    /// name is used, so it may be given relative to the directory or not.
+    ///
+    /// Set this whenever file names do not sort in the order files are written, since otherwise
+    /// the file that sorts last is taken to be the one being written.
+    pub fn active_file<P: AsRef<Path>>(mut self, active_file: P) -> Self {
+        self.active_file = Some(active_file.as_ref().to_path_buf());
+        self
+    }
+
+    /// Removes data older than `max_age`.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Removes the oldest files until the files in the directory take up at most `max_total_size`
+    /// bytes in total. The file currently being written counts towards the total, but is never
+    /// removed.
+    pub fn max_total_size(mut self, max_total_size: u64) -> Self {
+        self.max_total_size = Some(max_total_size);
+        self
+    }
+
+    /// Enforces the limits on whole files, deleting every complete file last modified longer ago
+    /// than the maximum age, and then the oldest files beyond the maximum total size.
+    pub fn enforce(&self) -> io::Result<RetentionReport> {
+        let mut report = RetentionReport::default();
+        let mut files = self.complete_files()?;
+
+        if let Some(cutoff) = self.cutoff() {
+            let mut remaining = Vec::with_capacity(files.len());
+
+            for (path, len) in files {
+                if fs::metadata(&path)?.modified()? < cutoff {
+                    fs::remove_file(&path)?;
+                    report.num_bytes_freed += len;
+                    report.num_files_removed += 1;
+                } else {
+                    remaining.push((path, len));
+                }
+            }
+
+            files = remaining;
+        }
+
+        self.enforce_size(files, &mut report)?;
+
+        Ok(report)
+    }
+
+    /// Enforces the limits record by record, using `timestamp_fn` to find the time of each record.
+    ///
+    /// Every complete file is rewritten without the records older than the maximum age, or deleted
+    /// if none remain; records without a timestamp are kept. Rewritten files replace the originals
+    /// atomically. The oldest files beyond the maximum total size are then deleted as in
+    /// [`Retention::enforce`].
+    pub fn enforce_by_record<F>(&self, mut timestamp_fn: F) -> io::Result<RetentionReport>
+    where
+        F: FnMut(&Value) -> Option<SystemTime>,
+    {
+        let mut report = RetentionReport::default();
+        let mut files = self.complete_files()?;
+
+        if let Some(cutoff) = self.cutoff() {
+            let mut remaining = Vec::with_capacity(files.len());
+
+            for (path, len) in files {
+                let new_len = rewrite(&path, cutoff, &mut timestamp_fn, &mut report)?;
+                report.num_bytes_freed += len - new_len;
+
+                if new_len == 0 {
+                    fs::remove_file(&path)?;
+                    report.num_files_removed += 1;
+                } else {
+                    remaining.push((path, new_len));
+                }
+            }
+
+            files = remaining;
+        }
+
+        self.enforce_size(files, &mut report)?;
+
+        Ok(report)
+    }
+
+    fn cutoff(&self) -> Option<SystemTime> {
+        self.max_age
+            .and_then(|max_age| SystemTime::now().checked_sub(max_age))
+    }
+
+    /// Deletes the oldest of `files` until the directory is within the maximum total size.
+    fn enforce_size(
+        &self,
+        files: Vec<(PathBuf, u64)>,
+        report: &mut RetentionReport,
+    ) -> io::Result<()> {
+        let max_total_size = match self.max_total_size {
+            Some(max_total_size) => max_total_size,
+            None => return Ok(()),
+        };
+
+        let mut total_size: u64 = self.list_files()?.iter().map(|(_, len)| len).sum();
+
+        for (path, len) in files {
+            if total_size <= max_total_size {
+                break;
+            }
+
+            fs::remove_file(&path)?;
+            total_size -= len;
+            report.num_bytes_freed += len;
+            report.num_files_removed += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Returns every file but the one currently being written, oldest first, along with its size.
+    fn complete_files(&self) -> io::Result<Vec<(PathBuf, u64)>> {
+        let mut files = self.list_files()?;
+
+        match &self.active_file {
+            Some(active_file) => {
+                files.retain(|(path, _)| path.file_name() != active_file.file_name())
+            }
+            None => {
+                files.pop();
+            }
+        }
+
+        Ok(files)
+    }
+
+    fn list_files(&self) -> io::Result<Vec<(PathBuf, u64)>> {
+        let mut files = Vec::new();
+
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let metadata = entry.metadata()?;
+
+            if !metadata.is_file() || path.extension() == Some(OsStr::new(TEMP_EXTENSION)) {
+                continue;
+            }
+
+            if let Some(extension) = &self.extension {
+                if path.extension() != Some(extension.as_os_str()) {
+                    continue;
+                }
+            }
+
+            files.push((path, metadata.len()));
+        }
+
+        files.sort();
+
+        Ok(files)
+    }
+}
+
+/// Rewrites the file at `path` without the records older than `cutoff`, returning its new size.
+/// The file is left untouched if no records are dropped.
+fn rewrite<F>(
+    path: &Path,
+    cutoff: SystemTime,
+    timestamp_fn: &mut F,
+    report: &mut RetentionReport,
+) -> io::Result<u64>
+where
+    F: FnMut(&Value) -> Option<SystemTime>,
+{
+    let mut temp_path = path.to_path_buf().into_os_string();
+    temp_path.push(".");
+    temp_path.push(TEMP_EXTENSION);
+
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut writer = BufWriter::new(File::create(&temp_path)?);
+    let mut line = String::new();
+    let mut new_len = 0;
+    let mut num_dropped = 0;
+
+    loop {
+        line.clear();
+
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+
+        let expired = serde_json::from_str(&line)
+            .ok()
+            .and_then(|record: Value| timestamp_fn(&record))
+            .is_some_and(|timestamp| timestamp < cutoff);
+
+        if expired {
+            num_dropped += 1;
+        } else {
+            writer.write_all(line.as_bytes())?;
+            new_len += line.len() as u64;
+        }
+    }
+
+    let file = writer.into_inner().map_err(|e| e.into_error())?;
+
+    if num_dropped == 0 {
+        drop(file);
+        fs::remove_file(&temp_path)?;
+        return Ok(new_len);
+    }
+
+    file.sync_all()?;
+    fs::rename(&temp_path, path)?;
+    report.num_records_removed += num_dropped;
+
+    Ok(new_len)
+}
@@ -0,0 +1,215 @@
+use crate::dataset::splitmix64;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "tokio")]
+use std::future::Future;
+
+type Predicate<E> = Arc<dyn Fn(&E) -> bool + Send + Sync>;
+
+static JITTER_STATE: AtomicU64 = AtomicU64::new(0);
+
+/// How much of each backoff delay in a [`RetryPolicy`] is randomized, so that clients that failed
+/// together do not all retry at the same moment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Jitter {
+    /// Waits for exactly the backoff delay.
+    None,
+    /// Waits for a random time between zero and the backoff delay.
+    #[default]
+    Full,
+    /// Waits for half the backoff delay, plus a random time up to the other half.
+    Equal,
+}
+
+/// When and how often to retry an operation that failed, shared by everything in this crate that
+/// retries: [`ConnectionBuilder::connect_tcp_with_retry`](crate::ConnectionBuilder::connect_tcp_with_retry),
+/// [`Client::call_with_retry`](crate::Client::call_with_retry),
+/// [`MultiEndpointClient::retry_policy`](crate::MultiEndpointClient::retry_policy) and
+/// [`ResumableConnection::reconnect`](crate::ResumableConnection::reconnect).
+///
+/// The delay before each retry starts at the initial backoff and doubles with every attempt, up to
+/// the maximum backoff, and is then randomized as set by [`RetryPolicy::jitter`]. Only errors for
+/// which the predicate given to [`RetryPolicy::retry_if`] returns `true` are retried; by default,
+/// every error is.
+pub struct RetryPolicy<E> {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: Jitter,
+    retry_if: Option<Predicate<E>>,
+}
+
+impl<E> Clone for RetryPolicy<E> {
+    fn clone(&self) -> Self {
+        Self {
+            max_attempts: self.max_attempts,
+            initial_backoff: self.initial_backoff,
+            max_backoff: self.max_backoff,
+            jitter: self.jitter,
+            retry_if: self.retry_if.clone(),
+        }
+    }
+}
+
+impl<E> fmt::Debug for RetryPolicy<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("initial_backoff", &self.initial_backoff)
+            .field("max_backoff", &self.max_backoff)
+            .field("jitter", &self.jitter)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<E> Default for RetryPolicy<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E> RetryPolicy<E> {
+    /// Creates a new `RetryPolicy` that makes up to three attempts, backing off from 100
+    /// milliseconds up to 10 seconds with full jitter, and retries every error.
+    pub fn new() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            jitter: Jitter::default(),
+            retry_if: None,
+        }
+    }
+
+    /// Sets the most attempts made in total, including the first. Zero is treated as one.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Sets the delay before the first retry, and the most any delay grows to.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Sets how much of each delay is randomized.
+    pub fn jitter(mut self, jitter: Jitter) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Only retries errors for which `f` returns `true`.
+    pub fn retry_if<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&E) -> bool + Send + Sync + 'static,
+    {
+        self.retry_if = Some(Arc::new(f));
+        self
+    }
+
+    /// Returns the most attempts made in total, including the first.
+    pub fn num_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Returns whether to try again after `num_attempts` attempts, the last of which failed with
+    /// `error`.
+    pub fn should_retry(&self, num_attempts: u32, error: &E) -> bool {
+        num_attempts < self.max_attempts && self.retry_if.as_ref().is_none_or(|f| f(error))
+    }
+
+    /// Returns how long to wait after `num_attempts` failed attempts before trying again,
+    /// including jitter.
+    pub fn delay(&self, num_attempts: u32) -> Duration {
+        let doublings = num_attempts.saturating_sub(1);
+        let backoff = self
+            .initial_backoff
+            .checked_mul(2u32.saturating_pow(doublings))
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff);
+
+        match self.jitter {
+            Jitter::None => backoff,
+            Jitter::Full => backoff.mul_f64(random_fraction()),
+            Jitter::Equal => backoff / 2 + (backoff / 2).mul_f64(random_fraction()),
+        }
+    }
+
+    /// Runs `f` until it succeeds, fails with an error that should not be retried, or has been
+    /// attempted as many times as allowed, sleeping between attempts. The last error is returned
+    /// if every attempt fails.
+    ///
+    /// This blocks the thread even with the `tokio` feature enabled, for the blocking APIs in this
+    /// crate.
+    pub(crate) fn run_blocking<T, F>(&self, mut f: F) -> Result<T, E>
+    where
+        F: FnMut() -> Result<T, E>,
+    {
+        let mut num_attempts = 0;
+
+        loop {
+            num_attempts += 1;
+
+            match f() {
+                Err(e) if self.should_retry(num_attempts, &e) => {
+                    std::thread::sleep(self.delay(num_attempts));
+                }
+                outcome => return outcome,
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl<E> RetryPolicy<E> {
+    /// Runs `f` until it succeeds, fails with an error that should not be retried, or has been
+    /// attempted as many times as allowed, sleeping between attempts. The last error is returned
+    /// if every attempt fails.
+    pub fn run<T, F>(&self, f: F) -> Result<T, E>
+    where
+        F: FnMut() -> Result<T, E>,
+    {
+        self.run_blocking(f)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<E> RetryPolicy<E> {
+    /// Runs the future returned by `f` until it succeeds, fails with an error that should not be
+    /// retried, or has been attempted as many times as allowed, sleeping between attempts. The
+    /// last error is returned if every attempt fails.
+    pub async fn run<T, F, Fut>(&self, mut f: F) -> Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let mut num_attempts = 0;
+
+        loop {
+            num_attempts += 1;
+
+            match f().await {
+                Err(e) if self.should_retry(num_attempts, &e) => {
+                    tokio::time::sleep(self.delay(num_attempts)).await;
+                }
+                outcome => return outcome,
+            }
+        }
+    }
+}
+
+/// Returns a pseudorandom number in `[0, 1)`, which is plenty for spreading out retries without
+/// needing the `rand` feature.
+fn random_fraction() -> f64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.subsec_nanos());
+    let state = JITTER_STATE.fetch_add(u64::from(nanos) | 1, Ordering::Relaxed);
+
+    (splitmix64(state) >> 11) as f64 / (1u64 << 53) as f64
+}
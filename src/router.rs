@@ -0,0 +1,222 @@
+#[cfg(not(feature = "tokio"))]
+use std::io::{BufRead, Write};
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncBufRead as BufRead, AsyncWrite as Write};
+
+use crate::{Connection, Message, ReadError, WriteError};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+
+/// An error that occurred while routing messages with [`Router`].
+#[derive(Debug, thiserror::Error)]
+pub enum RouterError {
+    #[error("failed reading message")]
+    Read(#[from] ReadError),
+    #[error("failed writing reply")]
+    Write(#[from] WriteError),
+    #[error("message for route `{route}` does not match the type its handler takes")]
+    Deserialize {
+        route: String,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error("failed serializing reply")]
+    Serialize(#[source] serde_json::Error),
+    #[error("message has no `{0}` field to route by")]
+    MissingField(String),
+    #[error("no route for message with `{field}` of {value}")]
+    NoRoute { field: String, value: String },
+}
+
+type Handler<C> = Box<dyn FnMut(&mut C, Value) -> Result<Value, RouterError>>;
+
+/// Routes each inbound message to a handler chosen by the value of one of its fields, such as the
+/// tag of a [`Message`] enum, like an HTTP router does by path.
+///
+/// Handlers are registered with [`Router::route`] for each value of the routing field, and take
+/// the message deserialized into whatever type suits them, often the payload of a single variant.
+/// Each handler is also given a mutable reference to a context of type `C`, which holds whatever
+/// the handlers share, such as a database handle or the state of the session. A fallback handler
+/// can be registered to take the messages no route matches.
+///
+/// What a handler returns is written back as a reply, unless it serializes to `null`, as `()` and
+/// `None` do. Routes are matched against string fields by their contents, and against any other
+/// value by its JSON form, so `{"op":1}` goes to the route `"1"`.
+///
+/// ```
+/// use jsonl::Router;
+/// use serde::{Deserialize, Serialize};
+/// use serde_json::{json, Value};
+///
+/// #[derive(Deserialize)]
+/// struct Add {
+///     n: u64,
+/// }
+///
+/// #[derive(Serialize)]
+/// struct Total {
+///     total: u64,
+/// }
+///
+/// let mut router = Router::new("type")
+///     .route("add", |total: &mut u64, add: Add| *total += add.n)
+///     .route("get", |total: &mut u64, _: Value| Total { total: *total });
+///
+/// let mut total = 0;
+/// router.handle(&mut total, json!({ "type": "add", "n": 2 }))?;
+/// router.handle(&mut total, json!({ "type": "add", "n": 3 }))?;
+///
+/// let reply = router.handle(&mut total, json!({ "type": "get" }))?;
+/// assert_eq!(reply, Some(json!({ "total": 5 })));
+/// # Ok::<_, jsonl::RouterError>(())
+/// ```
+pub struct Router<C> {
+    field: String,
+    routes: HashMap<String, Handler<C>>,
+    fallback: Option<Handler<C>>,
+}
+
+impl<C> fmt::Debug for Router<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Router")
+            .field("field", &self.field)
+            .field("routes", &self.routes.keys().collect::<Vec<_>>())
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
+
+impl<C> Router<C> {
+    /// Creates a new `Router` with no routes that routes messages by the field called `field`.
+    pub fn new(field: &str) -> Self {
+        Self {
+            field: field.to_string(),
+            routes: HashMap::new(),
+            fallback: None,
+        }
+    }
+
+    /// Creates a new `Router` with no routes that routes messages by the tag field of the
+    /// [`Message`] type `M`, so that each route is one of its variants.
+    pub fn for_message<M: Message>() -> Self {
+        Self::new(M::TAG)
+    }
+
+    /// Registers `handler` for messages whose routing field is `value`, replacing any handler
+    /// registered for it before.
+    pub fn route<P, R, F>(mut self, value: &str, mut handler: F) -> Self
+    where
+        P: DeserializeOwned,
+        R: Serialize,
+        F: FnMut(&mut C, P) -> R + 'static,
+    {
+        let route = value.to_string();
+
+        self.routes.insert(
+            route.clone(),
+            Box::new(move |context, message| {
+                let message =
+                    serde_json::from_value(message).map_err(|source| RouterError::Deserialize {
+                        route: route.clone(),
+                        source,
+                    })?;
+                serde_json::to_value(handler(context, message)).map_err(RouterError::Serialize)
+            }),
+        );
+        self
+    }
+
+    /// Registers `handler` for the messages no route matches, including those without the routing
+    /// field at all. Without a fallback these fail with [`RouterError::NoRoute`] or
+    /// [`RouterError::MissingField`].
+    pub fn fallback<R, F>(mut self, mut handler: F) -> Self
+    where
+        R: Serialize,
+        F: FnMut(&mut C, Value) -> R + 'static,
+    {
+        self.fallback = Some(Box::new(move |context, message| {
+            serde_json::to_value(handler(context, message)).map_err(RouterError::Serialize)
+        }));
+        self
+    }
+
+    /// Routes a single message to its handler, returning the reply to write back, if any.
+    ///
+    /// Use this to drive a `Router` from a loop of your own, rather than with [`Router::run`].
+    pub fn handle(
+        &mut self,
+        context: &mut C,
+        message: Value,
+    ) -> Result<Option<Value>, RouterError> {
+        let value = match message.get(&self.field) {
+            Some(Value::String(s)) => Some(s.clone()),
+            Some(other) => Some(other.to_string()),
+            None => None,
+        };
+
+        let routes = &mut self.routes;
+        let handler = value
+            .as_deref()
+            .and_then(|value| routes.get_mut(value))
+            .or(self.fallback.as_mut());
+
+        let handler = match (handler, value) {
+            (Some(handler), _) => handler,
+            (None, Some(value)) => {
+                return Err(RouterError::NoRoute {
+                    field: self.field.clone(),
+                    value,
+                })
+            }
+            (None, None) => return Err(RouterError::MissingField(self.field.clone())),
+        };
+
+        match handler(context, message)? {
+            Value::Null => Ok(None),
+            reply => Ok(Some(reply)),
+        }
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl<C> Router<C> {
+    /// Reads messages from `connection` and routes each to its handler, writing back any replies,
+    /// until the connection reaches EOF or an error occurs.
+    pub fn run<R: BufRead, W: Write>(
+        &mut self,
+        connection: &mut Connection<R, W>,
+        context: &mut C,
+    ) -> Result<(), RouterError> {
+        while let Some(message) = connection.read_opt::<Value>()? {
+            if let Some(reply) = self.handle(context, message)? {
+                connection.write(&reply)?;
+                connection.flush().map_err(WriteError::Io)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<C> Router<C> {
+    /// Reads messages from `connection` and routes each to its handler, writing back any replies,
+    /// until the connection reaches EOF or an error occurs.
+    pub async fn run<R: BufRead + Unpin, W: Write + Unpin>(
+        &mut self,
+        connection: &mut Connection<R, W>,
+        context: &mut C,
+    ) -> Result<(), RouterError> {
+        while let Some(message) = connection.read_opt::<Value>().await? {
+            if let Some(reply) = self.handle(context, message)? {
+                connection.write(&reply).await?;
+                connection.flush().await.map_err(WriteError::Io)?;
+            }
+        }
+
+        Ok(())
+    }
+}
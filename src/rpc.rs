@@ -0,0 +1,1593 @@
+use crate::{ReadError, RetryPolicy, WriteError};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{BufRead, Write};
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
+
+/// A request or notification sent to a [`Service`]. Requests carry an ID, which is echoed back in
+/// their [`Response`]; notifications do not, and receive no response.
+///
+/// A request may also carry a timeout in milliseconds, which [`serve`] enforces as a deadline
+/// counted from when the request is received. The timeout is relative rather than an absolute
+/// time so that it is unaffected by clock skew between client and server.
+///
+/// Requests with `stream` set are answered with a stream of [`StreamFrame`]s rather than a single
+/// response. Requests with `client_stream` set are followed by a stream of items from the client,
+/// sent with the same `item` and `end` frames as a streamed response; setting both makes a
+/// bidirectional call.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Request {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stream: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub client_stream: bool,
+}
+
+/// The response to a [`Request`], holding either a result or an error.
+///
+/// Responses that are part of a stream are marked with the kind of frame they are.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    pub id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorObject>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream: Option<StreamFrame>,
+}
+
+/// The kind of a [`Response`] that is part of a stream answering a single request.
+///
+/// A stream consists of a `start` frame, any number of `item` frames each holding one item in
+/// `result`, and an `end` frame, which holds an `error` if the stream ended early because of one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamFrame {
+    Start,
+    Item,
+    End,
+}
+
+impl Response {
+    /// Creates the response to the request with the given ID from the outcome of handling it.
+    pub fn new(id: Option<u64>, outcome: Result<Value, ErrorObject>) -> Self {
+        match outcome {
+            Ok(result) => Self {
+                id,
+                result: Some(result),
+                error: None,
+                stream: None,
+            },
+            Err(error) => Self {
+                id,
+                result: None,
+                error: Some(error),
+                stream: None,
+            },
+        }
+    }
+
+    fn frame(
+        id: u64,
+        frame: StreamFrame,
+        result: Option<Value>,
+        error: Option<ErrorObject>,
+    ) -> Self {
+        Self {
+            id: Some(id),
+            result,
+            error,
+            stream: Some(frame),
+        }
+    }
+
+    /// Converts the response into the outcome of the request.
+    pub fn into_result(self) -> Result<Value, ErrorObject> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.result.unwrap_or(Value::Null)),
+        }
+    }
+}
+
+/// The error returned by a [`Service`] when a request fails, using the error codes of
+/// [JSON-RPC 2.0](https://www.jsonrpc.org/specification#error_object).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, thiserror::Error)]
+#[error("{message} (code {code})")]
+pub struct ErrorObject {
+    pub code: i64,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl ErrorObject {
+    pub const PARSE_ERROR: i64 = -32700;
+    pub const INVALID_REQUEST: i64 = -32600;
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    pub const INVALID_PARAMS: i64 = -32602;
+    pub const INTERNAL_ERROR: i64 = -32603;
+    /// A code from the range JSON-RPC reserves for server errors, used when a request’s deadline
+    /// passes before it has been handled.
+    pub const DEADLINE_EXCEEDED: i64 = -32001;
+    /// A code from the range JSON-RPC reserves for server errors, used when a request is rejected
+    /// by [`RateLimit`](crate::RateLimit).
+    pub const RATE_LIMITED: i64 = -32002;
+
+    /// Creates a new `ErrorObject` with the given code and message.
+    pub fn new<S: Into<String>>(code: i64, message: S) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    /// The error for a request naming a method the service does not have.
+    pub fn method_not_found(method: &str) -> Self {
+        Self::new(
+            Self::METHOD_NOT_FOUND,
+            format!("method `{}` not found", method),
+        )
+    }
+
+    /// The error for a request whose parameters could not be deserialized.
+    pub fn invalid_params<E: fmt::Display>(e: E) -> Self {
+        Self::new(Self::INVALID_PARAMS, e.to_string())
+    }
+
+    /// The error for a request whose deadline passed before it was handled.
+    pub fn deadline_exceeded() -> Self {
+        Self::new(Self::DEADLINE_EXCEEDED, "deadline exceeded")
+    }
+
+    /// The error for a request rejected for exceeding a rate limit.
+    pub fn rate_limited() -> Self {
+        Self::new(Self::RATE_LIMITED, "rate limit exceeded")
+    }
+
+    /// The error for a request that failed for reasons internal to the service.
+    pub fn internal_error<E: fmt::Display>(e: E) -> Self {
+        Self::new(Self::INTERNAL_ERROR, e.to_string())
+    }
+
+    /// The error for a request that failed because of `e`, as an internal error whose `data` lists
+    /// the messages of the errors that caused it, outermost first.
+    pub fn from_error<E: std::error::Error + ?Sized>(e: &E) -> Self {
+        let mut causes = Vec::new();
+        let mut source = e.source();
+
+        while let Some(e) = source {
+            causes.push(Value::from(e.to_string()));
+            source = e.source();
+        }
+
+        let error = Self::internal_error(e);
+
+        if causes.is_empty() {
+            error
+        } else {
+            error.with_data(serde_json::json!({ "causes": causes }))
+        }
+    }
+
+    /// Attaches additional information about the error.
+    pub fn with_data(mut self, data: Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+}
+
+impl From<&ReadError> for ErrorObject {
+    /// Converts an error reading a message into the error to send back for it: a parse error for
+    /// malformed JSON, an invalid request error for JSON of the wrong shape or a line that is too
+    /// long, and an internal error otherwise.
+    fn from(e: &ReadError) -> Self {
+        match e {
+            ReadError::Deserialize(e) if e.is_data() => {
+                Self::new(Self::INVALID_REQUEST, e.to_string())
+            }
+            ReadError::Deserialize(e) => Self::new(Self::PARSE_ERROR, e.to_string()),
+            ReadError::LineTooLong { .. } => Self::new(Self::INVALID_REQUEST, e.to_string()),
+            ReadError::Io(_) | ReadError::Eof => Self::internal_error(e),
+        }
+    }
+}
+
+/// A line reporting an error, in the form `{"error":{"code":...,"message":...}}`, for services
+/// that do not use [`Request`] and [`Response`] but should still report errors in a consistent,
+/// machine-readable way.
+///
+/// ```
+/// use jsonl::{ErrorObject, ErrorResponse};
+///
+/// let response = ErrorResponse::new(ErrorObject::METHOD_NOT_FOUND, "no such method");
+///
+/// assert_eq!(
+///     serde_json::to_string(&response)?,
+///     r#"{"error":{"code":-32601,"message":"no such method"}}"#,
+/// );
+/// # Ok::<_, serde_json::Error>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub error: ErrorObject,
+}
+
+impl ErrorResponse {
+    /// Creates a new `ErrorResponse` with the given code and message.
+    pub fn new<S: Into<String>>(code: i64, message: S) -> Self {
+        Self::from(ErrorObject::new(code, message))
+    }
+}
+
+impl From<ErrorObject> for ErrorResponse {
+    fn from(error: ErrorObject) -> Self {
+        Self { error }
+    }
+}
+
+impl From<&ReadError> for ErrorResponse {
+    fn from(e: &ReadError) -> Self {
+        Self::from(ErrorObject::from(e))
+    }
+}
+
+/// An error that occurred while making or serving remote procedure calls.
+#[derive(Debug, thiserror::Error)]
+pub enum RpcError {
+    #[error("failed reading from connection")]
+    Read(#[from] ReadError),
+    #[error("failed writing to connection")]
+    Write(#[from] WriteError),
+    #[error("remote procedure call failed")]
+    Remote(#[source] ErrorObject),
+}
+
+/// Something that handles remote procedure calls, such as a [`Dispatcher`] or a server generated
+/// by the `rpc` attribute macro.
+pub trait Service {
+    /// Handles a call to `method`, returning its result.
+    fn call(&mut self, method: &str, params: Value) -> Result<Value, ErrorObject>;
+
+    /// Handles a call to `method` whose results are streamed, sending each of them to `items`.
+    ///
+    /// The default implementation has no streaming methods, and fails with
+    /// [`ErrorObject::method_not_found`].
+    fn call_stream(
+        &mut self,
+        method: &str,
+        params: Value,
+        items: &mut ItemSink<'_>,
+    ) -> Result<(), ErrorObject> {
+        let _ = (params, items);
+        Err(ErrorObject::method_not_found(method))
+    }
+
+    /// Handles a call to `method` that receives a stream of items from the client through `items`,
+    /// returning a single result.
+    ///
+    /// The default implementation has no client-streaming methods, and fails with
+    /// [`ErrorObject::method_not_found`].
+    fn call_client_stream(
+        &mut self,
+        method: &str,
+        params: Value,
+        items: &mut ItemSource<'_>,
+    ) -> Result<Value, ErrorObject> {
+        let _ = (params, items);
+        Err(ErrorObject::method_not_found(method))
+    }
+
+    /// Handles a bidirectional call to `method`, which receives a stream of items from the client
+    /// through `incoming` while sending its results to `outgoing`.
+    ///
+    /// The default implementation has no bidirectional methods, and fails with
+    /// [`ErrorObject::method_not_found`].
+    fn call_bidi(
+        &mut self,
+        method: &str,
+        params: Value,
+        incoming: &mut ItemSource<'_>,
+        outgoing: &mut ItemSink<'_>,
+    ) -> Result<(), ErrorObject> {
+        let _ = (params, incoming, outgoing);
+        Err(ErrorObject::method_not_found(method))
+    }
+
+    /// Wraps this service in the middleware of `layer`.
+    fn layer<L: crate::Layer<Self>>(self, layer: L) -> L::Service
+    where
+        Self: Sized,
+    {
+        layer.layer(self)
+    }
+}
+
+impl<S: Service + ?Sized> Service for &mut S {
+    fn call(&mut self, method: &str, params: Value) -> Result<Value, ErrorObject> {
+        (**self).call(method, params)
+    }
+
+    fn call_stream(
+        &mut self,
+        method: &str,
+        params: Value,
+        items: &mut ItemSink<'_>,
+    ) -> Result<(), ErrorObject> {
+        (**self).call_stream(method, params, items)
+    }
+
+    fn call_client_stream(
+        &mut self,
+        method: &str,
+        params: Value,
+        items: &mut ItemSource<'_>,
+    ) -> Result<Value, ErrorObject> {
+        (**self).call_client_stream(method, params, items)
+    }
+
+    fn call_bidi(
+        &mut self,
+        method: &str,
+        params: Value,
+        incoming: &mut ItemSource<'_>,
+        outgoing: &mut ItemSink<'_>,
+    ) -> Result<(), ErrorObject> {
+        (**self).call_bidi(method, params, incoming, outgoing)
+    }
+}
+
+impl<S: Service + ?Sized> Service for Box<S> {
+    fn call(&mut self, method: &str, params: Value) -> Result<Value, ErrorObject> {
+        (**self).call(method, params)
+    }
+
+    fn call_stream(
+        &mut self,
+        method: &str,
+        params: Value,
+        items: &mut ItemSink<'_>,
+    ) -> Result<(), ErrorObject> {
+        (**self).call_stream(method, params, items)
+    }
+
+    fn call_client_stream(
+        &mut self,
+        method: &str,
+        params: Value,
+        items: &mut ItemSource<'_>,
+    ) -> Result<Value, ErrorObject> {
+        (**self).call_client_stream(method, params, items)
+    }
+
+    fn call_bidi(
+        &mut self,
+        method: &str,
+        params: Value,
+        incoming: &mut ItemSource<'_>,
+        outgoing: &mut ItemSink<'_>,
+    ) -> Result<(), ErrorObject> {
+        (**self).call_bidi(method, params, incoming, outgoing)
+    }
+}
+
+/// Where a streaming method sends its results, each of which is written to the connection as an
+/// `item` frame as soon as it is sent.
+pub struct ItemSink<'a> {
+    id: Option<u64>,
+    writer: &'a mut dyn Write,
+    error: Option<WriteError>,
+}
+
+impl fmt::Debug for ItemSink<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ItemSink")
+            .field("id", &self.id)
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+impl ItemSink<'_> {
+    /// Sends one result of the call.
+    ///
+    /// If the connection has failed the error is returned, and the handler should stop and return
+    /// it; the connection’s own error is then reported by [`serve`].
+    pub fn send<T: Serialize>(&mut self, item: &T) -> Result<(), ErrorObject> {
+        if self.error.is_some() {
+            return Err(ErrorObject::internal_error("connection failed"));
+        }
+
+        let item = serde_json::to_value(item).map_err(ErrorObject::internal_error)?;
+
+        // Items of notifications are discarded, since notifications receive no response.
+        let id = match self.id {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+
+        let frame = Response::frame(id, StreamFrame::Item, Some(item), None);
+
+        let result = crate::blocking::write(&mut self.writer, &frame)
+            .and_then(|()| self.writer.flush().map_err(WriteError::Io));
+
+        result.map_err(|e| {
+            self.error = Some(e);
+            ErrorObject::internal_error("connection failed")
+        })
+    }
+}
+
+/// Where a client-streaming or bidirectional method receives the items sent by the client.
+///
+/// While a method is receiving items, requests that arrive for other calls are held back and
+/// served once it returns. Lines that are not valid JSON are handled as the [`ErrorPolicy`] of
+/// [`serve_with_policy`] says.
+pub struct ItemSource<'a> {
+    id: Option<u64>,
+    reader: &'a mut dyn BufRead,
+    held: &'a mut VecDeque<Value>,
+    policy: ErrorPolicy,
+    is_done: bool,
+    error: Option<ReadError>,
+}
+
+impl fmt::Debug for ItemSource<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ItemSource")
+            .field("id", &self.id)
+            .field("is_done", &self.is_done)
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+impl ItemSource<'_> {
+    /// Receives the next item sent by the client, or `None` once the client has sent them all.
+    ///
+    /// If the connection has failed an error is returned, and the handler should stop and return
+    /// it; the connection’s own error is then reported by [`serve`]. So is a line that is not valid
+    /// JSON, unless the policy is [`ErrorPolicy::Drop`], in which case it is skipped.
+    pub fn recv<T: DeserializeOwned>(&mut self) -> Result<Option<T>, ErrorObject> {
+        let item = match self.recv_value()? {
+            Some(item) => item,
+            None => return Ok(None),
+        };
+
+        serde_json::from_value(item)
+            .map(Some)
+            .map_err(ErrorObject::invalid_params)
+    }
+
+    fn recv_value(&mut self) -> Result<Option<Value>, ErrorObject> {
+        let id = match self.id {
+            Some(id) if !self.is_done => id,
+            _ => return Ok(None),
+        };
+
+        loop {
+            let value: Value = match crate::blocking::read(&mut self.reader) {
+                Ok(value) => value,
+                Err(ReadError::Deserialize(e)) => match self.policy {
+                    ErrorPolicy::Drop => continue,
+                    ErrorPolicy::Reply => {
+                        return Err(ErrorObject::new(ErrorObject::PARSE_ERROR, e.to_string()));
+                    }
+                    ErrorPolicy::Close => {
+                        self.is_done = true;
+                        self.error = Some(ReadError::Deserialize(e));
+                        return Err(ErrorObject::internal_error("connection failed"));
+                    }
+                },
+                Err(e) => {
+                    self.is_done = true;
+
+                    // EOF is left to be noticed by the next read in `serve`.
+                    if !matches!(e, ReadError::Eof) {
+                        self.error = Some(e);
+                    }
+
+                    return Err(ErrorObject::internal_error("connection failed"));
+                }
+            };
+
+            let frame = match as_frame(&value) {
+                Some(frame) if frame.id == Some(id) => frame,
+                _ => {
+                    self.held.push_back(value);
+                    continue;
+                }
+            };
+
+            match frame.stream {
+                Some(StreamFrame::Item) => return Ok(Some(frame.result.unwrap_or(Value::Null))),
+                Some(StreamFrame::End) => {
+                    self.is_done = true;
+
+                    return match frame.error {
+                        Some(error) => Err(error),
+                        None => Ok(None),
+                    };
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Discards whatever the client has left to send, so that the connection is ready for the next
+    /// request.
+    fn drain(&mut self) {
+        loop {
+            match self.recv_value() {
+                Ok(Some(_)) => {}
+                // Invalid lines that do not end the call are skipped like any other item.
+                Err(_) if !self.is_done => {}
+                _ => break,
+            }
+        }
+    }
+}
+
+/// Interprets a line read by [`serve`] as a stream frame sent by a client, if it is one.
+fn as_frame(value: &Value) -> Option<Response> {
+    if value.get("method").is_some() {
+        return None;
+    }
+
+    serde_json::from_value::<Response>(value.clone())
+        .ok()
+        .filter(|frame| frame.stream.is_some())
+}
+
+type Handler = Box<dyn FnMut(Value) -> Result<Value, ErrorObject>>;
+type StreamHandler = Box<dyn FnMut(Value, &mut ItemSink<'_>) -> Result<(), ErrorObject>>;
+type ClientStreamHandler = Box<dyn FnMut(Value, &mut ItemSource<'_>) -> Result<Value, ErrorObject>>;
+type BidiHandler =
+    Box<dyn FnMut(Value, &mut ItemSource<'_>, &mut ItemSink<'_>) -> Result<(), ErrorObject>>;
+
+/// A [`Service`] that routes calls to handlers registered by method name.
+#[derive(Default)]
+pub struct Dispatcher {
+    handlers: HashMap<String, Handler>,
+    stream_handlers: HashMap<String, StreamHandler>,
+    client_stream_handlers: HashMap<String, ClientStreamHandler>,
+    bidi_handlers: HashMap<String, BidiHandler>,
+}
+
+impl fmt::Debug for Dispatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Dispatcher")
+            .field("methods", &self.handlers.keys().collect::<Vec<_>>())
+            .field(
+                "streaming_methods",
+                &self.stream_handlers.keys().collect::<Vec<_>>(),
+            )
+            .field(
+                "client_streaming_methods",
+                &self.client_stream_handlers.keys().collect::<Vec<_>>(),
+            )
+            .field(
+                "bidi_methods",
+                &self.bidi_handlers.keys().collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl Dispatcher {
+    /// Creates a new `Dispatcher` with no methods.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `handler` as the method called `name`. Its parameters are deserialized from the
+    /// request’s `params`, and its result serialized into the response’s `result`.
+    pub fn method<P, T, F>(mut self, name: &str, mut handler: F) -> Self
+    where
+        P: DeserializeOwned,
+        T: Serialize,
+        F: FnMut(P) -> Result<T, ErrorObject> + 'static,
+    {
+        self.handlers.insert(
+            name.to_string(),
+            Box::new(move |params| {
+                let params = serde_json::from_value(params).map_err(ErrorObject::invalid_params)?;
+                serde_json::to_value(handler(params)?).map_err(ErrorObject::internal_error)
+            }),
+        );
+        self
+    }
+
+    /// Registers `handler` as the streaming method called `name`, which sends any number of results
+    /// to the [`ItemSink`] it is given.
+    pub fn streaming_method<P, F>(mut self, name: &str, mut handler: F) -> Self
+    where
+        P: DeserializeOwned,
+        F: FnMut(P, &mut ItemSink<'_>) -> Result<(), ErrorObject> + 'static,
+    {
+        self.stream_handlers.insert(
+            name.to_string(),
+            Box::new(move |params, items| {
+                let params = serde_json::from_value(params).map_err(ErrorObject::invalid_params)?;
+                handler(params, items)
+            }),
+        );
+        self
+    }
+
+    /// Registers `handler` as the client-streaming method called `name`, which receives any number
+    /// of items from the [`ItemSource`] it is given before returning its result.
+    pub fn client_streaming_method<P, T, F>(mut self, name: &str, mut handler: F) -> Self
+    where
+        P: DeserializeOwned,
+        T: Serialize,
+        F: FnMut(P, &mut ItemSource<'_>) -> Result<T, ErrorObject> + 'static,
+    {
+        self.client_stream_handlers.insert(
+            name.to_string(),
+            Box::new(move |params, items| {
+                let params = serde_json::from_value(params).map_err(ErrorObject::invalid_params)?;
+                serde_json::to_value(handler(params, items)?).map_err(ErrorObject::internal_error)
+            }),
+        );
+        self
+    }
+
+    /// Registers `handler` as the bidirectional method called `name`, which receives items from
+    /// the [`ItemSource`] and sends results to the [`ItemSink`] it is given, in whatever order it
+    /// likes.
+    pub fn bidi_method<P, F>(mut self, name: &str, mut handler: F) -> Self
+    where
+        P: DeserializeOwned,
+        F: FnMut(P, &mut ItemSource<'_>, &mut ItemSink<'_>) -> Result<(), ErrorObject> + 'static,
+    {
+        self.bidi_handlers.insert(
+            name.to_string(),
+            Box::new(move |params, incoming, outgoing| {
+                let params = serde_json::from_value(params).map_err(ErrorObject::invalid_params)?;
+                handler(params, incoming, outgoing)
+            }),
+        );
+        self
+    }
+}
+
+impl Service for Dispatcher {
+    fn call(&mut self, method: &str, params: Value) -> Result<Value, ErrorObject> {
+        match self.handlers.get_mut(method) {
+            Some(handler) => handler(params),
+            None => Err(ErrorObject::method_not_found(method)),
+        }
+    }
+
+    fn call_stream(
+        &mut self,
+        method: &str,
+        params: Value,
+        items: &mut ItemSink<'_>,
+    ) -> Result<(), ErrorObject> {
+        match self.stream_handlers.get_mut(method) {
+            Some(handler) => handler(params, items),
+            None => Err(ErrorObject::method_not_found(method)),
+        }
+    }
+
+    fn call_client_stream(
+        &mut self,
+        method: &str,
+        params: Value,
+        items: &mut ItemSource<'_>,
+    ) -> Result<Value, ErrorObject> {
+        match self.client_stream_handlers.get_mut(method) {
+            Some(handler) => handler(params, items),
+            None => Err(ErrorObject::method_not_found(method)),
+        }
+    }
+
+    fn call_bidi(
+        &mut self,
+        method: &str,
+        params: Value,
+        incoming: &mut ItemSource<'_>,
+        outgoing: &mut ItemSink<'_>,
+    ) -> Result<(), ErrorObject> {
+        match self.bidi_handlers.get_mut(method) {
+            Some(handler) => handler(params, incoming, outgoing),
+            None => Err(ErrorObject::method_not_found(method)),
+        }
+    }
+}
+
+thread_local! {
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// Returns the deadline of the request currently being handled by [`serve`] on this thread, if it
+/// has one.
+///
+/// Handlers doing lengthy work can check this periodically and give up with
+/// [`ErrorObject::deadline_exceeded`] once it has passed, since their result would be discarded
+/// anyway.
+pub fn current_deadline() -> Option<Instant> {
+    DEADLINE.with(Cell::get)
+}
+
+/// What [`serve_with_policy`] does with a line that is not a valid request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ErrorPolicy {
+    /// Skips the line silently.
+    Drop,
+    /// Answers the line with a response holding a parse or invalid request error, as JSON-RPC
+    /// prescribes.
+    Reply,
+    /// Stops serving, returning the error that made the line invalid.
+    Close,
+}
+
+/// Serves requests read from `reader` with `service`, writing responses to `writer`, until the
+/// reader reaches EOF.
+///
+/// This is [`serve_with_policy`] with [`ErrorPolicy::Reply`], so lines that are not valid requests
+/// are answered with an error rather than ending the connection.
+pub fn serve<S, R, W>(service: S, reader: R, writer: W) -> Result<(), RpcError>
+where
+    S: Service,
+    R: BufRead,
+    W: Write,
+{
+    serve_with_policy(service, reader, writer, ErrorPolicy::Reply)
+}
+
+/// Serves requests read from `reader` with `service`, writing responses to `writer`, until the
+/// reader reaches EOF, handling lines that are not valid requests as `policy` says.
+///
+/// Requests are handled one at a time, in order. Requests whose deadline has passed by the time
+/// they are handled are rejected without being passed to `service`, and a request that finishes
+/// after its deadline is answered with [`ErrorObject::deadline_exceeded`] in place of its result.
+pub fn serve_with_policy<S, R, W>(
+    mut service: S,
+    mut reader: R,
+    mut writer: W,
+    policy: ErrorPolicy,
+) -> Result<(), RpcError>
+where
+    S: Service,
+    R: BufRead,
+    W: Write,
+{
+    let mut held = VecDeque::new();
+
+    loop {
+        let value = match held.pop_front() {
+            Some(value) => value,
+            None => match crate::blocking::read::<_, Value>(&mut reader) {
+                Ok(value) => value,
+                Err(ReadError::Eof) => return Ok(()),
+                Err(ReadError::Deserialize(e)) => {
+                    on_invalid(policy, ErrorObject::PARSE_ERROR, e, &mut writer)?;
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            },
+        };
+
+        // Frames left over from a client-streaming call that has already been answered.
+        if as_frame(&value).is_some() {
+            continue;
+        }
+
+        let request: Request = match serde_json::from_value(value) {
+            Ok(request) => request,
+            Err(e) => {
+                on_invalid(policy, ErrorObject::INVALID_REQUEST, e, &mut writer)?;
+                continue;
+            }
+        };
+
+        // A timeout too long to represent is treated as no timeout at all.
+        let deadline = request
+            .timeout_ms
+            .and_then(|timeout_ms| Instant::now().checked_add(Duration::from_millis(timeout_ms)));
+
+        let Request {
+            id,
+            method,
+            params,
+            stream,
+            client_stream,
+            ..
+        } = request;
+
+        let mut incoming = ItemSource {
+            id,
+            reader: &mut reader,
+            held: &mut held,
+            policy,
+            is_done: !client_stream,
+            error: None,
+        };
+
+        let response = if stream {
+            if let Some(id) = id {
+                let start = Response::frame(id, StreamFrame::Start, None, None);
+                crate::blocking::write(&mut writer, &start)?;
+            }
+
+            let mut outgoing = ItemSink {
+                id,
+                writer: &mut writer,
+                error: None,
+            };
+
+            let outcome = with_deadline(deadline, || {
+                if client_stream {
+                    service.call_bidi(&method, params, &mut incoming, &mut outgoing)
+                } else {
+                    service.call_stream(&method, params, &mut outgoing)
+                }
+            });
+
+            if let Some(e) = outgoing.error {
+                return Err(e.into());
+            }
+
+            id.map(|id| Response::frame(id, StreamFrame::End, None, outcome.err()))
+        } else {
+            let outcome = with_deadline(deadline, || {
+                if client_stream {
+                    service.call_client_stream(&method, params, &mut incoming)
+                } else {
+                    service.call(&method, params)
+                }
+            });
+
+            id.map(|id| Response::new(Some(id), outcome))
+        };
+
+        incoming.drain();
+
+        if let Some(e) = incoming.error {
+            return Err(e.into());
+        }
+
+        if let Some(response) = response {
+            crate::blocking::write(&mut writer, &response)?;
+            writer.flush().map_err(WriteError::Io)?;
+        }
+    }
+}
+
+/// Handles a line that is not a valid request as `policy` says, with `code` being the error code to
+/// reply with.
+fn on_invalid<W: Write>(
+    policy: ErrorPolicy,
+    code: i64,
+    e: serde_json::Error,
+    writer: &mut W,
+) -> Result<(), RpcError> {
+    match policy {
+        ErrorPolicy::Drop => Ok(()),
+        ErrorPolicy::Reply => {
+            let error = ErrorObject::new(code, e.to_string());
+            crate::blocking::write(&mut *writer, &Response::new(None, Err(error)))?;
+            writer.flush().map_err(WriteError::Io)?;
+            Ok(())
+        }
+        ErrorPolicy::Close => Err(ReadError::Deserialize(e).into()),
+    }
+}
+
+/// Runs `f`, the handling of a request, unless `deadline` has already passed, making the deadline
+/// available to it through [`current_deadline`].
+fn with_deadline<T>(
+    deadline: Option<Instant>,
+    f: impl FnOnce() -> Result<T, ErrorObject>,
+) -> Result<T, ErrorObject> {
+    let is_past = || deadline.is_some_and(|deadline| Instant::now() >= deadline);
+
+    if is_past() {
+        return Err(ErrorObject::deadline_exceeded());
+    }
+
+    let previous = DEADLINE.with(|current| current.replace(deadline));
+    let outcome = f();
+    DEADLINE.with(|current| current.set(previous));
+
+    if is_past() {
+        return Err(ErrorObject::deadline_exceeded());
+    }
+
+    outcome
+}
+
+/// The number of most recent calls [`LatencyStats`] keeps.
+const LATENCY_WINDOW: usize = 1024;
+
+/// Round-trip latencies of the most recent calls made by a [`Client`], from which percentiles can
+/// be read.
+///
+/// Only the last 1024 calls are kept, so the statistics follow changes in an endpoint’s behaviour
+/// rather than being dominated by its history.
+#[derive(Debug, Clone, Default)]
+pub struct LatencyStats {
+    samples: VecDeque<Duration>,
+    num_calls: u64,
+}
+
+impl LatencyStats {
+    fn record(&mut self, latency: Duration) {
+        if self.samples.len() == LATENCY_WINDOW {
+            self.samples.pop_front();
+        }
+
+        self.samples.push_back(latency);
+        self.num_calls += 1;
+    }
+
+    /// Returns the total number of calls recorded, including those no longer in the window.
+    pub fn num_calls(&self) -> u64 {
+        self.num_calls
+    }
+
+    /// Returns the latency below which the given fraction of recent calls fell, such as `0.99` for
+    /// the 99th percentile, or `None` if no calls have been made. `quantile` is clamped to `[0, 1]`.
+    pub fn percentile(&self, quantile: f64) -> Option<Duration> {
+        let mut samples: Vec<_> = self.samples.iter().copied().collect();
+        samples.sort_unstable();
+
+        let last = samples.len().checked_sub(1)?;
+        let rank = (quantile.clamp(0.0, 1.0) * last as f64).round() as usize;
+
+        samples.get(rank).copied()
+    }
+
+    /// Returns the mean latency of recent calls, or `None` if no calls have been made.
+    pub fn mean(&self) -> Option<Duration> {
+        let len = u32::try_from(self.samples.len())
+            .ok()
+            .filter(|len| *len > 0)?;
+
+        Some(self.samples.iter().sum::<Duration>() / len)
+    }
+}
+
+type LatencyCallback = Box<dyn FnMut(&str, Duration) + Send>;
+
+/// Makes remote procedure calls to a [`Service`] served with [`serve`].
+///
+/// Each request is given a fresh ID, and responses are matched to requests by ID, so a server that
+/// answers out of order is handled correctly. The round-trip latency of every call is recorded in
+/// [`Client::latency`], and can also be passed to a callback set with [`Client::on_latency`]; for
+/// streaming calls this is the time until the stream ends.
+///
+/// The RPC layer is blocking and built on `std` IO whether or not the `tokio` feature is enabled,
+/// so from asynchronous code a `Client` should be driven on a blocking thread, such as with
+/// `tokio::task::spawn_blocking`.
+pub struct Client<R, W> {
+    reader: R,
+    writer: W,
+    next_id: u64,
+    stashed: HashMap<u64, VecDeque<Response>>,
+    abandoned: HashSet<u64>,
+    latency: LatencyStats,
+    on_latency: Option<LatencyCallback>,
+    timeout: Option<Duration>,
+}
+
+impl<R: fmt::Debug, W: fmt::Debug> fmt::Debug for Client<R, W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Client")
+            .field("reader", &self.reader)
+            .field("writer", &self.writer)
+            .field("next_id", &self.next_id)
+            .field("stashed", &self.stashed)
+            .field("latency", &self.latency)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl<R: BufRead, W: Write> Client<R, W> {
+    /// Creates a new `Client` sending requests to `writer` and reading responses from `reader`.
+    pub fn new(reader: R, writer: W) -> Self {
+        Self {
+            reader,
+            writer,
+            next_id: 0,
+            stashed: HashMap::new(),
+            abandoned: HashSet::new(),
+            latency: LatencyStats::default(),
+            on_latency: None,
+            timeout: None,
+        }
+    }
+
+    /// Sets a timeout sent with every request, after which the server stops working on it and
+    /// answers with [`ErrorObject::deadline_exceeded`].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets a callback that is passed the method name and round-trip latency of every call once its
+    /// response arrives, for exporting to a metrics system.
+    pub fn on_latency<F: FnMut(&str, Duration) + Send + 'static>(mut self, callback: F) -> Self {
+        self.on_latency = Some(Box::new(callback));
+        self
+    }
+
+    /// Returns the round-trip latencies of recent calls.
+    pub fn latency(&self) -> &LatencyStats {
+        &self.latency
+    }
+
+    /// Calls `method` with the given parameters, waiting for its result.
+    pub fn call<P, T>(&mut self, method: &str, params: &P) -> Result<T, RpcError>
+    where
+        P: Serialize,
+        T: DeserializeOwned,
+    {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let start = Instant::now();
+        self.send(Some(id), method, params, false, false)?;
+
+        let response = self.receive(id)?;
+        self.record_latency(method, start.elapsed());
+
+        let result = response.into_result().map_err(RpcError::Remote)?;
+
+        Ok(serde_json::from_value(result).map_err(ReadError::Deserialize)?)
+    }
+
+    /// Calls `method` with the given parameters, waiting for its result, and calling it again as
+    /// often as `policy` allows if it fails.
+    ///
+    /// Every attempt is made over this client’s connection, so this is best suited to errors the
+    /// service may recover from, such as [`ErrorObject::RATE_LIMITED`]; pick them out with
+    /// [`RetryPolicy::retry_if`]. To retry on other connections, use a
+    /// [`MultiEndpointClient`](crate::MultiEndpointClient).
+    pub fn call_with_retry<P, T>(
+        &mut self,
+        policy: &RetryPolicy<RpcError>,
+        method: &str,
+        params: &P,
+    ) -> Result<T, RpcError>
+    where
+        P: Serialize,
+        T: DeserializeOwned,
+    {
+        policy.run_blocking(|| self.call(method, params))
+    }
+
+    /// Calls the streaming method `method` with the given parameters, returning an iterator over
+    /// its results as they arrive.
+    ///
+    /// The iterator ends after the last result, or after yielding the error that ended the stream.
+    /// If it is dropped early, the rest of the stream is skipped as it arrives.
+    pub fn call_stream<P, T>(
+        &mut self,
+        method: &str,
+        params: &P,
+    ) -> Result<ResponseStream<'_, R, W, T>, RpcError>
+    where
+        P: Serialize,
+        T: DeserializeOwned,
+    {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let start = Instant::now();
+        self.send(Some(id), method, params, true, false)?;
+
+        Ok(ResponseStream {
+            client: self,
+            id,
+            method: method.to_string(),
+            start,
+            is_done: false,
+            _item: PhantomData,
+        })
+    }
+
+    /// Calls the client-streaming method `method` with the given parameters, sending it every item
+    /// of `items` before waiting for its result.
+    pub fn call_client_stream<P, I, T>(
+        &mut self,
+        method: &str,
+        params: &P,
+        items: I,
+    ) -> Result<T, RpcError>
+    where
+        P: Serialize,
+        I: IntoIterator,
+        I::Item: Serialize,
+        T: DeserializeOwned,
+    {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let start = Instant::now();
+        self.send(Some(id), method, params, false, true)?;
+
+        for item in items {
+            self.send_item(id, &item)?;
+        }
+
+        self.send_end(id)?;
+
+        let response = self.receive(id)?;
+        self.record_latency(method, start.elapsed());
+
+        let result = response.into_result().map_err(RpcError::Remote)?;
+
+        Ok(serde_json::from_value(result).map_err(ReadError::Deserialize)?)
+    }
+
+    /// Starts a bidirectional call to `method` with the given parameters. Items are sent to the
+    /// server with [`BidiCall::send`], and its results read by iterating over the returned
+    /// [`BidiCall`].
+    pub fn call_bidi<P, T>(
+        &mut self,
+        method: &str,
+        params: &P,
+    ) -> Result<BidiCall<'_, R, W, T>, RpcError>
+    where
+        P: Serialize,
+        T: DeserializeOwned,
+    {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let start = Instant::now();
+        self.send(Some(id), method, params, true, true)?;
+
+        Ok(BidiCall {
+            results: ResponseStream {
+                client: self,
+                id,
+                method: method.to_string(),
+                start,
+                is_done: false,
+                _item: PhantomData,
+            },
+            is_sending: true,
+        })
+    }
+
+    /// Sends a notification calling `method` with the given parameters, for which no response is
+    /// sent.
+    pub fn notify<P: Serialize>(&mut self, method: &str, params: &P) -> Result<(), RpcError> {
+        self.send(None, method, params, false, false)
+    }
+
+    /// Consumes the `Client`, returning the contained reader and writer.
+    pub fn into_parts(self) -> (R, W) {
+        (self.reader, self.writer)
+    }
+
+    fn record_latency(&mut self, method: &str, latency: Duration) {
+        self.latency.record(latency);
+
+        if let Some(on_latency) = &mut self.on_latency {
+            on_latency(method, latency);
+        }
+    }
+
+    /// Reads the next response to the request with the given ID, stashing responses to other
+    /// requests until they are asked for.
+    fn receive(&mut self, id: u64) -> Result<Response, RpcError> {
+        loop {
+            if let Some(queue) = self.stashed.get_mut(&id) {
+                if let Some(response) = queue.pop_front() {
+                    if queue.is_empty() {
+                        self.stashed.remove(&id);
+                    }
+
+                    return Ok(response);
+                }
+            }
+
+            let response: Response = crate::blocking::read(&mut self.reader)?;
+
+            match response.id {
+                Some(response_id) if response_id == id => return Ok(response),
+                Some(response_id) if self.abandoned.contains(&response_id) => {
+                    let is_end = !matches!(
+                        response.stream,
+                        Some(StreamFrame::Start) | Some(StreamFrame::Item)
+                    );
+
+                    if is_end {
+                        self.abandoned.remove(&response_id);
+                    }
+                }
+                Some(response_id) => self
+                    .stashed
+                    .entry(response_id)
+                    .or_default()
+                    .push_back(response),
+                // Responses without an ID report requests the server could not parse, which are
+                // never sent by this client.
+                None => {}
+            }
+        }
+    }
+
+    fn send<P: Serialize>(
+        &mut self,
+        id: Option<u64>,
+        method: &str,
+        params: &P,
+        stream: bool,
+        client_stream: bool,
+    ) -> Result<(), RpcError> {
+        let request = Request {
+            id,
+            method: method.to_string(),
+            params: serde_json::to_value(params).map_err(WriteError::Serialize)?,
+            timeout_ms: self
+                .timeout
+                .map(|timeout| u64::try_from(timeout.as_millis()).unwrap_or(u64::MAX)),
+            stream,
+            client_stream,
+        };
+
+        crate::blocking::write(&mut self.writer, &request)?;
+        self.writer.flush().map_err(WriteError::Io)?;
+
+        Ok(())
+    }
+
+    fn send_item<T: Serialize>(&mut self, id: u64, item: &T) -> Result<(), RpcError> {
+        let item = serde_json::to_value(item).map_err(WriteError::Serialize)?;
+
+        crate::blocking::write(
+            &mut self.writer,
+            &Response::frame(id, StreamFrame::Item, Some(item), None),
+        )?;
+        self.writer.flush().map_err(WriteError::Io)?;
+
+        Ok(())
+    }
+
+    fn send_end(&mut self, id: u64) -> Result<(), RpcError> {
+        crate::blocking::write(
+            &mut self.writer,
+            &Response::frame(id, StreamFrame::End, None, None),
+        )?;
+        self.writer.flush().map_err(WriteError::Io)?;
+
+        Ok(())
+    }
+}
+
+/// An iterator over the results of a streaming call, created by [`Client::call_stream`].
+///
+/// Like the rest of the RPC layer this is blocking: each call to `next` waits for the server’s
+/// next result, so it is an [`Iterator`] rather than a `futures` `Stream`.
+#[derive(Debug)]
+pub struct ResponseStream<'a, R, W, T> {
+    client: &'a mut Client<R, W>,
+    id: u64,
+    method: String,
+    start: Instant,
+    is_done: bool,
+    _item: PhantomData<fn() -> T>,
+}
+
+impl<R: BufRead, W: Write, T: DeserializeOwned> Iterator for ResponseStream<'_, R, W, T> {
+    type Item = Result<T, RpcError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.is_done {
+            let response = match self.client.receive(self.id) {
+                Ok(response) => response,
+                Err(e) => {
+                    self.is_done = true;
+                    return Some(Err(e));
+                }
+            };
+
+            match response.stream {
+                Some(StreamFrame::Start) => {}
+                Some(StreamFrame::Item) => {
+                    let item = response.result.unwrap_or(Value::Null);
+
+                    return Some(
+                        serde_json::from_value(item).map_err(|e| ReadError::Deserialize(e).into()),
+                    );
+                }
+                // A response that is not part of a stream comes from a server which rejected the
+                // request outright, so it also ends the stream.
+                Some(StreamFrame::End) | None => {
+                    self.is_done = true;
+                    self.client
+                        .record_latency(&self.method, self.start.elapsed());
+
+                    return response.error.map(|e| Err(RpcError::Remote(e)));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl<R, W, T> Drop for ResponseStream<'_, R, W, T> {
+    fn drop(&mut self) {
+        if !self.is_done {
+            self.client.stashed.remove(&self.id);
+            self.client.abandoned.insert(self.id);
+        }
+    }
+}
+
+/// A bidirectional call in progress, created by [`Client::call_bidi`].
+///
+/// Iterating over a `BidiCall` yields the server’s results, like [`ResponseStream`]. Since the
+/// client is blocking, sending and receiving are interleaved by the caller; a server that sends
+/// results only after receiving everything must be told that the client is done with
+/// [`BidiCall::finish`] first. Dropping a `BidiCall` finishes it.
+#[derive(Debug)]
+pub struct BidiCall<'a, R: BufRead, W: Write, T> {
+    results: ResponseStream<'a, R, W, T>,
+    is_sending: bool,
+}
+
+impl<R: BufRead, W: Write, T> BidiCall<'_, R, W, T> {
+    /// Sends an item to the server.
+    pub fn send<U: Serialize>(&mut self, item: &U) -> Result<(), RpcError> {
+        let id = self.results.id;
+        self.results.client.send_item(id, item)
+    }
+
+    /// Tells the server that no more items will be sent.
+    pub fn finish(&mut self) -> Result<(), RpcError> {
+        if !self.is_sending {
+            return Ok(());
+        }
+
+        self.is_sending = false;
+
+        let id = self.results.id;
+        self.results.client.send_end(id)
+    }
+}
+
+impl<R: BufRead, W: Write, T: DeserializeOwned> Iterator for BidiCall<'_, R, W, T> {
+    type Item = Result<T, RpcError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.results.next()
+    }
+}
+
+impl<R: BufRead, W: Write, T> Drop for BidiCall<'_, R, W, T> {
+    fn drop(&mut self) {
+        // An error here means the connection has failed, which the next use of the client reports.
+        let _ = self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{self, BufReader, Cursor};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    fn dispatcher() -> Dispatcher {
+        Dispatcher::new()
+            .method("add", |(a, b): (i64, i64)| Ok(a + b))
+            .streaming_method("count", |n: u64, items| {
+                for i in 0..n {
+                    items.send(&i)?;
+                }
+                Ok(())
+            })
+            .client_streaming_method("sum", |(), items| {
+                let mut sum = 0;
+                while let Some(item) = items.recv::<i64>()? {
+                    sum += item;
+                }
+                Ok(sum)
+            })
+            .bidi_method("double", |(), incoming, outgoing| {
+                while let Some(item) = incoming.recv::<i64>()? {
+                    outgoing.send(&(item * 2))?;
+                }
+                Ok(())
+            })
+    }
+
+    /// Serves [`dispatcher`] on a new thread, returning a client connected to it.
+    fn start() -> io::Result<Client<BufReader<TcpStream>, TcpStream>> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let stream = TcpStream::connect(listener.local_addr()?)?;
+
+        thread::spawn(move || -> Result<(), RpcError> {
+            let (stream, _) = listener.accept().map_err(ReadError::Io)?;
+            let reader = BufReader::new(stream.try_clone().map_err(ReadError::Io)?);
+            serve(dispatcher(), reader, stream)
+        });
+
+        Ok(Client::new(BufReader::new(stream.try_clone()?), stream))
+    }
+
+    /// Serves the given lines with [`dispatcher`] under `policy`, returning the responses written.
+    fn serve_lines(lines: &[&str], policy: ErrorPolicy) -> Result<Vec<Response>, RpcError> {
+        let input: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+        let mut output = Vec::new();
+
+        serve_with_policy(dispatcher(), Cursor::new(input), &mut output, policy)?;
+
+        let mut reader = Cursor::new(output);
+        let mut responses = Vec::new();
+
+        loop {
+            match crate::blocking::read(&mut reader) {
+                Ok(response) => responses.push(response),
+                Err(ReadError::Eof) => return Ok(responses),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    const SUM_WITH_INVALID_ITEM: &[&str] = &[
+        r#"{"id":0,"method":"sum","params":null,"client_stream":true}"#,
+        r#"{"id":0,"result":1,"stream":"item"}"#,
+        "not json",
+        r#"{"id":0,"result":2,"stream":"item"}"#,
+        r#"{"id":0,"stream":"end"}"#,
+    ];
+
+    #[test]
+    fn calls_are_answered() -> Result<(), Box<dyn std::error::Error>> {
+        let mut client = start()?;
+
+        assert_eq!(client.call::<_, i64>("add", &(2, 3))?, 5);
+
+        assert!(matches!(
+            client.call::<_, i64>("subtract", &(2, 3)),
+            Err(RpcError::Remote(e)) if e.code == ErrorObject::METHOD_NOT_FOUND
+        ));
+        assert!(matches!(
+            client.call::<_, i64>("add", &"two and three"),
+            Err(RpcError::Remote(e)) if e.code == ErrorObject::INVALID_PARAMS
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn streamed_results_arrive_in_order() -> Result<(), Box<dyn std::error::Error>> {
+        let mut client = start()?;
+
+        let items = client
+            .call_stream::<_, u64>("count", &3)?
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(items, [0, 1, 2]);
+
+        // The client is still usable once a stream is dropped early.
+        let mut stream = client.call_stream::<_, u64>("count", &3)?;
+        assert_eq!(stream.next().transpose()?, Some(0));
+        drop(stream);
+        assert_eq!(client.call::<_, i64>("add", &(1, 1))?, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn client_streams_and_bidirectional_calls() -> Result<(), Box<dyn std::error::Error>> {
+        let mut client = start()?;
+
+        assert_eq!(
+            client.call_client_stream::<_, _, i64>("sum", &(), vec![1, 2, 3])?,
+            6
+        );
+
+        let mut call = client.call_bidi::<_, i64>("double", &())?;
+        call.send(&1)?;
+        call.send(&2)?;
+        call.finish()?;
+        assert_eq!(call.collect::<Result<Vec<_>, _>>()?, [2, 4]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn latency_is_recorded_for_every_kind_of_call() -> Result<(), Box<dyn std::error::Error>> {
+        let methods = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&methods);
+
+        let mut client = start()?.on_latency(move |method, _| {
+            if let Ok(mut methods) = recorded.lock() {
+                methods.push(method.to_string());
+            }
+        });
+
+        client.call::<_, i64>("add", &(1, 2))?;
+        client
+            .call_stream::<_, u64>("count", &2)?
+            .collect::<Result<Vec<_>, _>>()?;
+        client.call_client_stream::<_, _, i64>("sum", &(), vec![1])?;
+
+        let mut call = client.call_bidi::<_, i64>("double", &())?;
+        call.finish()?;
+        call.collect::<Result<Vec<_>, _>>()?;
+
+        assert_eq!(client.latency().num_calls(), 4);
+        assert!(client.latency().percentile(0.5).is_some());
+
+        let methods = methods.lock().map_err(|_| "latency callback panicked")?;
+        assert_eq!(*methods, ["add", "count", "sum", "double"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_requests_are_handled_as_the_policy_says() -> Result<(), Box<dyn std::error::Error>> {
+        let lines = &["not json", r#"{"id":0,"method":"add","params":[1,2]}"#];
+
+        let responses = serve_lines(lines, ErrorPolicy::Drop)?;
+        assert_eq!(responses, [Response::new(Some(0), Ok(3.into()))]);
+
+        let responses = serve_lines(lines, ErrorPolicy::Reply)?;
+        assert_eq!(responses.len(), 2);
+        assert_eq!(
+            responses
+                .first()
+                .and_then(|r| r.error.as_ref())
+                .map(|e| e.code),
+            Some(ErrorObject::PARSE_ERROR)
+        );
+
+        assert!(matches!(
+            serve_lines(lines, ErrorPolicy::Close),
+            Err(RpcError::Read(ReadError::Deserialize(_)))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_items_are_handled_as_the_policy_says() -> Result<(), Box<dyn std::error::Error>> {
+        let responses = serve_lines(SUM_WITH_INVALID_ITEM, ErrorPolicy::Drop)?;
+        assert_eq!(responses, [Response::new(Some(0), Ok(3.into()))]);
+
+        let responses = serve_lines(SUM_WITH_INVALID_ITEM, ErrorPolicy::Reply)?;
+        assert_eq!(responses.len(), 1);
+        assert_eq!(
+            responses
+                .first()
+                .and_then(|r| r.error.as_ref())
+                .map(|e| e.code),
+            Some(ErrorObject::PARSE_ERROR)
+        );
+
+        assert!(matches!(
+            serve_lines(SUM_WITH_INVALID_ITEM, ErrorPolicy::Close),
+            Err(RpcError::Read(ReadError::Deserialize(_)))
+        ));
+
+        Ok(())
+    }
+}
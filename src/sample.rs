@@ -0,0 +1,203 @@
+use crate::ReadError;
+use serde::de::DeserializeOwned;
+use std::io::BufRead;
+use std::marker::PhantomData;
+
+/// An iterator over the first records of a JSON Lines reader, created by [`head`].
+#[derive(Debug)]
+pub struct Head<R, T> {
+    reader: R,
+    remaining: usize,
+    _record: PhantomData<fn() -> T>,
+}
+
+/// Yields at most the first `n` records from `reader`, without reading any further.
+pub fn head<R: BufRead, T: DeserializeOwned>(reader: R, n: usize) -> Head<R, T> {
+    Head {
+        reader,
+        remaining: n,
+        _record: PhantomData,
+    }
+}
+
+impl<R: BufRead, T: DeserializeOwned> Iterator for Head<R, T> {
+    type Item = Result<T, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+
+        match crate::blocking::read(&mut self.reader) {
+            Err(ReadError::Eof) => {
+                self.remaining = 0;
+                None
+            }
+            result => Some(result),
+        }
+    }
+}
+
+/// An iterator over every nth record of a JSON Lines reader, created by [`stride`].
+#[derive(Debug)]
+pub struct Stride<R, T> {
+    reader: R,
+    n: usize,
+    buf: String,
+    _record: PhantomData<fn() -> T>,
+}
+
+/// Yields the first record from `reader` and every `n`th record after it.
+///
+/// The records in between are skipped without being deserialized, so they need not even be valid
+/// JSON.
+///
+/// # Panics
+///
+/// Panics if `n` is zero.
+pub fn stride<R: BufRead, T: DeserializeOwned>(reader: R, n: usize) -> Stride<R, T> {
+    assert!(n > 0, "stride must be greater than zero");
+
+    Stride {
+        reader,
+        n,
+        buf: String::new(),
+        _record: PhantomData,
+    }
+}
+
+impl<R: BufRead, T: DeserializeOwned> Iterator for Stride<R, T> {
+    type Item = Result<T, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = match crate::blocking::read(&mut self.reader) {
+            Err(ReadError::Eof) => return None,
+            result => result,
+        };
+
+        for _ in 1..self.n {
+            self.buf.clear();
+
+            match self.reader.read_line(&mut self.buf) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) => return Some(Err(ReadError::Io(e))),
+            }
+        }
+
+        Some(record)
+    }
+}
+
+/// Reads every record from `reader`, returning a uniformly random sample of `k` of them in the
+/// order they appeared (or every record, if there are no more than `k`).
+///
+/// Only the lines currently in the sample are held in memory, and only those that end up in the
+/// final sample are deserialized, so this is suitable for inputs far larger than memory.
+#[cfg(feature = "rand")]
+pub fn sample_reservoir<R, T, G>(mut reader: R, k: usize, rng: &mut G) -> Result<Vec<T>, ReadError>
+where
+    R: BufRead,
+    T: DeserializeOwned,
+    G: rand::Rng + ?Sized,
+{
+    let mut reservoir: Vec<(usize, String)> = Vec::with_capacity(k);
+    let mut num_lines = 0;
+
+    loop {
+        let mut line = String::new();
+
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+
+        if reservoir.len() < k {
+            reservoir.push((num_lines, line));
+        } else {
+            let i = rng.gen_range(0..=num_lines);
+
+            if i < k {
+                reservoir[i] = (num_lines, line);
+            }
+        }
+
+        num_lines += 1;
+    }
+
+    reservoir.sort_by_key(|(i, _)| *i);
+
+    reservoir
+        .into_iter()
+        .map(|(_, line)| serde_json::from_str(&line).map_err(ReadError::Deserialize))
+        .collect()
+}
+
+/// An iterator over the records of a JSON Lines reader in pseudo-random order, created by
+/// [`shuffle`].
+#[cfg(feature = "rand")]
+#[derive(Debug)]
+pub struct Shuffle<R, T> {
+    reader: R,
+    buffer: Vec<String>,
+    buffer_size: usize,
+    rng: rand::rngs::StdRng,
+    eof: bool,
+    _record: PhantomData<fn() -> T>,
+}
+
+/// Yields the records from `reader` in a pseudo-random order that is the same for a given `seed`,
+/// holding at most `buffer_size` lines in memory.
+///
+/// Lines are read into a buffer, and once it is full each record yielded is chosen at random from
+/// it and replaced with the next line read. A record can therefore only move forward by about
+/// `buffer_size` places, so the larger the buffer, the better the shuffle: make it at least as
+/// large as any run of similar records in the input.
+///
+/// Lines are only deserialized as they are yielded.
+#[cfg(feature = "rand")]
+pub fn shuffle<R: BufRead, T: DeserializeOwned>(
+    reader: R,
+    buffer_size: usize,
+    seed: u64,
+) -> Shuffle<R, T> {
+    use rand::SeedableRng;
+
+    Shuffle {
+        reader,
+        buffer: Vec::with_capacity(buffer_size),
+        buffer_size: buffer_size.max(1),
+        rng: rand::rngs::StdRng::seed_from_u64(seed),
+        eof: false,
+        _record: PhantomData,
+    }
+}
+
+#[cfg(feature = "rand")]
+impl<R: BufRead, T: DeserializeOwned> Iterator for Shuffle<R, T> {
+    type Item = Result<T, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        use rand::Rng;
+
+        while !self.eof && self.buffer.len() < self.buffer_size {
+            let mut line = String::new();
+
+            match self.reader.read_line(&mut line) {
+                Ok(0) => self.eof = true,
+                Ok(_) if line.trim().is_empty() => {}
+                Ok(_) => self.buffer.push(line),
+                Err(e) => return Some(Err(ReadError::Io(e))),
+            }
+        }
+
+        if self.buffer.is_empty() {
+            return None;
+        }
+
+        let i = self.rng.gen_range(0..self.buffer.len());
+        let line = self.buffer.swap_remove(i);
+
+        Some(serde_json::from_str(&line).map_err(ReadError::Deserialize))
+    }
+}
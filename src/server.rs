@@ -0,0 +1,1340 @@
+#[cfg(not(feature = "tokio"))]
+mod imports {
+    pub(super) use std::io::{self, BufReader, Read, Write};
+    pub(super) use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+    pub(super) use std::thread;
+}
+#[cfg(feature = "tokio")]
+mod imports {
+    pub(super) use std::future::Future;
+    pub(super) use std::pin::Pin;
+    pub(super) use std::task::{Context, Poll};
+    pub(super) use tokio::io::{self, AsyncRead, AsyncWrite, BufReader, ReadBuf};
+    pub(super) use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+}
+
+use crate::health::Ping;
+use crate::{Connection, Session, WriteError};
+use imports::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use socket2::SockRef;
+#[cfg(unix)]
+use socket2::{Domain, Socket, Type};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr};
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+#[cfg(unix)]
+use std::process::{Child, Command};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, TryLockError, Weak};
+use std::time::{Duration, Instant};
+
+/// The environment variable through which [`Server::spawn_successor`] tells the new process which
+/// file descriptor holds the listening socket.
+#[cfg(unix)]
+pub const LISTENER_FD_ENV: &str = "JSONL_LISTENER_FD";
+
+/// The shortest interval at which the watchdog checks connections for inactivity, which is also
+/// how often [`Broadcaster::announce_shutdown`] checks whether every connection has closed.
+const MIN_WATCHDOG_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A connection accepted by a [`Server`].
+pub type ServerConnection = Connection<BufReader<ServerStream>, ServerStream>;
+
+type Registry = Mutex<HashMap<u64, Arc<Shared>>>;
+type RejectWith = Box<dyn Fn(Rejection) -> Value + Send + Sync>;
+
+/// Why a [`Server`] turned a connection away instead of handing it to the handler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rejection {
+    /// The server already has as many connections as [`Server::max_connections`] allows.
+    TooManyConnections,
+    /// The peer’s IP address already has as many connections as
+    /// [`Server::max_connections_per_ip`] allows.
+    TooManyConnectionsFromAddress,
+    /// Connections are arriving faster than [`Server::accept_rate`] allows.
+    RateLimited,
+}
+
+/// Accepts TCP connections and hands each to a handler as a [`ServerConnection`], keeping track of
+/// them so that those whose peers have silently vanished can be closed.
+///
+/// With [`Server::idle_timeout`] set, a watchdog closes every connection that has neither read nor
+/// written anything for that long, so that clients that disappear without closing their end do not
+/// leak file descriptors. With [`Server::ping_before_close`] also set, an idle connection is first
+/// sent a health check and only closed if nothing, such as the reply, arrives within the grace
+/// period. Handlers should then read with
+/// [`Connection::read_answering_health_checks`](crate::Connection::read_answering_health_checks),
+/// which skips those replies, and clients should answer with it too.
+///
+/// Closing a connection shuts its socket down, so the handler sees EOF on its next read.
+///
+/// To keep a burst of clients from exhausting the process, connections can be limited in number
+/// overall and per IP address, and in how fast they are accepted. Connections over a limit are
+/// closed straight away, after being sent the line chosen with [`Server::reject_with`] if any.
+///
+/// On Unix, a new version of the program can take over accepting connections without any being
+/// refused in between. With [`Server::bind_reuse_port`], the old and new processes each bind
+/// their own socket to the same address, and the kernel spreads new connections between them until
+/// the old one stops. Alternatively, the old process can start the new one with
+/// [`Server::spawn_successor`], which passes it the very same socket to pick up with
+/// [`Server::from_inherited_listener`]; this also works where `SO_REUSEPORT` does not, and loses
+/// no connections waiting to be accepted when the old process stops.
+///
+/// Handlers given to [`Server::serve_with_sessions`] are also given a [`Session`], which keeps
+/// per-connection state, such as the peer’s identity, for as long as the connection is open.
+///
+/// Use a [`Broadcaster`] to send lines to every connection, and to shut the server down gracefully.
+pub struct Server {
+    listener: TcpListener,
+    registry: Arc<Registry>,
+    draining: Arc<AtomicBool>,
+    idle_timeout: Option<Duration>,
+    ping_grace: Option<Duration>,
+    max_connections: Option<usize>,
+    max_connections_per_ip: Option<usize>,
+    accept_rate: Option<TokenBucket>,
+    reject_with: Option<RejectWith>,
+}
+
+impl fmt::Debug for Server {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Server")
+            .field("listener", &self.listener)
+            .field("idle_timeout", &self.idle_timeout)
+            .field("ping_grace", &self.ping_grace)
+            .field("max_connections", &self.max_connections)
+            .field("max_connections_per_ip", &self.max_connections_per_ip)
+            .field("accept_rate", &self.accept_rate)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Limits how fast connections are accepted, in the same way as [`RateLimit`](crate::RateLimit)
+/// limits calls.
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    tokens_per_second: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(num_connections: u32, per: Duration) -> Self {
+        let capacity = f64::from(num_connections);
+
+        Self {
+            capacity,
+            tokens: capacity,
+            tokens_per_second: capacity / per.as_secs_f64(),
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn try_take(&mut self) -> bool {
+        let now = Instant::now();
+        let refill = now.duration_since(self.last_refill).as_secs_f64() * self.tokens_per_second;
+
+        // A zero `per` gives an infinite rate, and a NaN refill, which `min` discards in favour of a
+        // full bucket.
+        self.tokens = (self.tokens + refill).min(self.capacity);
+        self.last_refill = now;
+
+        if self.tokens < 1.0 {
+            return false;
+        }
+
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+impl Server {
+    /// Creates a new `Server` that accepts connections from `listener`, with no watchdog.
+    pub fn from_listener(listener: TcpListener) -> Self {
+        Self {
+            listener,
+            registry: Arc::default(),
+            draining: Arc::default(),
+            idle_timeout: None,
+            ping_grace: None,
+            max_connections: None,
+            max_connections_per_ip: None,
+            accept_rate: None,
+            reject_with: None,
+        }
+    }
+
+    /// Closes connections that have been idle for longer than `timeout`.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Sends a health check to connections that have been idle for longer than the idle timeout,
+    /// and only closes them if nothing arrives within `grace` afterwards. A connection whose socket
+    /// will not take the health check straight away is closed at once.
+    ///
+    /// Without the `tokio` feature, health checks can only be sent on Unix, and elsewhere idle
+    /// connections are closed as if there were no grace period.
+    pub fn ping_before_close(mut self, grace: Duration) -> Self {
+        self.ping_grace = Some(grace);
+        self
+    }
+
+    /// Turns away new connections while `max` connections are open.
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.max_connections = Some(max);
+        self
+    }
+
+    /// Turns away new connections from an IP address while `max` connections from it are open.
+    pub fn max_connections_per_ip(mut self, max: usize) -> Self {
+        self.max_connections_per_ip = Some(max);
+        self
+    }
+
+    /// Accepts at most `num_connections` connections every `per`, turning away the rest. Short
+    /// bursts of up to `num_connections` are allowed as long as the average rate stays within it.
+    pub fn accept_rate(mut self, num_connections: u32, per: Duration) -> Self {
+        self.accept_rate = Some(TokenBucket::new(num_connections, per));
+        self
+    }
+
+    /// Sends the line returned by `f` to connections that are turned away, before closing them. By
+    /// default they are closed without a word.
+    ///
+    /// ```
+    /// use jsonl::{Rejection, Server};
+    /// use serde_json::json;
+    ///
+    /// # fn f(server: Server) -> Server {
+    /// server
+    ///     .max_connections(1024)
+    ///     .reject_with(|rejection: Rejection| json!({ "error": rejection }))
+    /// # }
+    /// ```
+    pub fn reject_with<F>(mut self, f: F) -> Self
+    where
+        F: Fn(Rejection) -> Value + Send + Sync + 'static,
+    {
+        self.reject_with = Some(Box::new(f));
+        self
+    }
+
+    /// Returns the address the server is accepting connections on.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Returns a [`Broadcaster`] for sending lines to every connection this server accepts.
+    pub fn broadcaster(&self) -> io::Result<Broadcaster> {
+        let mut wake_addr = self.local_addr()?;
+
+        if wake_addr.ip().is_unspecified() {
+            wake_addr.set_ip(match wake_addr {
+                SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+                SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+            });
+        }
+
+        Ok(Broadcaster {
+            registry: Arc::clone(&self.registry),
+            draining: Arc::clone(&self.draining),
+            wake_addr,
+        })
+    }
+
+    /// Decides whether to hand a connection from `peer` to the handler, given the connections
+    /// already open.
+    fn admit(&mut self, peer: &SocketAddr) -> Result<(), Rejection> {
+        let registry = self.registry.lock().unwrap_or_else(|e| e.into_inner());
+
+        if let Some(max) = self.max_connections {
+            if registry.len() >= max {
+                return Err(Rejection::TooManyConnections);
+            }
+        }
+
+        if let Some(max) = self.max_connections_per_ip {
+            let ip = peer.ip();
+            let num_from_ip = registry.values().filter(|shared| shared.ip == ip).count();
+
+            if num_from_ip >= max {
+                return Err(Rejection::TooManyConnectionsFromAddress);
+            }
+        }
+
+        if let Some(bucket) = self.accept_rate.as_mut() {
+            if !bucket.try_take() {
+                return Err(Rejection::RateLimited);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the line to send a connection turned away for `rejection`, if any.
+    fn rejection_line(&self, rejection: Rejection) -> Option<Vec<u8>> {
+        let f = self.reject_with.as_ref()?;
+        let mut line = serde_json::to_vec(&f(rejection)).ok()?;
+        line.push(b'\n');
+        Some(line)
+    }
+
+    fn watchdog(&self) -> Option<Watchdog> {
+        let idle_timeout = self.idle_timeout?;
+
+        Some(Watchdog {
+            idle_timeout,
+            ping_grace: self.ping_grace,
+            pinged: HashMap::new(),
+        })
+    }
+}
+
+/// Converts a listener made with `socket2` into the type `Server` accepts from.
+#[cfg(unix)]
+fn into_listener(socket: Socket) -> io::Result<TcpListener> {
+    let listener = std::net::TcpListener::from(socket);
+
+    #[cfg(feature = "tokio")]
+    let listener = {
+        listener.set_nonblocking(true)?;
+        TcpListener::from_std(listener)?
+    };
+
+    Ok(listener)
+}
+
+#[cfg(unix)]
+impl Server {
+    /// Creates a new `Server` listening on `addr` with `SO_REUSEPORT` set, so that other processes
+    /// (such as a newer version of this one) can listen on the same address at the same time.
+    ///
+    /// With the `tokio` feature, this must be called from within a Tokio runtime.
+    pub fn bind_reuse_port(addr: SocketAddr) -> io::Result<Self> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+        socket.set_reuse_address(true)?;
+        socket.set_reuse_port(true)?;
+        socket.bind(&addr.into())?;
+        socket.listen(1024)?;
+
+        Ok(Self::from_listener(into_listener(socket)?))
+    }
+
+    /// Creates a new `Server` from the listening socket passed down by the process that started
+    /// this one with [`Server::spawn_successor`], or returns `None` if there was none.
+    ///
+    /// The environment variable naming the socket is removed so that child processes do not
+    /// mistake it for their own, and all subsequent calls return `None`. With the `tokio` feature,
+    /// this must be called from within a Tokio runtime.
+    pub fn from_inherited_listener() -> io::Result<Option<Self>> {
+        let fd = match std::env::var(LISTENER_FD_ENV) {
+            Ok(fd) => fd,
+            Err(_) => return Ok(None),
+        };
+        std::env::remove_var(LISTENER_FD_ENV);
+
+        let fd: RawFd = fd.parse().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is not a valid file descriptor", LISTENER_FD_ENV),
+            )
+        })?;
+
+        // SAFETY: the process that started us passed this file descriptor for us alone, and
+        // removing the environment variable above ensures we only ever take it once.
+        let socket = unsafe { Socket::from_raw_fd(fd) };
+
+        if socket.r#type()? != Type::STREAM {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("file descriptor {} is not a stream socket", fd),
+            ));
+        }
+
+        socket.set_cloexec(true)?;
+
+        Ok(Some(Self::from_listener(into_listener(socket)?)))
+    }
+
+    /// Spawns `command` as the successor to this process, passing it the listening socket to take
+    /// over with [`Server::from_inherited_listener`].
+    ///
+    /// Both processes then accept connections from the socket until this one stops serving, so
+    /// stop only once the successor is ready, for instance after it has answered a
+    /// [health check](crate::Connection::health_check).
+    pub fn spawn_successor(&self, mut command: Command) -> io::Result<Child> {
+        // The duplicate is closed in this process once the child has been spawned.
+        let inherited = SockRef::from(&self.listener).try_clone()?;
+        inherited.set_cloexec(false)?;
+
+        command
+            .env(LISTENER_FD_ENV, inherited.as_raw_fd().to_string())
+            .spawn()
+    }
+}
+
+/// The state of an accepted connection shared between its [`ServerStream`]s and the server.
+#[derive(Debug)]
+struct Shared {
+    stream: TcpStream,
+    ip: IpAddr,
+    session: Session,
+    last_activity: Mutex<Instant>,
+    write: Mutex<WriteState>,
+}
+
+#[derive(Debug)]
+struct WriteState {
+    /// Whether everything written so far ended with a newline, so that a line can be injected
+    /// without splitting one the handler is partway through writing.
+    at_line_boundary: bool,
+    /// Injected lines waiting for the handler to finish the line it is partway through writing.
+    queued: Vec<u8>,
+    /// The part of an injected line that the socket has not accepted yet, which is written before
+    /// anything else.
+    pending: Vec<u8>,
+}
+
+impl Shared {
+    fn new(stream: TcpStream, ip: IpAddr, session: Session) -> Self {
+        Self {
+            stream,
+            ip,
+            session,
+            last_activity: Mutex::new(Instant::now()),
+            write: Mutex::new(WriteState {
+                at_line_boundary: true,
+                queued: Vec::new(),
+                pending: Vec::new(),
+            }),
+        }
+    }
+
+    fn touch(&self) {
+        *self.last_activity.lock().unwrap_or_else(|e| e.into_inner()) = Instant::now();
+    }
+
+    fn last_activity(&self) -> Instant {
+        *self.last_activity.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn close(&self) {
+        let _ = SockRef::from(&self.stream).shutdown(Shutdown::Both);
+    }
+
+    fn record_write(state: &mut WriteState, written: &[u8]) {
+        if let Some(last) = written.last() {
+            state.at_line_boundary = *last == b'\n';
+        }
+    }
+
+    /// Like `inject`, but never waits: fails with `WouldBlock` if the handler is writing, an
+    /// earlier line has not been written in full yet, or the socket accepts none of `line`, each of
+    /// which suggests the peer has stopped reading.
+    fn try_inject(&self, line: &[u8]) -> io::Result<()> {
+        let mut state = match self.write.try_lock() {
+            Ok(state) => state,
+            Err(TryLockError::Poisoned(e)) => e.into_inner(),
+            Err(TryLockError::WouldBlock) => return Err(io::ErrorKind::WouldBlock.into()),
+        };
+
+        if !state.at_line_boundary {
+            state.queued.extend_from_slice(line);
+            return Ok(());
+        }
+
+        self.try_write_pending(&mut state)?;
+
+        if !state.pending.is_empty() {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+
+        let num_written = self.try_write(line)?;
+        state.pending = line.get(num_written..).unwrap_or_default().to_vec();
+
+        Ok(())
+    }
+
+    /// Writes `line` to the stream, or if the handler is partway through writing a line of its own,
+    /// queues it to be written as soon as that line is finished.
+    ///
+    /// This never waits for the peer: whatever the socket does not accept straight away is written
+    /// before the handler’s next write or flush. A peer that has not taken the rest of an earlier
+    /// injected line by now has stopped reading, so it is disconnected rather than waited for.
+    #[cfg(any(unix, feature = "tokio"))]
+    fn inject(&self, line: &[u8]) -> io::Result<()> {
+        let mut state = self.write.lock().unwrap_or_else(|e| e.into_inner());
+
+        if !state.at_line_boundary {
+            state.queued.extend_from_slice(line);
+            return Ok(());
+        }
+
+        self.try_write_pending(&mut state)?;
+
+        if !state.pending.is_empty() {
+            self.close();
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+
+        let num_written = match self.try_write(line) {
+            Ok(num_written) => num_written,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => 0,
+            Err(e) => return Err(e),
+        };
+        state.pending = line.get(num_written..).unwrap_or_default().to_vec();
+
+        Ok(())
+    }
+
+    /// Writes as much of the rest of an injected line as the socket accepts without waiting.
+    fn try_write_pending(&self, state: &mut WriteState) -> io::Result<()> {
+        if state.pending.is_empty() {
+            return Ok(());
+        }
+
+        match self.try_write(&state.pending) {
+            Ok(num_written) => {
+                state.pending.drain(..num_written);
+                Ok(())
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl Shared {
+    /// Writes `line` to the stream, or if the handler is partway through writing a line of its own,
+    /// queues it to be written as soon as that line is finished.
+    ///
+    /// Blocking sockets can only be written to without waiting on Unix, so elsewhere this waits
+    /// for the peer to accept the line.
+    #[cfg(not(any(unix, feature = "tokio")))]
+    fn inject(&self, line: &[u8]) -> io::Result<()> {
+        let mut state = self.write.lock().unwrap_or_else(|e| e.into_inner());
+
+        if !state.at_line_boundary {
+            state.queued.extend_from_slice(line);
+            return Ok(());
+        }
+
+        self.write_pending(&mut state)?;
+        (&self.stream).write_all(line)
+    }
+
+    /// Writes as much of `buf` as the socket accepts without waiting.
+    #[cfg(unix)]
+    fn try_write(&self, buf: &[u8]) -> io::Result<usize> {
+        SockRef::from(&self.stream).send_with_flags(buf, libc::MSG_DONTWAIT)
+    }
+
+    /// Blocking sockets can only be written to without waiting on Unix, so elsewhere the peer is
+    /// never pinged.
+    #[cfg(not(unix))]
+    fn try_write(&self, _: &[u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    /// Writes the rest of a line injected by `try_inject` that the socket did not accept at the
+    /// time.
+    fn write_pending(&self, state: &mut WriteState) -> io::Result<()> {
+        if !state.pending.is_empty() {
+            (&self.stream).write_all(&state.pending)?;
+            state.pending.clear();
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Shared {
+    fn try_write(&self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.try_write(buf)
+    }
+
+    fn poll_write_pending(
+        &self,
+        state: &mut WriteState,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        while !state.pending.is_empty() {
+            match self.stream.poll_write_ready(cx) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+
+            match self.stream.try_write(&state.pending) {
+                Ok(num_written) => {
+                    state.pending.drain(..num_written);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// One side of a TCP stream accepted by a [`Server`], which records activity for the watchdog.
+#[derive(Debug, Clone)]
+pub struct ServerStream {
+    shared: Arc<Shared>,
+}
+
+impl ServerStream {
+    /// Returns the address of the peer at the other end of the stream.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.shared.stream.peer_addr()
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl Read for ServerStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let num_read = (&self.shared.stream).read(buf)?;
+        if num_read > 0 {
+            self.shared.touch();
+        }
+        Ok(num_read)
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl Write for ServerStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.shared.write.lock().unwrap_or_else(|e| e.into_inner());
+        self.shared.write_pending(&mut state)?;
+        let num_written = (&self.shared.stream).write(buf)?;
+
+        Shared::record_write(&mut state, buf.get(..num_written).unwrap_or_default());
+        self.shared.touch();
+
+        if state.at_line_boundary && !state.queued.is_empty() {
+            let queued = std::mem::take(&mut state.queued);
+            // The handler’s own bytes were written, so any error is left for its next write to
+            // report.
+            let _ = (&self.shared.stream).write_all(&queued);
+        }
+
+        Ok(num_written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut state = self.shared.write.lock().unwrap_or_else(|e| e.into_inner());
+        self.shared.write_pending(&mut state)?;
+        (&self.shared.stream).flush()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncRead for ServerStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            match self.shared.stream.poll_read_ready(cx) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+
+            match self.shared.stream.try_read(buf.initialize_unfilled()) {
+                Ok(num_read) => {
+                    buf.advance(num_read);
+                    if num_read > 0 {
+                        self.shared.touch();
+                    }
+                    return Poll::Ready(Ok(()));
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncWrite for ServerStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut state = self.shared.write.lock().unwrap_or_else(|e| e.into_inner());
+
+        match self.shared.poll_write_pending(&mut state, cx) {
+            Poll::Ready(Ok(())) => {}
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Pending => return Poll::Pending,
+        }
+
+        loop {
+            match self.shared.stream.poll_write_ready(cx) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+
+            match self.shared.stream.try_write(buf) {
+                Ok(num_written) => {
+                    Shared::record_write(&mut state, buf.get(..num_written).unwrap_or_default());
+                    self.shared.touch();
+
+                    if state.at_line_boundary {
+                        let queued = std::mem::take(&mut state.queued);
+                        state.pending.extend_from_slice(&queued);
+                    }
+
+                    return Poll::Ready(Ok(num_written));
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut state = self.shared.write.lock().unwrap_or_else(|e| e.into_inner());
+        self.shared.poll_write_pending(&mut state, cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut state = self.shared.write.lock().unwrap_or_else(|e| e.into_inner());
+
+        match self.shared.poll_write_pending(&mut state, cx) {
+            Poll::Ready(Ok(())) => {}
+            other => return other,
+        }
+
+        Poll::Ready(SockRef::from(&self.shared.stream).shutdown(Shutdown::Write))
+    }
+}
+
+/// Closes idle connections on behalf of a [`Server`].
+#[derive(Debug)]
+struct Watchdog {
+    idle_timeout: Duration,
+    ping_grace: Option<Duration>,
+    /// When each connection that has been sent a health check was sent it.
+    pinged: HashMap<u64, Instant>,
+}
+
+impl Watchdog {
+    fn interval(&self) -> Duration {
+        let shortest = match self.ping_grace {
+            Some(grace) => self.idle_timeout.min(grace),
+            None => self.idle_timeout,
+        };
+
+        (shortest / 4).max(MIN_WATCHDOG_INTERVAL)
+    }
+
+    /// Checks every connection in `registry`, pinging or closing those that have been idle too
+    /// long.
+    ///
+    /// The registry is only locked to copy it and to remove the connections closed, and pings are
+    /// written without waiting, so that a peer that has stopped reading holds up neither the
+    /// watchdog nor the server.
+    fn check(&mut self, registry: &Registry) {
+        let now = Instant::now();
+        let connections = registry.lock().unwrap_or_else(|e| e.into_inner()).clone();
+
+        self.pinged.retain(|id, _| connections.contains_key(id));
+
+        let mut dead = Vec::new();
+
+        for (id, shared) in &connections {
+            let last_activity = shared.last_activity();
+
+            let pinged_at = match self.pinged.get(id) {
+                Some(pinged_at) if last_activity > *pinged_at => {
+                    self.pinged.remove(id);
+                    None
+                }
+                pinged_at => pinged_at.copied(),
+            };
+
+            let is_dead = match (pinged_at, self.ping_grace) {
+                (Some(pinged_at), Some(grace)) => now.duration_since(pinged_at) >= grace,
+                (None, Some(_)) => {
+                    if now.duration_since(last_activity) >= self.idle_timeout {
+                        self.pinged.insert(*id, now);
+                        shared.try_inject(&Ping::line()).is_err()
+                    } else {
+                        false
+                    }
+                }
+                (_, None) => now.duration_since(last_activity) >= self.idle_timeout,
+            };
+
+            if is_dead {
+                shared.close();
+                self.pinged.remove(id);
+                dead.push(*id);
+            }
+        }
+
+        if !dead.is_empty() {
+            let mut registry = registry.lock().unwrap_or_else(|e| e.into_inner());
+            for id in dead {
+                registry.remove(&id);
+            }
+        }
+    }
+}
+
+/// Adds a newly accepted stream to `registry`, returning its ID and the stream for its handler.
+fn register(
+    registry: &Registry,
+    next_id: &mut u64,
+    stream: TcpStream,
+    peer: SocketAddr,
+) -> (u64, ServerStream) {
+    let id = *next_id;
+    *next_id += 1;
+
+    let session = Session::new(id, peer);
+    let shared = Arc::new(Shared::new(stream, peer.ip(), session));
+    registry
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(id, Arc::clone(&shared));
+
+    (id, ServerStream { shared })
+}
+
+/// Removes a connection from the registry when dropped, so that it is forgotten however its
+/// handler ends, including by panicking.
+struct Deregister {
+    registry: Weak<Registry>,
+    id: u64,
+}
+
+impl Drop for Deregister {
+    fn drop(&mut self) {
+        if let Some(registry) = self.registry.upgrade() {
+            registry
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(&self.id);
+        }
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl Server {
+    /// Creates a new `Server` listening on `addr`.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        Ok(Self::from_listener(TcpListener::bind(addr)?))
+    }
+
+    /// Accepts connections until [`Broadcaster::announce_shutdown`] is called, handling each on its
+    /// own thread with `handler`, which is given the connection and the address of its peer.
+    ///
+    /// An error is only returned if accepting a connection fails.
+    pub fn serve<F>(self, handler: F) -> io::Result<()>
+    where
+        F: Fn(ServerConnection, SocketAddr) + Send + Sync + 'static,
+    {
+        self.serve_with_sessions(move |connection, session: Session| {
+            handler(connection, session.peer_addr())
+        })
+    }
+
+    /// Accepts connections like [`Server::serve`], giving `handler` the connection’s [`Session`]
+    /// instead of the address of its peer.
+    pub fn serve_with_sessions<F>(mut self, handler: F) -> io::Result<()>
+    where
+        F: Fn(ServerConnection, Session) + Send + Sync + 'static,
+    {
+        let handler = Arc::new(handler);
+        let mut next_id = 0;
+
+        if let Some(mut watchdog) = self.watchdog() {
+            let registry = Arc::downgrade(&self.registry);
+            thread::spawn(move || {
+                while let Some(registry) = registry.upgrade() {
+                    watchdog.check(&registry);
+                    drop(registry);
+                    thread::sleep(watchdog.interval());
+                }
+            });
+        }
+
+        loop {
+            let (stream, peer) = self.listener.accept()?;
+
+            if self.draining.load(Ordering::SeqCst) {
+                return Ok(());
+            }
+
+            if let Err(rejection) = self.admit(&peer) {
+                if let Some(line) = self.rejection_line(rejection) {
+                    let _ = (&stream).write_all(&line);
+                }
+                continue;
+            }
+
+            let (id, stream) = register(&self.registry, &mut next_id, stream, peer);
+            let session = stream.shared.session.clone();
+            let connection = Connection::new(BufReader::new(stream.clone()), stream);
+            let handler = Arc::clone(&handler);
+            let deregister = Deregister {
+                registry: Arc::downgrade(&self.registry),
+                id,
+            };
+
+            thread::spawn(move || {
+                let _deregister = deregister;
+                handler(connection, session);
+            });
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Server {
+    /// Creates a new `Server` listening on `addr`.
+    pub async fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        Ok(Self::from_listener(TcpListener::bind(addr).await?))
+    }
+
+    /// Accepts connections until [`Broadcaster::announce_shutdown`] is called, handling each in its
+    /// own task with `handler`, which is given the connection and the address of its peer.
+    ///
+    /// An error is only returned if accepting a connection fails.
+    pub async fn serve<F, Fut>(self, handler: F) -> io::Result<()>
+    where
+        F: Fn(ServerConnection, SocketAddr) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.serve_with_sessions(move |connection, session: Session| {
+            handler(connection, session.peer_addr())
+        })
+        .await
+    }
+
+    /// Accepts connections like [`Server::serve`], giving `handler` the connection’s [`Session`]
+    /// instead of the address of its peer.
+    pub async fn serve_with_sessions<F, Fut>(mut self, handler: F) -> io::Result<()>
+    where
+        F: Fn(ServerConnection, Session) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let mut next_id = 0;
+
+        if let Some(mut watchdog) = self.watchdog() {
+            let registry = Arc::downgrade(&self.registry);
+            tokio::spawn(async move {
+                while let Some(registry) = registry.upgrade() {
+                    watchdog.check(&registry);
+                    drop(registry);
+                    tokio::time::sleep(watchdog.interval()).await;
+                }
+            });
+        }
+
+        loop {
+            let (stream, peer) = self.listener.accept().await?;
+
+            if self.draining.load(Ordering::SeqCst) {
+                return Ok(());
+            }
+
+            if let Err(rejection) = self.admit(&peer) {
+                // The socket’s send buffer is still empty, so a line this short is written in one go.
+                if let Some(line) = self.rejection_line(rejection) {
+                    let _ = stream.try_write(&line);
+                }
+                continue;
+            }
+
+            let (id, stream) = register(&self.registry, &mut next_id, stream, peer);
+            let session = stream.shared.session.clone();
+            let connection = Connection::new(BufReader::new(stream.clone()), stream);
+            let deregister = Deregister {
+                registry: Arc::downgrade(&self.registry),
+                id,
+            };
+            let handling = handler(connection, session);
+
+            tokio::spawn(async move {
+                let _deregister = deregister;
+                handling.await;
+            });
+        }
+    }
+}
+
+/// The notice sent to every connection by [`Broadcaster::announce_shutdown`], written as
+/// `{"shutdown":{"reason":"upgrading","grace_ms":30000}}`.
+///
+/// Well-behaved clients that receive it stop sending new requests, finish what is in flight and
+/// reconnect, possibly to another server, before the grace period runs out and the connection is
+/// closed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ShutdownNotice {
+    pub reason: String,
+    pub grace_ms: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Goodbye {
+    shutdown: ShutdownNotice,
+}
+
+impl ShutdownNotice {
+    /// Returns the shutdown notice `value` holds, if it is one rather than an ordinary message.
+    pub fn from_value(value: &Value) -> Option<Self> {
+        let object = value.as_object()?;
+
+        if !object.contains_key("shutdown") {
+            return None;
+        }
+
+        serde_json::from_value::<Goodbye>(value.clone())
+            .ok()
+            .map(|goodbye| goodbye.shutdown)
+    }
+
+    /// Returns how long the server waits before closing connections.
+    pub fn grace(&self) -> Duration {
+        Duration::from_millis(self.grace_ms)
+    }
+}
+
+/// Sends lines to every connection accepted by a [`Server`], and shuts it down gracefully.
+///
+/// Lines are written between the lines written by each connection’s handler, never in the middle
+/// of one, and without waiting for peers that have stopped reading, which are disconnected instead.
+/// Create a `Broadcaster` with [`Server::broadcaster`] before calling [`Server::serve`];
+/// it can be cloned freely and kept wherever the program decides when to shut down.
+#[derive(Debug, Clone)]
+pub struct Broadcaster {
+    registry: Arc<Registry>,
+    draining: Arc<AtomicBool>,
+    wake_addr: SocketAddr,
+}
+
+impl Broadcaster {
+    /// Writes `t` to every open connection, returning how many it was written to.
+    pub fn broadcast<T: Serialize>(&self, t: &T) -> Result<usize, WriteError> {
+        let mut line = serde_json::to_vec(t).map_err(WriteError::Serialize)?;
+        line.push(b'\n');
+
+        let connections: Vec<_> = self
+            .registry
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .cloned()
+            .collect();
+
+        Ok(connections
+            .iter()
+            .filter(|shared| shared.inject(&line).is_ok())
+            .count())
+    }
+
+    /// Returns the [`Session`] of the open connection with the ID `id`, if there is one.
+    pub fn session(&self, id: u64) -> Option<Session> {
+        self.registry
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&id)
+            .map(|shared| shared.session.clone())
+    }
+
+    /// Returns the number of connections still open.
+    pub fn num_connections(&self) -> usize {
+        self.registry
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .len()
+    }
+
+    fn start_draining(&self, reason: &str, grace: Duration) -> Result<(), WriteError> {
+        self.draining.store(true, Ordering::SeqCst);
+
+        self.broadcast(&Goodbye {
+            shutdown: ShutdownNotice {
+                reason: reason.to_string(),
+                grace_ms: u64::try_from(grace.as_millis()).unwrap_or(u64::MAX),
+            },
+        })
+        .map(|_| ())
+    }
+
+    fn close_all(&self) {
+        let connections: Vec<_> = self
+            .registry
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .drain()
+            .collect();
+
+        for (_, shared) in connections {
+            shared.close();
+        }
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl Broadcaster {
+    /// Stops the server accepting connections, sends a [`ShutdownNotice`] to every connection, and
+    /// waits until they have all closed or `grace` has passed, at which point those left are
+    /// closed.
+    ///
+    /// [`Server::serve`] returns once it has stopped accepting connections, while their handlers
+    /// carry on until their connections close.
+    pub fn announce_shutdown(&self, reason: &str, grace: Duration) -> Result<(), WriteError> {
+        let deadline = Instant::now().checked_add(grace);
+
+        self.start_draining(reason, grace)?;
+        // Wakes the server from waiting to accept a connection, so that it notices it is draining.
+        let _ = TcpStream::connect(self.wake_addr);
+
+        while self.num_connections() > 0
+            && deadline.is_none_or(|deadline| Instant::now() < deadline)
+        {
+            thread::sleep(MIN_WATCHDOG_INTERVAL);
+        }
+
+        self.close_all();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Broadcaster {
+    /// Stops the server accepting connections, sends a [`ShutdownNotice`] to every connection, and
+    /// waits until they have all closed or `grace` has passed, at which point those left are
+    /// closed.
+    ///
+    /// [`Server::serve`] returns once it has stopped accepting connections, while their handlers
+    /// carry on until their connections close.
+    pub async fn announce_shutdown(&self, reason: &str, grace: Duration) -> Result<(), WriteError> {
+        let deadline = Instant::now().checked_add(grace);
+
+        self.start_draining(reason, grace)?;
+        // Wakes the server from waiting to accept a connection, so that it notices it is draining.
+        let _ = TcpStream::connect(self.wake_addr).await;
+
+        while self.num_connections() > 0
+            && deadline.is_none_or(|deadline| Instant::now() < deadline)
+        {
+            tokio::time::sleep(MIN_WATCHDOG_INTERVAL).await;
+        }
+
+        self.close_all();
+        Ok(())
+    }
+}
+
+#[cfg(all(test, not(feature = "tokio")))]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, Read};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Starts `server` on its own thread with a handler that reads until EOF, returning its
+    /// address.
+    fn start(server: Server) -> io::Result<SocketAddr> {
+        let addr = server.local_addr()?;
+
+        thread::spawn(move || {
+            server.serve(|mut connection, _| while connection.read::<Value>().is_ok() {})
+        });
+
+        Ok(addr)
+    }
+
+    fn connect(addr: SocketAddr) -> io::Result<TcpStream> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        Ok(stream)
+    }
+
+    /// Reads from `stream` until EOF, failing if that takes longer than the read timeout.
+    fn read_to_eof(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received)?;
+        Ok(received)
+    }
+
+    #[test]
+    fn idle_connections_are_closed() -> Result<(), Box<dyn std::error::Error>> {
+        let server = Server::bind("127.0.0.1:0")?.idle_timeout(Duration::from_millis(50));
+        let mut stream = connect(start(server)?)?;
+
+        let started = Instant::now();
+        assert_eq!(read_to_eof(&mut stream)?, b"");
+        assert!(started.elapsed() >= Duration::from_millis(50));
+
+        Ok(())
+    }
+
+    #[test]
+    fn active_connections_are_kept_open() -> Result<(), Box<dyn std::error::Error>> {
+        let server = Server::bind("127.0.0.1:0")?.idle_timeout(Duration::from_millis(200));
+        let mut stream = connect(start(server)?)?;
+
+        for _ in 0..10 {
+            thread::sleep(Duration::from_millis(50));
+            stream.write_all(b"1\n")?;
+        }
+
+        stream.set_nonblocking(true)?;
+        let mut buf = [0; 1];
+        assert!(matches!(stream.read(&mut buf), Err(e) if e.kind() == io::ErrorKind::WouldBlock));
+
+        Ok(())
+    }
+
+    #[test]
+    fn idle_connections_are_pinged_before_being_closed() -> Result<(), Box<dyn std::error::Error>> {
+        let server = Server::bind("127.0.0.1:0")?
+            .idle_timeout(Duration::from_millis(50))
+            .ping_before_close(Duration::from_millis(100));
+        let mut stream = BufReader::new(connect(start(server)?)?);
+
+        let mut line = String::new();
+        stream.read_line(&mut line)?;
+        let ping: Value = serde_json::from_str(&line)?;
+        assert!(ping.get("health_check").is_some_and(Value::is_u64));
+
+        line.clear();
+        assert_eq!(stream.read_line(&mut line)?, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn connections_over_the_limit_are_rejected() -> Result<(), Box<dyn std::error::Error>> {
+        let server = Server::bind("127.0.0.1:0")?
+            .max_connections(1)
+            .reject_with(|rejection| serde_json::json!({ "rejected": rejection }));
+        let addr = start(server)?;
+
+        let _first = connect(addr)?;
+        let mut second = connect(addr)?;
+
+        assert_eq!(
+            read_to_eof(&mut second)?,
+            b"{\"rejected\":\"too_many_connections\"}\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn connections_over_the_accept_rate_are_rejected() -> Result<(), Box<dyn std::error::Error>> {
+        let server = Server::bind("127.0.0.1:0")?
+            .accept_rate(1, Duration::from_secs(3600))
+            .reject_with(|rejection| serde_json::json!({ "rejected": rejection }));
+        let addr = start(server)?;
+
+        let _first = connect(addr)?;
+        let mut second = connect(addr)?;
+
+        assert_eq!(
+            read_to_eof(&mut second)?,
+            b"{\"rejected\":\"rate_limited\"}\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn token_bucket_refills_over_time() {
+        let mut bucket = TokenBucket::new(2, Duration::from_millis(100));
+
+        assert!(bucket.try_take());
+        assert!(bucket.try_take());
+        assert!(!bucket.try_take());
+
+        thread::sleep(Duration::from_millis(60));
+        assert!(bucket.try_take());
+
+        let mut unlimited = TokenBucket::new(1, Duration::ZERO);
+        assert!((0..100).all(|_| unlimited.try_take()));
+    }
+
+    /// Waits until `broadcaster` has `num_connections` connections open.
+    fn wait_for_connections(broadcaster: &Broadcaster, num_connections: usize) -> bool {
+        let started = Instant::now();
+
+        while broadcaster.num_connections() != num_connections {
+            if started.elapsed() > TIMEOUT {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        true
+    }
+
+    #[test]
+    fn broadcasts_reach_every_connection() -> Result<(), Box<dyn std::error::Error>> {
+        let server = Server::bind("127.0.0.1:0")?;
+        let broadcaster = server.broadcaster()?;
+        let addr = start(server)?;
+
+        let mut streams = vec![
+            BufReader::new(connect(addr)?),
+            BufReader::new(connect(addr)?),
+        ];
+        assert!(wait_for_connections(&broadcaster, 2));
+
+        assert_eq!(broadcaster.broadcast(&serde_json::json!({ "n": 1 }))?, 2);
+
+        for stream in &mut streams {
+            let mut line = String::new();
+            stream.read_line(&mut line)?;
+            assert_eq!(line, "{\"n\":1}\n");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn broadcasts_do_not_wait_for_peers_that_stop_reading() -> Result<(), Box<dyn std::error::Error>>
+    {
+        let server = Server::bind("127.0.0.1:0")?;
+        let broadcaster = server.broadcaster()?;
+        let addr = start(server)?;
+
+        let _stalled = connect(addr)?;
+        assert!(wait_for_connections(&broadcaster, 1));
+
+        let line = "x".repeat(4 * 1024 * 1024);
+        let started = Instant::now();
+
+        for _ in 0..8 {
+            broadcaster.broadcast(&line)?;
+        }
+
+        assert!(started.elapsed() < TIMEOUT);
+        assert!(wait_for_connections(&broadcaster, 0));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// The state a [`Server`](crate::Server) keeps for one connection, handed to the handler given to
+/// [`Server::serve_with_sessions`](crate::Server::serve_with_sessions).
+///
+/// A session holds the identity the peer has authenticated as, named counters, and any number of
+/// values of other types, at most one of each, so that stateful protocols need no global map keyed
+/// by some ID of their own. It can be cloned cheaply, and all clones share the same state, so it
+/// can be handed on to whatever else needs it while the connection is open. A session can also be
+/// looked up by its ID with [`Broadcaster::session`](crate::Broadcaster::session).
+///
+/// The server forgets a session once its connection’s handler returns, and its state is dropped
+/// along with the last clone.
+#[derive(Clone)]
+pub struct Session {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    id: u64,
+    peer_addr: SocketAddr,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    identity: Option<String>,
+    counters: HashMap<String, u64>,
+    values: HashMap<TypeId, Box<dyn Any + Send>>,
+}
+
+impl fmt::Debug for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state();
+
+        f.debug_struct("Session")
+            .field("id", &self.inner.id)
+            .field("peer_addr", &self.inner.peer_addr)
+            .field("identity", &state.identity)
+            .field("counters", &state.counters)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Session {
+    pub(crate) fn new(id: u64, peer_addr: SocketAddr) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                id,
+                peer_addr,
+                state: Mutex::new(State::default()),
+            }),
+        }
+    }
+
+    /// Returns the ID of the connection, which is unique among those accepted by one server.
+    pub fn id(&self) -> u64 {
+        self.inner.id
+    }
+
+    /// Returns the address of the peer at the other end of the connection.
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.inner.peer_addr
+    }
+
+    /// Returns the identity the peer has authenticated as, if it has.
+    pub fn identity(&self) -> Option<String> {
+        self.state().identity.clone()
+    }
+
+    /// Records that the peer has authenticated as `identity`.
+    pub fn set_identity<S: Into<String>>(&self, identity: S) {
+        self.state().identity = Some(identity.into());
+    }
+
+    /// Forgets the identity the peer has authenticated as, such as when it logs out.
+    pub fn clear_identity(&self) {
+        self.state().identity = None;
+    }
+
+    /// Adds one to the counter called `name`, returning its new value. Counters start at zero.
+    pub fn increment(&self, name: &str) -> u64 {
+        let mut state = self.state();
+        let counter = state.counters.entry(name.to_string()).or_insert(0);
+        *counter = counter.saturating_add(1);
+        *counter
+    }
+
+    /// Returns the value of the counter called `name`.
+    pub fn counter(&self, name: &str) -> u64 {
+        self.state().counters.get(name).copied().unwrap_or(0)
+    }
+
+    /// Stores `value` in the session, returning the value of the same type stored before, if any.
+    pub fn insert<T: Any + Send>(&self, value: T) -> Option<T> {
+        self.state()
+            .values
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(downcast)
+    }
+
+    /// Returns a clone of the value of type `T` stored in the session, if any. Store an
+    /// [`Arc`](std::sync::Arc) around a value to share it rather than copy it, with a
+    /// [`Mutex`](std::sync::Mutex) inside to change it in place.
+    pub fn get<T: Any + Send + Clone>(&self) -> Option<T> {
+        self.state()
+            .values
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref::<T>())
+            .cloned()
+    }
+
+    /// Removes the value of type `T` from the session, returning it.
+    pub fn remove<T: Any + Send>(&self) -> Option<T> {
+        self.state()
+            .values
+            .remove(&TypeId::of::<T>())
+            .and_then(downcast)
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.inner.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn downcast<T: Any>(value: Box<dyn Any + Send>) -> Option<T> {
+    value.downcast().ok().map(|value| *value)
+}
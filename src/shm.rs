@@ -0,0 +1,587 @@
+#[cfg(not(feature = "tokio"))]
+mod imports {
+    pub(super) use std::io::{self, BufReader, Read, Write};
+    pub(super) use std::thread;
+}
+#[cfg(feature = "tokio")]
+mod imports {
+    pub(super) use std::future::Future;
+    pub(super) use std::pin::Pin;
+    pub(super) use std::task::{Context, Poll};
+    pub(super) use tokio::io::{self, AsyncRead, AsyncWrite, BufReader, ReadBuf};
+}
+
+use crate::Transport;
+use imports::*;
+use std::convert::{Infallible, TryFrom};
+use std::fs::{File, OpenOptions};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Identifies a file laid out by [`ShmTransport::create`].
+const MAGIC: u64 = u64::from_le_bytes(*b"JSONLSHM");
+/// The size of the file header, which holds the magic number, the capacity of each ring and the
+/// process ID of each side.
+const FILE_HEADER_LEN: usize = 64;
+const MAGIC_OFFSET: usize = 0;
+const CAPACITY_OFFSET: usize = 8;
+const CREATOR_PID_OFFSET: usize = 16;
+const OPENER_PID_OFFSET: usize = 24;
+/// The size of each ring’s header. The writer’s and reader’s positions are on separate cache lines
+/// so that the two sides do not contend.
+const RING_HEADER_LEN: usize = 128;
+const HEAD_OFFSET: usize = 0;
+const WRITER_CLOSED_OFFSET: usize = 8;
+const TAIL_OFFSET: usize = 64;
+const READER_CLOSED_OFFSET: usize = 72;
+/// How many times to spin before yielding while waiting for the other side.
+#[cfg(not(feature = "tokio"))]
+const SPINS_BEFORE_YIELD: u32 = 128;
+/// How many times to wait for the other side without sleeping, counting spins, before sleeping
+/// between checks instead.
+const WAITS_BEFORE_SLEEP: u32 = 192;
+/// The shortest and longest sleeps between checks once a side has waited a while for the other,
+/// doubling from one to the other.
+const MIN_SLEEP: Duration = Duration::from_micros(10);
+const MAX_SLEEP: Duration = Duration::from_millis(1);
+
+/// A shared mapping of a file, unmapped once every ring using it has been dropped.
+#[derive(Debug)]
+struct Mapping {
+    ptr: *mut u8,
+    len: usize,
+}
+
+// SAFETY: the mapping is only accessed through atomics and through the byte ranges that the
+// single-producer single-consumer protocol gives each side exclusive use of.
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    fn new(file: &File, len: usize) -> std::io::Result<Self> {
+        // SAFETY: we map a file we hold open with a length no greater than its size, and never
+        // create references to the mapped bytes other than to the atomics in the headers.
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+
+        if ptr == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            ptr: ptr as *mut u8,
+            len,
+        })
+    }
+
+    fn atomic(&self, offset: usize) -> &AtomicU64 {
+        // SAFETY: every offset passed is a multiple of eight inside a header, and the mapping is
+        // page-aligned, so this points to an aligned u64 inside the mapping that lives as long as
+        // `self`. The other process only accesses it atomically too.
+        unsafe { &*(self.ptr.add(offset) as *const AtomicU64) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        // SAFETY: ptr and len describe a mapping created by mmap that nothing refers to any more.
+        unsafe {
+            libc::munmap(self.ptr as *mut libc::c_void, self.len);
+        }
+    }
+}
+
+/// One direction of a [`ShmTransport`]: a single-producer single-consumer ring of bytes.
+#[derive(Debug)]
+struct Ring {
+    mapping: Arc<Mapping>,
+    offset: usize,
+    capacity: usize,
+    /// Where in the file header the other side’s process ID is.
+    peer_pid_offset: usize,
+}
+
+impl Ring {
+    fn head(&self) -> &AtomicU64 {
+        self.mapping.atomic(self.offset + HEAD_OFFSET)
+    }
+
+    fn tail(&self) -> &AtomicU64 {
+        self.mapping.atomic(self.offset + TAIL_OFFSET)
+    }
+
+    fn writer_closed(&self) -> &AtomicU64 {
+        self.mapping.atomic(self.offset + WRITER_CLOSED_OFFSET)
+    }
+
+    fn reader_closed(&self) -> &AtomicU64 {
+        self.mapping.atomic(self.offset + READER_CLOSED_OFFSET)
+    }
+
+    /// Returns whether the process on the other side is still running, or has yet to open the
+    /// file. A process that exits without dropping its side never marks it closed, so this is
+    /// checked whenever a side has waited long enough to start sleeping.
+    fn is_peer_alive(&self) -> bool {
+        let pid = match self
+            .mapping
+            .atomic(self.peer_pid_offset)
+            .load(Ordering::Acquire)
+        {
+            0 => return true,
+            pid => pid,
+        };
+
+        let pid = match libc::pid_t::try_from(pid) {
+            Ok(pid) => pid,
+            Err(_) => return false,
+        };
+
+        // SAFETY: signal 0 sends nothing, only checking whether the process exists.
+        if unsafe { libc::kill(pid, 0) } == 0 {
+            return true;
+        }
+
+        std::io::Error::last_os_error().raw_os_error() != Some(libc::ESRCH)
+    }
+
+    /// Returns the number of bytes written but not yet read, checking that the other side has not
+    /// left the positions in an impossible state.
+    fn len(&self) -> std::io::Result<usize> {
+        let head = self.head().load(Ordering::Acquire);
+        let tail = self.tail().load(Ordering::Acquire);
+
+        match head.checked_sub(tail).map(usize::try_from) {
+            Some(Ok(len)) if len <= self.capacity => Ok(len),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "shared memory ring is corrupt",
+            )),
+        }
+    }
+
+    /// Copies bytes between `buf` and the ring, starting at stream position `pos` and wrapping
+    /// around the end of the ring.
+    fn copy(&self, pos: u64, buf: *mut u8, len: usize, into_ring: bool) {
+        let start = (pos % self.capacity as u64) as usize;
+        let first = len.min(self.capacity - start);
+
+        // SAFETY: both ranges lie inside the ring’s data, which the protocol gives this side
+        // exclusive use of until it publishes its new position, and inside `buf`, which the
+        // caller guarantees holds `len` bytes.
+        unsafe {
+            let data = self.mapping.ptr.add(self.offset + RING_HEADER_LEN);
+
+            if into_ring {
+                ptr::copy_nonoverlapping(buf, data.add(start), first);
+                ptr::copy_nonoverlapping(buf.add(first), data, len - first);
+            } else {
+                ptr::copy_nonoverlapping(data.add(start), buf, first);
+                ptr::copy_nonoverlapping(data, buf.add(first), len - first);
+            }
+        }
+    }
+
+    /// Reads as much as is available into `buf`, returning `None` if nothing is available yet and
+    /// the writer is still open.
+    fn try_read(&self, buf: &mut [u8]) -> std::io::Result<Option<usize>> {
+        let available = self.len()?;
+
+        if available == 0 {
+            return Ok(if self.writer_closed().load(Ordering::Acquire) != 0 {
+                Some(0)
+            } else {
+                None
+            });
+        }
+
+        let len = available.min(buf.len());
+        let tail = self.tail().load(Ordering::Relaxed);
+
+        self.copy(tail, buf.as_mut_ptr(), len, false);
+        self.tail().store(tail + len as u64, Ordering::Release);
+
+        Ok(Some(len))
+    }
+
+    /// Writes as much of `buf` as there is room for, returning `None` if the ring is full.
+    fn try_write(&self, buf: &[u8]) -> std::io::Result<Option<usize>> {
+        if self.reader_closed().load(Ordering::Acquire) != 0 {
+            return Err(std::io::ErrorKind::BrokenPipe.into());
+        }
+
+        let room = self.capacity - self.len()?;
+
+        if room == 0 && !buf.is_empty() {
+            return Ok(None);
+        }
+
+        let len = room.min(buf.len());
+        let head = self.head().load(Ordering::Relaxed);
+
+        self.copy(head, buf.as_ptr() as *mut u8, len, true);
+        self.head().store(head + len as u64, Ordering::Release);
+
+        Ok(Some(len))
+    }
+}
+
+/// The half of a [`ShmTransport`] that is read from.
+///
+/// Reading returns end of file once the other side’s [`ShmWriter`] has been dropped and
+/// everything it wrote has been read, and fails with [`io::ErrorKind::UnexpectedEof`] if the other
+/// process exits without dropping it.
+#[derive(Debug)]
+pub struct ShmReader {
+    ring: Ring,
+    waiter: Waiter,
+}
+
+impl Drop for ShmReader {
+    fn drop(&mut self) {
+        self.ring.reader_closed().store(1, Ordering::Release);
+    }
+}
+
+/// The half of a [`ShmTransport`] that is written to.
+///
+/// Writing fails with [`io::ErrorKind::BrokenPipe`] once the other side’s [`ShmReader`] has been
+/// dropped or the other process has exited.
+#[derive(Debug)]
+pub struct ShmWriter {
+    ring: Ring,
+    waiter: Waiter,
+}
+
+impl Drop for ShmWriter {
+    fn drop(&mut self) {
+        self.ring.writer_closed().store(1, Ordering::Release);
+    }
+}
+
+/// A [`Transport`] between two processes on the same host through a pair of single-producer
+/// single-consumer rings in a shared memory file, for when even Unix domain sockets are too slow.
+///
+/// One process creates the file with [`ShmTransport::create`], and the other maps it with
+/// [`ShmTransport::open`]; put it on a memory-backed file system such as `/dev/shm` so that it is
+/// never written back to disk. Each side then creates a [`Connection`](crate::Connection) with
+/// [`Connection::from_transport`](crate::Connection::from_transport).
+///
+/// No system calls are made to send or receive lines while both sides keep up with each other. A
+/// side waiting for the other spins on the CPU at first, then yields, and then sleeps between
+/// checks for up to a millisecond at a time, so an idle connection costs little CPU but the first
+/// line after a pause may take that long to be noticed. With the `tokio` feature enabled the task
+/// is rescheduled rather than spinning, and sleeps on Tokio’s timer, so the runtime must have time
+/// enabled.
+///
+/// Each side records its process ID in the file, so that a side waiting on one that has exited
+/// without closing its end fails rather than waiting forever. Each file connects exactly one pair
+/// of processes, and should be removed once both have opened it.
+#[derive(Debug)]
+pub struct ShmTransport {
+    reader: ShmReader,
+    writer: ShmWriter,
+}
+
+impl ShmTransport {
+    /// Creates a new shared memory file at `path` with two rings of `capacity` bytes each, and
+    /// returns the creating side of the transport. Fails if the file already exists.
+    ///
+    /// The capacity is rounded up to a multiple of 64 bytes.
+    pub fn create<P: AsRef<Path>>(path: P, capacity: usize) -> std::io::Result<Self> {
+        let (capacity, len) = capacity
+            .max(1)
+            .checked_add(63)
+            .map(|capacity| capacity / 64 * 64)
+            .and_then(|capacity| Some((capacity, file_len(capacity)?)))
+            .ok_or_else(|| {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, "capacity is too large")
+            })?;
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(path)?;
+        file.set_len(len as u64)?;
+
+        let mapping = Arc::new(Mapping::new(&file, len)?);
+        mapping
+            .atomic(CAPACITY_OFFSET)
+            .store(capacity as u64, Ordering::Relaxed);
+        mapping
+            .atomic(CREATOR_PID_OFFSET)
+            .store(u64::from(std::process::id()), Ordering::Relaxed);
+        mapping.atomic(MAGIC_OFFSET).store(MAGIC, Ordering::Release);
+
+        Ok(Self::side(mapping, capacity, 0, 1))
+    }
+
+    /// Maps the shared memory file at `path` created by [`ShmTransport::create`] in another
+    /// process, and returns the opening side of the transport.
+    pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let file_size = usize::try_from(file.metadata()?.len()).unwrap_or(usize::MAX);
+
+        if file_size < FILE_HEADER_LEN {
+            return Err(not_shm_file());
+        }
+
+        let header = Mapping::new(&file, FILE_HEADER_LEN)?;
+
+        if header.atomic(MAGIC_OFFSET).load(Ordering::Acquire) != MAGIC {
+            return Err(not_shm_file());
+        }
+
+        let (capacity, len) =
+            usize::try_from(header.atomic(CAPACITY_OFFSET).load(Ordering::Relaxed))
+                .ok()
+                .filter(|capacity| *capacity > 0 && *capacity % 64 == 0)
+                .and_then(|capacity| Some((capacity, file_len(capacity)?)))
+                .filter(|(_, len)| *len <= file_size)
+                .ok_or_else(not_shm_file)?;
+
+        let mapping = Arc::new(Mapping::new(&file, len)?);
+        mapping
+            .atomic(OPENER_PID_OFFSET)
+            .store(u64::from(std::process::id()), Ordering::Release);
+
+        Ok(Self::side(mapping, capacity, 1, 0))
+    }
+
+    /// Returns the side of the transport that reads from one ring and writes to the other.
+    fn side(mapping: Arc<Mapping>, capacity: usize, read_ring: usize, write_ring: usize) -> Self {
+        // The creator reads from the first ring, and the opener from the second.
+        let peer_pid_offset = if read_ring == 0 {
+            OPENER_PID_OFFSET
+        } else {
+            CREATOR_PID_OFFSET
+        };
+
+        let ring = |i: usize| Ring {
+            mapping: Arc::clone(&mapping),
+            offset: FILE_HEADER_LEN + i * (RING_HEADER_LEN + capacity),
+            capacity,
+            peer_pid_offset,
+        };
+
+        Self {
+            reader: ShmReader {
+                ring: ring(read_ring),
+                waiter: Waiter::default(),
+            },
+            writer: ShmWriter {
+                ring: ring(write_ring),
+                waiter: Waiter::default(),
+            },
+        }
+    }
+}
+
+/// Returns the size of a file holding two rings of `capacity` bytes.
+fn file_len(capacity: usize) -> Option<usize> {
+    capacity
+        .checked_add(RING_HEADER_LEN)?
+        .checked_mul(2)?
+        .checked_add(FILE_HEADER_LEN)
+}
+
+fn not_shm_file() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "file is not a shared memory transport",
+    )
+}
+
+impl Transport for ShmTransport {
+    type Reader = BufReader<ShmReader>;
+    type Writer = ShmWriter;
+    type Error = Infallible;
+
+    fn split(self) -> Result<(Self::Reader, Self::Writer), Infallible> {
+        Ok((BufReader::new(self.reader), self.writer))
+    }
+}
+
+/// Backs off while a side waits for the other: from checking again straight away to sleeping for
+/// longer and longer between checks, up to [`MAX_SLEEP`].
+#[derive(Debug, Default)]
+struct Waiter {
+    num_waits: u32,
+    #[cfg(feature = "tokio")]
+    sleep: Option<Pin<Box<tokio::time::Sleep>>>,
+}
+
+impl Waiter {
+    /// Starts backing off from the beginning, once the other side has made progress.
+    fn reset(&mut self) {
+        self.num_waits = 0;
+    }
+
+    /// Returns how long to sleep before checking again, or `None` to check again without
+    /// sleeping.
+    fn next_sleep(&mut self) -> Option<Duration> {
+        self.num_waits = self.num_waits.saturating_add(1);
+        let num_sleeps = self.num_waits.checked_sub(WAITS_BEFORE_SLEEP)?;
+
+        Some(
+            MIN_SLEEP
+                .saturating_mul(1 << num_sleeps.min(7))
+                .min(MAX_SLEEP),
+        )
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl Waiter {
+    /// Waits for the other side of `ring`, failing with `peer_gone` if its process has exited.
+    fn wait(&mut self, ring: &Ring, peer_gone: io::ErrorKind) -> io::Result<()> {
+        match self.next_sleep() {
+            None if self.num_waits <= SPINS_BEFORE_YIELD => std::hint::spin_loop(),
+            None => thread::yield_now(),
+            Some(_) if !ring.is_peer_alive() => return Err(peer_gone.into()),
+            Some(sleep) => thread::sleep(sleep),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Waiter {
+    /// Waits for the other side of `ring`, failing with `peer_gone` if its process has exited.
+    /// Returns ready once it is time to check again.
+    fn poll_wait(
+        &mut self,
+        ring: &Ring,
+        peer_gone: io::ErrorKind,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            if let Some(sleep) = &mut self.sleep {
+                if sleep.as_mut().poll(cx).is_pending() {
+                    return Poll::Pending;
+                }
+
+                self.sleep = None;
+                return Poll::Ready(Ok(()));
+            }
+
+            match self.next_sleep() {
+                None => {
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+                Some(_) if !ring.is_peer_alive() => return Poll::Ready(Err(peer_gone.into())),
+                Some(sleep) => self.sleep = Some(Box::pin(tokio::time::sleep(sleep))),
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl Read for ShmReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(num_bytes) = self.ring.try_read(buf)? {
+                self.waiter.reset();
+                return Ok(num_bytes);
+            }
+
+            self.waiter.wait(&self.ring, io::ErrorKind::UnexpectedEof)?;
+        }
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl Write for ShmWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        loop {
+            if let Some(num_bytes) = self.ring.try_write(buf)? {
+                self.waiter.reset();
+                return Ok(num_bytes);
+            }
+
+            self.waiter.wait(&self.ring, io::ErrorKind::BrokenPipe)?;
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncRead for ShmReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        loop {
+            if let Some(num_bytes) = this.ring.try_read(buf.initialize_unfilled())? {
+                this.waiter.reset();
+                buf.advance(num_bytes);
+                return Poll::Ready(Ok(()));
+            }
+
+            match this
+                .waiter
+                .poll_wait(&this.ring, io::ErrorKind::UnexpectedEof, cx)
+            {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncWrite for ShmWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        loop {
+            if let Some(num_bytes) = this.ring.try_write(buf)? {
+                this.waiter.reset();
+                return Poll::Ready(Ok(num_bytes));
+            }
+
+            match this
+                .waiter
+                .poll_wait(&this.ring, io::ErrorKind::BrokenPipe, cx)
+            {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.ring.writer_closed().store(1, Ordering::Release);
+        Poll::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,242 @@
+use crate::ReadError;
+use rusqlite::types::ValueRef;
+use rusqlite::{params_from_iter, Connection, ToSql};
+use serde_json::{Map, Number, Value};
+use std::collections::HashSet;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+/// An error that occurred while moving JSON Lines into or out of SQLite.
+#[derive(Debug, thiserror::Error)]
+pub enum SqliteError {
+    #[error("failed reading JSON Lines")]
+    Read(#[from] ReadError),
+    #[error("SQLite operation failed")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("record {0} is not a JSON object")]
+    NotAnObject(usize),
+    #[error("record {0} has no keys to create the table’s columns from")]
+    NoColumns(usize),
+}
+
+/// How JSON records are stored in a SQLite table by [`to_sqlite`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SqliteLayout<'a> {
+    /// Every key of every record becomes a column, added to the table as it is first seen. Records
+    /// must be JSON objects. Arrays and objects nested inside records are stored as JSON text.
+    ///
+    /// A table that does not exist yet is created with the keys of the first record, so that record
+    /// must have at least one.
+    Columns,
+    /// Each record is stored whole as JSON text in the single column of the given name, to be
+    /// queried with SQLite’s JSON functions.
+    JsonColumn(&'a str),
+}
+
+fn quote_identifier(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// Converts a JSON value into the SQLite value stored for it in [`SqliteLayout::Columns`].
+fn to_sql(value: Value) -> Box<dyn ToSql> {
+    match value {
+        Value::Null => Box::new(rusqlite::types::Null),
+        Value::Bool(b) => Box::new(b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => Box::new(i),
+            None => Box::new(n.as_f64()),
+        },
+        Value::String(s) => Box::new(s),
+        value @ (Value::Array(_) | Value::Object(_)) => Box::new(value.to_string()),
+    }
+}
+
+/// Reads every record from `reader` and inserts it into `table` in the SQLite database at `path`,
+/// creating the database and the table if they do not exist. Returns the number of records
+/// inserted.
+///
+/// All records are inserted in a single transaction, so either all of them are inserted or none
+/// are.
+pub fn to_sqlite<P: AsRef<Path>, R: BufRead>(
+    path: P,
+    table: &str,
+    layout: SqliteLayout<'_>,
+    mut reader: R,
+) -> Result<usize, SqliteError> {
+    let mut connection = Connection::open(path)?;
+    let transaction = connection.transaction()?;
+    let table = quote_identifier(table);
+    let mut num_records = 0;
+
+    match layout {
+        SqliteLayout::Columns => {
+            let mut columns = HashSet::new();
+
+            let mut statement = transaction.prepare(&format!("PRAGMA table_info({})", table))?;
+            for name in statement.query_map([], |row| row.get::<_, String>(1))? {
+                columns.insert(name?);
+            }
+            drop(statement);
+
+            loop {
+                let record = match crate::blocking::read(&mut reader) {
+                    Ok(Value::Object(record)) => record,
+                    Ok(_) => return Err(SqliteError::NotAnObject(num_records)),
+                    Err(ReadError::Eof) => break,
+                    Err(e) => return Err(e.into()),
+                };
+
+                if columns.is_empty() {
+                    if record.is_empty() {
+                        return Err(SqliteError::NoColumns(num_records));
+                    }
+
+                    let names: Vec<_> = record.keys().map(|key| quote_identifier(key)).collect();
+                    transaction.execute_batch(&format!(
+                        "CREATE TABLE {} ({})",
+                        table,
+                        names.join(", ")
+                    ))?;
+                    columns.extend(record.keys().cloned());
+                }
+
+                for key in record.keys() {
+                    if columns.insert(key.clone()) {
+                        transaction.execute_batch(&format!(
+                            "ALTER TABLE {} ADD COLUMN {}",
+                            table,
+                            quote_identifier(key)
+                        ))?;
+                    }
+                }
+
+                if record.is_empty() {
+                    transaction
+                        .prepare_cached(&format!("INSERT INTO {} DEFAULT VALUES", table))?
+                        .execute([])?;
+                    num_records += 1;
+                    continue;
+                }
+
+                let names: Vec<_> = record.keys().map(|key| quote_identifier(key)).collect();
+                let placeholders = vec!["?"; names.len()].join(", ");
+                let values = record.into_iter().map(|(_, value)| to_sql(value));
+
+                transaction
+                    .prepare_cached(&format!(
+                        "INSERT INTO {} ({}) VALUES ({})",
+                        table,
+                        names.join(", "),
+                        placeholders
+                    ))?
+                    .execute(params_from_iter(values))?;
+
+                num_records += 1;
+            }
+        }
+        SqliteLayout::JsonColumn(column) => {
+            let column = quote_identifier(column);
+
+            transaction.execute_batch(&format!(
+                "CREATE TABLE IF NOT EXISTS {} ({} TEXT NOT NULL)",
+                table, column
+            ))?;
+
+            let mut statement =
+                transaction.prepare(&format!("INSERT INTO {} ({}) VALUES (?)", table, column))?;
+
+            loop {
+                let record: Value = match crate::blocking::read(&mut reader) {
+                    Ok(record) => record,
+                    Err(ReadError::Eof) => break,
+                    Err(e) => return Err(e.into()),
+                };
+
+                statement.execute([record.to_string()])?;
+                num_records += 1;
+            }
+        }
+    }
+
+    transaction.commit()?;
+
+    Ok(num_records)
+}
+
+/// The number of rows read ahead of the consumer of [`SqliteRows`].
+const ROW_BUFFER_LEN: usize = 256;
+
+/// An iterator over the rows of a SQLite query as JSON objects, created by [`from_sqlite`].
+#[derive(Debug)]
+pub struct SqliteRows {
+    rows: Receiver<Result<Value, SqliteError>>,
+}
+
+impl Iterator for SqliteRows {
+    type Item = Result<Value, SqliteError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.rows.recv().ok()
+    }
+}
+
+/// Runs `query` against the SQLite database at `path`, yielding each resulting row as a JSON
+/// object keyed by column name, ready to be written as JSON Lines.
+///
+/// Integers and reals become numbers, text becomes strings and blobs become arrays of bytes. Text
+/// holding JSON (for instance a column written with [`SqliteLayout::JsonColumn`]) is left as a
+/// string; use `json(column)` in the query to have SQLite return it as JSON text, which is still a
+/// string, and parse it yourself if needed.
+///
+/// Rows are streamed from a background thread as the iterator is advanced rather than being
+/// collected up front. If the query fails, the iterator yields a single error.
+pub fn from_sqlite<P: AsRef<Path>>(path: P, query: &str) -> SqliteRows {
+    let path = PathBuf::from(path.as_ref());
+    let query = query.to_string();
+    let (sender, receiver) = mpsc::sync_channel(ROW_BUFFER_LEN);
+
+    thread::spawn(move || {
+        let result = (|| {
+            let connection = Connection::open(path)?;
+            let mut statement = connection.prepare(&query)?;
+            let names: Vec<_> = statement
+                .column_names()
+                .into_iter()
+                .map(String::from)
+                .collect();
+            let mut rows = statement.query([])?;
+
+            while let Some(row) = rows.next()? {
+                let mut object = Map::with_capacity(names.len());
+
+                for (i, name) in names.iter().enumerate() {
+                    object.insert(name.clone(), from_sql(row.get_ref(i)?));
+                }
+
+                if sender.send(Ok(Value::Object(object))).is_err() {
+                    break;
+                }
+            }
+
+            Ok(())
+        })();
+
+        if let Err(e) = result {
+            let _ = sender.send(Err(e));
+        }
+    });
+
+    SqliteRows { rows: receiver }
+}
+
+fn from_sql(value: ValueRef<'_>) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(i) => Value::from(i),
+        ValueRef::Real(f) => Number::from_f64(f).map_or(Value::Null, Value::Number),
+        ValueRef::Text(text) => Value::from(String::from_utf8_lossy(text).into_owned()),
+        ValueRef::Blob(blob) => Value::from(blob.to_vec()),
+    }
+}
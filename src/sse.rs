@@ -0,0 +1,121 @@
+use crate::ReadError;
+use serde::de::DeserializeOwned;
+
+/// Reads JSON values from the streaming responses of LLM APIs and other services that send JSON
+/// either as server-sent events, as newline-delimited JSON, or as a mixture of the two.
+///
+/// Each line is handled on its own:
+///
+/// - `data:` prefixes (with or without a following space) are stripped, and the rest of the line
+///   is deserialized.
+/// - A `data: [DONE]` sentinel ends the stream, after which every read returns
+///   [`ReadError::Eof`].
+/// - Blank lines, keep-alive comments (lines starting with `:`), and the `event:`, `id:` and
+///   `retry:` fields are skipped.
+/// - Any other line is deserialized as-is.
+///
+/// Values spread across several `data:` lines of a single event are not joined back together,
+/// since the APIs this is intended for never split them.
+#[derive(Debug)]
+pub struct EventStreamReader<R> {
+    reader: R,
+    is_done: bool,
+}
+
+enum Line<'a> {
+    Data(&'a str),
+    Done,
+    Skip,
+}
+
+fn classify(line: &str) -> Line<'_> {
+    let line = line.trim_end_matches(&['\r', '\n'][..]);
+
+    let data = match line.strip_prefix("data:") {
+        Some(data) => data.strip_prefix(' ').unwrap_or(data),
+        None if line.trim().is_empty() || line.starts_with(':') => return Line::Skip,
+        None if ["event:", "id:", "retry:"]
+            .iter()
+            .any(|field| line.starts_with(field)) =>
+        {
+            return Line::Skip
+        }
+        None => line,
+    };
+
+    match data.trim() {
+        "[DONE]" => Line::Done,
+        "" => Line::Skip,
+        _ => Line::Data(data),
+    }
+}
+
+impl<R> EventStreamReader<R> {
+    /// Creates a new `EventStreamReader` reading from the given reader.
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            is_done: false,
+        }
+    }
+
+    /// Returns whether a `[DONE]` sentinel has been read.
+    pub fn is_done(&self) -> bool {
+        self.is_done
+    }
+
+    /// Consumes the `EventStreamReader`, returning the wrapped reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl<R: std::io::BufRead> EventStreamReader<R> {
+    /// Reads the next JSON value from the stream and deserializes it into a given type.
+    pub fn read<T: DeserializeOwned>(&mut self) -> Result<T, ReadError> {
+        let mut buf = String::new();
+
+        while !self.is_done {
+            buf.clear();
+
+            if self.reader.read_line(&mut buf)? == 0 {
+                break;
+            }
+
+            match classify(&buf) {
+                Line::Data(data) => return Ok(serde_json::from_str(data)?),
+                Line::Done => self.is_done = true,
+                Line::Skip => {}
+            }
+        }
+
+        Err(ReadError::Eof)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R: tokio::io::AsyncBufRead + Unpin> EventStreamReader<R> {
+    /// Reads the next JSON value from the stream and deserializes it into a given type.
+    pub async fn read<T: DeserializeOwned>(&mut self) -> Result<T, ReadError> {
+        use tokio::io::AsyncBufReadExt;
+
+        let mut buf = String::new();
+
+        while !self.is_done {
+            buf.clear();
+
+            if self.reader.read_line(&mut buf).await? == 0 {
+                break;
+            }
+
+            match classify(&buf) {
+                Line::Data(data) => return Ok(serde_json::from_str(data)?),
+                Line::Done => self.is_done = true,
+                Line::Skip => {}
+            }
+        }
+
+        Err(ReadError::Eof)
+    }
+}
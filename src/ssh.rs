@@ -0,0 +1,50 @@
+#[cfg(not(feature = "tokio"))]
+mod imports {
+    pub(super) use std::io::{self, BufReader};
+    pub(super) use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+}
+#[cfg(feature = "tokio")]
+mod imports {
+    pub(super) use std::process::Stdio;
+    pub(super) use tokio::io::{self, BufReader};
+    pub(super) use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+}
+
+use crate::Connection;
+use imports::*;
+
+impl Connection<BufReader<ChildStdout>, ChildStdin> {
+    /// Runs `command` on `host` using the system’s `ssh` client, and creates a new `Connection`
+    /// that writes to the remote command’s `stdin` and reads from its `stdout`. The `ssh` child
+    /// process is returned alongside the `Connection` so that it can be waited on or killed.
+    ///
+    /// `host` is anything `ssh` accepts as a destination, such as `user@example.com` or an alias
+    /// from `~/.ssh/config`. `command` is interpreted by the remote user’s shell.
+    ///
+    /// `ssh` is run without a pseudoterminal, in batch mode (so it never prompts for a password
+    /// and hangs waiting for input that will never come) and with the escape character disabled,
+    /// so that the data passing through it is never mangled. The remote command’s `stderr` is
+    /// inherited.
+    pub fn new_from_ssh(host: &str, command: &str) -> io::Result<(Self, Child)> {
+        let mut child = Command::new("ssh")
+            .args([
+                "-T",
+                "-e",
+                "none",
+                "-o",
+                "BatchMode=yes",
+                "--",
+                host,
+                command,
+            ])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+
+        let missing = || io::Error::other("ssh child process is missing piped stdio");
+        let stdin = child.stdin.take().ok_or_else(missing)?;
+        let stdout = child.stdout.take().ok_or_else(missing)?;
+
+        Ok((Connection::new(BufReader::new(stdout), stdin), child))
+    }
+}
@@ -0,0 +1,115 @@
+//! [`proptest`] strategies for generating JSON Lines, both valid and adversarial, for testing code
+//! that reads JSON Lines from untrusted sources.
+//!
+//! ```no_run
+//! use jsonl::strategies::{adversarial_stream, split_frames};
+//! use proptest::prelude::*;
+//!
+//! proptest! {
+//!     #[test]
+//!     fn never_panics(chunks in split_frames(adversarial_stream())) {
+//!         // Feed `chunks` to the code under test one at a time.
+//!     }
+//! }
+//! ```
+
+use proptest::collection::{hash_map, vec};
+use proptest::prelude::*;
+use serde_json::{Number, Value as Json};
+
+/// Generates arbitrary JSON values, nested up to a few levels deep.
+pub fn json_value() -> impl Strategy<Value = Json> {
+    let leaf = prop_oneof![
+        Just(Json::Null),
+        any::<bool>().prop_map(Json::Bool),
+        any::<i64>().prop_map(Json::from),
+        any::<u64>().prop_map(Json::from),
+        any::<f64>().prop_filter_map("JSON numbers must be finite", |f| {
+            Number::from_f64(f).map(Json::Number)
+        }),
+        any::<String>().prop_map(Json::String),
+    ];
+
+    leaf.prop_recursive(4, 64, 8, |inner| {
+        prop_oneof![
+            vec(inner.clone(), 0..8).prop_map(Json::Array),
+            hash_map(any::<String>(), inner, 0..8)
+                .prop_map(|object| Json::Object(object.into_iter().collect())),
+        ]
+    })
+}
+
+/// Generates a single valid line of JSON Lines, including its trailing newline.
+pub fn valid_line() -> impl Strategy<Value = Vec<u8>> {
+    json_value().prop_map(|value| format!("{}\n", value).into_bytes())
+}
+
+/// Generates a valid JSON Lines stream of up to 16 values.
+pub fn valid_stream() -> impl Strategy<Value = Vec<u8>> {
+    vec(valid_line(), 0..16).prop_map(|lines| lines.concat())
+}
+
+/// Generates a single line, including its trailing newline, that is likely to trip up a careless
+/// reader: valid JSON, or one of very long lines, deeply nested arrays, invalid UTF-8, NUL bytes,
+/// truncated JSON, blank lines and CRLF line endings.
+pub fn adversarial_line() -> impl Strategy<Value = Vec<u8>> {
+    prop_oneof![
+        valid_line(),
+        (1usize << 16..1 << 20).prop_map(|len| format!("\"{}\"\n", "a".repeat(len)).into_bytes()),
+        (1usize..10_000)
+            .prop_map(|depth| format!("{}{}\n", "[".repeat(depth), "]".repeat(depth)).into_bytes()),
+        vec(any::<u8>(), 0..64).prop_map(|mut bytes| {
+            // 0xff never appears in UTF-8.
+            bytes.push(0xff);
+            bytes.push(b'\n');
+            bytes
+        }),
+        Just(b"\0\n".to_vec()),
+        json_value().prop_map(|value| {
+            let json = value.to_string().into_bytes();
+            let mut line = json[..json.len() / 2].to_vec();
+            line.push(b'\n');
+            line
+        }),
+        Just(b"\n".to_vec()),
+        json_value().prop_map(|value| format!("{}\r\n", value).into_bytes()),
+    ]
+}
+
+/// Generates a stream of up to 16 adversarial lines (see [`adversarial_line`]), which may be
+/// missing the final newline.
+pub fn adversarial_stream() -> impl Strategy<Value = Vec<u8>> {
+    (vec(adversarial_line(), 0..16), any::<bool>()).prop_map(|(lines, drop_last_newline)| {
+        let mut stream = lines.concat();
+        if drop_last_newline && stream.last() == Some(&b'\n') {
+            stream.pop();
+        }
+        stream
+    })
+}
+
+/// Splits the streams generated by `streams` into arbitrary chunks, as they might arrive from a
+/// socket, so that lines (and UTF-8 characters) are split across reads.
+pub fn split_frames<S>(streams: S) -> impl Strategy<Value = Vec<Vec<u8>>>
+where
+    S: Strategy<Value = Vec<u8>>,
+{
+    streams.prop_flat_map(|stream| {
+        let len = stream.len();
+
+        vec(0..=len, 0..8).prop_map(move |mut cuts| {
+            cuts.sort_unstable();
+
+            let mut chunks = Vec::with_capacity(cuts.len() + 1);
+            let mut start = 0;
+
+            for cut in cuts {
+                chunks.push(stream[start..cut].to_vec());
+                start = cut;
+            }
+            chunks.push(stream[start..].to_vec());
+
+            chunks
+        })
+    })
+}
@@ -0,0 +1,135 @@
+use crate::{Connection, ReadError, WriteError};
+use futures::{Sink, Stream};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncBufRead, AsyncWrite};
+
+/// A [`Connection`] adapted into a [`Stream`] of the messages read from it and a [`Sink`] for the
+/// messages written to it, created with [`Connection::into_stream_sink`].
+///
+/// This lets a connection be used with the combinators in `StreamExt` and `SinkExt`, in
+/// `select!`, and anywhere else that works with streams and sinks. The stream ends cleanly when the
+/// reader reaches EOF. Split it into separate halves with `StreamExt::split` if reading and writing
+/// happen in different tasks.
+#[derive(Debug)]
+pub struct MessageStream<R: AsyncBufRead, W: AsyncWrite, T> {
+    connection: Connection<R, W>,
+    write_buf: Vec<u8>,
+    write_pos: usize,
+    _message: PhantomData<fn() -> T>,
+}
+
+impl<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin> Connection<R, W> {
+    /// Adapts the `Connection` into a [`Stream`] and [`Sink`] of messages of type `T`; see
+    /// [`MessageStream`].
+    pub fn into_stream_sink<T>(self) -> MessageStream<R, W, T> {
+        MessageStream {
+            connection: self,
+            write_buf: Vec::new(),
+            write_pos: 0,
+            _message: PhantomData,
+        }
+    }
+}
+
+impl<R: AsyncBufRead, W: AsyncWrite, T> MessageStream<R, W, T> {
+    /// Returns a mutable reference to the underlying `Connection`.
+    pub fn get_mut(&mut self) -> &mut Connection<R, W> {
+        &mut self.connection
+    }
+
+    /// Consumes the `MessageStream`, returning the underlying `Connection`. Any messages sent but
+    /// not yet flushed are lost.
+    pub fn into_inner(self) -> Connection<R, W> {
+        self.connection
+    }
+}
+
+impl<R: AsyncBufRead + Unpin, W: AsyncWrite + Unpin, T> MessageStream<R, W, T> {
+    /// Writes out every message sent so far, without flushing the writer.
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), WriteError>> {
+        while self.write_pos < self.write_buf.len() {
+            let remaining = self.write_buf.get(self.write_pos..).unwrap_or_default();
+
+            match Pin::new(self.connection.writer_mut()).poll_write(cx, remaining) {
+                Poll::Ready(Ok(0)) => {
+                    return Poll::Ready(Err(WriteError::Io(std::io::ErrorKind::WriteZero.into())))
+                }
+                Poll::Ready(Ok(num_bytes)) => self.write_pos += num_bytes,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(WriteError::Io(e))),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        self.write_buf.clear();
+        self.write_pos = 0;
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R, W, T> Stream for MessageStream<R, W, T>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+    T: DeserializeOwned,
+{
+    type Item = Result<T, ReadError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.get_mut().connection.poll_read(cx) {
+            Poll::Ready(Err(ReadError::Eof)) => Poll::Ready(None),
+            Poll::Ready(result) => Poll::Ready(Some(result)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<R, W, T, U> Sink<U> for MessageStream<R, W, T>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+    U: Serialize,
+{
+    type Error = WriteError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), WriteError>> {
+        self.get_mut().poll_drain(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: U) -> Result<(), WriteError> {
+        let line = crate::format::encode_line(&item)?;
+        self.get_mut().write_buf.extend_from_slice(&line);
+
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), WriteError>> {
+        let this = self.get_mut();
+
+        match this.poll_drain(cx) {
+            Poll::Ready(Ok(())) => {}
+            other => return other,
+        }
+
+        Pin::new(this.connection.writer_mut())
+            .poll_flush(cx)
+            .map_err(WriteError::Io)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), WriteError>> {
+        let this = self.get_mut();
+
+        match Sink::<U>::poll_flush(Pin::new(&mut *this), cx) {
+            Poll::Ready(Ok(())) => {}
+            other => return other,
+        }
+
+        Pin::new(this.connection.writer_mut())
+            .poll_shutdown(cx)
+            .map_err(WriteError::Io)
+    }
+}
@@ -0,0 +1,390 @@
+use crate::ReadError;
+use serde::de::DeserializeOwned;
+use std::collections::{HashSet, VecDeque};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// The default for [`DirectoryTail::poll_interval`].
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How far back [`DirectoryTail::metrics`] looks when measuring arrival rates, in seconds.
+const RATE_WINDOW_SECS: u64 = 60;
+
+/// How much data a [`DirectoryTail`] has read, and how quickly it has been arriving, as returned
+/// by [`DirectoryTail::metrics`].
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Default)]
+pub struct TailMetrics {
+    /// The number of records read in total.
+    pub records_read: u64,
+    /// The number of bytes read in total, including blank lines.
+    pub bytes_read: u64,
+    /// The average number of records read per second over the last minute.
+    pub records_per_sec: f64,
+    /// The average number of bytes read per second over the last minute.
+    pub bytes_per_sec: f64,
+}
+
+/// How far a [`DirectoryTail`] is behind the end of the data written so far, as returned by
+/// [`DirectoryTail::lag`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct TailLag {
+    /// The number of bytes not yet read, across the current file and every file after it.
+    pub bytes: u64,
+    /// An estimate of the number of records not yet read, from the average size of those read so
+    /// far, or `None` if none have been read yet.
+    pub records: Option<u64>,
+}
+
+/// Counts of what was read during each of the last [`RATE_WINDOW_SECS`] seconds.
+#[derive(Debug)]
+struct ArrivalRate {
+    started: Instant,
+    buckets: VecDeque<(u64, u64, u64)>,
+    records_read: u64,
+    bytes_read: u64,
+}
+
+impl ArrivalRate {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            buckets: VecDeque::new(),
+            records_read: 0,
+            bytes_read: 0,
+        }
+    }
+
+    fn record(&mut self, num_records: u64, num_bytes: u64) {
+        let second = self.started.elapsed().as_secs();
+
+        match self.buckets.back_mut() {
+            Some((s, records, bytes)) if *s == second => {
+                *records += num_records;
+                *bytes += num_bytes;
+            }
+            _ => self.buckets.push_back((second, num_records, num_bytes)),
+        }
+
+        while self
+            .buckets
+            .front()
+            .is_some_and(|(s, _, _)| s + RATE_WINDOW_SECS <= second)
+        {
+            self.buckets.pop_front();
+        }
+
+        self.records_read += num_records;
+        self.bytes_read += num_bytes;
+    }
+
+    fn metrics(&self) -> TailMetrics {
+        let elapsed = self.started.elapsed();
+        let now = elapsed.as_secs();
+        let window = elapsed.as_secs_f64().min(RATE_WINDOW_SECS as f64).max(1.0);
+
+        let (records, bytes) = self
+            .buckets
+            .iter()
+            .filter(|(s, _, _)| s + RATE_WINDOW_SECS > now)
+            .fold((0, 0), |(records, bytes), (_, r, b)| {
+                (records + r, bytes + b)
+            });
+
+        TailMetrics {
+            records_read: self.records_read,
+            bytes_read: self.bytes_read,
+            records_per_sec: records as f64 / window,
+            bytes_per_sec: bytes as f64 / window,
+        }
+    }
+}
+
+/// The order in which a [`DirectoryTail`] reads the files in its directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FileOrder {
+    /// In lexicographic order of file name, which suits files named after a timestamp or a
+    /// sequence number.
+    Name,
+    /// From least to most recently modified, with ties broken by file name.
+    Modified,
+}
+
+#[derive(Debug)]
+struct CurrentFile {
+    path: PathBuf,
+    reader: BufReader<File>,
+    offset: u64,
+}
+
+/// Reads records from every JSON Lines file in a directory as though they were one stream,
+/// following the files as they grow and as new ones appear, in the manner of `tail -F`.
+///
+/// Files are read one at a time in the configured [`FileOrder`]. When the file being read has no
+/// more complete lines, `DirectoryTail` moves on to the next unread file if there is one, and
+/// otherwise waits for either the current file to grow or a new file to appear. A file is never
+/// returned to once it has been left, so files should only be appended to until a newer file has
+/// been created, as with most log rotation schemes. Blank lines are skipped.
+#[derive(Debug)]
+pub struct DirectoryTail {
+    dir: PathBuf,
+    order: FileOrder,
+    extension: Option<OsString>,
+    poll_interval: Duration,
+    done: HashSet<PathBuf>,
+    current: Option<CurrentFile>,
+    buf: Vec<u8>,
+    rate: ArrivalRate,
+}
+
+impl DirectoryTail {
+    /// Creates a new `DirectoryTail` reading every file in `dir` from the beginning, in order of
+    /// file name.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            order: FileOrder::Name,
+            extension: None,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            done: HashSet::new(),
+            current: None,
+            buf: Vec::new(),
+            rate: ArrivalRate::new(),
+        }
+    }
+
+    /// Sets the order in which files are read.
+    pub fn order(mut self, order: FileOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Only reads files with the given extension (such as `"jsonl"`), ignoring all others.
+    pub fn extension<S: Into<OsString>>(mut self, extension: S) -> Self {
+        self.extension = Some(extension.into());
+        self
+    }
+
+    /// Sets how long to wait before checking again for new data when there is none. Defaults to
+    /// 250 milliseconds.
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Resumes reading from `offset` bytes into the file at `path`, skipping every file that comes
+    /// before it. Use this with a position previously returned by [`DirectoryTail::position`] to
+    /// carry on where an earlier `DirectoryTail` left off.
+    pub fn resume_at<P: AsRef<Path>>(mut self, path: P, offset: u64) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        for file in self.list_files()? {
+            if file == path {
+                break;
+            }
+            self.done.insert(file);
+        }
+
+        let mut file = File::open(&path)?;
+        file.seek(SeekFrom::Start(offset))?;
+
+        self.current = Some(CurrentFile {
+            path,
+            reader: BufReader::new(file),
+            offset,
+        });
+        self.buf.clear();
+
+        Ok(self)
+    }
+
+    /// Returns the file currently being read and the offset just past the last record read from
+    /// it.
+    pub fn position(&self) -> Option<(&Path, u64)> {
+        self.current
+            .as_ref()
+            .map(|current| (current.path.as_path(), current.offset))
+    }
+
+    /// Returns how much has been read so far, and the rates at which records and bytes have
+    /// been arriving.
+    pub fn metrics(&self) -> TailMetrics {
+        self.rate.metrics()
+    }
+
+    /// Returns how far behind the end of the data written so far reading is, for alerting on
+    /// ingestion lag. This lists the directory, so should not be called for every record.
+    pub fn lag(&self) -> io::Result<TailLag> {
+        let mut bytes = 0;
+        let current = self.current.as_ref().map(|current| &current.path);
+
+        if let Some(current) = &self.current {
+            let len = fs::metadata(&current.path)?.len();
+            bytes += len.saturating_sub(current.offset);
+        }
+
+        for file in self.list_files()? {
+            if Some(&file) != current && !self.done.contains(&file) {
+                bytes += fs::metadata(&file)?.len();
+            }
+        }
+
+        let records = match (self.rate.records_read, self.rate.bytes_read) {
+            (0, _) | (_, 0) => None,
+            (records_read, bytes_read) => {
+                Some((bytes as f64 * records_read as f64 / bytes_read as f64).round() as u64)
+            }
+        };
+
+        Ok(TailLag { bytes, records })
+    }
+
+    /// Reads the next record, blocking until one is available.
+    pub fn read<T: DeserializeOwned>(&mut self) -> Result<T, ReadError> {
+        let line = self.read_line()?;
+        let line = decode_line(&line).map_err(ReadError::Io)?;
+        serde_json::from_str(line).map_err(ReadError::Deserialize)
+    }
+
+    /// Reads the next non-blank line without decoding or deserializing it, blocking until one is
+    /// available.
+    ///
+    /// The line is read as bytes so that the position moves past it even if it is not valid
+    /// UTF-8, which [`decode_line`] checks for.
+    pub(crate) fn read_line(&mut self) -> Result<Vec<u8>, ReadError> {
+        loop {
+            let current = match &mut self.current {
+                Some(current) => current,
+                None => {
+                    if !self.open_next()? {
+                        thread::sleep(self.poll_interval);
+                    }
+                    continue;
+                }
+            };
+
+            current.reader.read_until(b'\n', &mut self.buf)?;
+
+            if self.buf.ends_with(b"\n") {
+                current.offset += self.buf.len() as u64;
+                if let Some(line) = self.take_line() {
+                    return Ok(line);
+                }
+                continue;
+            }
+
+            // The current file has no more complete lines. A trailing partial line is kept in the
+            // buffer in case the rest of it is still to be written, unless there is a newer file
+            // to move on to.
+            if self.open_next()? {
+                if let Some(line) = self.take_line() {
+                    return Ok(line);
+                }
+            } else {
+                thread::sleep(self.poll_interval);
+            }
+        }
+    }
+
+    /// Takes the buffered line, leaving the buffer empty, or returns `None` if the line is blank.
+    fn take_line(&mut self) -> Option<Vec<u8>> {
+        if self.buf.iter().all(u8::is_ascii_whitespace) {
+            self.rate.record(0, self.buf.len() as u64);
+            self.buf.clear();
+            None
+        } else {
+            self.rate.record(1, self.buf.len() as u64);
+            Some(mem::take(&mut self.buf))
+        }
+    }
+
+    /// Moves on to the first unread file, returning whether there was one.
+    fn open_next(&mut self) -> io::Result<bool> {
+        let current = self.current.as_ref().map(|current| &current.path);
+        let next = self
+            .list_files()?
+            .into_iter()
+            .find(|file| Some(file) != current && !self.done.contains(file));
+
+        let next = match next {
+            Some(next) => next,
+            None => return Ok(false),
+        };
+
+        let reader = BufReader::new(File::open(&next)?);
+
+        if let Some(previous) = self.current.replace(CurrentFile {
+            path: next,
+            reader,
+            offset: 0,
+        }) {
+            self.done.insert(previous.path);
+        }
+
+        Ok(true)
+    }
+
+    fn list_files(&self) -> io::Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let path = entry.path();
+
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+
+            if let Some(extension) = &self.extension {
+                if path.extension() != Some(extension.as_os_str()) {
+                    continue;
+                }
+            }
+
+            let modified = match self.order {
+                FileOrder::Name => SystemTime::UNIX_EPOCH,
+                FileOrder::Modified => entry.metadata()?.modified()?,
+            };
+
+            files.push((modified, path));
+        }
+
+        files.sort();
+
+        Ok(files.into_iter().map(|(_, path)| path).collect())
+    }
+}
+
+/// Checks that a line read by [`DirectoryTail::read_line`] is valid UTF-8.
+pub(crate) fn decode_line(line: &[u8]) -> io::Result<&str> {
+    std::str::from_utf8(line)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "line is not valid UTF-8"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_utf8_is_skipped_past() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("0.jsonl");
+        fs::write(&path, b"1\n\"\xff\"\n2\n")?;
+
+        let mut tail = DirectoryTail::new(dir.path());
+
+        assert_eq!(tail.read::<u32>()?, 1);
+        assert!(
+            matches!(tail.read::<String>(), Err(ReadError::Io(e)) if e.kind() == io::ErrorKind::InvalidData)
+        );
+        assert_eq!(tail.position(), Some((path.as_path(), 6)));
+        assert_eq!(tail.read::<u32>()?, 2);
+        assert_eq!(tail.position(), Some((path.as_path(), 8)));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,180 @@
+use serde_json::Value;
+use std::io::{self, IsTerminal, Write};
+
+/// What [`TerminalWriter`] does when the writer it wraps turns out to be a terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TerminalMode {
+    /// Writes JSON Lines unchanged, as if the writer were not a terminal.
+    Plain,
+    /// Expands each line over multiple indented lines so it can be read by a human.
+    Pretty,
+    /// Expands each line like [`TerminalMode::Pretty`], and highlights it with ANSI colours.
+    Colorize,
+    /// Fails every write with an error rather than spewing protocol traffic into an interactive
+    /// shell.
+    Refuse,
+}
+
+/// A writer that changes how JSON Lines are written when its output is a terminal, for CLI tools
+/// that normally speak JSON Lines to another program over stdio but are sometimes run by hand.
+///
+/// When the wrapped writer is not a terminal every write passes through untouched, so the same
+/// program keeps producing valid JSON Lines when piped.
+#[derive(Debug)]
+pub struct TerminalWriter<W: Write> {
+    inner: W,
+    mode: TerminalMode,
+    buf: Vec<u8>,
+}
+
+impl<W: Write + IsTerminal> TerminalWriter<W> {
+    /// Creates a new `TerminalWriter` that applies `mode` if `inner` is a terminal.
+    pub fn new(inner: W, mode: TerminalMode) -> Self {
+        let mode = if inner.is_terminal() {
+            mode
+        } else {
+            TerminalMode::Plain
+        };
+
+        Self::with_mode(inner, mode)
+    }
+}
+
+impl<W: Write> TerminalWriter<W> {
+    /// Creates a new `TerminalWriter` that applies `mode` unconditionally, for writers which cannot
+    /// tell whether they are a terminal.
+    pub fn with_mode(inner: W, mode: TerminalMode) -> Self {
+        Self {
+            inner,
+            mode,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Returns the mode in effect, which is [`TerminalMode::Plain`] if the writer turned out not to
+    /// be a terminal.
+    pub fn mode(&self) -> TerminalMode {
+        self.mode
+    }
+
+    /// Consumes the `TerminalWriter`, returning the wrapped writer. Any incomplete line that has
+    /// not been flushed is lost.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    fn write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        let value = std::str::from_utf8(line)
+            .ok()
+            .and_then(|line| serde_json::from_str::<Value>(line).ok());
+
+        // Anything that is not a single JSON value is written unchanged, since it can only be made
+        // harder to read by reformatting it.
+        let value = match value {
+            Some(value) => value,
+            None => return self.inner.write_all(line),
+        };
+
+        let formatted = match self.mode {
+            TerminalMode::Colorize => {
+                let mut formatted = String::new();
+                colorize(&value, 0, &mut formatted);
+                formatted
+            }
+            _ => serde_json::to_string_pretty(&value)?,
+        };
+
+        self.inner.write_all(formatted.as_bytes())
+    }
+}
+
+impl<W: Write> Write for TerminalWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.mode {
+            TerminalMode::Plain => return self.inner.write(buf),
+            TerminalMode::Refuse => {
+                return Err(io::Error::other(
+                    "refusing to write JSON Lines to a terminal",
+                ))
+            }
+            TerminalMode::Pretty | TerminalMode::Colorize => {}
+        }
+
+        self.buf.extend_from_slice(buf);
+
+        while let Some(i) = self.buf.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buf.drain(..=i).collect();
+            self.write_line(line.get(..i).unwrap_or_default())?;
+            self.inner.write_all(b"\n")?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buf.is_empty() {
+            let line = std::mem::take(&mut self.buf);
+            self.inner.write_all(&line)?;
+        }
+
+        self.inner.flush()
+    }
+}
+
+const KEY: &str = "\x1b[1;34m";
+const STRING: &str = "\x1b[32m";
+const NUMBER: &str = "\x1b[36m";
+const LITERAL: &str = "\x1b[35m";
+const RESET: &str = "\x1b[0m";
+
+/// Writes `value` to `out` indented the same way as [`serde_json::to_string_pretty`], with ANSI
+/// colours for keys and each kind of scalar.
+fn colorize(value: &Value, indent: usize, out: &mut String) {
+    let painted = |color: &str, text: &str, out: &mut String| {
+        out.push_str(color);
+        out.push_str(text);
+        out.push_str(RESET);
+    };
+
+    match value {
+        Value::Null | Value::Bool(_) => painted(LITERAL, &value.to_string(), out),
+        Value::Number(n) => painted(NUMBER, &n.to_string(), out),
+        Value::String(_) => painted(STRING, &value.to_string(), out),
+        Value::Array(values) if values.is_empty() => out.push_str("[]"),
+        Value::Object(object) if object.is_empty() => out.push_str("{}"),
+        Value::Array(values) => {
+            out.push('[');
+
+            for (i, value) in values.iter().enumerate() {
+                out.push_str(if i == 0 { "\n" } else { ",\n" });
+                push_indent(indent + 1, out);
+                colorize(value, indent + 1, out);
+            }
+
+            out.push('\n');
+            push_indent(indent, out);
+            out.push(']');
+        }
+        Value::Object(object) => {
+            out.push('{');
+
+            for (i, (key, value)) in object.iter().enumerate() {
+                out.push_str(if i == 0 { "\n" } else { ",\n" });
+                push_indent(indent + 1, out);
+                painted(KEY, &Value::from(key.as_str()).to_string(), out);
+                out.push_str(": ");
+                colorize(value, indent + 1, out);
+            }
+
+            out.push('\n');
+            push_indent(indent, out);
+            out.push('}');
+        }
+    }
+}
+
+fn push_indent(indent: usize, out: &mut String) {
+    for _ in 0..indent {
+        out.push_str("  ");
+    }
+}
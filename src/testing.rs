@@ -0,0 +1,176 @@
+//! Golden-file tests for protocols spoken over JSON Lines.
+//!
+//! A transcript is a JSON Lines file recording a conversation with the code under test, one message
+//! per line, each wrapped in an object saying which way it goes:
+//!
+//! ```text
+//! {"send": {"method": "ping"}}
+//! {"expect": {"result": "pong"}}
+//! ```
+//!
+//! [`Transcript::drive`] plays the part of the other side of the conversation: it writes each
+//! `send` message to the code under test, and reads a message back for each `expect`, checking that
+//! it is equal to the expected one.
+
+use crate::{Connection, ReadError, WriteError};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+#[cfg(not(feature = "tokio"))]
+use std::io::{BufRead, Write};
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncBufRead as BufRead, AsyncWrite as Write};
+
+/// A single message in a [`Transcript`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Step {
+    /// A message to send to the code under test.
+    Send(Value),
+    /// A message the code under test is expected to send.
+    Expect(Value),
+}
+
+/// An error that occurred while driving a [`Transcript`], including the code under test not
+/// behaving as expected.
+#[derive(Debug, thiserror::Error)]
+pub enum TranscriptError {
+    #[error("failed reading from connection at step {step}")]
+    Read {
+        step: usize,
+        #[source]
+        source: ReadError,
+    },
+    #[error("failed writing to connection at step {step}")]
+    Write {
+        step: usize,
+        #[source]
+        source: WriteError,
+    },
+    #[error("step {step}: expected {expected}, but received {actual}")]
+    Mismatch {
+        step: usize,
+        expected: Value,
+        actual: Value,
+    },
+}
+
+/// A recorded conversation to replay against the code under test. See the [module
+/// documentation](self) for the format.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Transcript {
+    steps: Vec<Step>,
+}
+
+impl Transcript {
+    /// Creates a new `Transcript` from a list of steps.
+    pub fn new(steps: Vec<Step>) -> Self {
+        Self { steps }
+    }
+
+    /// Loads a transcript from the file at `path`. Blank lines are skipped.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Loads a transcript from a reader. Blank lines are skipped.
+    pub fn from_reader<R: io::BufRead>(reader: R) -> io::Result<Self> {
+        let mut steps = Vec::new();
+
+        for line in reader.lines() {
+            let line = line?;
+
+            if !line.trim().is_empty() {
+                steps.push(serde_json::from_str(&line)?);
+            }
+        }
+
+        Ok(Self { steps })
+    }
+
+    /// Returns the steps of the transcript.
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl Transcript {
+    /// Plays the transcript over `connection`, which should be connected to the code under test.
+    /// Stops at the first message that differs from the transcript.
+    pub fn drive<R: BufRead, W: Write>(
+        &self,
+        connection: &mut Connection<R, W>,
+    ) -> Result<(), TranscriptError> {
+        for (step, message) in self.steps.iter().enumerate() {
+            match message {
+                Step::Send(message) => {
+                    connection
+                        .write(message)
+                        .and_then(|()| connection.flush().map_err(WriteError::Io))
+                        .map_err(|source| TranscriptError::Write { step, source })?;
+                }
+                Step::Expect(expected) => {
+                    let actual: Value = connection
+                        .read()
+                        .map_err(|source| TranscriptError::Read { step, source })?;
+                    check(step, expected, actual)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Transcript {
+    /// Plays the transcript over `connection`, which should be connected to the code under test.
+    /// Stops at the first message that differs from the transcript.
+    pub async fn drive<R: BufRead + Unpin, W: Write + Unpin>(
+        &self,
+        connection: &mut Connection<R, W>,
+    ) -> Result<(), TranscriptError> {
+        for (step, message) in self.steps.iter().enumerate() {
+            match message {
+                Step::Send(message) => {
+                    connection
+                        .write(message)
+                        .await
+                        .map_err(|source| TranscriptError::Write { step, source })?;
+                    connection
+                        .flush()
+                        .await
+                        .map_err(|e| TranscriptError::Write {
+                            step,
+                            source: WriteError::Io(e),
+                        })?;
+                }
+                Step::Expect(expected) => {
+                    let actual: Value = connection
+                        .read()
+                        .await
+                        .map_err(|source| TranscriptError::Read { step, source })?;
+                    check(step, expected, actual)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn check(step: usize, expected: &Value, actual: Value) -> Result<(), TranscriptError> {
+    if *expected != actual {
+        return Err(TranscriptError::Mismatch {
+            step,
+            expected: expected.clone(),
+            actual,
+        });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,129 @@
+use crate::ReadError;
+use serde_json::Value;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::ops::Range;
+
+/// Once the binary search has narrowed the start of the range down to this many bytes, the rest is
+/// scanned line by line.
+const SCAN_THRESHOLD: u64 = 64 * 1024;
+
+/// An iterator over the records of a JSON Lines file whose timestamps fall within a range, created
+/// by [`TimeRange::new`].
+#[derive(Debug)]
+pub struct TimeRange<R, K, F> {
+    reader: BufReader<R>,
+    range: Range<K>,
+    timestamp_fn: F,
+    buf: String,
+    done: bool,
+}
+
+impl<R, K, F> TimeRange<R, K, F>
+where
+    R: Read + Seek,
+    K: Ord,
+    F: FnMut(&Value) -> Option<K>,
+{
+    /// Creates a new `TimeRange` yielding the records of `reader` whose timestamp, as returned by
+    /// `timestamp_fn`, is within `range`.
+    ///
+    /// The records must be in order of timestamp, as is the case for most logs. The first record in
+    /// the range is found with a binary search over byte offsets into the file, so only a handful of
+    /// records are read before reaching it, however large the file is. Reading stops at the first
+    /// record at or after the end of the range. Records for which `timestamp_fn` returns `None` are
+    /// skipped.
+    ///
+    /// Timestamps can be anything that is ordered, for instance seconds since the Unix epoch or
+    /// RFC 3339 strings that all use the same offset.
+    pub fn new(mut reader: R, range: Range<K>, mut timestamp_fn: F) -> io::Result<Self> {
+        let mut lo = 0;
+        let mut hi = reader.seek(SeekFrom::End(0))?;
+        let mut reader = BufReader::new(reader);
+        let mut buf = String::new();
+
+        while hi - lo > SCAN_THRESHOLD {
+            let mid = lo + (hi - lo) / 2;
+            reader.seek(SeekFrom::Start(mid))?;
+            reader.read_line(&mut buf)?;
+
+            let mut before_start = false;
+            loop {
+                buf.clear();
+
+                if reader.read_line(&mut buf)? == 0 {
+                    break;
+                }
+
+                let timestamp = serde_json::from_str(&buf)
+                    .ok()
+                    .and_then(|record: Value| timestamp_fn(&record));
+
+                if let Some(timestamp) = timestamp {
+                    before_start = timestamp < range.start;
+                    break;
+                }
+            }
+
+            if before_start {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+
+        reader.seek(SeekFrom::Start(lo))?;
+        buf.clear();
+
+        // The line containing `lo` is known to be before the start of the range, unless `lo` is the
+        // start of the file.
+        if lo > 0 {
+            reader.read_line(&mut buf)?;
+        }
+
+        Ok(Self {
+            reader,
+            range,
+            timestamp_fn,
+            buf,
+            done: false,
+        })
+    }
+}
+
+impl<R, K, F> Iterator for TimeRange<R, K, F>
+where
+    R: Read,
+    K: Ord,
+    F: FnMut(&Value) -> Option<K>,
+{
+    type Item = Result<Value, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            self.buf.clear();
+
+            match self.reader.read_line(&mut self.buf) {
+                Ok(0) => self.done = true,
+                Ok(_) => {}
+                Err(e) => return Some(Err(e.into())),
+            }
+
+            if self.done || self.buf.trim().is_empty() {
+                continue;
+            }
+
+            let record: Value = match serde_json::from_str(&self.buf) {
+                Ok(record) => record,
+                Err(e) => return Some(Err(e.into())),
+            };
+
+            match (self.timestamp_fn)(&record) {
+                Some(timestamp) if timestamp >= self.range.end => self.done = true,
+                Some(timestamp) if timestamp >= self.range.start => return Some(Ok(record)),
+                _ => {}
+            }
+        }
+
+        None
+    }
+}
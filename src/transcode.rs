@@ -0,0 +1,64 @@
+use crate::{ReadError, WriteError};
+use std::io::{BufRead, Write};
+
+/// An error that occurred while transcoding with [`transcode`].
+#[derive(Debug, thiserror::Error)]
+pub enum TranscodeError {
+    #[error("failed reading line {line}")]
+    Read {
+        line: usize,
+        #[source]
+        source: ReadError,
+    },
+    #[error("failed writing transcoded line")]
+    Write(#[from] WriteError),
+}
+
+/// Validates every line read from `reader` and writes it to `writer` in normalized form, without
+/// insignificant whitespace, returning the number of lines transcoded.
+///
+/// Each line is streamed straight from the parser to the serializer, without building a
+/// `serde_json::Value` or any typed structure, so this is much faster than reading and writing
+/// `Value`s when linting or normalizing large files. Object keys are kept in their original order;
+/// see [`canonicalize`](crate::canonicalize) to sort them as well.
+///
+/// Transcoding stops at the first invalid line, reporting its line number, counting from one.
+pub fn transcode<R: BufRead, W: Write>(
+    mut reader: R,
+    mut writer: W,
+) -> Result<usize, TranscodeError> {
+    let mut line = String::new();
+    let mut out = Vec::new();
+    let mut num_lines = 0;
+
+    loop {
+        line.clear();
+
+        let read_error = |source| TranscodeError::Read {
+            line: num_lines + 1,
+            source,
+        };
+
+        if reader
+            .read_line(&mut line)
+            .map_err(|e| read_error(ReadError::Io(e)))?
+            == 0
+        {
+            return Ok(num_lines);
+        }
+
+        out.clear();
+
+        let mut deserializer = serde_json::Deserializer::from_str(&line);
+        let mut serializer = serde_json::Serializer::new(&mut out);
+
+        serde_transcode::transcode(&mut deserializer, &mut serializer)
+            .and_then(|()| deserializer.end())
+            .map_err(|e| read_error(ReadError::Deserialize(e)))?;
+
+        out.push(b'\n');
+        writer.write_all(&out).map_err(WriteError::Io)?;
+
+        num_lines += 1;
+    }
+}
@@ -0,0 +1,146 @@
+use crate::blocking::StdioTransport;
+use crate::{Connection, ServerStream};
+use std::convert::Infallible;
+use tokio::io::{self, AsyncBufRead as BufRead, AsyncWrite as Write, BufReader, Stdin, Stdout};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf, ReadHalf, WriteHalf};
+use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::{unix, UnixStream};
+use tokio::process::{Child, ChildStdin, ChildStdout};
+
+/// A byte stream that a [`Connection`] can be created from with [`Connection::from_transport`].
+///
+/// Implement this to speak JSON Lines over a transport this crate does not know about, such as a
+/// shared memory ring, RDMA or a test double, without wrapping it to fit [`Connection::new`]. It is
+/// implemented for the streams this crate connects with, and for any pair of a reader and writer.
+///
+/// This is the asynchronous `Transport` used with the `tokio` feature. The `std`-based one stays
+/// available as [`blocking::Transport`](crate::blocking::Transport).
+pub trait Transport {
+    /// The half of the transport that lines are read from.
+    type Reader: BufRead;
+    /// The half of the transport that lines are written to.
+    type Writer: Write;
+    /// The error splitting the transport can fail with, which is [`Infallible`] for transports
+    /// that cannot fail.
+    type Error;
+
+    /// Splits the transport into the halves used for reading and for writing.
+    fn split(self) -> Result<(Self::Reader, Self::Writer), Self::Error>;
+}
+
+impl<R: BufRead, W: Write> Transport for (R, W) {
+    type Reader = R;
+    type Writer = W;
+    type Error = Infallible;
+
+    fn split(self) -> Result<(R, W), Infallible> {
+        Ok(self)
+    }
+}
+
+impl<R: BufRead, W: Write> Connection<R, W> {
+    /// Creates a new `Connection` from any [`Transport`].
+    pub fn from_transport<T>(transport: T) -> Result<Self, T::Error>
+    where
+        T: Transport<Reader = R, Writer = W>,
+    {
+        let (reader, writer) = transport.split()?;
+        Ok(Self::new(reader, writer))
+    }
+
+    /// Creates a new `Connection` from a [`Transport`] that cannot fail to split.
+    pub(crate) fn from_infallible_transport<T>(transport: T) -> Self
+    where
+        T: Transport<Reader = R, Writer = W, Error = Infallible>,
+    {
+        match transport.split() {
+            Ok((reader, writer)) => Self::new(reader, writer),
+            Err(never) => match never {},
+        }
+    }
+}
+
+impl Transport for StdioTransport {
+    type Reader = BufReader<Stdin>;
+    type Writer = Stdout;
+    type Error = Infallible;
+
+    fn split(self) -> Result<(Self::Reader, Self::Writer), Infallible> {
+        Ok((BufReader::new(io::stdin()), io::stdout()))
+    }
+}
+
+/// A child process, spawned with its `stdin` and `stdout` piped, whose `stdout` is read from and
+/// whose `stdin` is written to.
+impl<'a> Transport for &'a mut Child {
+    type Reader = BufReader<&'a mut ChildStdout>;
+    type Writer = &'a mut ChildStdin;
+    type Error = io::Error;
+
+    fn split(self) -> io::Result<(Self::Reader, Self::Writer)> {
+        match (self.stdout.as_mut(), self.stdin.as_mut()) {
+            (Some(stdout), Some(stdin)) => Ok((BufReader::new(stdout), stdin)),
+            _ => Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "child process has no piped stdin or stdout",
+            )),
+        }
+    }
+}
+
+impl Transport for ServerStream {
+    type Reader = BufReader<ServerStream>;
+    type Writer = ServerStream;
+    type Error = Infallible;
+
+    fn split(self) -> Result<(Self::Reader, Self::Writer), Infallible> {
+        Ok((BufReader::new(self.clone()), self))
+    }
+}
+
+impl Transport for TcpStream {
+    type Reader = BufReader<OwnedReadHalf>;
+    type Writer = OwnedWriteHalf;
+    type Error = Infallible;
+
+    fn split(self) -> Result<(Self::Reader, Self::Writer), Infallible> {
+        let (read_half, write_half) = self.into_split();
+        Ok((BufReader::new(read_half), write_half))
+    }
+}
+
+impl<'a> Transport for &'a mut TcpStream {
+    type Reader = BufReader<ReadHalf<'a>>;
+    type Writer = WriteHalf<'a>;
+    type Error = Infallible;
+
+    fn split(self) -> Result<(Self::Reader, Self::Writer), Infallible> {
+        let (read_half, write_half) = TcpStream::split(self);
+        Ok((BufReader::new(read_half), write_half))
+    }
+}
+
+#[cfg(unix)]
+impl Transport for UnixStream {
+    type Reader = BufReader<unix::OwnedReadHalf>;
+    type Writer = unix::OwnedWriteHalf;
+    type Error = Infallible;
+
+    fn split(self) -> Result<(Self::Reader, Self::Writer), Infallible> {
+        let (read_half, write_half) = self.into_split();
+        Ok((BufReader::new(read_half), write_half))
+    }
+}
+
+#[cfg(unix)]
+impl<'a> Transport for &'a mut UnixStream {
+    type Reader = BufReader<unix::ReadHalf<'a>>;
+    type Writer = unix::WriteHalf<'a>;
+    type Error = Infallible;
+
+    fn split(self) -> Result<(Self::Reader, Self::Writer), Infallible> {
+        let (read_half, write_half) = UnixStream::split(self);
+        Ok((BufReader::new(read_half), write_half))
+    }
+}
@@ -0,0 +1,237 @@
+//! Serde adapters for types that JSON has no good representation of, for use with
+//! `#[serde(with = "…")]`, so that they survive the trip through tools that read JSON Lines with
+//! other languages’ parsers.
+//!
+//! Most JSON parsers read numbers as doubles, so integers beyond 2<sup>53</sup> and decimals are
+//! written as strings. Deserializing accepts what other producers commonly write as well as what
+//! these adapters write, such as plain numbers for integers and uppercase UUIDs.
+//!
+//! ```
+//! use serde::{Deserialize, Serialize};
+//! use std::time::{Duration, SystemTime};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Transfer {
+//!     #[serde(with = "jsonl::types::u128_string")]
+//!     amount: u128,
+//!     #[serde(with = "jsonl::types::rfc3339")]
+//!     at: SystemTime,
+//! }
+//!
+//! let transfer = Transfer {
+//!     amount: u128::MAX,
+//!     at: SystemTime::UNIX_EPOCH + Duration::from_millis(1_714_564_800_250),
+//! };
+//!
+//! assert_eq!(
+//!     serde_json::to_string(&transfer)?,
+//!     r#"{"amount":"340282366920938463463374607431768211455","at":"2024-05-01T12:00:00.250Z"}"#,
+//! );
+//! # Ok::<_, serde_json::Error>(())
+//! ```
+
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serializer};
+use std::convert::TryFrom;
+use std::fmt;
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+/// Reads an integer from either a string or a number, including numbers of up to 128 bits from
+/// deserializers that hand them over as such.
+struct IntVisitor<T>(PhantomData<T>);
+
+impl<'de, T> Visitor<'de> for IntVisitor<T>
+where
+    T: FromStr + TryFrom<u64> + TryFrom<i64> + TryFrom<u128> + TryFrom<i128>,
+{
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("an integer or a string holding one")
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<T, E> {
+        T::try_from(v).map_err(|_| E::invalid_value(de::Unexpected::Unsigned(v), &self))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<T, E> {
+        T::try_from(v).map_err(|_| E::invalid_value(de::Unexpected::Signed(v), &self))
+    }
+
+    fn visit_u128<E: de::Error>(self, v: u128) -> Result<T, E> {
+        T::try_from(v)
+            .map_err(|_| E::invalid_value(de::Unexpected::Other("128-bit integer"), &self))
+    }
+
+    fn visit_i128<E: de::Error>(self, v: i128) -> Result<T, E> {
+        T::try_from(v)
+            .map_err(|_| E::invalid_value(de::Unexpected::Other("128-bit integer"), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<T, E> {
+        v.parse()
+            .map_err(|_| E::invalid_value(de::Unexpected::Str(v), &self))
+    }
+}
+
+/// Reads a string and parses it with `parse`, failing with `expected` in the message.
+fn deserialize_parsed<'de, D, T, F>(
+    deserializer: D,
+    expected: &'static str,
+    parse: F,
+) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    F: FnOnce(&str) -> Option<T>,
+{
+    let s = String::deserialize(deserializer)?;
+    parse(&s).ok_or_else(|| de::Error::invalid_value(de::Unexpected::Str(&s), &expected))
+}
+
+/// Writes a `u128` as a decimal string, such as `"340282366920938463463374607431768211455"`, and
+/// reads it from either a string or a number.
+///
+/// `serde_json` only hands over numbers that fit in 64 bits as integers, failing on larger ones
+/// rather than rounding them, so values beyond 64 bits are only read from strings, as this writes
+/// them.
+pub mod u128_string {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(n: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(n)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u128, D::Error> {
+        deserializer.deserialize_any(IntVisitor(PhantomData))
+    }
+}
+
+/// Writes an `i128` as a decimal string, such as `"-170141183460469231731687303715884105728"`,
+/// and reads it from either a string or a number.
+///
+/// `serde_json` only hands over numbers that fit in 64 bits as integers, failing on larger ones
+/// rather than rounding them, so values beyond 64 bits are only read from strings, as this writes
+/// them.
+pub mod i128_string {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(n: &i128, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(n)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i128, D::Error> {
+        deserializer.deserialize_any(IntVisitor(PhantomData))
+    }
+}
+
+/// Writes a [`rust_decimal::Decimal`] as a string, such as `"12.50"`, keeping its scale, and reads
+/// it from a string in plain or scientific notation or from an integer.
+#[cfg(feature = "rust_decimal")]
+pub mod decimal_string {
+    use super::*;
+    use rust_decimal::Decimal;
+
+    pub fn serialize<S: Serializer>(d: &Decimal, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(d)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Decimal, D::Error> {
+        struct DecimalVisitor;
+
+        impl<'de> Visitor<'de> for DecimalVisitor {
+            type Value = Decimal;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a decimal number in a string, or an integer")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Decimal, E> {
+                Ok(Decimal::from(v))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Decimal, E> {
+                Ok(Decimal::from(v))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Decimal, E> {
+                Decimal::from_str(v)
+                    .or_else(|_| Decimal::from_scientific(v))
+                    .map_err(|_| E::invalid_value(de::Unexpected::Str(v), &self))
+            }
+        }
+
+        deserializer.deserialize_any(DecimalVisitor)
+    }
+}
+
+/// Writes a [`SystemTime`](std::time::SystemTime) as an RFC 3339 timestamp in UTC, such as
+/// `2024-05-01T12:00:00.250Z`, and reads it from an RFC 3339 timestamp with any offset.
+///
+/// Fractional seconds are written in groups of three digits, only as many as are needed, as
+/// [`TimestampNormalizer`](crate::TimestampNormalizer) does. Times outside the years 0 to 9999,
+/// which RFC 3339 cannot represent, fail to serialize.
+pub mod rfc3339 {
+    use super::*;
+    use crate::datetime::{format_rfc3339, parse_rfc3339};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    /// The range of seconds since the Unix epoch that RFC 3339 can represent.
+    const SECS_RANGE: std::ops::Range<i64> = -62_167_219_200..253_402_300_800;
+
+    pub fn serialize<S: Serializer>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+        let (secs, nanos) = match time.duration_since(UNIX_EPOCH) {
+            Ok(since) => (i64::try_from(since.as_secs()).ok(), since.subsec_nanos()),
+            Err(e) => {
+                let before = e.duration();
+                let secs = i64::try_from(before.as_secs()).ok().map(|secs| -secs);
+
+                match before.subsec_nanos() {
+                    0 => (secs, 0),
+                    nanos => (secs.map(|secs| secs - 1), 1_000_000_000 - nanos),
+                }
+            }
+        };
+
+        match secs {
+            Some(secs) if SECS_RANGE.contains(&secs) => {
+                serializer.collect_str(&format_rfc3339(secs, nanos))
+            }
+            _ => Err(serde::ser::Error::custom(
+                "time is outside the range of RFC 3339",
+            )),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SystemTime, D::Error> {
+        deserialize_parsed(deserializer, "an RFC 3339 timestamp", |s| {
+            let (secs, nanos) = parse_rfc3339(s)?;
+            let since = Duration::new(secs.unsigned_abs(), 0);
+
+            let whole = if secs >= 0 {
+                UNIX_EPOCH.checked_add(since)
+            } else {
+                UNIX_EPOCH.checked_sub(since)
+            };
+
+            whole?.checked_add(Duration::from_nanos(u64::from(nanos)))
+        })
+    }
+}
+
+/// Writes a [`uuid::Uuid`] in its lowercase hyphenated form, such as
+/// `"67e55044-10b1-426f-9247-bb680e5fe0c8"`, and reads it in any of the forms `uuid` parses,
+/// whatever its case.
+#[cfg(feature = "uuid")]
+pub mod uuid_lowercase {
+    use super::*;
+    use uuid::Uuid;
+
+    pub fn serialize<S: Serializer>(uuid: &Uuid, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&uuid.hyphenated())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Uuid, D::Error> {
+        deserialize_parsed(deserializer, "a UUID", |s| Uuid::parse_str(s).ok())
+    }
+}
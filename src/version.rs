@@ -0,0 +1,217 @@
+#[cfg(not(feature = "tokio"))]
+use std::io::{BufRead, Write};
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncBufRead as BufRead, AsyncWrite as Write};
+
+use crate::map::{ReadMap, WriteMap};
+use crate::{Connection, MappedConnection, ReadError, WriteError};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+type Migration = Box<dyn FnMut(Value) -> Value + Send>;
+type Negotiated<R, W, In, Out> = (MappedConnection<R, W, In, Out>, u32);
+
+/// An error that occurred while agreeing on a protocol version with a peer.
+#[derive(Debug, thiserror::Error)]
+pub enum VersionError {
+    #[error("failed reading peer’s protocol versions")]
+    Read(#[from] ReadError),
+    #[error("failed writing protocol versions")]
+    Write(#[from] WriteError),
+    #[error("no protocol version in common: we speak {ours:?}, peer speaks {theirs:?}")]
+    Incompatible {
+        ours: RangeInclusive<u32>,
+        theirs: RangeInclusive<u32>,
+    },
+}
+
+/// The first line each side sends when negotiating a protocol version.
+#[derive(Serialize, Deserialize)]
+struct Hello {
+    protocol_version: u32,
+    min_protocol_version: u32,
+}
+
+/// Lets peers speaking different versions of a protocol interoperate, by translating messages
+/// between the version agreed on with the peer and the current version the rest of the program
+/// uses.
+///
+/// Each version is one more than the last. Register a function to upgrade messages from every
+/// older version supported to the next, and one to downgrade them back; the oldest version
+/// reachable both ways is the oldest supported. [`Versioning::negotiate`] then agrees on the
+/// newest version both peers speak, and chains the functions as needed on every message read and
+/// written.
+///
+/// Messages are translated as [`serde_json::Value`]s, since the Rust types for old versions
+/// usually no longer exist.
+pub struct Versioning {
+    current: u32,
+    upgrades: BTreeMap<u32, Migration>,
+    downgrades: BTreeMap<u32, Migration>,
+}
+
+impl Versioning {
+    /// Creates a new `Versioning` for a protocol whose current version is `current`, with no older
+    /// versions supported yet.
+    pub fn new(current: u32) -> Self {
+        Self {
+            current,
+            upgrades: BTreeMap::new(),
+            downgrades: BTreeMap::new(),
+        }
+    }
+
+    /// Registers `f` to upgrade messages from version `from` to version `from + 1`.
+    pub fn upgrade<F>(mut self, from: u32, f: F) -> Self
+    where
+        F: FnMut(Value) -> Value + Send + 'static,
+    {
+        self.upgrades.insert(from, Box::new(f));
+        self
+    }
+
+    /// Registers `f` to downgrade messages from version `to + 1` to version `to`.
+    pub fn downgrade<F>(mut self, to: u32, f: F) -> Self
+    where
+        F: FnMut(Value) -> Value + Send + 'static,
+    {
+        self.downgrades.insert(to, Box::new(f));
+        self
+    }
+
+    /// Returns the range of protocol versions supported.
+    pub fn supported(&self) -> RangeInclusive<u32> {
+        let mut oldest = self.current;
+
+        while let Some(older) = oldest.checked_sub(1) {
+            if !self.upgrades.contains_key(&older) || !self.downgrades.contains_key(&older) {
+                break;
+            }
+            oldest = older;
+        }
+
+        oldest..=self.current
+    }
+
+    fn hello(&self) -> Hello {
+        Hello {
+            protocol_version: self.current,
+            min_protocol_version: *self.supported().start(),
+        }
+    }
+
+    /// Picks the newest version spoken by both us and the peer who sent `hello`.
+    fn agree(&self, hello: &Hello) -> Result<u32, VersionError> {
+        let ours = self.supported();
+        let theirs = hello.min_protocol_version..=hello.protocol_version;
+        let agreed = self.current.min(hello.protocol_version);
+
+        if ours.contains(&agreed) && theirs.contains(&agreed) {
+            Ok(agreed)
+        } else {
+            Err(VersionError::Incompatible { ours, theirs })
+        }
+    }
+
+    /// Builds the conversions between version `agreed` on the wire and the current version.
+    fn into_maps<In, Out>(self, agreed: u32) -> (ReadMap<In>, WriteMap<Out>)
+    where
+        In: DeserializeOwned + 'static,
+        Out: Serialize + 'static,
+    {
+        let current = self.current;
+        let mut upgrades = self.upgrades;
+        let mut downgrades = self.downgrades;
+
+        let read_map: ReadMap<In> = Box::new(move |raw| {
+            let mut value: Value = serde_json::from_str(raw.get())?;
+            for upgrade in upgrades.range_mut(agreed..current).map(|(_, f)| f) {
+                value = upgrade(value);
+            }
+            serde_json::from_value(value)
+        });
+
+        let write_map: WriteMap<Out> = Box::new(move |t| {
+            let mut value = serde_json::to_value(t)?;
+            for downgrade in downgrades.range_mut(agreed..current).rev().map(|(_, f)| f) {
+                value = downgrade(value);
+            }
+            serde_json::value::to_raw_value(&value)
+        });
+
+        (read_map, write_map)
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+impl Versioning {
+    /// Agrees on a protocol version with the peer at the other end of `connection`, returning the
+    /// version agreed on and a connection that reads and writes messages in the current version.
+    ///
+    /// Both peers must call this before sending anything else.
+    pub fn negotiate<R, W, In, Out>(
+        self,
+        mut connection: Connection<R, W>,
+    ) -> Result<Negotiated<R, W, In, Out>, VersionError>
+    where
+        R: BufRead,
+        W: Write,
+        In: DeserializeOwned + 'static,
+        Out: Serialize + 'static,
+    {
+        connection.write(&self.hello())?;
+        connection.flush().map_err(WriteError::Io)?;
+
+        let hello: Hello = connection.read()?;
+        let agreed = self.agree(&hello)?;
+        let (read_map, write_map) = self.into_maps(agreed);
+
+        Ok((
+            MappedConnection::from_parts(connection, read_map, write_map),
+            agreed,
+        ))
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Versioning {
+    /// Agrees on a protocol version with the peer at the other end of `connection`, returning the
+    /// version agreed on and a connection that reads and writes messages in the current version.
+    ///
+    /// Both peers must call this before sending anything else.
+    pub async fn negotiate<R, W, In, Out>(
+        self,
+        mut connection: Connection<R, W>,
+    ) -> Result<Negotiated<R, W, In, Out>, VersionError>
+    where
+        R: BufRead + Unpin,
+        W: Write + Unpin,
+        In: DeserializeOwned + 'static,
+        Out: Serialize + 'static,
+    {
+        connection.write(&self.hello()).await?;
+        connection.flush().await.map_err(WriteError::Io)?;
+
+        let hello: Hello = connection.read().await?;
+        let agreed = self.agree(&hello)?;
+        let (read_map, write_map) = self.into_maps(agreed);
+
+        Ok((
+            MappedConnection::from_parts(connection, read_map, write_map),
+            agreed,
+        ))
+    }
+}
+
+impl fmt::Debug for Versioning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Versioning")
+            .field("current", &self.current)
+            .field("supported", &self.supported())
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,345 @@
+use crate::{ReadError, WriteError};
+use serde::de::DeserializeOwned;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// Writes records to a write-ahead log.
+///
+/// A write-ahead log is JSON Lines with every line prefixed by the CRC-32 checksum (as eight hex
+/// digits) and the length in bytes of the JSON that follows:
+///
+/// ```text
+/// 561bacaf 7 {"a":1}
+/// ```
+///
+/// If the process crashes partway through writing a record, the torn record is detected by its
+/// length or checksum and removed by [`recover`] before the log is appended to again.
+#[derive(Debug)]
+pub struct WalWriter {
+    file: File,
+    path: PathBuf,
+}
+
+impl WalWriter {
+    /// Opens the write-ahead log at `path` for appending, creating it if it does not exist.
+    ///
+    /// This runs [`recover`] first, so that new records are never appended after a torn one.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+
+        if path.exists() {
+            recover(path)?;
+        }
+
+        Ok(Self {
+            file: OpenOptions::new().create(true).append(true).open(path)?,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Appends a record to the log.
+    ///
+    /// Each record is written with a single write call, but is not durable until
+    /// [`WalWriter::sync`] has been called.
+    pub fn append<T: serde::Serialize>(&mut self, t: &T) -> Result<(), WriteError> {
+        let json = serde_json::to_string(t)?;
+        let frame = format!(
+            "{:08x} {} {}\n",
+            crc32fast::hash(json.as_bytes()),
+            json.len(),
+            json
+        );
+
+        self.file.write_all(frame.as_bytes())?;
+
+        Ok(())
+    }
+
+    /// Flushes every record appended so far to disk.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+
+    /// Replaces the log with a fresh one starting from a snapshot, bounding the size of logs that
+    /// record changes to some state.
+    ///
+    /// `snapshot_fn` is given a writer for the fresh log, to which it should append whatever records
+    /// are needed to rebuild the current state. Once it returns, the fresh log is synced and renamed
+    /// over the old one, so after a crash the log holds either every old record or just the
+    /// snapshot, never a mixture. From then on this writer appends to the fresh log.
+    ///
+    /// If anything fails before the rename, the fresh log is removed and this writer carries on
+    /// appending to the old one.
+    pub fn compact<F>(&mut self, snapshot_fn: F) -> Result<(), WriteError>
+    where
+        F: FnOnce(&mut Self) -> Result<(), WriteError>,
+    {
+        let mut temp_path = self.path.clone().into_os_string();
+        temp_path.push(".compact");
+        let temp_path = PathBuf::from(temp_path);
+
+        let fresh = (|| -> Result<Self, WriteError> {
+            let mut fresh = Self {
+                file: OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .truncate(true)
+                    .open(&temp_path)?,
+                path: self.path.clone(),
+            };
+
+            snapshot_fn(&mut fresh)?;
+            fresh.file.sync_all()?;
+            fs::rename(&temp_path, &self.path)?;
+
+            Ok(fresh)
+        })();
+
+        let fresh = match fresh {
+            Ok(fresh) => fresh,
+            Err(e) => {
+                let _ = fs::remove_file(&temp_path);
+                return Err(e);
+            }
+        };
+
+        self.file = fresh.file;
+
+        // The rename itself is only durable once the directory containing the log is synced.
+        #[cfg(unix)]
+        {
+            let dir = match self.path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            File::open(dir)?.sync_all()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads records from a write-ahead log written by [`WalWriter`].
+#[derive(Debug)]
+pub struct WalReader {
+    reader: BufReader<File>,
+    buf: Vec<u8>,
+}
+
+impl WalReader {
+    /// Opens the write-ahead log at `path` for reading.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self {
+            reader: BufReader::new(File::open(path)?),
+            buf: Vec::new(),
+        })
+    }
+
+    /// Reads the next record from the log and deserializes it into a given type.
+    ///
+    /// A torn or corrupted record results in an [`io::ErrorKind::InvalidData`] error.
+    pub fn read<T: DeserializeOwned>(&mut self) -> Result<T, ReadError> {
+        self.buf.clear();
+
+        if self.reader.read_until(b'\n', &mut self.buf)? == 0 {
+            return Err(ReadError::Eof);
+        }
+
+        match parse_frame(&self.buf) {
+            Some(json) => Ok(serde_json::from_slice(json)?),
+            None => Err(ReadError::Io(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "torn or corrupted write-ahead log record",
+            ))),
+        }
+    }
+}
+
+/// The outcome of [`recover`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Recovery {
+    /// The number of intact records in the log.
+    pub num_records: u64,
+    /// The number of bytes removed from the end of the log.
+    pub num_truncated_bytes: u64,
+}
+
+/// Scans the write-ahead log at `path` up to the last intact record and truncates everything after
+/// it, such as a record torn by a crash.
+///
+/// Scanning stops at the first record that fails its length or checksum check, so any records
+/// after a corrupted one are removed too.
+pub fn recover<P: AsRef<Path>>(path: P) -> io::Result<Recovery> {
+    let file = OpenOptions::new().read(true).write(true).open(path)?;
+    let len = file.metadata()?.len();
+    let mut reader = BufReader::new(&file);
+    let mut buf = Vec::new();
+    let mut valid_len = 0;
+    let mut num_records = 0;
+
+    loop {
+        buf.clear();
+
+        if reader.read_until(b'\n', &mut buf)? == 0 || parse_frame(&buf).is_none() {
+            break;
+        }
+
+        valid_len += buf.len() as u64;
+        num_records += 1;
+    }
+
+    if valid_len < len {
+        file.set_len(valid_len)?;
+        file.sync_all()?;
+    }
+
+    Ok(Recovery {
+        num_records,
+        num_truncated_bytes: len.saturating_sub(valid_len),
+    })
+}
+
+/// Checks a line of a write-ahead log, including its trailing newline, returning the JSON it
+/// contains if it is intact.
+fn parse_frame(line: &[u8]) -> Option<&[u8]> {
+    let line = line.strip_suffix(b"\n")?;
+    let (checksum, rest) = split_at_space(line)?;
+    let (len, json) = split_at_space(rest)?;
+
+    let checksum = u32::from_str_radix(std::str::from_utf8(checksum).ok()?, 16).ok()?;
+    let len: usize = std::str::from_utf8(len).ok()?.parse().ok()?;
+
+    if json.len() != len || crc32fast::hash(json) != checksum {
+        return None;
+    }
+
+    Some(json)
+}
+
+fn split_at_space(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let i = bytes.iter().position(|b| *b == b' ')?;
+    Some((&bytes[..i], &bytes[i + 1..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_all(path: &Path) -> Result<Vec<u32>, ReadError> {
+        let mut reader = WalReader::open(path)?;
+        let mut records = Vec::new();
+
+        loop {
+            match reader.read() {
+                Ok(record) => records.push(record),
+                Err(ReadError::Eof) => return Ok(records),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    #[test]
+    fn records_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("log");
+
+        let mut writer = WalWriter::open(&path)?;
+        for n in 1..=3 {
+            writer.append(&n)?;
+        }
+        writer.sync()?;
+
+        assert!(fs::read(&path)?.starts_with(b"83dcefb7 1 1\n"));
+        assert_eq!(read_all(&path)?, [1, 2, 3]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn torn_records_are_truncated_on_open() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("log");
+
+        let mut writer = WalWriter::open(&path)?;
+        writer.append(&1)?;
+        writer.append(&2)?;
+        drop(writer);
+
+        let intact_len = fs::metadata(&path)?.len();
+        let mut file = OpenOptions::new().append(true).open(&path)?;
+        file.write_all(b"83dcefb7 3 12")?;
+        drop(file);
+
+        assert!(
+            matches!(read_all(&path), Err(ReadError::Io(e)) if e.kind() == io::ErrorKind::InvalidData)
+        );
+
+        let mut writer = WalWriter::open(&path)?;
+        assert_eq!(fs::metadata(&path)?.len(), intact_len);
+        writer.append(&3)?;
+
+        assert_eq!(read_all(&path)?, [1, 2, 3]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn recovery_stops_at_a_bad_checksum() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("log");
+        fs::write(&path, b"83dcefb7 1 1\n00000000 1 2\n1ad5be0d 1 3\n")?;
+
+        let recovery = recover(&path)?;
+
+        assert_eq!(
+            recovery,
+            Recovery {
+                num_records: 1,
+                num_truncated_bytes: 26,
+            }
+        );
+        assert_eq!(read_all(&path)?, [1]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn compaction_replaces_the_log() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("log");
+
+        let mut writer = WalWriter::open(&path)?;
+        for n in 1..=3 {
+            writer.append(&n)?;
+        }
+        writer.compact(|fresh| fresh.append(&6))?;
+        writer.append(&7)?;
+
+        assert_eq!(read_all(&path)?, [6, 7]);
+        assert!(!dir.path().join("log.compact").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn failed_compaction_keeps_the_old_log() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("log");
+
+        let mut writer = WalWriter::open(&path)?;
+        writer.append(&1)?;
+
+        let result = writer.compact(|fresh| {
+            fresh.append(&6)?;
+            Err(WriteError::Io(io::Error::other("snapshot failed")))
+        });
+        assert!(result.is_err());
+        writer.append(&2)?;
+
+        assert_eq!(read_all(&path)?, [1, 2]);
+        assert!(!dir.path().join("log.compact").exists());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+use serde_json::Value;
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Once, RwLock};
+
+/// The environment variable that turns on wire logging when set to anything other than `0` or the
+/// empty string.
+pub const WIRE_LOG_ENV: &str = "JSONL_WIRE_LOG";
+
+/// The environment variable holding a comma-separated list of JSON pointers to redact from wire
+/// logs, such as `/password,/user/ssn`.
+pub const WIRE_LOG_REDACT_ENV: &str = "JSONL_WIRE_LOG_REDACT";
+
+/// The string that redacted values are replaced with.
+const REDACTED: &str = "[REDACTED]";
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static REDACT: RwLock<Vec<String>> = RwLock::new(Vec::new());
+static INIT_FROM_ENV: Once = Once::new();
+
+/// Turns on logging of every line read and written by this crate, with the values at the given
+/// JSON pointers replaced by `"[REDACTED]"`.
+///
+/// Lines are logged through the `log` crate at the debug level with a target of `jsonl::wire`,
+/// prefixed with `<-` if they were read and `->` if they were written. Wire logging can also be
+/// turned on without code changes by setting the `JSONL_WIRE_LOG` environment variable, in which
+/// case the pointers to redact are taken from `JSONL_WIRE_LOG_REDACT`.
+pub fn enable_wire_logging<I, S>(redact: I)
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    init_from_env();
+    *REDACT.write().unwrap_or_else(|e| e.into_inner()) =
+        redact.into_iter().map(Into::into).collect();
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Turns off wire logging, whether it was turned on by [`enable_wire_logging`] or by the
+/// environment.
+pub fn disable_wire_logging() {
+    init_from_env();
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+fn init_from_env() {
+    INIT_FROM_ENV.call_once(|| {
+        let enabled = env::var(WIRE_LOG_ENV).is_ok_and(|value| !value.is_empty() && value != "0");
+
+        if enabled {
+            if let Ok(redact) = env::var(WIRE_LOG_REDACT_ENV) {
+                *REDACT.write().unwrap_or_else(|e| e.into_inner()) = redact
+                    .split(',')
+                    .filter(|pointer| !pointer.is_empty())
+                    .map(String::from)
+                    .collect();
+            }
+
+            ENABLED.store(true, Ordering::Relaxed);
+        }
+    });
+}
+
+pub(crate) fn inbound(line: &str) {
+    log_line("<-", line);
+}
+
+pub(crate) fn outbound(line: &str) {
+    log_line("->", line);
+}
+
+fn log_line(prefix: &str, line: &str) {
+    init_from_env();
+
+    if !ENABLED.load(Ordering::Relaxed)
+        || !log::log_enabled!(target: "jsonl::wire", log::Level::Debug)
+    {
+        return;
+    }
+
+    let line = line.trim_end();
+    let redact = REDACT.read().unwrap_or_else(|e| e.into_inner());
+
+    if redact.is_empty() {
+        log::debug!(target: "jsonl::wire", "{} {}", prefix, line);
+        return;
+    }
+
+    match serde_json::from_str::<Value>(line) {
+        Ok(mut value) => {
+            for pointer in redact.iter() {
+                if let Some(field) = value.pointer_mut(pointer) {
+                    *field = Value::from(REDACTED);
+                }
+            }
+
+            log::debug!(target: "jsonl::wire", "{} {}", prefix, value);
+        }
+        // A line that is not valid JSON cannot be redacted, so it is not logged in full either.
+        Err(_) => {
+            log::debug!(target: "jsonl::wire", "{} <{} bytes of invalid JSON>", prefix, line.len())
+        }
+    }
+}
@@ -0,0 +1,110 @@
+#[cfg(not(feature = "tokio"))]
+mod imports {
+    pub(super) use std::io::{BufRead, Write};
+}
+#[cfg(feature = "tokio")]
+mod imports {
+    pub(super) use tokio::io::{AsyncBufRead as BufRead, AsyncWrite as Write, AsyncWriteExt};
+}
+
+use crate::{Connection, WriteError};
+use imports::*;
+use serde::Serialize;
+
+/// Lines written to a [`Connection`] that are sent all together or not at all, created with
+/// [`Connection::batch`].
+///
+/// Nothing is written to the connection until [`WriteBatch::commit`], which writes every line in
+/// one contiguous write and flushes it, so that a logical unit spanning several lines is never
+/// interleaved with lines from other writers sharing the same stream, nor left half-sent. Dropping
+/// a `WriteBatch` without committing it, or calling [`WriteBatch::abort`], discards every line.
+///
+/// The connection is borrowed for as long as the batch is alive, so it cannot be written to in
+/// the meantime.
+#[derive(Debug)]
+#[must_use = "lines in a batch are discarded unless it is committed"]
+pub struct WriteBatch<'a, R: BufRead, W: Write> {
+    connection: &'a mut Connection<R, W>,
+    bytes: Vec<u8>,
+    num_lines: usize,
+}
+
+impl<R: BufRead, W: Write> Connection<R, W> {
+    /// Starts a batch of lines to be written together; see [`WriteBatch`].
+    pub fn batch(&mut self) -> WriteBatch<'_, R, W> {
+        WriteBatch {
+            connection: self,
+            bytes: Vec::new(),
+            num_lines: 0,
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> WriteBatch<'_, R, W> {
+    /// Adds a given value to the batch, serializing it into JSON straight away so that
+    /// serialization errors are returned here rather than on commit.
+    pub fn write<T: Serialize>(&mut self, t: &T) -> Result<(), WriteError> {
+        let line = crate::format::encode_line(t)?;
+        self.bytes.extend_from_slice(&line);
+        self.num_lines += 1;
+
+        Ok(())
+    }
+
+    /// Returns the number of lines in the batch.
+    pub fn len(&self) -> usize {
+        self.num_lines
+    }
+
+    /// Returns whether the batch has no lines.
+    pub fn is_empty(&self) -> bool {
+        self.num_lines == 0
+    }
+
+    /// Discards every line in the batch without writing anything. This is the same as dropping
+    /// it, but makes the intent clear.
+    pub fn abort(self) {}
+}
+
+#[cfg(not(feature = "tokio"))]
+impl<R: BufRead, W: Write> WriteBatch<'_, R, W> {
+    /// Writes every line in the batch in one contiguous write and flushes the writer, returning
+    /// the number of bytes written.
+    ///
+    /// If this fails, an unknown part of the batch may have been written, as with any write to a
+    /// stream.
+    pub fn commit(self) -> Result<usize, WriteError> {
+        if self.bytes.is_empty() {
+            return Ok(0);
+        }
+
+        let writer = self.connection.writer_mut();
+        writer.write_all(&self.bytes).map_err(WriteError::Io)?;
+        writer.flush().map_err(WriteError::Io)?;
+
+        Ok(self.bytes.len())
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<R: BufRead + Unpin, W: Write + Unpin> WriteBatch<'_, R, W> {
+    /// Writes every line in the batch in one contiguous write and flushes the writer, returning
+    /// the number of bytes written.
+    ///
+    /// If this fails, an unknown part of the batch may have been written, as with any write to a
+    /// stream.
+    pub async fn commit(self) -> Result<usize, WriteError> {
+        if self.bytes.is_empty() {
+            return Ok(0);
+        }
+
+        let writer = self.connection.writer_mut();
+        writer
+            .write_all(&self.bytes)
+            .await
+            .map_err(WriteError::Io)?;
+        writer.flush().await.map_err(WriteError::Io)?;
+
+        Ok(self.bytes.len())
+    }
+}